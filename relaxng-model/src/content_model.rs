@@ -0,0 +1,240 @@
+//! The content models of elements, as trees that a documentation generator can render, such as
+//! `title, id, updated, (link | author | entry)*`
+//!
+//! References to definitions are expanded in place, as far as a given depth, beyond which they
+//! are left as `ContentModel::Ref`.  Child elements are given only by name, since each has a
+//! content model of its own.
+
+use crate::model::{DefineRule, NameClass, Pattern};
+use crate::visit::{Visitor, Walker, walk_pattern};
+use std::fmt;
+use std::mem;
+
+/// What an element may contain, in the terms of the schema which defines it
+///
+/// The `Display` form is a grammar production's right hand side, with `,` separating the
+/// members of a group, `|` the alternatives of a choice, `&` the members of an interleave, and
+/// `@` marking attributes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ContentModel {
+    Element(NameClass),
+    Attribute(NameClass),
+    Text,
+    /// data of the named type, such as `xsd:int` (any `except` is left out)
+    Data(String),
+    /// a fixed value, as it could be written in the schema
+    Value(String),
+    List(Box<ContentModel>),
+    Empty,
+    NotAllowed,
+    Group(Vec<ContentModel>),
+    Choice(Vec<ContentModel>),
+    Interleave(Vec<ContentModel>),
+    Optional(Box<ContentModel>),
+    ZeroOrMore(Box<ContentModel>),
+    OneOrMore(Box<ContentModel>),
+    /// a reference to a definition which was not expanded, being nested too deeply
+    Ref(String),
+}
+
+/// The content model of the first element in the schema whose name is the given one, either a
+/// local name or, for an element in a namespace, the name in Clark notation such as
+/// `{http://www.w3.org/2005/Atom}feed`
+///
+/// References are expanded `depth` deep, so that with a depth of 0 the model is given in terms
+/// of the definitions the element's content refers to.
+pub fn element_content_model(
+    start_rule: &DefineRule,
+    name: &str,
+    depth: usize,
+) -> Option<ContentModel> {
+    let mut finder = FindElement {
+        name,
+        depth,
+        found: None,
+    };
+    walk_pattern(&mut finder, start_rule.pattern());
+    finder.found
+}
+
+struct FindElement<'a> {
+    name: &'a str,
+    depth: usize,
+    found: Option<ContentModel>,
+}
+
+impl Visitor for FindElement<'_> {
+    fn visit_element(&mut self, walker: &mut Walker, name_class: &NameClass, content: &Pattern) {
+        if self.found.is_some() {
+            return;
+        }
+        if let NameClass::Named {
+            namespace_uri,
+            name,
+        } = name_class
+            && (self.name == name || self.name == format!("{{{namespace_uri}}}{name}"))
+        {
+            self.found = Some(ContentModel::of(content, self.depth));
+            return;
+        }
+        walker.walk(self, content)
+    }
+}
+
+impl ContentModel {
+    /// The content model of the given pattern, expanding references `depth` deep
+    pub fn of(pattern: &Pattern, depth: usize) -> ContentModel {
+        match pattern {
+            Pattern::Element(name_class, _) => ContentModel::Element(name_class.clone()),
+            Pattern::Attribute(name_class, _) => ContentModel::Attribute(name_class.clone()),
+            Pattern::Choice(alternatives) => {
+                ContentModel::Choice(Self::members(alternatives, depth, ContentModel::Choice))
+            }
+            Pattern::Interleave(members) => {
+                ContentModel::Interleave(Self::members(members, depth, ContentModel::Interleave))
+            }
+            Pattern::Group(members) => {
+                ContentModel::Group(Self::members(members, depth, ContentModel::Group))
+            }
+            Pattern::Mixed(content) => {
+                let mut members = vec![ContentModel::Text];
+                match ContentModel::of(content, depth) {
+                    ContentModel::Interleave(rest) => members.extend(rest),
+                    other => members.push(other),
+                }
+                ContentModel::Interleave(members)
+            }
+            Pattern::Empty => ContentModel::Empty,
+            Pattern::Text => ContentModel::Text,
+            Pattern::NotAllowed => ContentModel::NotAllowed,
+            Pattern::Optional(content) => {
+                ContentModel::Optional(Box::new(ContentModel::of(content, depth)))
+            }
+            Pattern::ZeroOrMore(content) => {
+                ContentModel::ZeroOrMore(Box::new(ContentModel::of(content, depth)))
+            }
+            Pattern::OneOrMore(content) => {
+                ContentModel::OneOrMore(Box::new(ContentModel::of(content, depth)))
+            }
+            Pattern::List(content) => {
+                ContentModel::List(Box::new(ContentModel::of(content, depth)))
+            }
+            Pattern::Ref(_, name, pat_ref) => match pat_ref.0.borrow().as_ref() {
+                Some(rule) if depth > 0 => ContentModel::of(rule.pattern(), depth - 1),
+                _ => ContentModel::Ref(name.clone()),
+            },
+            Pattern::DatatypeValue { datatype } => ContentModel::Value(datatype.lexical()),
+            Pattern::DatatypeName { datatype, .. } => ContentModel::Data(datatype.name()),
+        }
+    }
+
+    /// The models of the members of a group, choice or interleave (whichever `kind` makes),
+    /// with those that are themselves of the same kind, as expanded references may be, merged
+    /// into it
+    fn members(
+        patterns: &[Pattern],
+        depth: usize,
+        kind: fn(Vec<ContentModel>) -> ContentModel,
+    ) -> Vec<ContentModel> {
+        let kind = mem::discriminant(&kind(vec![]));
+        let mut result = vec![];
+        for pattern in patterns {
+            let model = ContentModel::of(pattern, depth);
+            let same_kind = mem::discriminant(&model) == kind;
+            match model {
+                ContentModel::Group(members)
+                | ContentModel::Choice(members)
+                | ContentModel::Interleave(members)
+                    if same_kind =>
+                {
+                    result.extend(members)
+                }
+                other => result.push(other),
+            }
+        }
+        result
+    }
+
+    /// Write the model, in brackets if it is made of several parts that would otherwise run
+    /// into whatever surrounds it
+    fn fmt_operand(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContentModel::Group(members)
+            | ContentModel::Choice(members)
+            | ContentModel::Interleave(members)
+                if members.len() > 1 =>
+            {
+                write!(f, "({self})")
+            }
+            ContentModel::Optional(_)
+            | ContentModel::ZeroOrMore(_)
+            | ContentModel::OneOrMore(_) => {
+                write!(f, "({self})")
+            }
+            _ => write!(f, "{self}"),
+        }
+    }
+}
+
+impl fmt::Display for ContentModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (members, separator) = match self {
+            ContentModel::Element(name_class) => return fmt_name_class(f, name_class),
+            ContentModel::Attribute(name_class) => {
+                f.write_str("@")?;
+                return fmt_name_class(f, name_class);
+            }
+            ContentModel::Text => return f.write_str("text"),
+            ContentModel::Data(name) => return f.write_str(name),
+            ContentModel::Value(value) => return write!(f, "{value:?}"),
+            ContentModel::List(content) => return write!(f, "list {{ {content} }}"),
+            ContentModel::Empty => return f.write_str("empty"),
+            ContentModel::NotAllowed => return f.write_str("notAllowed"),
+            ContentModel::Ref(name) => return f.write_str(name),
+            ContentModel::Optional(content) => {
+                content.fmt_operand(f)?;
+                return f.write_str("?");
+            }
+            ContentModel::ZeroOrMore(content) => {
+                content.fmt_operand(f)?;
+                return f.write_str("*");
+            }
+            ContentModel::OneOrMore(content) => {
+                content.fmt_operand(f)?;
+                return f.write_str("+");
+            }
+            ContentModel::Group(members) => (members, ", "),
+            ContentModel::Choice(members) => (members, " | "),
+            ContentModel::Interleave(members) => (members, " & "),
+        };
+        for (i, member) in members.iter().enumerate() {
+            if i > 0 {
+                f.write_str(separator)?;
+            }
+            match member {
+                ContentModel::Optional(_)
+                | ContentModel::ZeroOrMore(_)
+                | ContentModel::OneOrMore(_) => write!(f, "{member}")?,
+                _ => member.fmt_operand(f)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Write a name class as the local names it matches, `{uri}*` for any name in a namespace, or
+/// `*` for any name at all
+fn fmt_name_class(f: &mut fmt::Formatter<'_>, name_class: &NameClass) -> fmt::Result {
+    match name_class {
+        NameClass::Named { name, .. } => f.write_str(name),
+        NameClass::NsName { namespace_uri, .. } => write!(f, "{{{namespace_uri}}}*"),
+        NameClass::AnyName { .. } => f.write_str("*"),
+        NameClass::Alt { a, b } => {
+            f.write_str("(")?;
+            fmt_name_class(f, a)?;
+            f.write_str(" | ")?;
+            fmt_name_class(f, b)?;
+            f.write_str(")")
+        }
+    }
+}
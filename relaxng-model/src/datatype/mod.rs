@@ -1,10 +1,15 @@
-use crate::Context;
+use crate::serial::codec_enum;
+use crate::{Context, RelaxWarning};
 use relaxng_syntax::types;
-use relaxng_syntax::types::{DatatypeName, NamespacedName};
+use relaxng_syntax::types::{DatatypeName, NamespacedName, NcName, QName};
 
 pub mod relax;
 pub mod xsd;
 
+/// The namespace of the datatype library of the RELAX NG DTD Compatibility specification, whose
+/// `ID`, `IDREF` and `IDREFS` types are checked as the XML Schema types of the same names
+pub const COMPATIBILITY_NAMESPACE_URI: &str = "http://relaxng.org/ns/compatibility/datatypes/1.0";
+
 pub trait Namespaces {
     fn resolve(&self, prefix: &str) -> Option<&str>;
 }
@@ -33,6 +38,7 @@ pub(crate) trait DatatypeCompiler {
         ctx: &Context,
         name: &types::DatatypeName,
         params: &[types::Param],
+        warnings: &mut Vec<RelaxWarning>,
     ) -> Result<Self::DT, Self::Error>;
 }
 
@@ -50,6 +56,30 @@ impl Datatype for DatatypeValues {
     }
 }
 impl DatatypeValues {
+    /// The name of the value's type, as for `Datatypes::name()`
+    pub fn name(&self) -> String {
+        match self {
+            DatatypeValues::Relax(relax::BuiltinDatatypeValue::TokenValue(_)) => {
+                "token".to_string()
+            }
+            DatatypeValues::Relax(relax::BuiltinDatatypeValue::StringValue(_)) => {
+                "string".to_string()
+            }
+            DatatypeValues::Xsd(xsd) => format!("xsd:{}", xsd.name()),
+        }
+    }
+
+    /// The value as it could be written in a schema (see `XsdDatatypeValues::lexical()`)
+    pub fn lexical(&self) -> String {
+        match self {
+            DatatypeValues::Relax(
+                relax::BuiltinDatatypeValue::TokenValue(val)
+                | relax::BuiltinDatatypeValue::StringValue(val),
+            ) => val.clone(),
+            DatatypeValues::Xsd(xsd) => xsd.lexical(),
+        }
+    }
+
     pub fn is_valid_with_ns(&self, value: &str, ns: &dyn Namespaces) -> bool {
         match self {
             DatatypeValues::Relax(relax) => relax.is_valid(value),
@@ -70,22 +100,121 @@ impl Datatype for Datatypes {
         }
     }
 }
+impl Datatypes {
+    /// The name of the type, such as `token` for a builtin type or `xsd:int` for a type from
+    /// XML Schema
+    pub fn name(&self) -> String {
+        match self {
+            Datatypes::Relax(relax::BuiltinDatatype::Token) => "token".to_string(),
+            Datatypes::Relax(relax::BuiltinDatatype::String) => "string".to_string(),
+            Datatypes::Xsd(xsd) => format!("xsd:{}", xsd.name()),
+        }
+    }
+
+    /// Whether the value is valid, using the given namespaces to resolve the prefix of an
+    /// `xsd:QName` value
+    pub fn is_valid_with_ns(&self, value: &str, ns: &dyn Namespaces) -> bool {
+        match self {
+            Datatypes::Relax(relax) => relax.is_valid(value),
+            Datatypes::Xsd(xsd) => xsd.is_valid_with_ns(value, ns),
+        }
+    }
+
+    /// A remark about a value that this datatype accepts, if the value is accepted only
+    /// because the datatype is checked leniently (such as an `xsd:anyURI` value which is not
+    /// really a URI)
+    pub fn note(&self, value: &str) -> Option<&'static str> {
+        match self {
+            Datatypes::Relax(_) => None,
+            Datatypes::Xsd(xsd) => xsd.note(value),
+        }
+    }
+
+    /// Why the datatype rejects the value, naming the facet that it fails, or `None` if it is
+    /// accepted or no facet is to blame (see `XsdDatatypes::explain()`)
+    pub fn explain(&self, value: &str) -> Option<String> {
+        match self {
+            Datatypes::Relax(_) => None,
+            Datatypes::Xsd(xsd) => xsd.explain(value),
+        }
+    }
+}
+codec_enum!(DatatypeValues {
+    0 => Relax(dt),
+    1 => Xsd(dt),
+});
+codec_enum!(Datatypes {
+    0 => Relax(dt),
+    1 => Xsd(dt),
+});
+
 #[derive(Debug)]
 pub enum Errors {
     UnsupportedDatatypeLibrary {
         span: codemap::Span,
         namespace: String,
     },
+    /// A parameter was given to one of the compatibility library's types, which take none
+    CompatibilityParamNotAllowed {
+        span: codemap::Span,
+        name: String,
+    },
     Relax(relax::Error),
     Xsd(xsd::XsdDatatypeError),
 }
 
+/// Check a single value against a datatype, as a `data` pattern in a schema would, without
+/// compiling a schema.
+///
+/// `library_uri` is `""` for the built-in `string` and `token` types, or the XML Schema
+/// datatypes namespace, `params` are the facets as `(name, value)` pairs, and `namespaces`
+/// resolves the prefixes of `xsd:QName` values.  Any warnings about the datatype (such as for
+/// ignored facets) are discarded.
+pub fn check(
+    library_uri: &str,
+    type_name: &str,
+    params: &[(&str, &str)],
+    value: &str,
+    namespaces: &dyn Namespaces,
+) -> Result<bool, Errors> {
+    // errors from the compiler refer to spans of this empty file
+    let file = codemap::CodeMap::new().add_file("<datatype>".to_string(), String::new());
+    let ctx = Context::new(file);
+    let name = DatatypeName::CName(QName(
+        NcName(0..0, library_uri.to_string()),
+        NcName(0..0, type_name.to_string()),
+    ));
+    let params: Vec<_> = params
+        .iter()
+        .map(|(name, value)| {
+            types::Param(
+                0..0,
+                None,
+                types::IdentifierOrKeyword::Identifier(types::Identifier(0..0, name.to_string())),
+                types::Literal::new(0..0, value.to_string()),
+            )
+        })
+        .collect();
+    let datatype = Compiler::default().datatype_name(&ctx, &name, &params, &mut vec![])?;
+    Ok(datatype.is_valid_with_ns(value, namespaces))
+}
+
 #[derive(Default)]
 pub struct Compiler {
     relax: relax::Compiler,
     xsd: xsd::Compiler,
 }
 
+impl Compiler {
+    pub(crate) fn set_strict_language_tags(&mut self, strict: bool) {
+        self.xsd.set_strict_language_tags(strict);
+    }
+
+    pub(crate) fn set_policy(&mut self, policy: xsd::DatatypePolicy) {
+        self.xsd.set_policy(policy);
+    }
+}
+
 impl DatatypeCompiler for Compiler {
     type DT = Datatypes;
     type DTValue = DatatypeValues;
@@ -124,19 +253,20 @@ impl DatatypeCompiler for Compiler {
         ctx: &Context,
         datatype_name: &types::DatatypeName,
         params: &[types::Param],
+        warnings: &mut Vec<RelaxWarning>,
     ) -> Result<Self::DT, Self::Error> {
         match datatype_name {
             types::DatatypeName::String | types::DatatypeName::Token => self
                 .relax
-                .datatype_name(ctx, datatype_name, params)
+                .datatype_name(ctx, datatype_name, params, warnings)
                 .map(Datatypes::Relax)
                 .map_err(Errors::Relax),
             DatatypeName::CName(types::QName(types::NcName(span, namespace_uri), _)) => {
-                self.dt_name(ctx, datatype_name, params, span, namespace_uri)
+                self.dt_name(ctx, datatype_name, params, warnings, span, namespace_uri)
             }
             DatatypeName::NamespacedName(NamespacedName { namespace_uri, .. }) => {
                 let ns = &namespace_uri.as_string_value()[..];
-                self.dt_name(ctx, datatype_name, params, &namespace_uri.0, ns)
+                self.dt_name(ctx, datatype_name, params, warnings, &namespace_uri.0, ns)
             }
         }
     }
@@ -148,20 +278,34 @@ impl Compiler {
         ctx: &Context,
         datatype_name: &DatatypeName,
         params: &[types::Param],
+        warnings: &mut Vec<RelaxWarning>,
         ns_span: &types::Span,
         ns: &str,
     ) -> Result<Datatypes, Errors> {
         match ns {
             "" => self
                 .relax
-                .datatype_name(ctx, datatype_name, params)
+                .datatype_name(ctx, datatype_name, params, warnings)
                 .map(Datatypes::Relax)
                 .map_err(Errors::Relax),
             xsd::NAMESPACE_URI => self
                 .xsd
-                .datatype_name(ctx, datatype_name, params)
+                .datatype_name(ctx, datatype_name, params, warnings)
                 .map(Datatypes::Xsd)
                 .map_err(Errors::Xsd),
+            COMPATIBILITY_NAMESPACE_URI => {
+                if let Some(param) = params.first() {
+                    return Err(Errors::CompatibilityParamNotAllowed {
+                        span: ctx.convert_span(&param.0),
+                        name: param.2.to_string(),
+                    });
+                }
+                Self::check_compatibility_type(ctx, datatype_name)?;
+                self.xsd
+                    .datatype_name(ctx, datatype_name, params, warnings)
+                    .map(Datatypes::Xsd)
+                    .map_err(Errors::Xsd)
+            }
             _ => Err(Errors::UnsupportedDatatypeLibrary {
                 span: ctx.convert_span(ns_span),
                 namespace: ns.to_string(),
@@ -189,10 +333,33 @@ impl Compiler {
                 .datatype_value(ctx, datatype_name, value, ns_bindings)
                 .map(DatatypeValues::Xsd)
                 .map_err(Errors::Xsd),
+            // values of the compatibility types are compared as tokens
+            COMPATIBILITY_NAMESPACE_URI => {
+                Self::check_compatibility_type(ctx, datatype_name)?;
+                Ok(DatatypeValues::Relax(
+                    relax::BuiltinDatatypeValue::TokenValue(relax::normalize_whitespace(value)),
+                ))
+            }
             _ => Err(Errors::UnsupportedDatatypeLibrary {
                 span: ctx.convert_span(ns_span),
                 namespace: ns.to_string(),
             }),
         }
     }
+
+    /// Fail unless the name is that of one of the types in the compatibility library
+    fn check_compatibility_type(ctx: &Context, datatype_name: &DatatypeName) -> Result<(), Errors> {
+        let localname = match datatype_name {
+            DatatypeName::CName(QName(_, localname))
+            | DatatypeName::NamespacedName(NamespacedName { localname, .. }) => localname,
+            DatatypeName::String | DatatypeName::Token => unreachable!(),
+        };
+        match &localname.1[..] {
+            "ID" | "IDREF" | "IDREFS" => Ok(()),
+            _ => Err(Errors::Relax(relax::Error::DatataypeNameUnknown {
+                span: ctx.convert_span(&localname.0),
+                name: localname.1.clone(),
+            })),
+        }
+    }
 }
@@ -1,7 +1,8 @@
 //! The RELAX NG built-in datatype library
 
 //use relaxng_model::model::{DatatypeName, Param, Span};
-use crate::Context;
+use crate::serial::codec_enum;
+use crate::{Context, RelaxWarning};
 use relaxng_syntax::types::NamespacedName;
 use relaxng_syntax::types::{DatatypeName, NcName, Param, QName};
 
@@ -55,6 +56,15 @@ impl super::Datatype for BuiltinDatatype {
     }
 }
 
+codec_enum!(BuiltinDatatypeValue {
+    0 => TokenValue(value),
+    1 => StringValue(value),
+});
+codec_enum!(BuiltinDatatype {
+    0 => Token,
+    1 => String,
+});
+
 #[derive(Debug)]
 pub enum Error {
     ParamNotAllowed { span: codemap::Span, name: String },
@@ -104,6 +114,7 @@ impl super::DatatypeCompiler for Compiler {
         ctx: &Context,
         name: &DatatypeName,
         params: &[Param],
+        _warnings: &mut Vec<RelaxWarning>,
     ) -> Result<Self::DT, Self::Error> {
         if !params.is_empty() {
             return Err(Error::ParamNotAllowed {
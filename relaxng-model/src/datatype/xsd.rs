@@ -1,19 +1,59 @@
-use crate::Context;
 use crate::datatype::relax::normalize_whitespace;
+use crate::serial::{self, Decode, Encode, Reader, Writer, codec_enum, codec_struct};
+use crate::{Context, RelaxWarning};
 use lazy_static::lazy_static;
 use relaxng_syntax::types;
 use relaxng_syntax::types::DatatypeName;
-use std::convert::TryFrom;
+use std::borrow::Cow;
 use std::fmt;
+use std::io;
 use std::str::FromStr;
 
 pub const NAMESPACE_URI: &str = "http://www.w3.org/2001/XMLSchema-datatypes";
 
-#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+/// The value given in a schema `value` pattern.
+///
+/// The `Double` and `Float` variants match instance values that are equal in value space, so
+/// `1.0` matches `1E0` and `1.00`.  Since `NaN` is not equal to anything, including itself, a
+/// `value` of `NaN` is accepted but never matches.
+#[derive(Clone, Debug)]
 pub enum XsdDatatypeValues {
     String(String),
     Token(String),
     QName(QNameVal),
+    Double(f64),
+    Float(f32),
+}
+
+// Equality and hashing are structural (bitwise for the floating point variants) so that
+// identical patterns can be shared; matching in value space is done by is_valid()
+impl PartialEq for XsdDatatypeValues {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (XsdDatatypeValues::String(a), XsdDatatypeValues::String(b)) => a == b,
+            (XsdDatatypeValues::Token(a), XsdDatatypeValues::Token(b)) => a == b,
+            (XsdDatatypeValues::QName(a), XsdDatatypeValues::QName(b)) => a == b,
+            (XsdDatatypeValues::Double(a), XsdDatatypeValues::Double(b)) => {
+                a.to_bits() == b.to_bits()
+            }
+            (XsdDatatypeValues::Float(a), XsdDatatypeValues::Float(b)) => {
+                a.to_bits() == b.to_bits()
+            }
+            _ => false,
+        }
+    }
+}
+impl Eq for XsdDatatypeValues {}
+impl std::hash::Hash for XsdDatatypeValues {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            XsdDatatypeValues::String(s) | XsdDatatypeValues::Token(s) => s.hash(state),
+            XsdDatatypeValues::QName(q) => q.hash(state),
+            XsdDatatypeValues::Double(v) => v.to_bits().hash(state),
+            XsdDatatypeValues::Float(v) => v.to_bits().hash(state),
+        }
+    }
 }
 
 impl super::Datatype for XsdDatatypeValues {
@@ -24,11 +64,36 @@ impl super::Datatype for XsdDatatypeValues {
             // QName validation requires namespace context; is_valid_with_ns should be used instead.
             // Without namespace context we cannot resolve prefixes, so we return false.
             XsdDatatypeValues::QName(_) => false,
+            XsdDatatypeValues::Double(v) => parse_double(value.trim()).is_some_and(|d| d == *v),
+            XsdDatatypeValues::Float(v) => parse_float(value.trim()).is_some_and(|f| f == *v),
         }
     }
 }
 
 impl XsdDatatypeValues {
+    /// The name of the value's type, such as `"token"` or `"double"`
+    pub fn name(&self) -> &'static str {
+        match self {
+            XsdDatatypeValues::String(_) => "string",
+            XsdDatatypeValues::Token(_) => "token",
+            XsdDatatypeValues::QName(_) => "QName",
+            XsdDatatypeValues::Double(_) => "double",
+            XsdDatatypeValues::Float(_) => "float",
+        }
+    }
+
+    /// The value as it could be written in a schema, with a `QName` given in Clark notation,
+    /// such as `{urn:example}name`
+    pub fn lexical(&self) -> String {
+        match self {
+            XsdDatatypeValues::String(s) | XsdDatatypeValues::Token(s) => s.clone(),
+            XsdDatatypeValues::QName(QNameVal(ns, local)) if ns.is_empty() => local.clone(),
+            XsdDatatypeValues::QName(QNameVal(ns, local)) => format!("{{{ns}}}{local}"),
+            XsdDatatypeValues::Double(v) => v.to_string(),
+            XsdDatatypeValues::Float(v) => v.to_string(),
+        }
+    }
+
     pub fn is_valid_with_ns(&self, value: &str, ns: &dyn super::Namespaces) -> bool {
         use super::Datatype as _;
         match self {
@@ -42,9 +107,30 @@ impl XsdDatatypeValues {
 
 lazy_static! {
     static ref LANG_RE: regex::Regex = regex::Regex::new(r"^[a-zA-Z]{1,8}(-[a-zA-Z0-9]{1,8})*$").unwrap();
-    static ref DATETIME_RE: regex::Regex = regex::Regex::new(r"^-?\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}(\.\d+)?(Z|[+-]\d{2}:\d{2})?$").unwrap();
-    static ref DURATION_RE: regex::Regex = regex::Regex::new(r"^P(([0-9]+([.,][0-9]*)?Y)?([0-9]+([.,][0-9]*)?M)?([0-9]+([.,][0-9]*)?D)?T?([0-9]+([.,][0-9]*)?H)?([0-9]+([.,][0-9]*)?M)?([0-9]+([.,][0-9]*)?S)?)|\d{4}-?(0[1-9]|11|12)-?(?:[0-2]\d|30|31)T((?:[0-1][0-9]|[2][0-3]):?(?:[0-5][0-9]):?(?:[0-5][0-9]|60)|2400|24:00)$").unwrap();
-    static ref TIME_RE: regex::Regex = regex::Regex::new(r"^\d{2}:\d{2}:\d{2}(\.\d+)?(Z|[+-]\d{2}:\d{2})?$").unwrap();
+    // the 'Language-Tag' production of RFC 5646, checking only well-formedness (not that subtags
+    // are present in the IANA registry)
+    static ref BCP47_RE: regex::Regex = regex::Regex::new(concat!(
+        r"^(?i:",
+        // langtag
+        r"(?:[a-z]{2,3}(?:-[a-z]{3}){0,3}|[a-z]{4}|[a-z]{5,8})",
+        r"(?:-[a-z]{4})?",
+        r"(?:-(?:[a-z]{2}|[0-9]{3}))?",
+        r"(?:-(?:[a-z0-9]{5,8}|[0-9][a-z0-9]{3}))*",
+        r"(?:-[0-9a-wyz](?:-[a-z0-9]{2,8})+)*",
+        r"(?:-x(?:-[a-z0-9]{1,8})+)?",
+        // privateuse
+        r"|x(?:-[a-z0-9]{1,8})+",
+        // grandfathered
+        r"|en-GB-oed|i-ami|i-bnn|i-default|i-enochian|i-hak|i-klingon|i-lux|i-mingo|i-navajo",
+        r"|i-pwn|i-tao|i-tay|i-tsu|sgn-BE-FR|sgn-BE-NL|sgn-CH-DE",
+        r"|art-lojban|cel-gaulish|no-bok|no-nyn|zh-guoyu|zh-hakka|zh-min|zh-min-nan|zh-xiang",
+        r")$",
+    )).unwrap();
+    // years have at least four digits, and no leading zero when there are more than four
+    static ref DATETIME_RE: regex::Regex = regex::Regex::new(r"^(-?(?:[1-9]\d{4,}|\d{4}))-(\d{2})-(\d{2})T(\d{2}):(\d{2}):(\d{2})(?:\.(\d+))?(Z|[+-]\d{2}:\d{2})?$").unwrap();
+    // every field is optional here; DurationValue::parse() checks that at least one is present
+    static ref DURATION_RE: regex::Regex = regex::Regex::new(r"^(-)?P(?:(\d+)Y)?(?:(\d+)M)?(?:(\d+)D)?(T(?:(\d+)H)?(?:(\d+)M)?(?:(\d+(?:\.\d*)?|\.\d+)S)?)?$").unwrap();
+    static ref TIME_RE: regex::Regex = regex::Regex::new(r"^(\d{2}):(\d{2}):(\d{2})(?:\.(\d+))?(Z|[+-]\d{2}:\d{2})?$").unwrap();
     static ref GYEAR_RE: regex::Regex = regex::Regex::new(r"^-?\d{4,}(Z|[+-]\d{2}:\d{2})?$").unwrap();
     static ref GYEARMONTH_RE: regex::Regex = regex::Regex::new(r"^-?\d{4,}-\d{2}(Z|[+-]\d{2}:\d{2})?$").unwrap();
     static ref GMONTH_RE: regex::Regex = regex::Regex::new(r"^--\d{2}(Z|[+-]\d{2}:\d{2})?$").unwrap();
@@ -52,6 +138,30 @@ lazy_static! {
     static ref GDAY_RE: regex::Regex = regex::Regex::new(r"^---\d{2}(Z|[+-]\d{2}:\d{2})?$").unwrap();
     static ref BASE64_RE: regex::Regex = regex::Regex::new(r"^[A-Za-z0-9+/\s]*={0,2}$").unwrap();
     static ref HEXBINARY_RE: regex::Regex = regex::Regex::new(r"^([0-9A-Fa-f]{2})*$").unwrap();
+    // the lexical spaces of the numeric types are narrower than what the Rust parsers accept
+    // (exponents in decimals, '_' separators in bigints, 'inf' / 'infinity' in floats, etc.)
+    static ref INTEGER_RE: regex::Regex = regex::Regex::new(r"^[+-]?[0-9]+$").unwrap();
+    static ref DECIMAL_RE: regex::Regex = regex::Regex::new(r"^[+-]?([0-9]+(\.[0-9]*)?|\.[0-9]+)$").unwrap();
+    static ref FLOAT_RE: regex::Regex = regex::Regex::new(r"^([+-]?([0-9]+(\.[0-9]*)?|\.[0-9]+)([eE][+-]?[0-9]+)?|-?INF|NaN)$").unwrap();
+}
+
+/// Parses the lexical form of an `xsd:double`, which (unlike `f64::from_str()`) only allows
+/// `INF`, `-INF` and `NaN` spelled exactly so
+fn parse_double(value: &str) -> Option<f64> {
+    if FLOAT_RE.is_match(value) {
+        value.parse().ok()
+    } else {
+        None
+    }
+}
+
+/// Parses the lexical form of an `xsd:float`
+fn parse_float(value: &str) -> Option<f32> {
+    if FLOAT_RE.is_match(value) {
+        value.parse().ok()
+    } else {
+        None
+    }
 }
 
 // TODO: actually apply all required facets to each datatype
@@ -73,22 +183,22 @@ pub enum XsdDatatypes {
         fraction_digits: Option<u16>,
         total_digits: Option<u16>,
     },
-    Double(Option<PatternFacet>),
+    Double(MinMaxFacet<FloatBound>, Option<PatternFacet>),
     NmTokens(LengthFacet),
     NmToken(LengthFacet),
     NcName(LengthFacet),
     Token(StringFacets),
-    Duration(Option<PatternFacet>),
+    Duration(Box<MinMaxFacet<DurationValue>>, Option<PatternFacet>),
     Date(Option<PatternFacet>),
     Datetime(Option<PatternFacet>),
     AnyURI(Option<PatternFacet>),
-    Language(Option<PatternFacet>),
+    Language(LanguageSyntax, Option<PatternFacet>),
     Boolean(Option<PatternFacet>),
     Id(Option<PatternFacet>),
     IdRef(Option<PatternFacet>),
     IdRefs(LengthFacet),
     // Previously unsupported types (Bug #4)
-    Float(Option<PatternFacet>),
+    Float(MinMaxFacet<FloatBound>, Option<PatternFacet>),
     NonNegativeInteger(MinMaxFacet<num_bigint::BigUint>, Option<PatternFacet>),
     NegativeInteger(MinMaxFacet<num_bigint::BigInt>, Option<PatternFacet>),
     NonPositiveInteger(MinMaxFacet<num_bigint::BigInt>, Option<PatternFacet>),
@@ -104,10 +214,12 @@ pub enum XsdDatatypes {
     Name(LengthFacet),
     QNameData,
     Entity(LengthFacet),
+    Entities(LengthFacet),
     Time(Option<PatternFacet>),
 }
 impl super::Datatype for XsdDatatypes {
     fn is_valid(&self, value: &str) -> bool {
+        let value = &*self.collapse(value);
         match self {
             XsdDatatypes::NormalizedString(str_facets) => {
                 let normal_val = super::relax::normalize_whitespace(value);
@@ -139,16 +251,18 @@ impl super::Datatype for XsdDatatypes {
                     && patt.as_ref().map(|p| p.1.is_match(value)).unwrap_or(true)
             }
             XsdDatatypes::Integer(min_max, patt) => {
-                num_bigint::BigInt::from_str(value)
-                    .ok()
-                    .is_some_and(|v| min_max.is_valid(&v))
+                INTEGER_RE.is_match(value)
+                    && num_bigint::BigInt::from_str(value)
+                        .ok()
+                        .is_some_and(|v| min_max.is_valid(&v))
                     && patt.as_ref().map(|p| p.1.is_match(value)).unwrap_or(true)
             }
             XsdDatatypes::PositiveInteger(min_max, patt) => {
                 let one = num_bigint::BigUint::from(1u32);
-                num_bigint::BigUint::from_str(value)
-                    .ok()
-                    .is_some_and(|v| v >= one && min_max.is_valid(&v))
+                INTEGER_RE.is_match(value)
+                    && num_bigint::BigUint::from_str(value)
+                        .ok()
+                        .is_some_and(|v| v >= one && min_max.is_valid(&v))
                     && patt.as_ref().map(|p| p.1.is_match(value)).unwrap_or(true)
             }
             XsdDatatypes::Decimal {
@@ -157,35 +271,24 @@ impl super::Datatype for XsdDatatypes {
                 fraction_digits: _,
                 total_digits: _,
             } => {
-                bigdecimal::BigDecimal::from_str(value)
-                    .ok()
-                    .is_some_and(|v| min_max.is_valid(&v))
+                DECIMAL_RE.is_match(value)
+                    && bigdecimal::BigDecimal::from_str(value)
+                        .ok()
+                        .is_some_and(|v| min_max.is_valid(&v))
                     && pat.as_ref().map(|p| p.1.is_match(value)).unwrap_or(true)
             }
             XsdDatatypes::NmTokens(len) => {
                 is_valid_nmtokens(value) && {
                     // length facets on NMTOKENS count the number of tokens
                     let token_count = value.split_ascii_whitespace().count();
-                    match len {
-                        LengthFacet::Unbounded => true,
-                        LengthFacet::MinLength(min) => token_count >= *min,
-                        LengthFacet::MaxLength(max) => token_count <= *max,
-                        LengthFacet::MinMaxLength(min, max) => {
-                            token_count >= *min && token_count <= *max
-                        }
-                        LengthFacet::Length(l) => token_count == *l,
-                    }
+                    len.is_valid_len(token_count)
                 }
             }
             XsdDatatypes::NmToken(len) => is_valid_nmtoken(value) && len.is_valid(value),
             XsdDatatypes::NcName(len) => len.is_valid(value) && is_valid_ncname(value),
-            XsdDatatypes::Token(facets) => {
-                // token: whitespace-collapsed string (no leading/trailing space,
-                // no consecutive internal spaces)
-                normalize_whitespace(value) == value && facets.is_valid(value)
-            }
-            XsdDatatypes::Duration(patt) => {
-                DURATION_RE.is_match(value)
+            XsdDatatypes::Token(facets) => facets.is_valid(value),
+            XsdDatatypes::Duration(min_max, patt) => {
+                DurationValue::parse(value).is_some_and(|v| min_max.is_valid(&v))
                     && patt.as_ref().map(|p| p.1.is_match(value)).unwrap_or(true)
             }
             XsdDatatypes::Date(patt) => {
@@ -193,21 +296,24 @@ impl super::Datatype for XsdDatatypes {
                     && patt.as_ref().map(|p| p.1.is_match(value)).unwrap_or(true)
             }
             XsdDatatypes::Datetime(patt) => {
-                DATETIME_RE.is_match(value)
+                is_valid_datetime(value)
                     && patt.as_ref().map(|p| p.1.is_match(value)).unwrap_or(true)
             }
-            XsdDatatypes::Double(patt) => {
-                value.parse::<f64>().is_ok()
+            XsdDatatypes::Double(min_max, patt) => {
+                parse_double(value).is_some_and(|v| min_max.contains_float(v))
                     && patt.as_ref().map(|p| p.1.is_match(value)).unwrap_or(true)
             }
             XsdDatatypes::AnyURI(patt) => {
                 // XSD anyURI accepts any string (Jing / XSD 1.0 practice).
-                // Whitespace collapsing is applied by the validator before this point.
+                // Whitespace has already been collapsed, as for other types.
                 patt.as_ref().map(|p| p.1.is_match(value)).unwrap_or(true)
             }
-            XsdDatatypes::Language(patt) => {
-                LANG_RE.is_match(value)
-                    && patt.as_ref().map(|p| p.1.is_match(value)).unwrap_or(true)
+            XsdDatatypes::Language(syntax, patt) => {
+                let re: &regex::Regex = match syntax {
+                    LanguageSyntax::Xsd => &LANG_RE,
+                    LanguageSyntax::Bcp47 => &BCP47_RE,
+                };
+                re.is_match(value) && patt.as_ref().map(|p| p.1.is_match(value)).unwrap_or(true)
             }
             XsdDatatypes::Boolean(patt) => {
                 (value == "true" || value == "false" || value == "1" || value == "0")
@@ -230,43 +336,36 @@ impl super::Datatype for XsdDatatypes {
             XsdDatatypes::IdRefs(len) => {
                 // IDREFS: space-separated list of one or more NCName (IDREF) values
                 let tokens: Vec<&str> = value.split_ascii_whitespace().collect();
-                !tokens.is_empty()
-                    && tokens.iter().all(|t| is_valid_ncname(t))
-                    && {
-                        let token_count = tokens.len();
-                        match len {
-                            LengthFacet::Unbounded => true,
-                            LengthFacet::MinLength(min) => token_count >= *min,
-                            LengthFacet::MaxLength(max) => token_count <= *max,
-                            LengthFacet::MinMaxLength(min, max) => {
-                                token_count >= *min && token_count <= *max
-                            }
-                            LengthFacet::Length(l) => token_count == *l,
-                        }
-                    }
+                !tokens.is_empty() && tokens.iter().all(|t| is_valid_ncname(t)) && {
+                    let token_count = tokens.len();
+                    len.is_valid_len(token_count)
+                }
             }
-            XsdDatatypes::Float(patt) => {
-                value.parse::<f32>().is_ok()
+            XsdDatatypes::Float(min_max, patt) => {
+                parse_float(value).is_some_and(|v| min_max.contains_float(f64::from(v)))
                     && patt.as_ref().map(|p| p.1.is_match(value)).unwrap_or(true)
             }
             XsdDatatypes::NonNegativeInteger(min_max, patt) => {
-                num_bigint::BigUint::from_str(value)
-                    .ok()
-                    .is_some_and(|v| min_max.is_valid(&v))
+                INTEGER_RE.is_match(value)
+                    && num_bigint::BigUint::from_str(value)
+                        .ok()
+                        .is_some_and(|v| min_max.is_valid(&v))
                     && patt.as_ref().map(|p| p.1.is_match(value)).unwrap_or(true)
             }
             XsdDatatypes::NegativeInteger(min_max, patt) => {
                 let minus_one = num_bigint::BigInt::from(-1i32);
-                num_bigint::BigInt::from_str(value)
-                    .ok()
-                    .is_some_and(|v| v <= minus_one && min_max.is_valid(&v))
+                INTEGER_RE.is_match(value)
+                    && num_bigint::BigInt::from_str(value)
+                        .ok()
+                        .is_some_and(|v| v <= minus_one && min_max.is_valid(&v))
                     && patt.as_ref().map(|p| p.1.is_match(value)).unwrap_or(true)
             }
             XsdDatatypes::NonPositiveInteger(min_max, patt) => {
                 let zero = num_bigint::BigInt::from(0i32);
-                num_bigint::BigInt::from_str(value)
-                    .ok()
-                    .is_some_and(|v| v <= zero && min_max.is_valid(&v))
+                INTEGER_RE.is_match(value)
+                    && num_bigint::BigInt::from_str(value)
+                        .ok()
+                        .is_some_and(|v| v <= zero && min_max.is_valid(&v))
                     && patt.as_ref().map(|p| p.1.is_match(value)).unwrap_or(true)
             }
             XsdDatatypes::Byte(min_max, patt) => {
@@ -282,75 +381,372 @@ impl super::Datatype for XsdDatatypes {
                     && patt.as_ref().map(|p| p.1.is_match(value)).unwrap_or(true)
             }
             XsdDatatypes::Base64Binary(len) => {
-                BASE64_RE.is_match(value) && {
-                    // length facet counts decoded octets
-                    let stripped: String =
-                        value.chars().filter(|c| !c.is_ascii_whitespace()).collect();
-                    // base64 string length in chars / 4 * 3 (minus padding)
-                    let char_len = stripped.len();
-                    let pad = stripped.chars().rev().take_while(|&c| c == '=').count();
-                    let decoded_len = if char_len == 0 {
-                        0
-                    } else {
-                        char_len * 3 / 4 - pad
-                    };
-                    match len {
-                        LengthFacet::Unbounded => true,
-                        LengthFacet::MinLength(min) => decoded_len >= *min,
-                        LengthFacet::MaxLength(max) => decoded_len <= *max,
-                        LengthFacet::MinMaxLength(min, max) => {
-                            decoded_len >= *min && decoded_len <= *max
-                        }
-                        LengthFacet::Length(l) => decoded_len == *l,
-                    }
-                }
+                // length facet counts decoded octets
+                is_valid_base64(value) && len.is_valid_len(base64_len(value))
             }
             XsdDatatypes::HexBinary(len) => {
                 HEXBINARY_RE.is_match(value) && {
                     // length facet counts octets (hex chars / 2)
                     let octet_len = value.len() / 2;
-                    match len {
-                        LengthFacet::Unbounded => true,
-                        LengthFacet::MinLength(min) => octet_len >= *min,
-                        LengthFacet::MaxLength(max) => octet_len <= *max,
-                        LengthFacet::MinMaxLength(min, max) => {
-                            octet_len >= *min && octet_len <= *max
-                        }
-                        LengthFacet::Length(l) => octet_len == *l,
-                    }
+                    len.is_valid_len(octet_len)
                 }
             }
             XsdDatatypes::GYear(patt) => {
-                GYEAR_RE.is_match(value)
+                is_valid_gregorian(&GYEAR_RE, value)
                     && patt.as_ref().map(|p| p.1.is_match(value)).unwrap_or(true)
             }
             XsdDatatypes::GYearMonth(patt) => {
-                GYEARMONTH_RE.is_match(value)
+                is_valid_gregorian(&GYEARMONTH_RE, value)
                     && patt.as_ref().map(|p| p.1.is_match(value)).unwrap_or(true)
             }
             XsdDatatypes::GMonth(patt) => {
-                GMONTH_RE.is_match(value)
+                is_valid_gregorian(&GMONTH_RE, value)
                     && patt.as_ref().map(|p| p.1.is_match(value)).unwrap_or(true)
             }
             XsdDatatypes::GMonthDay(patt) => {
-                GMONTHDAY_RE.is_match(value)
+                is_valid_gregorian(&GMONTHDAY_RE, value)
                     && patt.as_ref().map(|p| p.1.is_match(value)).unwrap_or(true)
             }
             XsdDatatypes::GDay(patt) => {
-                GDAY_RE.is_match(value)
+                is_valid_gregorian(&GDAY_RE, value)
                     && patt.as_ref().map(|p| p.1.is_match(value)).unwrap_or(true)
             }
             XsdDatatypes::Name(len) => is_valid_name(value) && len.is_valid(value),
             XsdDatatypes::QNameData => is_valid_qname_syntax(value),
             XsdDatatypes::Entity(len) => is_valid_ncname(value) && len.is_valid(value),
+            XsdDatatypes::Entities(len) => {
+                let tokens: Vec<&str> = value.split_ascii_whitespace().collect();
+                !tokens.is_empty()
+                    && tokens.iter().all(|t| is_valid_ncname(t))
+                    && len.is_valid_len(tokens.len())
+            }
             XsdDatatypes::Time(patt) => {
-                TIME_RE.is_match(value)
-                    && patt.as_ref().map(|p| p.1.is_match(value)).unwrap_or(true)
+                is_valid_time(value) && patt.as_ref().map(|p| p.1.is_match(value)).unwrap_or(true)
+            }
+        }
+    }
+}
+
+impl XsdDatatypes {
+    /// The name of the type, such as `"int"` or `"dateTime"`
+    pub fn name(&self) -> &'static str {
+        match self {
+            XsdDatatypes::NormalizedString(_) => "normalizedString",
+            XsdDatatypes::String(_) => "string",
+            XsdDatatypes::Short(..) => "short",
+            XsdDatatypes::UnsignedShort(..) => "unsignedShort",
+            XsdDatatypes::Long(..) => "long",
+            XsdDatatypes::Int(..) => "int",
+            XsdDatatypes::Integer(..) => "integer",
+            XsdDatatypes::PositiveInteger(..) => "positiveInteger",
+            XsdDatatypes::UnsignedInt(..) => "unsignedInt",
+            XsdDatatypes::UnsignedLong(..) => "unsignedLong",
+            XsdDatatypes::Decimal { .. } => "decimal",
+            XsdDatatypes::Double(..) => "double",
+            XsdDatatypes::NmTokens(_) => "NMTOKENS",
+            XsdDatatypes::NmToken(_) => "NMTOKEN",
+            XsdDatatypes::NcName(_) => "NCName",
+            XsdDatatypes::Token(_) => "token",
+            XsdDatatypes::Duration(..) => "duration",
+            XsdDatatypes::Date(_) => "date",
+            XsdDatatypes::Datetime(_) => "dateTime",
+            XsdDatatypes::AnyURI(_) => "anyURI",
+            XsdDatatypes::Language(..) => "language",
+            XsdDatatypes::Boolean(_) => "boolean",
+            XsdDatatypes::Id(_) => "ID",
+            XsdDatatypes::IdRef(_) => "IDREF",
+            XsdDatatypes::IdRefs(_) => "IDREFS",
+            XsdDatatypes::Float(..) => "float",
+            XsdDatatypes::NonNegativeInteger(..) => "nonNegativeInteger",
+            XsdDatatypes::NegativeInteger(..) => "negativeInteger",
+            XsdDatatypes::NonPositiveInteger(..) => "nonPositiveInteger",
+            XsdDatatypes::Byte(..) => "byte",
+            XsdDatatypes::UnsignedByte(..) => "unsignedByte",
+            XsdDatatypes::Base64Binary(_) => "base64Binary",
+            XsdDatatypes::HexBinary(_) => "hexBinary",
+            XsdDatatypes::GYear(_) => "gYear",
+            XsdDatatypes::GYearMonth(_) => "gYearMonth",
+            XsdDatatypes::GMonth(_) => "gMonth",
+            XsdDatatypes::GMonthDay(_) => "gMonthDay",
+            XsdDatatypes::GDay(_) => "gDay",
+            XsdDatatypes::Name(_) => "Name",
+            XsdDatatypes::QNameData => "QName",
+            XsdDatatypes::Entity(_) => "ENTITY",
+            XsdDatatypes::Entities(_) => "ENTITIES",
+            XsdDatatypes::Time(_) => "time",
+        }
+    }
+
+    /// The value with its whitespace collapsed, as the `whiteSpace` facet of every type but
+    /// `string` and `normalizedString` requires before the value is checked
+    fn collapse<'v>(&self, value: &'v str) -> Cow<'v, str> {
+        match self {
+            XsdDatatypes::String(_) | XsdDatatypes::NormalizedString(_) => Cow::Borrowed(value),
+            _ if !value.contains(|c: char| c.is_ascii_whitespace()) => Cow::Borrowed(value),
+            _ => Cow::Owned(normalize_whitespace(value)),
+        }
+    }
+
+    pub fn is_valid_with_ns(&self, value: &str, ns: &dyn super::Namespaces) -> bool {
+        use super::Datatype as _;
+        match self {
+            XsdDatatypes::QNameData => {
+                QNameVal::from_val_with_dyn_ns(&self.collapse(value), ns).is_ok()
+            }
+            _ => self.is_valid(value),
+        }
+    }
+
+    /// Why the value is rejected, naming the first facet that it fails, such as `"12" is
+    /// greater than maxInclusive 10`.  `None` if the value is accepted, or if it fails only
+    /// because it is not a value of the type at all.
+    pub fn explain(&self, value: &str) -> Option<String> {
+        use super::Datatype as _;
+        if self.is_valid(value) {
+            return None;
+        }
+        let value = &*self.collapse(value);
+        let tokens = || value.split_ascii_whitespace().count();
+        let reason = match self {
+            XsdDatatypes::NormalizedString(facets) => facets.explain(&normalize_whitespace(value)),
+            XsdDatatypes::String(facets) | XsdDatatypes::Token(facets) => facets.explain(value),
+            XsdDatatypes::Short(min_max, patt) => {
+                explain_bounds(i16::from_str(value).ok(), min_max, patt, value)
+            }
+            XsdDatatypes::UnsignedShort(min_max, patt) => {
+                explain_bounds(u16::from_str(value).ok(), min_max, patt, value)
+            }
+            XsdDatatypes::Long(min_max, patt) => {
+                explain_bounds(i64::from_str(value).ok(), min_max, patt, value)
+            }
+            XsdDatatypes::Int(min_max, patt) => {
+                explain_bounds(i32::from_str(value).ok(), min_max, patt, value)
+            }
+            XsdDatatypes::UnsignedInt(min_max, patt) => {
+                explain_bounds(u32::from_str(value).ok(), min_max, patt, value)
+            }
+            XsdDatatypes::UnsignedLong(min_max, patt) => {
+                explain_bounds(u64::from_str(value).ok(), min_max, patt, value)
+            }
+            XsdDatatypes::Byte(min_max, patt) => {
+                explain_bounds(i8::from_str(value).ok(), min_max, patt, value)
+            }
+            XsdDatatypes::UnsignedByte(min_max, patt) => {
+                explain_bounds(u8::from_str(value).ok(), min_max, patt, value)
+            }
+            XsdDatatypes::Integer(min_max, patt)
+            | XsdDatatypes::NegativeInteger(min_max, patt)
+            | XsdDatatypes::NonPositiveInteger(min_max, patt) => {
+                let v = INTEGER_RE.is_match(value).then(|| value.parse().ok());
+                explain_bounds(v.flatten(), min_max, patt, value)
+            }
+            XsdDatatypes::PositiveInteger(min_max, patt)
+            | XsdDatatypes::NonNegativeInteger(min_max, patt) => {
+                let v = INTEGER_RE.is_match(value).then(|| value.parse().ok());
+                explain_bounds(v.flatten(), min_max, patt, value)
+            }
+            XsdDatatypes::Decimal {
+                min_max,
+                pattern: patt,
+                ..
+            } => {
+                let v = DECIMAL_RE.is_match(value).then(|| value.parse().ok());
+                explain_bounds(v.flatten(), min_max, patt, value)
+            }
+            XsdDatatypes::Double(min_max, patt) => {
+                let v = parse_double(value).filter(|v| !v.is_nan());
+                explain_bounds(v.map(FloatBound), min_max, patt, value)
+            }
+            XsdDatatypes::Float(min_max, patt) => {
+                let v = parse_float(value).filter(|v| !v.is_nan());
+                explain_bounds(v.map(|v| FloatBound(v.into())), min_max, patt, value)
+            }
+            // durations are only partially ordered, so a bound is not explained
+            XsdDatatypes::Duration(_, patt)
+            | XsdDatatypes::Date(patt)
+            | XsdDatatypes::Datetime(patt)
+            | XsdDatatypes::AnyURI(patt)
+            | XsdDatatypes::Language(_, patt)
+            | XsdDatatypes::Boolean(patt)
+            | XsdDatatypes::Id(patt)
+            | XsdDatatypes::IdRef(patt)
+            | XsdDatatypes::GYear(patt)
+            | XsdDatatypes::GYearMonth(patt)
+            | XsdDatatypes::GMonth(patt)
+            | XsdDatatypes::GMonthDay(patt)
+            | XsdDatatypes::GDay(patt)
+            | XsdDatatypes::Time(patt) => explain_pattern(patt, value),
+            // a length is only explained for a value of the right form, so that any other value
+            // is reported as not being of the type at all
+            XsdDatatypes::NmToken(len) => is_valid_nmtoken(value)
+                .then(|| len.explain(value.chars().count(), "character"))
+                .flatten(),
+            XsdDatatypes::NcName(len) | XsdDatatypes::Entity(len) => is_valid_ncname(value)
+                .then(|| len.explain(value.chars().count(), "character"))
+                .flatten(),
+            XsdDatatypes::Name(len) => is_valid_name(value)
+                .then(|| len.explain(value.chars().count(), "character"))
+                .flatten(),
+            XsdDatatypes::NmTokens(len) => is_valid_nmtokens(value)
+                .then(|| len.explain(tokens(), "token"))
+                .flatten(),
+            XsdDatatypes::IdRefs(len) | XsdDatatypes::Entities(len) => is_valid_ncnames(value)
+                .then(|| len.explain(tokens(), "token"))
+                .flatten(),
+            XsdDatatypes::Base64Binary(len) => is_valid_base64(value)
+                .then(|| len.explain(base64_len(value), "octet"))
+                .flatten(),
+            XsdDatatypes::HexBinary(len) => HEXBINARY_RE
+                .is_match(value)
+                .then(|| len.explain(value.len() / 2, "octet"))
+                .flatten(),
+            XsdDatatypes::QNameData => None,
+        }?;
+        Some(format!("{value:?} {reason}"))
+    }
+
+    /// A remark about a value that this datatype accepts, for types that are checked more
+    /// leniently than XML Schema describes
+    pub fn note(&self, value: &str) -> Option<&'static str> {
+        match self {
+            XsdDatatypes::AnyURI(_) if !is_uri_reference(&normalize_whitespace(value)) => {
+                Some("accepted as anyURI, but it is not a well-formed URI reference")
             }
+            _ => None,
         }
     }
 }
 
+/// Whether a value is a whitespace-separated list of one or more NCNames, as `IDREFS` and
+/// `ENTITIES` values are
+fn is_valid_ncnames(value: &str) -> bool {
+    let mut tokens = value.split_ascii_whitespace().peekable();
+    tokens.peek().is_some() && tokens.all(is_valid_ncname)
+}
+
+/// Whether a value is in the lexical space of `base64Binary`: groups of four characters, the
+/// last of which may end in padding
+fn is_valid_base64(value: &str) -> bool {
+    let char_len = value.chars().filter(|c| !c.is_ascii_whitespace()).count();
+    BASE64_RE.is_match(value) && char_len % 4 == 0
+}
+
+/// The number of octets that a valid `base64Binary` value encodes
+fn base64_len(value: &str) -> usize {
+    let stripped: String = value.chars().filter(|c| !c.is_ascii_whitespace()).collect();
+    // base64 string length in chars / 4 * 3 (minus padding), where a valid value has at least
+    // one group of four characters for any padding to be in
+    let char_len = stripped.len();
+    let pad = stripped.chars().rev().take_while(|&c| c == '=').count();
+    char_len * 3 / 4 - pad
+}
+
+/// The first of the range facets and the `pattern` facet that a value fails, given the value
+/// parsed into the type's value space (`None` if it could not be)
+fn explain_bounds<T: PartialOrd + fmt::Display>(
+    parsed: Option<T>,
+    min_max: &MinMaxFacet<T>,
+    pattern: &Option<PatternFacet>,
+    value: &str,
+) -> Option<String> {
+    parsed
+        .and_then(|v| min_max.explain(&v))
+        .or_else(|| explain_pattern(pattern, value))
+}
+
+fn explain_pattern(pattern: &Option<PatternFacet>, value: &str) -> Option<String> {
+    pattern.as_ref().and_then(|p| p.explain(value))
+}
+
+/// Checks for the characters that RFC 3986 does not allow anywhere in a URI reference (other
+/// than non-ASCII characters, which are allowed in IRIs), and for malformed percent-encoding
+fn is_uri_reference(value: &str) -> bool {
+    let bytes = value.as_bytes();
+    bytes.iter().enumerate().all(|(i, &b)| match b {
+        b'%' => {
+            bytes.len() > i + 2
+                && bytes[i + 1].is_ascii_hexdigit()
+                && bytes[i + 2].is_ascii_hexdigit()
+        }
+        b' ' | b'"' | b'<' | b'>' | b'\\' | b'^' | b'`' | b'{' | b'|' | b'}' => false,
+        _ => !b.is_ascii_control(),
+    })
+}
+
+/// Checks a `dateTime` value's lexical form and the ranges of its fields.  Negative years are
+/// allowed, but year `0000` is not (there is no year zero in XSD 1.0), and `24:00:00` is
+/// accepted as the end of the given day.
+fn is_valid_datetime(value: &str) -> bool {
+    let Some(c) = DATETIME_RE.captures(value) else {
+        return false;
+    };
+    let Ok(year) = c[1].parse::<i64>() else {
+        return false;
+    };
+    let month: u32 = c[2].parse().unwrap();
+    let day: u32 = c[3].parse().unwrap();
+    year != 0
+        && (1..=12).contains(&month)
+        && day >= 1
+        && day <= days_in_month(year, month)
+        && is_valid_time_of_day(&c[4], &c[5], &c[6], c.get(7).map(|m| m.as_str()))
+        && is_valid_timezone(c.get(8).map(|m| m.as_str()))
+}
+
+/// Checks a `time` value's lexical form and the ranges of its fields
+fn is_valid_time(value: &str) -> bool {
+    let Some(c) = TIME_RE.captures(value) else {
+        return false;
+    };
+    is_valid_time_of_day(&c[1], &c[2], &c[3], c.get(4).map(|m| m.as_str()))
+        && is_valid_timezone(c.get(5).map(|m| m.as_str()))
+}
+
+/// Any number of fractional second digits is allowed, but `24:00:00` is only valid when the
+/// fraction is zero, since 24:00:00.5 would be in the following day
+fn is_valid_time_of_day(hour: &str, minute: &str, second: &str, fraction: Option<&str>) -> bool {
+    let hour: u32 = hour.parse().unwrap();
+    let minute: u32 = minute.parse().unwrap();
+    let second: u32 = second.parse().unwrap();
+    if hour == 24 {
+        minute == 0 && second == 0 && fraction.is_none_or(|f| f.bytes().all(|b| b == b'0'))
+    } else {
+        hour < 24 && minute < 60 && second < 60
+    }
+}
+
+/// Checks a value of one of the `g*` types against the given regex, whose only group is the
+/// optional timezone
+fn is_valid_gregorian(re: &regex::Regex, value: &str) -> bool {
+    re.captures(value)
+        .is_some_and(|c| is_valid_timezone(c.get(1).map(|m| m.as_str())))
+}
+
+/// Offsets are limited to -14:00..+14:00
+fn is_valid_timezone(tz: Option<&str>) -> bool {
+    match tz {
+        None | Some("Z") => true,
+        Some(offset) => {
+            let hours: u32 = offset[1..3].parse().unwrap();
+            let minutes: u32 = offset[4..6].parse().unwrap();
+            minutes < 60 && (hours < 14 || (hours == 14 && minutes == 0))
+        }
+    }
+}
+
+/// XSD 1.0 has no year zero, so year `-0001` is the year before `0001` and is a leap year
+fn days_in_month(year: i64, month: u32) -> u32 {
+    let astronomical = if year < 0 { year + 1 } else { year };
+    let leap = astronomical.rem_euclid(4) == 0
+        && (astronomical.rem_euclid(100) != 0 || astronomical.rem_euclid(400) == 0);
+    match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
 fn is_valid_ncname(text: &str) -> bool {
     match relaxng_syntax::compact::nc_name(relaxng_syntax::compact::Span::new(text)) {
         Ok((rest, _name)) => rest.fragment().is_empty(),
@@ -414,6 +810,12 @@ impl StringFacets {
             }
     }
 
+    fn explain(&self, value: &str) -> Option<String> {
+        self.len
+            .explain(value.chars().count(), "character")
+            .or_else(|| explain_pattern(&self.pattern, value))
+    }
+
     pub fn bounded(&self) -> bool {
         !matches!(self.len, LengthFacet::Unbounded)
     }
@@ -457,16 +859,184 @@ pub enum XsdDatatypeError {
         span: codemap::Span,
         type_name: &'static str,
     },
+    /// The datatype is not among those allowed by the `DatatypePolicy`
+    DatatypeNotAllowed {
+        span: codemap::Span,
+        name: String,
+    },
+    /// A `pattern` facet was given, but the `DatatypePolicy` rejects them
+    PatternNotAllowed {
+        span: codemap::Span,
+    },
 }
 #[derive(Debug)]
 pub enum FacetError {
-    ConflictingFacet(&'static str),
+    /// Facets which cannot all be given together, found once all of the datatype's parameters
+    /// have been read, so that every conflict among them is reported at once
+    ConflictingFacets(Vec<FacetConflict>),
     InvalidInt(codemap::Span, String),
     InvalidFloat(codemap::Span, String),
+    /// a bound for the `duration` datatype which is not itself a duration
+    InvalidDuration(codemap::Span, String),
     InvalidPattern(codemap::Span, regex::Error),
-    InvalidFacet(codemap::Span, String),
+    /// a `pattern` facet whose regular expression would compile to more than the given number of
+    /// bytes, the limit set by `DatatypePolicy::pattern_size_limit`
+    PatternTooLarge(codemap::Span, usize),
+    /// a parameter which the datatype does not take
+    InvalidFacet {
+        span: codemap::Span,
+        /// the name of the parameter
+        name: String,
+        /// whether the parameter is a facet that some other datatype takes, as when
+        /// `fractionDigits` is given for `string`, rather than no facet at all
+        known: bool,
+    },
+}
+
+/// The facets that at least one datatype takes as a parameter.  (`enumeration` and
+/// `whiteSpace` are facets in XML Schema, but not parameters in RELAX NG.)
+const FACETS: &[&str] = &[
+    "length",
+    "minLength",
+    "maxLength",
+    "pattern",
+    "minInclusive",
+    "minExclusive",
+    "maxInclusive",
+    "maxExclusive",
+    "totalDigits",
+    "fractionDigits",
+];
+
+/// A pair of facets which contradict each other, such as `minInclusive "10"` with
+/// `maxInclusive "5"`
+#[derive(Debug)]
+pub struct FacetConflict {
+    /// the names of the two facets, each with the span of the parameter that gave it
+    pub facets: [(&'static str, codemap::Span); 2],
+    /// what is wrong, e.g. "minInclusive is greater than maxInclusive"
+    pub reason: String,
+}
+
+impl FacetConflict {
+    /// Set a facet, or note a conflict with the earlier parameter if the facet has already been
+    /// given; the later value replaces the earlier one either way
+    fn set<T>(
+        conflicts: &mut Vec<FacetConflict>,
+        facet: &mut Option<(T, codemap::Span)>,
+        name: &'static str,
+        val: T,
+        span: codemap::Span,
+    ) {
+        if let Some((_, earlier)) = facet.replace((val, span)) {
+            conflicts.push(FacetConflict {
+                facets: [(name, span), (name, earlier)],
+                reason: format!("{name} is given more than once"),
+            });
+        }
+    }
+
+    fn new(
+        (a, a_span): (&'static str, codemap::Span),
+        (b, b_span): (&'static str, codemap::Span),
+        problem: &str,
+    ) -> FacetConflict {
+        FacetConflict {
+            facets: [(a, a_span), (b, b_span)],
+            reason: format!("{a} {problem} {b}"),
+        }
+    }
 }
 
+/// The `length`, `minLength` and `maxLength` facets given for a datatype, gathered while reading
+/// its parameters and then checked against each other by `validate_facets()`
+#[derive(Default)]
+struct LengthFacets {
+    length: Option<(usize, codemap::Span)>,
+    min_length: Option<(usize, codemap::Span)>,
+    max_length: Option<(usize, codemap::Span)>,
+    /// facets given more than once
+    repeated: Vec<FacetConflict>,
+}
+impl LengthFacets {
+    fn length(&mut self, len: usize, span: codemap::Span) {
+        FacetConflict::set(&mut self.repeated, &mut self.length, "length", len, span);
+    }
+    fn min_length(&mut self, len: usize, span: codemap::Span) {
+        FacetConflict::set(
+            &mut self.repeated,
+            &mut self.min_length,
+            "minLength",
+            len,
+            span,
+        );
+    }
+    fn max_length(&mut self, len: usize, span: codemap::Span) {
+        FacetConflict::set(
+            &mut self.repeated,
+            &mut self.max_length,
+            "maxLength",
+            len,
+            span,
+        );
+    }
+
+    /// Check every pair of the facets given, reporting all conflicts together, along with any
+    /// facet that was given more than once.  (Only the last value of a repeated facet is
+    /// checked against the others.)
+    fn validate_facets(self) -> Result<LengthFacet, FacetError> {
+        let mut conflicts = self.repeated;
+        if let Some((_, len)) = self.length {
+            for (name, other) in [
+                ("minLength", self.min_length),
+                ("maxLength", self.max_length),
+            ] {
+                if let Some((_, other)) = other {
+                    conflicts.push(FacetConflict::new(
+                        ("length", len),
+                        (name, other),
+                        "cannot be given together with",
+                    ));
+                }
+            }
+        }
+        if let (Some((min, min_span)), Some((max, max_span))) = (self.min_length, self.max_length)
+            && min > max
+        {
+            conflicts.push(FacetConflict::new(
+                ("minLength", min_span),
+                ("maxLength", max_span),
+                "is greater than",
+            ));
+        }
+        if !conflicts.is_empty() {
+            return Err(FacetError::ConflictingFacets(conflicts));
+        }
+        Ok(match (self.length, self.min_length, self.max_length) {
+            (Some((len, _)), _, _) => LengthFacet::Length(len),
+            (None, Some((min, _)), Some((max, _))) => LengthFacet::MinMaxLength(min, max),
+            (None, Some((min, _)), None) => LengthFacet::MinLength(min),
+            (None, None, Some((max, _))) => LengthFacet::MaxLength(max),
+            (None, None, None) => LengthFacet::Unbounded,
+        })
+    }
+}
+
+/// The `length`, `minLength` and `maxLength` facets.
+///
+/// What is being counted depends on the datatype.  For the string-derived types (`string`,
+/// `token`, `NCName` etc.) it is the number of characters, which XSD defines as Unicode scalar
+/// values -- so a character outside the Basic Multilingual Plane, like an emoji, counts as 1
+/// even though it is 2 code units in UTF-16 and 4 bytes in UTF-8, and a base character plus a
+/// combining mark counts as 2.  For `hexBinary` and
+/// `base64Binary` it is the number of octets of decoded binary data, and for the list types
+/// (`NMTOKENS`, `IDREFS`, `ENTITIES`) it is the number of list items; those types count with
+/// `is_valid_len()` rather than `is_valid()`.
+///
+/// A RELAX NG `list` pattern has no facets of its own.  A datatype used within a `list` is
+/// matched against each whitespace-separated item in turn, so in
+/// `list { xsd:string { minLength = "3" }+ }` the facet requires every item to have at least
+/// 3 characters; the number of items is constrained only by the list's content pattern.
 #[derive(PartialEq, Eq, Hash, Clone, Debug)]
 pub enum LengthFacet {
     Unbounded,
@@ -476,8 +1046,13 @@ pub enum LengthFacet {
     Length(usize),
 }
 impl LengthFacet {
+    /// Check the length of a string-derived value, measured in Unicode scalar values
     fn is_valid(&self, value: &str) -> bool {
-        let actual = value.chars().count();
+        self.is_valid_len(value.chars().count())
+    }
+
+    /// Check a length that the caller has measured in the units appropriate to the datatype
+    fn is_valid_len(&self, actual: usize) -> bool {
         match self {
             LengthFacet::Unbounded => true,
             LengthFacet::MinLength(min) => *min <= actual,
@@ -487,35 +1062,35 @@ impl LengthFacet {
         }
     }
 
-    fn merge(&mut self, other: LengthFacet) -> Result<(), FacetError> {
-        *self = match self {
-            LengthFacet::Unbounded => other,
-            LengthFacet::MinLength(min) => match other {
-                LengthFacet::Unbounded | LengthFacet::MinMaxLength(_, _) => unreachable!(),
-                LengthFacet::MinLength(_min) => {
-                    return Err(FacetError::ConflictingFacet("minLength"));
-                }
-                LengthFacet::MaxLength(max) => {
-                    if *min > max {
-                        return Err(FacetError::ConflictingFacet(
-                            "minLength greater than maxLength",
-                        ));
-                    }
-                    LengthFacet::MinMaxLength(*min, max)
-                }
-                LengthFacet::Length(_) => return Err(FacetError::ConflictingFacet("length")),
-            },
-            LengthFacet::MaxLength(_) => {
-                unimplemented!()
-            }
-            LengthFacet::MinMaxLength(_, _) => {
-                unimplemented!()
-            }
-            LengthFacet::Length(_) => {
-                unimplemented!()
+    /// How a length, counted in the given unit, fails the facet
+    fn explain(&self, actual: usize, unit: &str) -> Option<String> {
+        let (min, max) = match *self {
+            LengthFacet::Unbounded => (None, None),
+            LengthFacet::MinLength(min) => (Some(min), None),
+            LengthFacet::MaxLength(max) => (None, Some(max)),
+            LengthFacet::MinMaxLength(min, max) => (Some(min), Some(max)),
+            LengthFacet::Length(len) if actual != len => {
+                return Some(format!("has {}, not length {len}", plural(actual, unit)));
             }
+            LengthFacet::Length(_) => (None, None),
         };
-        Ok(())
+        if let Some(min) = min.filter(|min| actual < *min) {
+            Some(format!(
+                "has {}, fewer than minLength {min}",
+                plural(actual, unit)
+            ))
+        } else {
+            max.filter(|max| actual > *max)
+                .map(|max| format!("has {}, more than maxLength {max}", plural(actual, unit)))
+        }
+    }
+}
+
+fn plural(n: usize, unit: &str) -> String {
+    if n == 1 {
+        format!("{n} {unit}")
+    } else {
+        format!("{n} {unit}s")
     }
 }
 
@@ -627,130 +1202,288 @@ where
         !matches!((&self.min, &self.max), (Min::Unbounded, Max::Unbounded))
     }
 
-    fn min_inclusive(&mut self, val: T) -> Result<(), FacetError> {
-        match &self.max {
-            Max::Unbounded => {}
-            Max::Inclusive(max) => {
-                if val > *max {
-                    return Err(FacetError::ConflictingFacet(
-                        "minInclusive conflicts with maxInclusive",
-                    ));
-                }
+    fn is_valid(&self, v: &T) -> bool {
+        self.min.is_valid(v) && self.max.is_valid(v)
+    }
+}
+
+impl<T: PartialOrd + fmt::Display> MinMaxFacet<T> {
+    /// How a value fails the bounds
+    fn explain(&self, v: &T) -> Option<String> {
+        match &self.min {
+            Min::Inclusive(min) if v < min => {
+                return Some(format!("is less than minInclusive {min}"));
             }
-            Max::Exclusive(max) => {
-                if val >= *max {
-                    return Err(FacetError::ConflictingFacet(
-                        "minInclusive conflicts with maxExclusive",
-                    ));
-                }
+            Min::Exclusive(min) if v <= min => {
+                return Some(format!("is not greater than minExclusive {min}"));
             }
+            _ => {}
         }
-        self.min = match self.min {
-            Min::Unbounded => Min::Inclusive(val),
-            Min::Inclusive(_) => unreachable!(),
-            Min::Exclusive(_) => {
-                return Err(FacetError::ConflictingFacet(
-                    "minInclusive conflicts with minExclusive",
-                ));
-            }
-        };
-        Ok(())
-    }
-    fn min_exclusive(&mut self, val: T) -> Result<(), FacetError> {
         match &self.max {
-            Max::Unbounded => {}
-            Max::Inclusive(max) => {
-                if val > *max {
-                    return Err(FacetError::ConflictingFacet(
-                        "minInclusive conflicts with maxInclusive",
-                    ));
-                }
-            }
-            Max::Exclusive(max) => {
-                if val >= *max {
-                    return Err(FacetError::ConflictingFacet(
-                        "minInclusive conflicts with maxExclusive",
-                    ));
-                }
-            }
+            Max::Inclusive(max) if v > max => Some(format!("is greater than maxInclusive {max}")),
+            Max::Exclusive(max) if v >= max => Some(format!("is not less than maxExclusive {max}")),
+            _ => None,
         }
-        self.min = match self.min {
-            Min::Unbounded => Min::Exclusive(val),
-            Min::Inclusive(_) => {
-                return Err(FacetError::ConflictingFacet(
-                    "minExclusive conflicts with minInclusive",
-                ));
-            }
-            Min::Exclusive(_) => unreachable!(),
-        };
-        Ok(())
     }
-    fn max_inclusive(&mut self, val: T) -> Result<(), FacetError> {
-        match &self.min {
-            Min::Unbounded => {}
-            Min::Inclusive(min) => {
-                if *min > val {
-                    return Err(FacetError::ConflictingFacet(
-                        "minInclusive conflicts with maxInclusive",
-                    ));
-                }
-            }
-            Min::Exclusive(min) => {
-                if *min >= val {
-                    return Err(FacetError::ConflictingFacet(
-                        "minInclusive conflicts with maxExclusive",
+}
+
+/// The `minInclusive`, `minExclusive`, `maxInclusive` and `maxExclusive` facets given for a
+/// datatype, gathered while reading its parameters and then checked against each other by
+/// `validate_facets()`
+struct BoundFacets<T> {
+    min_inclusive: Option<(T, codemap::Span)>,
+    min_exclusive: Option<(T, codemap::Span)>,
+    max_inclusive: Option<(T, codemap::Span)>,
+    max_exclusive: Option<(T, codemap::Span)>,
+    /// facets given more than once
+    repeated: Vec<FacetConflict>,
+}
+impl<T> Default for BoundFacets<T> {
+    fn default() -> Self {
+        BoundFacets {
+            min_inclusive: None,
+            min_exclusive: None,
+            max_inclusive: None,
+            max_exclusive: None,
+            repeated: vec![],
+        }
+    }
+}
+impl<T: PartialOrd> BoundFacets<T> {
+    fn min_inclusive(&mut self, val: T, span: codemap::Span) {
+        FacetConflict::set(
+            &mut self.repeated,
+            &mut self.min_inclusive,
+            "minInclusive",
+            val,
+            span,
+        );
+    }
+    fn min_exclusive(&mut self, val: T, span: codemap::Span) {
+        FacetConflict::set(
+            &mut self.repeated,
+            &mut self.min_exclusive,
+            "minExclusive",
+            val,
+            span,
+        );
+    }
+    fn max_inclusive(&mut self, val: T, span: codemap::Span) {
+        FacetConflict::set(
+            &mut self.repeated,
+            &mut self.max_inclusive,
+            "maxInclusive",
+            val,
+            span,
+        );
+    }
+    fn max_exclusive(&mut self, val: T, span: codemap::Span) {
+        FacetConflict::set(
+            &mut self.repeated,
+            &mut self.max_exclusive,
+            "maxExclusive",
+            val,
+            span,
+        );
+    }
+
+    /// Check every pair of the facets given, reporting all conflicts together, along with any
+    /// facet that was given more than once.  A lower and an upper bound conflict when no value
+    /// could satisfy both.
+    fn validate_facets(self) -> Result<MinMaxFacet<T>, FacetError> {
+        let mut conflicts = self.repeated;
+        if let (Some((_, a)), Some((_, b))) = (&self.min_inclusive, &self.min_exclusive) {
+            conflicts.push(FacetConflict::new(
+                ("minInclusive", *a),
+                ("minExclusive", *b),
+                "cannot be given together with",
+            ));
+        }
+        if let (Some((_, a)), Some((_, b))) = (&self.max_inclusive, &self.max_exclusive) {
+            conflicts.push(FacetConflict::new(
+                ("maxInclusive", *a),
+                ("maxExclusive", *b),
+                "cannot be given together with",
+            ));
+        }
+        let mins = [
+            ("minInclusive", &self.min_inclusive, false),
+            ("minExclusive", &self.min_exclusive, true),
+        ];
+        let maxes = [
+            ("maxInclusive", &self.max_inclusive, false),
+            ("maxExclusive", &self.max_exclusive, true),
+        ];
+        for (min_name, min, min_exclusive) in &mins {
+            for (max_name, max, max_exclusive) in &maxes {
+                let (Some((min, min_span)), Some((max, max_span))) = (min, max) else {
+                    continue;
+                };
+                let problem = if *min_exclusive || *max_exclusive {
+                    (min >= max).then_some("is not less than")
+                } else {
+                    (min > max).then_some("is greater than")
+                };
+                if let Some(problem) = problem {
+                    conflicts.push(FacetConflict::new(
+                        (min_name, *min_span),
+                        (max_name, *max_span),
+                        problem,
                     ));
                 }
             }
         }
-        self.max = match self.max {
-            Max::Unbounded => Max::Inclusive(val),
-            Max::Inclusive(_) => unreachable!(),
-            Max::Exclusive(_) => {
-                return Err(FacetError::ConflictingFacet(
-                    "maxInclusive conflicts with maxExclusive",
-                ));
-            }
+        if !conflicts.is_empty() {
+            return Err(FacetError::ConflictingFacets(conflicts));
+        }
+        let min = match (self.min_inclusive, self.min_exclusive) {
+            (Some((v, _)), _) => Min::Inclusive(v),
+            (None, Some((v, _))) => Min::Exclusive(v),
+            (None, None) => Min::Unbounded,
         };
-        Ok(())
+        let max = match (self.max_inclusive, self.max_exclusive) {
+            (Some((v, _)), _) => Max::Inclusive(v),
+            (None, Some((v, _))) => Max::Exclusive(v),
+            (None, None) => Max::Unbounded,
+        };
+        Ok(MinMaxFacet { min, max })
     }
-    fn max_exclusive(&mut self, val: T) -> Result<(), FacetError> {
-        match &self.min {
-            Min::Unbounded => {}
-            Min::Inclusive(min) => {
-                if *min > val {
-                    return Err(FacetError::ConflictingFacet(
-                        "minInclusive conflicts with maxInclusive",
-                    ));
-                }
-            }
-            Min::Exclusive(min) => {
-                if *min >= val {
-                    return Err(FacetError::ConflictingFacet(
-                        "minInclusive conflicts with maxExclusive",
-                    ));
-                }
-            }
+}
+
+/// A bound given by a `minInclusive` etc. facet of `double` or `float`.  Bounds are always
+/// finite (see `Compiler::f64()`), so unlike `f64` this can be `Eq` and `Hash`.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub struct FloatBound(f64);
+impl Eq for FloatBound {}
+impl std::hash::Hash for FloatBound {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        // -0 and 0 are equal, so must hash alike
+        (self.0 + 0.0).to_bits().hash(state)
+    }
+}
+
+impl fmt::Display for FloatBound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl MinMaxFacet<FloatBound> {
+    /// `NaN` is not ordered with respect to any other value, so it is within the range only if
+    /// the range is unbounded
+    fn contains_float(&self, v: f64) -> bool {
+        if v.is_nan() {
+            !self.bounded()
+        } else {
+            self.is_valid(&FloatBound(v))
         }
-        self.max = match self.max {
-            Max::Unbounded => Max::Exclusive(val),
-            Max::Inclusive(_) => {
-                return Err(FacetError::ConflictingFacet(
-                    "maxExclusive conflicts with maxInclusive",
-                ));
-            }
-            Max::Exclusive(_) => unreachable!(),
+    }
+}
+
+/// A value of the `duration` datatype, in XSD's two-component model: a number of months, from
+/// the years and months fields, and a number of seconds, from the rest
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DurationValue {
+    months: num_bigint::BigInt,
+    seconds: bigdecimal::BigDecimal,
+}
+
+/// The months (as year and month) from whose start XSD measures two durations in order to
+/// compare them.  A month, a year and so on have different lengths depending on where they
+/// start, so one duration is less than another only if it is shorter from all four.
+const DURATION_REFERENCE_MONTHS: [(i128, i128); 4] = [(1696, 9), (1697, 2), (1903, 3), (1903, 7)];
+
+impl DurationValue {
+    /// Parses the lexical form of a `duration`, such as `P1Y2M`, `-PT1.5S` or `P3DT4H`
+    fn parse(value: &str) -> Option<DurationValue> {
+        let caps = DURATION_RE.captures(value)?;
+        let has_date = (2..=4).any(|i| caps.get(i).is_some());
+        let has_time = (6..=8).any(|i| caps.get(i).is_some());
+        // 'T' must be followed by at least one time field, and 'P' by at least one field
+        if !has_time && (caps.get(5).is_some() || !has_date) {
+            return None;
+        }
+        let int = |i: usize| {
+            caps.get(i).map_or_else(
+                || num_bigint::BigInt::from(0),
+                |m| num_bigint::BigInt::from_str(m.as_str()).unwrap(),
+            )
         };
-        Ok(())
+        let decimal = |i: usize| {
+            caps.get(i).map_or_else(
+                || bigdecimal::BigDecimal::from(0),
+                |m| {
+                    // the decimal parser does not accept a trailing '.', as in "1.S"
+                    let digits = m.as_str().trim_end_matches('.');
+                    bigdecimal::BigDecimal::from_str(digits).unwrap()
+                },
+            )
+        };
+        let mut months: num_bigint::BigInt = int(2) * 12 + int(3);
+        let mut seconds: bigdecimal::BigDecimal =
+            decimal(4) * 86400 + decimal(6) * 3600 + decimal(7) * 60 + decimal(8);
+        if caps.get(1).is_some() {
+            months = -months;
+            seconds = -seconds;
+        }
+        Some(DurationValue { months, seconds })
     }
 
-    fn is_valid(&self, v: &T) -> bool {
-        self.min.is_valid(v) && self.max.is_valid(v)
+    /// The length of the duration in seconds, when it begins at the start of the given month
+    fn seconds_from(&self, (year, month): (i128, i128)) -> bigdecimal::BigDecimal {
+        let end = num_bigint::BigInt::from(year * 12 + month - 1) + &self.months;
+        let (end_year, end_month) = div_floor(&end, 12);
+        let days = days_from_civil(&end_year, end_month + 1)
+            - days_from_civil(&num_bigint::BigInt::from(year), month);
+        bigdecimal::BigDecimal::from(days * 86400) + &self.seconds
+    }
+}
+
+impl PartialOrd for DurationValue {
+    /// Durations are only partially ordered: `P1M` is neither less than, equal to nor greater
+    /// than `P30D`, since some months are shorter and some longer.  A bound on a duration is
+    /// then not satisfied, as XSD requires.
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        use std::cmp::Ordering;
+        if self == other {
+            return Some(Ordering::Equal);
+        }
+        let mut orders = DURATION_REFERENCE_MONTHS
+            .iter()
+            .map(|&start| self.seconds_from(start).cmp(&other.seconds_from(start)));
+        let first = orders.next()?;
+        // durations which are the same length from every starting point, like P400Y and
+        // P146097D, are not equal either, since only identical durations are
+        (first != Ordering::Equal && orders.all(|o| o == first)).then_some(first)
     }
 }
 
+/// The number of days from 1970-01-01 to the first of the given month in the proleptic
+/// Gregorian calendar
+fn days_from_civil(year: &num_bigint::BigInt, month: i128) -> num_bigint::BigInt {
+    // count from March, so that the leap day is at the end of the year
+    let year = if month <= 2 { year - 1 } else { year.clone() };
+    let (era, year_of_era) = div_floor(&year, 400);
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + (day_of_era - 719468)
+}
+
+/// The quotient of the given division rounded down, and the remainder, which is never negative
+fn div_floor(value: &num_bigint::BigInt, divisor: i128) -> (num_bigint::BigInt, i128) {
+    let quotient = value / divisor;
+    let remainder = i128::try_from(value - &quotient * divisor).unwrap();
+    if remainder < 0 {
+        (quotient - 1, remainder + divisor)
+    } else {
+        (quotient, remainder)
+    }
+}
+
+/// A compiled `pattern` facet, keeping the pattern as written and the size limit it was compiled
+/// under so that it can be compiled the same way again when a serialized model is read
 #[derive(Clone)]
-pub struct PatternFacet(String, regex::Regex);
+pub struct PatternFacet(String, regex::Regex, Option<usize>);
 impl PartialEq for PatternFacet {
     fn eq(&self, other: &Self) -> bool {
         self.0 == other.0
@@ -768,13 +1501,70 @@ impl fmt::Debug for PatternFacet {
     }
 }
 impl PatternFacet {
+    /// Compile a `pattern` facet, written in XML Schema's regular expression syntax, into a
+    /// regular expression of at most `size_limit` bytes (or the `regex` crate's default limit)
+    fn new(raw: String, size_limit: Option<usize>) -> Result<PatternFacet, regex::Error> {
+        let translated = xsd_regex_to_rust(&raw);
+        // XSD spec: pattern facet must match the entire lexical value (implicit ^ and $).
+        let anchored = format!("^(?:{translated})$");
+        let mut builder = regex::RegexBuilder::new(&anchored);
+        if let Some(limit) = size_limit {
+            builder.size_limit(limit);
+        }
+        builder.build().map(|re| PatternFacet(raw, re, size_limit))
+    }
+
     fn is_valid(&self, value: &str) -> bool {
         self.1.is_match(value)
     }
+
+    fn explain(&self, value: &str) -> Option<String> {
+        (!self.is_valid(value)).then(|| format!("does not match pattern {}", self.0))
+    }
+}
+
+/// The rules used to check values of the `language` datatype
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum LanguageSyntax {
+    /// The lexical rule given by XML Schema 1.0, which permits any sequence of up to 8
+    /// alphanumeric subtags
+    Xsd,
+    /// Well-formed BCP 47 (RFC 5646) language tags
+    Bcp47,
+}
+
+/// Limits on the XML Schema datatypes that a schema may use, for compiling schemas which come
+/// from untrusted sources (see `Compiler::set_datatype_policy()`)
+///
+/// The default policy allows everything.
+#[derive(Clone, Debug, Default)]
+pub struct DatatypePolicy {
+    pub patterns: PatternPolicy,
+    /// The largest size in bytes to which the regular expression of a `pattern` facet may
+    /// compile, or `None` for the `regex` crate's default.  Matching always takes time linear in
+    /// the length of the value, so this bounds the memory and compile time a pattern can use.
+    pub pattern_size_limit: Option<usize>,
+    /// The names (such as `"int"` or `"dateTime"`) of the only datatypes that may be used, or
+    /// `None` to allow them all
+    pub allowed_types: Option<Vec<String>>,
+}
+
+/// What to do with the `pattern` facets of a schema
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PatternPolicy {
+    #[default]
+    Enforce,
+    /// Accept the schema, but check nothing against the pattern, giving a warning
+    Ignore,
+    /// Fail to compile the schema
+    Reject,
 }
 
 #[derive(Default)]
-pub struct Compiler;
+pub struct Compiler {
+    strict_language_tags: bool,
+    policy: DatatypePolicy,
+}
 impl super::DatatypeCompiler for Compiler {
     type DT = XsdDatatypes;
     type DTValue = XsdDatatypeValues;
@@ -789,6 +1579,7 @@ impl super::DatatypeCompiler for Compiler {
     ) -> Result<Self::DTValue, Self::Error> {
         match datatype_name {
             DatatypeName::CName(types::QName(_namespace_uri, name)) => {
+                self.check_allowed(ctx, name)?;
                 self.compile_value(ctx, &name.0, &name.1, value, ns)
             }
             DatatypeName::NamespacedName(_) => {
@@ -803,9 +1594,29 @@ impl super::DatatypeCompiler for Compiler {
         ctx: &Context,
         datatype_name: &types::DatatypeName,
         params: &[types::Param],
+        warnings: &mut Vec<RelaxWarning>,
     ) -> Result<Self::DT, Self::Error> {
         match datatype_name {
             types::DatatypeName::CName(types::QName(_namespace_uri, name)) => {
+                self.check_allowed(ctx, name)?;
+                let mut patterns = params.iter().filter(|p| p.2.to_string() == "pattern");
+                match self.policy.patterns {
+                    PatternPolicy::Enforce => {}
+                    PatternPolicy::Reject => {
+                        if let Some(param) = patterns.next() {
+                            return Err(XsdDatatypeError::PatternNotAllowed {
+                                span: ctx.convert_span(&param.0),
+                            });
+                        }
+                    }
+                    PatternPolicy::Ignore => {
+                        warnings.extend(patterns.map(|param| RelaxWarning::IgnoredFacet {
+                            span: ctx.convert_span(&param.0),
+                            type_name: name.1.clone(),
+                            facet: "pattern".to_string(),
+                        }));
+                    }
+                }
                 self.compile(ctx, &name.0, &name.1, params)
             }
             _ => panic!("Unexpected {datatype_name:?}"),
@@ -814,6 +1625,26 @@ impl super::DatatypeCompiler for Compiler {
 }
 
 impl Compiler {
+    pub(crate) fn set_strict_language_tags(&mut self, strict: bool) {
+        self.strict_language_tags = strict;
+    }
+
+    pub(crate) fn set_policy(&mut self, policy: DatatypePolicy) {
+        self.policy = policy;
+    }
+
+    fn check_allowed(&self, ctx: &Context, name: &types::NcName) -> Result<(), XsdDatatypeError> {
+        match &self.policy.allowed_types {
+            Some(allowed) if !allowed.contains(&name.1) => {
+                Err(XsdDatatypeError::DatatypeNotAllowed {
+                    span: ctx.convert_span(&name.0),
+                    name: name.1.clone(),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
     fn compile(
         &self,
         ctx: &Context,
@@ -1068,24 +1899,26 @@ impl Compiler {
                 }),
             "QName" => {
                 // data type="QName" (without value): validate QName syntax only
-                if !params.is_empty() {
+                if let Some(param) = params.first() {
                     return Err(XsdDatatypeError::Facet {
                         type_name: "QName",
-                        facet: FacetError::InvalidFacet(
-                            ctx.convert_span(span),
-                            "QName data type does not support facets".to_string(),
-                        ),
+                        facet: Self::invalid_facet(ctx, param),
                     });
                 }
                 Ok(XsdDatatypes::QNameData)
             }
-            "ENTITY" | "ENTITIES" => {
-                self.length_only(ctx, params, XsdDatatypes::Entity)
-                    .map_err(|facet| XsdDatatypeError::Facet {
-                        type_name: "ENTITY",
-                        facet,
-                    })
-            }
+            "ENTITY" => self
+                .length_only(ctx, params, XsdDatatypes::Entity)
+                .map_err(|facet| XsdDatatypeError::Facet {
+                    type_name: "ENTITY",
+                    facet,
+                }),
+            "ENTITIES" => self
+                .length_only(ctx, params, XsdDatatypes::Entities)
+                .map_err(|facet| XsdDatatypeError::Facet {
+                    type_name: "ENTITIES",
+                    facet,
+                }),
             "time" => self
                 .pattern_only(ctx, params, XsdDatatypes::Time)
                 .map_err(|facet| XsdDatatypeError::Facet {
@@ -1118,6 +1951,18 @@ impl Compiler {
                     }
                 })?,
             )),
+            "double" => parse_double(value.trim())
+                .map(XsdDatatypeValues::Double)
+                .ok_or_else(|| XsdDatatypeError::InvalidValueOfType {
+                    span: ctx.convert_span(span),
+                    type_name: "double",
+                }),
+            "float" => parse_float(value.trim())
+                .map(XsdDatatypeValues::Float)
+                .ok_or_else(|| XsdDatatypeError::InvalidValueOfType {
+                    span: ctx.convert_span(span),
+                    type_name: "float",
+                }),
             _ => unimplemented!("{:?} not yet supported", name),
         }
     }
@@ -1127,24 +1972,26 @@ impl Compiler {
         ctx: &Context,
         params: &[types::Param],
     ) -> Result<XsdDatatypes, FacetError> {
-        let mut len = LengthFacet::Unbounded;
+        let mut lengths = LengthFacets::default();
         let mut pattern = None;
 
         for param in params {
             match &param.2.to_string()[..] {
-                "length" => len.merge(LengthFacet::Length(Self::usize(ctx, param)?))?,
-                "minLength" => len.merge(LengthFacet::MinLength(Self::usize(ctx, param)?))?,
-                "maxLength" => len.merge(LengthFacet::MaxLength(Self::usize(ctx, param)?))?,
-                "pattern" => pattern = Some(self.pattern(ctx, param)?),
+                "length" => lengths.length(Self::usize(ctx, param)?, ctx.convert_span(&param.0)),
+                "minLength" => {
+                    lengths.min_length(Self::usize(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "maxLength" => {
+                    lengths.max_length(Self::usize(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "pattern" => pattern = self.pattern(ctx, param)?,
                 _ => {
-                    return Err(FacetError::InvalidFacet(
-                        ctx.convert_span(&param.0),
-                        param.2.to_string(),
-                    ));
+                    return Err(Self::invalid_facet(ctx, param));
                 }
             }
         }
 
+        let len = lengths.validate_facets()?;
         Ok(XsdDatatypes::NormalizedString(StringFacets {
             len,
             pattern,
@@ -1152,47 +1999,55 @@ impl Compiler {
     }
 
     fn string(&self, ctx: &Context, params: &[types::Param]) -> Result<XsdDatatypes, FacetError> {
-        let mut len = LengthFacet::Unbounded;
+        let mut lengths = LengthFacets::default();
         let mut pattern = None;
 
         for param in params {
             match &param.2.to_string()[..] {
-                "length" => len.merge(LengthFacet::Length(Self::usize(ctx, param)?))?,
-                "minLength" => len.merge(LengthFacet::MinLength(Self::usize(ctx, param)?))?,
-                "maxLength" => len.merge(LengthFacet::MaxLength(Self::usize(ctx, param)?))?,
-                "pattern" => pattern = Some(self.pattern(ctx, param)?),
+                "length" => lengths.length(Self::usize(ctx, param)?, ctx.convert_span(&param.0)),
+                "minLength" => {
+                    lengths.min_length(Self::usize(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "maxLength" => {
+                    lengths.max_length(Self::usize(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "pattern" => pattern = self.pattern(ctx, param)?,
                 _ => {
-                    return Err(FacetError::InvalidFacet(
-                        ctx.convert_span(&param.0),
-                        param.2.to_string(),
-                    ));
+                    return Err(Self::invalid_facet(ctx, param));
                 }
             }
         }
 
+        let len = lengths.validate_facets()?;
         Ok(XsdDatatypes::String(StringFacets { len, pattern }))
     }
 
     fn short(&self, ctx: &Context, params: &[types::Param]) -> Result<XsdDatatypes, FacetError> {
-        let mut min_max = MinMaxFacet::default();
+        let mut bounds = BoundFacets::default();
         let mut pattern = None;
 
         for param in params {
             match &param.2.to_string()[..] {
-                "minInclusive" => min_max.min_inclusive(Self::i16(ctx, param)?)?,
-                "minExclusive" => min_max.min_exclusive(Self::i16(ctx, param)?)?,
-                "maxInclusive" => min_max.max_inclusive(Self::i16(ctx, param)?)?,
-                "maxExclusive" => min_max.max_exclusive(Self::i16(ctx, param)?)?,
-                "pattern" => pattern = Some(self.pattern(ctx, param)?),
+                "minInclusive" => {
+                    bounds.min_inclusive(Self::i16(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "minExclusive" => {
+                    bounds.min_exclusive(Self::i16(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "maxInclusive" => {
+                    bounds.max_inclusive(Self::i16(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "maxExclusive" => {
+                    bounds.max_exclusive(Self::i16(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "pattern" => pattern = self.pattern(ctx, param)?,
                 _ => {
-                    return Err(FacetError::InvalidFacet(
-                        ctx.convert_span(&param.0),
-                        param.2.to_string(),
-                    ));
+                    return Err(Self::invalid_facet(ctx, param));
                 }
             }
         }
 
+        let min_max = bounds.validate_facets()?;
         Ok(XsdDatatypes::Short(min_max, pattern))
     }
 
@@ -1201,93 +2056,117 @@ impl Compiler {
         ctx: &Context,
         params: &[types::Param],
     ) -> Result<XsdDatatypes, FacetError> {
-        let mut min_max = MinMaxFacet::default();
+        let mut bounds = BoundFacets::default();
         let mut pattern = None;
 
         for param in params {
             match &param.2.to_string()[..] {
-                "minInclusive" => min_max.min_inclusive(Self::u16(ctx, param)?)?,
-                "minExclusive" => min_max.min_exclusive(Self::u16(ctx, param)?)?,
-                "maxInclusive" => min_max.max_inclusive(Self::u16(ctx, param)?)?,
-                "maxExclusive" => min_max.max_exclusive(Self::u16(ctx, param)?)?,
-                "pattern" => pattern = Some(self.pattern(ctx, param)?),
+                "minInclusive" => {
+                    bounds.min_inclusive(Self::u16(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "minExclusive" => {
+                    bounds.min_exclusive(Self::u16(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "maxInclusive" => {
+                    bounds.max_inclusive(Self::u16(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "maxExclusive" => {
+                    bounds.max_exclusive(Self::u16(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "pattern" => pattern = self.pattern(ctx, param)?,
                 _ => {
-                    return Err(FacetError::InvalidFacet(
-                        ctx.convert_span(&param.0),
-                        param.2.to_string(),
-                    ));
+                    return Err(Self::invalid_facet(ctx, param));
                 }
             }
         }
 
+        let min_max = bounds.validate_facets()?;
         Ok(XsdDatatypes::UnsignedShort(min_max, pattern))
     }
 
     fn long(&self, ctx: &Context, params: &[types::Param]) -> Result<XsdDatatypes, FacetError> {
-        let mut min_max = MinMaxFacet::default();
+        let mut bounds = BoundFacets::default();
         let mut pattern = None;
 
         for param in params {
             match &param.2.to_string()[..] {
-                "minInclusive" => min_max.min_inclusive(Self::i64(ctx, param)?)?,
-                "minExclusive" => min_max.min_exclusive(Self::i64(ctx, param)?)?,
-                "maxInclusive" => min_max.max_inclusive(Self::i64(ctx, param)?)?,
-                "maxExclusive" => min_max.max_exclusive(Self::i64(ctx, param)?)?,
-                "pattern" => pattern = Some(self.pattern(ctx, param)?),
+                "minInclusive" => {
+                    bounds.min_inclusive(Self::i64(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "minExclusive" => {
+                    bounds.min_exclusive(Self::i64(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "maxInclusive" => {
+                    bounds.max_inclusive(Self::i64(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "maxExclusive" => {
+                    bounds.max_exclusive(Self::i64(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "pattern" => pattern = self.pattern(ctx, param)?,
                 _ => {
-                    return Err(FacetError::InvalidFacet(
-                        ctx.convert_span(&param.0),
-                        param.2.to_string(),
-                    ));
+                    return Err(Self::invalid_facet(ctx, param));
                 }
             }
         }
 
+        let min_max = bounds.validate_facets()?;
         Ok(XsdDatatypes::Long(min_max, pattern))
     }
 
     fn int(&self, ctx: &Context, params: &[types::Param]) -> Result<XsdDatatypes, FacetError> {
-        let mut min_max = MinMaxFacet::default();
+        let mut bounds = BoundFacets::default();
         let mut pattern = None;
 
         for param in params {
             match &param.2.to_string()[..] {
-                "minInclusive" => min_max.min_inclusive(Self::i32(ctx, param)?)?,
-                "minExclusive" => min_max.min_exclusive(Self::i32(ctx, param)?)?,
-                "maxInclusive" => min_max.max_inclusive(Self::i32(ctx, param)?)?,
-                "maxExclusive" => min_max.max_exclusive(Self::i32(ctx, param)?)?,
-                "pattern" => pattern = Some(self.pattern(ctx, param)?),
+                "minInclusive" => {
+                    bounds.min_inclusive(Self::i32(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "minExclusive" => {
+                    bounds.min_exclusive(Self::i32(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "maxInclusive" => {
+                    bounds.max_inclusive(Self::i32(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "maxExclusive" => {
+                    bounds.max_exclusive(Self::i32(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "pattern" => pattern = self.pattern(ctx, param)?,
                 _ => {
-                    return Err(FacetError::InvalidFacet(
-                        ctx.convert_span(&param.0),
-                        param.2.to_string(),
-                    ));
+                    return Err(Self::invalid_facet(ctx, param));
                 }
             }
         }
 
+        let min_max = bounds.validate_facets()?;
         Ok(XsdDatatypes::Int(min_max, pattern))
     }
     fn integer(&self, ctx: &Context, params: &[types::Param]) -> Result<XsdDatatypes, FacetError> {
-        let mut min_max = MinMaxFacet::default();
+        let mut bounds = BoundFacets::default();
         let mut pattern = None;
 
         for param in params {
             match &param.2.to_string()[..] {
-                "minInclusive" => min_max.min_inclusive(Self::bigint(ctx, param)?)?,
-                "minExclusive" => min_max.min_exclusive(Self::bigint(ctx, param)?)?,
-                "maxInclusive" => min_max.max_inclusive(Self::bigint(ctx, param)?)?,
-                "maxExclusive" => min_max.max_exclusive(Self::bigint(ctx, param)?)?,
-                "pattern" => pattern = Some(self.pattern(ctx, param)?),
+                "minInclusive" => {
+                    bounds.min_inclusive(Self::bigint(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "minExclusive" => {
+                    bounds.min_exclusive(Self::bigint(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "maxInclusive" => {
+                    bounds.max_inclusive(Self::bigint(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "maxExclusive" => {
+                    bounds.max_exclusive(Self::bigint(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "pattern" => pattern = self.pattern(ctx, param)?,
                 _ => {
-                    return Err(FacetError::InvalidFacet(
-                        ctx.convert_span(&param.0),
-                        param.2.to_string(),
-                    ));
+                    return Err(Self::invalid_facet(ctx, param));
                 }
             }
         }
 
+        let min_max = bounds.validate_facets()?;
         Ok(XsdDatatypes::Integer(min_max, pattern))
     }
 
@@ -1296,52 +2175,68 @@ impl Compiler {
         ctx: &Context,
         params: &[types::Param],
     ) -> Result<XsdDatatypes, FacetError> {
-        let mut min_max = MinMaxFacet::default();
+        let mut bounds = BoundFacets::default();
         let mut pattern = None;
 
         for param in params {
             match &param.2.to_string()[..] {
-                "minInclusive" => min_max.min_inclusive(Self::biguint(ctx, param)?)?,
-                "minExclusive" => min_max.min_exclusive(Self::biguint(ctx, param)?)?,
-                "maxInclusive" => min_max.max_inclusive(Self::biguint(ctx, param)?)?,
-                "maxExclusive" => min_max.max_exclusive(Self::biguint(ctx, param)?)?,
-                "pattern" => pattern = Some(self.pattern(ctx, param)?),
+                "minInclusive" => bounds.min_inclusive(
+                    Self::biguint_at_least(ctx, param, "positiveInteger", 1)?,
+                    ctx.convert_span(&param.0),
+                ),
+                "minExclusive" => bounds.min_exclusive(
+                    Self::biguint_at_least(ctx, param, "positiveInteger", 1)?,
+                    ctx.convert_span(&param.0),
+                ),
+                "maxInclusive" => bounds.max_inclusive(
+                    Self::biguint_at_least(ctx, param, "positiveInteger", 1)?,
+                    ctx.convert_span(&param.0),
+                ),
+                "maxExclusive" => bounds.max_exclusive(
+                    Self::biguint_at_least(ctx, param, "positiveInteger", 1)?,
+                    ctx.convert_span(&param.0),
+                ),
+                "pattern" => pattern = self.pattern(ctx, param)?,
                 _ => {
-                    return Err(FacetError::InvalidFacet(
-                        ctx.convert_span(&param.0),
-                        param.2.to_string(),
-                    ));
+                    return Err(Self::invalid_facet(ctx, param));
                 }
             }
         }
 
+        let min_max = bounds.validate_facets()?;
         Ok(XsdDatatypes::PositiveInteger(min_max, pattern))
     }
 
     fn decimal(&self, ctx: &Context, params: &[types::Param]) -> Result<XsdDatatypes, FacetError> {
-        let mut min_max = MinMaxFacet::default();
+        let mut bounds = BoundFacets::default();
         let mut pattern = None;
         let mut fraction_digits = None;
         let mut total_digits = None;
 
         for param in params {
             match &param.2.to_string()[..] {
-                "minInclusive" => min_max.min_inclusive(Self::bigdecimal(ctx, param)?)?,
-                "minExclusive" => min_max.min_exclusive(Self::bigdecimal(ctx, param)?)?,
-                "maxInclusive" => min_max.max_inclusive(Self::bigdecimal(ctx, param)?)?,
-                "maxExclusive" => min_max.max_exclusive(Self::bigdecimal(ctx, param)?)?,
-                "pattern" => pattern = Some(self.pattern(ctx, param)?),
+                "minInclusive" => {
+                    bounds.min_inclusive(Self::bigdecimal(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "minExclusive" => {
+                    bounds.min_exclusive(Self::bigdecimal(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "maxInclusive" => {
+                    bounds.max_inclusive(Self::bigdecimal(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "maxExclusive" => {
+                    bounds.max_exclusive(Self::bigdecimal(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "pattern" => pattern = self.pattern(ctx, param)?,
                 "fractionDigits" => fraction_digits = Some(Self::u16(ctx, param)?),
                 "totalDigits" => total_digits = Some(Self::u16(ctx, param)?),
                 _ => {
-                    return Err(FacetError::InvalidFacet(
-                        ctx.convert_span(&param.0),
-                        param.2.to_string(),
-                    ));
+                    return Err(Self::invalid_facet(ctx, param));
                 }
             }
         }
 
+        let min_max = bounds.validate_facets()?;
         Ok(XsdDatatypes::Decimal {
             min_max,
             pattern,
@@ -1350,126 +2245,155 @@ impl Compiler {
         })
     }
     fn double(&self, ctx: &Context, params: &[types::Param]) -> Result<XsdDatatypes, FacetError> {
-        let mut min_max = MinMaxFacet::default();
+        let mut bounds = BoundFacets::default();
         let mut pattern = None;
 
         for param in params {
             match &param.2.to_string()[..] {
-                "minInclusive" => min_max.min_inclusive(Self::f64(ctx, param)?)?,
-                "minExclusive" => min_max.min_exclusive(Self::f64(ctx, param)?)?,
-                "maxInclusive" => min_max.max_inclusive(Self::f64(ctx, param)?)?,
-                "maxExclusive" => min_max.max_exclusive(Self::f64(ctx, param)?)?,
-                "pattern" => pattern = Some(self.pattern(ctx, param)?),
+                "minInclusive" => {
+                    bounds.min_inclusive(Self::f64(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "minExclusive" => {
+                    bounds.min_exclusive(Self::f64(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "maxInclusive" => {
+                    bounds.max_inclusive(Self::f64(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "maxExclusive" => {
+                    bounds.max_exclusive(Self::f64(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "pattern" => pattern = self.pattern(ctx, param)?,
                 _ => {
-                    return Err(FacetError::InvalidFacet(
-                        ctx.convert_span(&param.0),
-                        param.2.to_string(),
-                    ));
+                    return Err(Self::invalid_facet(ctx, param));
                 }
             }
         }
 
-        Ok(XsdDatatypes::Double(pattern))
+        let min_max = bounds.validate_facets()?;
+        Ok(XsdDatatypes::Double(min_max, pattern))
     }
 
     fn nmtokens(&self, ctx: &Context, params: &[types::Param]) -> Result<XsdDatatypes, FacetError> {
-        let mut len = LengthFacet::Unbounded;
+        let mut lengths = LengthFacets::default();
 
         for param in params {
             match &param.2.to_string()[..] {
-                "length" => len.merge(LengthFacet::Length(Self::usize(ctx, param)?))?,
-                "minLength" => len.merge(LengthFacet::MinLength(Self::usize(ctx, param)?))?,
-                "maxLength" => len.merge(LengthFacet::MaxLength(Self::usize(ctx, param)?))?,
+                "length" => lengths.length(Self::usize(ctx, param)?, ctx.convert_span(&param.0)),
+                "minLength" => {
+                    lengths.min_length(Self::usize(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "maxLength" => {
+                    lengths.max_length(Self::usize(ctx, param)?, ctx.convert_span(&param.0))
+                }
                 _ => {
-                    return Err(FacetError::InvalidFacet(
-                        ctx.convert_span(&param.0),
-                        param.2.to_string(),
-                    ));
+                    return Err(Self::invalid_facet(ctx, param));
                 }
             }
         }
 
+        let len = lengths.validate_facets()?;
         Ok(XsdDatatypes::NmTokens(len))
     }
 
     fn nmtoken(&self, ctx: &Context, params: &[types::Param]) -> Result<XsdDatatypes, FacetError> {
-        let mut len = LengthFacet::Unbounded;
+        let mut lengths = LengthFacets::default();
 
         for param in params {
             match &param.2.to_string()[..] {
-                "length" => len.merge(LengthFacet::Length(Self::usize(ctx, param)?))?,
-                "minLength" => len.merge(LengthFacet::MinLength(Self::usize(ctx, param)?))?,
-                "maxLength" => len.merge(LengthFacet::MaxLength(Self::usize(ctx, param)?))?,
+                "length" => lengths.length(Self::usize(ctx, param)?, ctx.convert_span(&param.0)),
+                "minLength" => {
+                    lengths.min_length(Self::usize(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "maxLength" => {
+                    lengths.max_length(Self::usize(ctx, param)?, ctx.convert_span(&param.0))
+                }
                 _ => {
-                    return Err(FacetError::InvalidFacet(
-                        ctx.convert_span(&param.0),
-                        param.2.to_string(),
-                    ));
+                    return Err(Self::invalid_facet(ctx, param));
                 }
             }
         }
 
+        let len = lengths.validate_facets()?;
         Ok(XsdDatatypes::NmToken(len))
     }
 
     fn ncname(&self, ctx: &Context, params: &[types::Param]) -> Result<XsdDatatypes, FacetError> {
-        let mut len = LengthFacet::Unbounded;
+        let mut lengths = LengthFacets::default();
 
         for param in params {
             match &param.2.to_string()[..] {
-                "length" => len.merge(LengthFacet::Length(Self::usize(ctx, param)?))?,
-                "minLength" => len.merge(LengthFacet::MinLength(Self::usize(ctx, param)?))?,
-                "maxLength" => len.merge(LengthFacet::MaxLength(Self::usize(ctx, param)?))?,
+                "length" => lengths.length(Self::usize(ctx, param)?, ctx.convert_span(&param.0)),
+                "minLength" => {
+                    lengths.min_length(Self::usize(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "maxLength" => {
+                    lengths.max_length(Self::usize(ctx, param)?, ctx.convert_span(&param.0))
+                }
                 _ => {
-                    return Err(FacetError::InvalidFacet(
-                        ctx.convert_span(&param.0),
-                        param.2.to_string(),
-                    ));
+                    return Err(Self::invalid_facet(ctx, param));
                 }
             }
         }
 
+        let len = lengths.validate_facets()?;
         Ok(XsdDatatypes::NcName(len))
     }
 
     fn token(&self, ctx: &Context, params: &[types::Param]) -> Result<XsdDatatypes, FacetError> {
-        let mut len = LengthFacet::Unbounded;
+        let mut lengths = LengthFacets::default();
         let mut pattern = None;
 
         for param in params {
             match &param.2.to_string()[..] {
-                "length" => len.merge(LengthFacet::Length(Self::usize(ctx, param)?))?,
-                "minLength" => len.merge(LengthFacet::MinLength(Self::usize(ctx, param)?))?,
-                "maxLength" => len.merge(LengthFacet::MaxLength(Self::usize(ctx, param)?))?,
-                "pattern" => pattern = Some(self.pattern(ctx, param)?),
+                "length" => lengths.length(Self::usize(ctx, param)?, ctx.convert_span(&param.0)),
+                "minLength" => {
+                    lengths.min_length(Self::usize(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "maxLength" => {
+                    lengths.max_length(Self::usize(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "pattern" => pattern = self.pattern(ctx, param)?,
                 _ => {
-                    return Err(FacetError::InvalidFacet(
-                        ctx.convert_span(&param.0),
-                        param.2.to_string(),
-                    ));
+                    return Err(Self::invalid_facet(ctx, param));
                 }
             }
         }
 
+        let len = lengths.validate_facets()?;
         Ok(XsdDatatypes::Token(StringFacets { len, pattern }))
     }
 
     fn duration(&self, ctx: &Context, params: &[types::Param]) -> Result<XsdDatatypes, FacetError> {
+        let mut bounds = BoundFacets::default();
         let mut pattern = None;
 
         for param in params {
             match &param.2.to_string()[..] {
-                "pattern" => pattern = Some(self.pattern(ctx, param)?),
+                "minInclusive" => bounds.min_inclusive(
+                    Self::duration_bound(ctx, param)?,
+                    ctx.convert_span(&param.0),
+                ),
+                "minExclusive" => bounds.min_exclusive(
+                    Self::duration_bound(ctx, param)?,
+                    ctx.convert_span(&param.0),
+                ),
+                "maxInclusive" => bounds.max_inclusive(
+                    Self::duration_bound(ctx, param)?,
+                    ctx.convert_span(&param.0),
+                ),
+                "maxExclusive" => bounds.max_exclusive(
+                    Self::duration_bound(ctx, param)?,
+                    ctx.convert_span(&param.0),
+                ),
+                "pattern" => pattern = self.pattern(ctx, param)?,
                 _ => {
-                    return Err(FacetError::InvalidFacet(
-                        ctx.convert_span(&param.0),
-                        param.2.to_string(),
-                    ));
+                    return Err(Self::invalid_facet(ctx, param));
                 }
             }
         }
 
-        Ok(XsdDatatypes::Duration(pattern))
+        let min_max = bounds.validate_facets()?;
+        Ok(XsdDatatypes::Duration(Box::new(min_max), pattern))
     }
 
     fn date(&self, ctx: &Context, params: &[types::Param]) -> Result<XsdDatatypes, FacetError> {
@@ -1477,12 +2401,9 @@ impl Compiler {
 
         for param in params {
             match &param.2.to_string()[..] {
-                "pattern" => pattern = Some(self.pattern(ctx, param)?),
+                "pattern" => pattern = self.pattern(ctx, param)?,
                 _ => {
-                    return Err(FacetError::InvalidFacet(
-                        ctx.convert_span(&param.0),
-                        param.2.to_string(),
-                    ));
+                    return Err(Self::invalid_facet(ctx, param));
                 }
             }
         }
@@ -1495,12 +2416,9 @@ impl Compiler {
 
         for param in params {
             match &param.2.to_string()[..] {
-                "pattern" => pattern = Some(self.pattern(ctx, param)?),
+                "pattern" => pattern = self.pattern(ctx, param)?,
                 _ => {
-                    return Err(FacetError::InvalidFacet(
-                        ctx.convert_span(&param.0),
-                        param.2.to_string(),
-                    ));
+                    return Err(Self::invalid_facet(ctx, param));
                 }
             }
         }
@@ -1513,12 +2431,9 @@ impl Compiler {
 
         for param in params {
             match &param.2.to_string()[..] {
-                "pattern" => pattern = Some(self.pattern(ctx, param)?),
+                "pattern" => pattern = self.pattern(ctx, param)?,
                 _ => {
-                    return Err(FacetError::InvalidFacet(
-                        ctx.convert_span(&param.0),
-                        param.2.to_string(),
-                    ));
+                    return Err(Self::invalid_facet(ctx, param));
                 }
             }
         }
@@ -1531,17 +2446,19 @@ impl Compiler {
 
         for param in params {
             match &param.2.to_string()[..] {
-                "pattern" => pattern = Some(self.pattern(ctx, param)?),
+                "pattern" => pattern = self.pattern(ctx, param)?,
                 _ => {
-                    return Err(FacetError::InvalidFacet(
-                        ctx.convert_span(&param.0),
-                        param.2.to_string(),
-                    ));
+                    return Err(Self::invalid_facet(ctx, param));
                 }
             }
         }
 
-        Ok(XsdDatatypes::Language(pattern))
+        let syntax = if self.strict_language_tags {
+            LanguageSyntax::Bcp47
+        } else {
+            LanguageSyntax::Xsd
+        };
+        Ok(XsdDatatypes::Language(syntax, pattern))
     }
 
     fn boolean(&self, ctx: &Context, params: &[types::Param]) -> Result<XsdDatatypes, FacetError> {
@@ -1549,12 +2466,9 @@ impl Compiler {
 
         for param in params {
             match &param.2.to_string()[..] {
-                "pattern" => pattern = Some(self.pattern(ctx, param)?),
+                "pattern" => pattern = self.pattern(ctx, param)?,
                 _ => {
-                    return Err(FacetError::InvalidFacet(
-                        ctx.convert_span(&param.0),
-                        param.2.to_string(),
-                    ));
+                    return Err(Self::invalid_facet(ctx, param));
                 }
             }
         }
@@ -1567,25 +2481,31 @@ impl Compiler {
         ctx: &Context,
         params: &[types::Param],
     ) -> Result<XsdDatatypes, FacetError> {
-        let mut min_max = MinMaxFacet::default();
+        let mut bounds = BoundFacets::default();
         let mut pattern = None;
 
         for param in params {
             match &param.2.to_string()[..] {
-                "minInclusive" => min_max.min_inclusive(Self::u32(ctx, param)?)?,
-                "minExclusive" => min_max.min_exclusive(Self::u32(ctx, param)?)?,
-                "maxInclusive" => min_max.max_inclusive(Self::u32(ctx, param)?)?,
-                "maxExclusive" => min_max.max_exclusive(Self::u32(ctx, param)?)?,
-                "pattern" => pattern = Some(self.pattern(ctx, param)?),
+                "minInclusive" => {
+                    bounds.min_inclusive(Self::u32(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "minExclusive" => {
+                    bounds.min_exclusive(Self::u32(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "maxInclusive" => {
+                    bounds.max_inclusive(Self::u32(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "maxExclusive" => {
+                    bounds.max_exclusive(Self::u32(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "pattern" => pattern = self.pattern(ctx, param)?,
                 _ => {
-                    return Err(FacetError::InvalidFacet(
-                        ctx.convert_span(&param.0),
-                        param.2.to_string(),
-                    ));
+                    return Err(Self::invalid_facet(ctx, param));
                 }
             }
         }
 
+        let min_max = bounds.validate_facets()?;
         Ok(XsdDatatypes::UnsignedInt(min_max, pattern))
     }
 
@@ -1594,25 +2514,31 @@ impl Compiler {
         ctx: &Context,
         params: &[types::Param],
     ) -> Result<XsdDatatypes, FacetError> {
-        let mut min_max = MinMaxFacet::default();
+        let mut bounds = BoundFacets::default();
         let mut pattern = None;
 
         for param in params {
             match &param.2.to_string()[..] {
-                "minInclusive" => min_max.min_inclusive(Self::u64(ctx, param)?)?,
-                "minExclusive" => min_max.min_exclusive(Self::u64(ctx, param)?)?,
-                "maxInclusive" => min_max.max_inclusive(Self::u64(ctx, param)?)?,
-                "maxExclusive" => min_max.max_exclusive(Self::u64(ctx, param)?)?,
-                "pattern" => pattern = Some(self.pattern(ctx, param)?),
+                "minInclusive" => {
+                    bounds.min_inclusive(Self::u64(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "minExclusive" => {
+                    bounds.min_exclusive(Self::u64(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "maxInclusive" => {
+                    bounds.max_inclusive(Self::u64(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "maxExclusive" => {
+                    bounds.max_exclusive(Self::u64(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "pattern" => pattern = self.pattern(ctx, param)?,
                 _ => {
-                    return Err(FacetError::InvalidFacet(
-                        ctx.convert_span(&param.0),
-                        param.2.to_string(),
-                    ));
+                    return Err(Self::invalid_facet(ctx, param));
                 }
             }
         }
 
+        let min_max = bounds.validate_facets()?;
         Ok(XsdDatatypes::UnsignedLong(min_max, pattern))
     }
 
@@ -1621,12 +2547,9 @@ impl Compiler {
 
         for param in params {
             match &param.2.to_string()[..] {
-                "pattern" => pattern = Some(self.pattern(ctx, param)?),
+                "pattern" => pattern = self.pattern(ctx, param)?,
                 _ => {
-                    return Err(FacetError::InvalidFacet(
-                        ctx.convert_span(&param.0),
-                        param.2.to_string(),
-                    ));
+                    return Err(Self::invalid_facet(ctx, param));
                 }
             }
         }
@@ -1639,12 +2562,9 @@ impl Compiler {
 
         for param in params {
             match &param.2.to_string()[..] {
-                "pattern" => pattern = Some(self.pattern(ctx, param)?),
+                "pattern" => pattern = self.pattern(ctx, param)?,
                 _ => {
-                    return Err(FacetError::InvalidFacet(
-                        ctx.convert_span(&param.0),
-                        param.2.to_string(),
-                    ));
+                    return Err(Self::invalid_facet(ctx, param));
                 }
             }
         }
@@ -1653,71 +2573,52 @@ impl Compiler {
     }
 
     fn idrefs(&self, ctx: &Context, params: &[types::Param]) -> Result<XsdDatatypes, FacetError> {
-        let mut len = LengthFacet::Unbounded;
+        let mut lengths = LengthFacets::default();
 
         for param in params {
             match &param.2.to_string()[..] {
+                "length" => lengths.length(Self::usize(ctx, param)?, ctx.convert_span(&param.0)),
                 "minLength" => {
-                    let v = Self::usize(ctx, param)?;
-                    len = match len {
-                        LengthFacet::Unbounded => LengthFacet::MinLength(v),
-                        LengthFacet::MaxLength(max) => LengthFacet::MinMaxLength(v, max),
-                        _ => {
-                            return Err(FacetError::InvalidFacet(
-                                ctx.convert_span(&param.0),
-                                param.2.to_string(),
-                            ));
-                        }
-                    };
+                    lengths.min_length(Self::usize(ctx, param)?, ctx.convert_span(&param.0))
                 }
                 "maxLength" => {
-                    let v = Self::usize(ctx, param)?;
-                    len = match len {
-                        LengthFacet::Unbounded => LengthFacet::MaxLength(v),
-                        LengthFacet::MinLength(min) => LengthFacet::MinMaxLength(min, v),
-                        _ => {
-                            return Err(FacetError::InvalidFacet(
-                                ctx.convert_span(&param.0),
-                                param.2.to_string(),
-                            ));
-                        }
-                    };
-                }
-                "length" => {
-                    let v = Self::usize(ctx, param)?;
-                    len = LengthFacet::Length(v);
+                    lengths.max_length(Self::usize(ctx, param)?, ctx.convert_span(&param.0))
                 }
                 _ => {
-                    return Err(FacetError::InvalidFacet(
-                        ctx.convert_span(&param.0),
-                        param.2.to_string(),
-                    ));
+                    return Err(Self::invalid_facet(ctx, param));
                 }
             }
         }
 
+        let len = lengths.validate_facets()?;
         Ok(XsdDatatypes::IdRefs(len))
     }
 
     fn float(&self, ctx: &Context, params: &[types::Param]) -> Result<XsdDatatypes, FacetError> {
-        let mut min_max = MinMaxFacet::default();
+        let mut bounds = BoundFacets::default();
         let mut pattern = None;
         for param in params {
             match &param.2.to_string()[..] {
-                "minInclusive" => min_max.min_inclusive(Self::f64(ctx, param)?)?,
-                "minExclusive" => min_max.min_exclusive(Self::f64(ctx, param)?)?,
-                "maxInclusive" => min_max.max_inclusive(Self::f64(ctx, param)?)?,
-                "maxExclusive" => min_max.max_exclusive(Self::f64(ctx, param)?)?,
-                "pattern" => pattern = Some(self.pattern(ctx, param)?),
+                "minInclusive" => {
+                    bounds.min_inclusive(Self::f32(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "minExclusive" => {
+                    bounds.min_exclusive(Self::f32(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "maxInclusive" => {
+                    bounds.max_inclusive(Self::f32(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "maxExclusive" => {
+                    bounds.max_exclusive(Self::f32(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "pattern" => pattern = self.pattern(ctx, param)?,
                 _ => {
-                    return Err(FacetError::InvalidFacet(
-                        ctx.convert_span(&param.0),
-                        param.2.to_string(),
-                    ));
+                    return Err(Self::invalid_facet(ctx, param));
                 }
             }
         }
-        Ok(XsdDatatypes::Float(pattern))
+        let min_max = bounds.validate_facets()?;
+        Ok(XsdDatatypes::Float(min_max, pattern))
     }
 
     fn non_negative_integer(
@@ -1725,23 +2626,29 @@ impl Compiler {
         ctx: &Context,
         params: &[types::Param],
     ) -> Result<XsdDatatypes, FacetError> {
-        let mut min_max = MinMaxFacet::default();
+        let mut bounds = BoundFacets::default();
         let mut pattern = None;
         for param in params {
             match &param.2.to_string()[..] {
-                "minInclusive" => min_max.min_inclusive(Self::biguint(ctx, param)?)?,
-                "minExclusive" => min_max.min_exclusive(Self::biguint(ctx, param)?)?,
-                "maxInclusive" => min_max.max_inclusive(Self::biguint(ctx, param)?)?,
-                "maxExclusive" => min_max.max_exclusive(Self::biguint(ctx, param)?)?,
-                "pattern" => pattern = Some(self.pattern(ctx, param)?),
+                "minInclusive" => {
+                    bounds.min_inclusive(Self::biguint(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "minExclusive" => {
+                    bounds.min_exclusive(Self::biguint(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "maxInclusive" => {
+                    bounds.max_inclusive(Self::biguint(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "maxExclusive" => {
+                    bounds.max_exclusive(Self::biguint(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "pattern" => pattern = self.pattern(ctx, param)?,
                 _ => {
-                    return Err(FacetError::InvalidFacet(
-                        ctx.convert_span(&param.0),
-                        param.2.to_string(),
-                    ));
+                    return Err(Self::invalid_facet(ctx, param));
                 }
             }
         }
+        let min_max = bounds.validate_facets()?;
         Ok(XsdDatatypes::NonNegativeInteger(min_max, pattern))
     }
 
@@ -1750,23 +2657,33 @@ impl Compiler {
         ctx: &Context,
         params: &[types::Param],
     ) -> Result<XsdDatatypes, FacetError> {
-        let mut min_max = MinMaxFacet::default();
+        let mut bounds = BoundFacets::default();
         let mut pattern = None;
         for param in params {
             match &param.2.to_string()[..] {
-                "minInclusive" => min_max.min_inclusive(Self::bigint(ctx, param)?)?,
-                "minExclusive" => min_max.min_exclusive(Self::bigint(ctx, param)?)?,
-                "maxInclusive" => min_max.max_inclusive(Self::bigint(ctx, param)?)?,
-                "maxExclusive" => min_max.max_exclusive(Self::bigint(ctx, param)?)?,
-                "pattern" => pattern = Some(self.pattern(ctx, param)?),
+                "minInclusive" => bounds.min_inclusive(
+                    Self::bigint_at_most(ctx, param, "negativeInteger", -1)?,
+                    ctx.convert_span(&param.0),
+                ),
+                "minExclusive" => bounds.min_exclusive(
+                    Self::bigint_at_most(ctx, param, "negativeInteger", -1)?,
+                    ctx.convert_span(&param.0),
+                ),
+                "maxInclusive" => bounds.max_inclusive(
+                    Self::bigint_at_most(ctx, param, "negativeInteger", -1)?,
+                    ctx.convert_span(&param.0),
+                ),
+                "maxExclusive" => bounds.max_exclusive(
+                    Self::bigint_at_most(ctx, param, "negativeInteger", -1)?,
+                    ctx.convert_span(&param.0),
+                ),
+                "pattern" => pattern = self.pattern(ctx, param)?,
                 _ => {
-                    return Err(FacetError::InvalidFacet(
-                        ctx.convert_span(&param.0),
-                        param.2.to_string(),
-                    ));
+                    return Err(Self::invalid_facet(ctx, param));
                 }
             }
         }
+        let min_max = bounds.validate_facets()?;
         Ok(XsdDatatypes::NegativeInteger(min_max, pattern))
     }
 
@@ -1775,44 +2692,60 @@ impl Compiler {
         ctx: &Context,
         params: &[types::Param],
     ) -> Result<XsdDatatypes, FacetError> {
-        let mut min_max = MinMaxFacet::default();
+        let mut bounds = BoundFacets::default();
         let mut pattern = None;
         for param in params {
             match &param.2.to_string()[..] {
-                "minInclusive" => min_max.min_inclusive(Self::bigint(ctx, param)?)?,
-                "minExclusive" => min_max.min_exclusive(Self::bigint(ctx, param)?)?,
-                "maxInclusive" => min_max.max_inclusive(Self::bigint(ctx, param)?)?,
-                "maxExclusive" => min_max.max_exclusive(Self::bigint(ctx, param)?)?,
-                "pattern" => pattern = Some(self.pattern(ctx, param)?),
+                "minInclusive" => bounds.min_inclusive(
+                    Self::bigint_at_most(ctx, param, "nonPositiveInteger", 0)?,
+                    ctx.convert_span(&param.0),
+                ),
+                "minExclusive" => bounds.min_exclusive(
+                    Self::bigint_at_most(ctx, param, "nonPositiveInteger", 0)?,
+                    ctx.convert_span(&param.0),
+                ),
+                "maxInclusive" => bounds.max_inclusive(
+                    Self::bigint_at_most(ctx, param, "nonPositiveInteger", 0)?,
+                    ctx.convert_span(&param.0),
+                ),
+                "maxExclusive" => bounds.max_exclusive(
+                    Self::bigint_at_most(ctx, param, "nonPositiveInteger", 0)?,
+                    ctx.convert_span(&param.0),
+                ),
+                "pattern" => pattern = self.pattern(ctx, param)?,
                 _ => {
-                    return Err(FacetError::InvalidFacet(
-                        ctx.convert_span(&param.0),
-                        param.2.to_string(),
-                    ));
+                    return Err(Self::invalid_facet(ctx, param));
                 }
             }
         }
+        let min_max = bounds.validate_facets()?;
         Ok(XsdDatatypes::NonPositiveInteger(min_max, pattern))
     }
 
     fn byte(&self, ctx: &Context, params: &[types::Param]) -> Result<XsdDatatypes, FacetError> {
-        let mut min_max = MinMaxFacet::default();
+        let mut bounds = BoundFacets::default();
         let mut pattern = None;
         for param in params {
             match &param.2.to_string()[..] {
-                "minInclusive" => min_max.min_inclusive(Self::i8(ctx, param)?)?,
-                "minExclusive" => min_max.min_exclusive(Self::i8(ctx, param)?)?,
-                "maxInclusive" => min_max.max_inclusive(Self::i8(ctx, param)?)?,
-                "maxExclusive" => min_max.max_exclusive(Self::i8(ctx, param)?)?,
-                "pattern" => pattern = Some(self.pattern(ctx, param)?),
+                "minInclusive" => {
+                    bounds.min_inclusive(Self::i8(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "minExclusive" => {
+                    bounds.min_exclusive(Self::i8(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "maxInclusive" => {
+                    bounds.max_inclusive(Self::i8(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "maxExclusive" => {
+                    bounds.max_exclusive(Self::i8(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "pattern" => pattern = self.pattern(ctx, param)?,
                 _ => {
-                    return Err(FacetError::InvalidFacet(
-                        ctx.convert_span(&param.0),
-                        param.2.to_string(),
-                    ));
+                    return Err(Self::invalid_facet(ctx, param));
                 }
             }
         }
+        let min_max = bounds.validate_facets()?;
         Ok(XsdDatatypes::Byte(min_max, pattern))
     }
 
@@ -1821,23 +2754,29 @@ impl Compiler {
         ctx: &Context,
         params: &[types::Param],
     ) -> Result<XsdDatatypes, FacetError> {
-        let mut min_max = MinMaxFacet::default();
+        let mut bounds = BoundFacets::default();
         let mut pattern = None;
         for param in params {
             match &param.2.to_string()[..] {
-                "minInclusive" => min_max.min_inclusive(Self::u8(ctx, param)?)?,
-                "minExclusive" => min_max.min_exclusive(Self::u8(ctx, param)?)?,
-                "maxInclusive" => min_max.max_inclusive(Self::u8(ctx, param)?)?,
-                "maxExclusive" => min_max.max_exclusive(Self::u8(ctx, param)?)?,
-                "pattern" => pattern = Some(self.pattern(ctx, param)?),
+                "minInclusive" => {
+                    bounds.min_inclusive(Self::u8(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "minExclusive" => {
+                    bounds.min_exclusive(Self::u8(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "maxInclusive" => {
+                    bounds.max_inclusive(Self::u8(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "maxExclusive" => {
+                    bounds.max_exclusive(Self::u8(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "pattern" => pattern = self.pattern(ctx, param)?,
                 _ => {
-                    return Err(FacetError::InvalidFacet(
-                        ctx.convert_span(&param.0),
-                        param.2.to_string(),
-                    ));
+                    return Err(Self::invalid_facet(ctx, param));
                 }
             }
         }
+        let min_max = bounds.validate_facets()?;
         Ok(XsdDatatypes::UnsignedByte(min_max, pattern))
     }
 
@@ -1846,20 +2785,22 @@ impl Compiler {
         ctx: &Context,
         params: &[types::Param],
     ) -> Result<XsdDatatypes, FacetError> {
-        let mut len = LengthFacet::Unbounded;
+        let mut lengths = LengthFacets::default();
         for param in params {
             match &param.2.to_string()[..] {
-                "length" => len.merge(LengthFacet::Length(Self::usize(ctx, param)?))?,
-                "minLength" => len.merge(LengthFacet::MinLength(Self::usize(ctx, param)?))?,
-                "maxLength" => len.merge(LengthFacet::MaxLength(Self::usize(ctx, param)?))?,
+                "length" => lengths.length(Self::usize(ctx, param)?, ctx.convert_span(&param.0)),
+                "minLength" => {
+                    lengths.min_length(Self::usize(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "maxLength" => {
+                    lengths.max_length(Self::usize(ctx, param)?, ctx.convert_span(&param.0))
+                }
                 _ => {
-                    return Err(FacetError::InvalidFacet(
-                        ctx.convert_span(&param.0),
-                        param.2.to_string(),
-                    ));
+                    return Err(Self::invalid_facet(ctx, param));
                 }
             }
         }
+        let len = lengths.validate_facets()?;
         Ok(XsdDatatypes::Base64Binary(len))
     }
 
@@ -1868,20 +2809,22 @@ impl Compiler {
         ctx: &Context,
         params: &[types::Param],
     ) -> Result<XsdDatatypes, FacetError> {
-        let mut len = LengthFacet::Unbounded;
+        let mut lengths = LengthFacets::default();
         for param in params {
             match &param.2.to_string()[..] {
-                "length" => len.merge(LengthFacet::Length(Self::usize(ctx, param)?))?,
-                "minLength" => len.merge(LengthFacet::MinLength(Self::usize(ctx, param)?))?,
-                "maxLength" => len.merge(LengthFacet::MaxLength(Self::usize(ctx, param)?))?,
+                "length" => lengths.length(Self::usize(ctx, param)?, ctx.convert_span(&param.0)),
+                "minLength" => {
+                    lengths.min_length(Self::usize(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "maxLength" => {
+                    lengths.max_length(Self::usize(ctx, param)?, ctx.convert_span(&param.0))
+                }
                 _ => {
-                    return Err(FacetError::InvalidFacet(
-                        ctx.convert_span(&param.0),
-                        param.2.to_string(),
-                    ));
+                    return Err(Self::invalid_facet(ctx, param));
                 }
             }
         }
+        let len = lengths.validate_facets()?;
         Ok(XsdDatatypes::HexBinary(len))
     }
 
@@ -1894,12 +2837,9 @@ impl Compiler {
         let mut pattern = None;
         for param in params {
             match &param.2.to_string()[..] {
-                "pattern" => pattern = Some(self.pattern(ctx, param)?),
+                "pattern" => pattern = self.pattern(ctx, param)?,
                 _ => {
-                    return Err(FacetError::InvalidFacet(
-                        ctx.convert_span(&param.0),
-                        param.2.to_string(),
-                    ));
+                    return Err(Self::invalid_facet(ctx, param));
                 }
             }
         }
@@ -1912,20 +2852,22 @@ impl Compiler {
         params: &[types::Param],
         make: fn(LengthFacet) -> XsdDatatypes,
     ) -> Result<XsdDatatypes, FacetError> {
-        let mut len = LengthFacet::Unbounded;
+        let mut lengths = LengthFacets::default();
         for param in params {
             match &param.2.to_string()[..] {
-                "length" => len.merge(LengthFacet::Length(Self::usize(ctx, param)?))?,
-                "minLength" => len.merge(LengthFacet::MinLength(Self::usize(ctx, param)?))?,
-                "maxLength" => len.merge(LengthFacet::MaxLength(Self::usize(ctx, param)?))?,
+                "length" => lengths.length(Self::usize(ctx, param)?, ctx.convert_span(&param.0)),
+                "minLength" => {
+                    lengths.min_length(Self::usize(ctx, param)?, ctx.convert_span(&param.0))
+                }
+                "maxLength" => {
+                    lengths.max_length(Self::usize(ctx, param)?, ctx.convert_span(&param.0))
+                }
                 _ => {
-                    return Err(FacetError::InvalidFacet(
-                        ctx.convert_span(&param.0),
-                        param.2.to_string(),
-                    ));
+                    return Err(Self::invalid_facet(ctx, param));
                 }
             }
         }
+        let len = lengths.validate_facets()?;
         Ok(make(len))
     }
 
@@ -2009,7 +2951,8 @@ impl Compiler {
             })
     }
 
-    fn f64(ctx: &Context, param: &types::Param) -> Result<f64, FacetError> {
+    /// Only finite bounds are accepted, so a `FloatBound` is never `NaN`
+    fn f64(ctx: &Context, param: &types::Param) -> Result<FloatBound, FacetError> {
         param
             .3
             .as_string_value()
@@ -2019,7 +2962,29 @@ impl Compiler {
             })
             .and_then(|v: f64| {
                 if v.is_finite() {
-                    Ok(v)
+                    Ok(FloatBound(v))
+                } else {
+                    Err(FacetError::InvalidFloat(
+                        ctx.convert_span(&param.0),
+                        "Only finite values allowed".to_string(),
+                    ))
+                }
+            })
+    }
+
+    /// A bound for `float` values, which is rounded to the nearest `f32` so that it compares
+    /// with values as XML Schema requires
+    fn f32(ctx: &Context, param: &types::Param) -> Result<FloatBound, FacetError> {
+        param
+            .3
+            .as_string_value()
+            .parse()
+            .map_err(|e: std::num::ParseFloatError| {
+                FacetError::InvalidFloat(ctx.convert_span(&param.0), e.to_string())
+            })
+            .and_then(|v: f32| {
+                if v.is_finite() {
+                    Ok(FloatBound(f64::from(v)))
                 } else {
                     Err(FacetError::InvalidFloat(
                         ctx.convert_span(&param.0),
@@ -2039,6 +3004,41 @@ impl Compiler {
             })
     }
 
+    /// The value of a bound facet of a type with no values above `max`.  Like the value of any
+    /// bound, it must itself be a value of the type.
+    fn bigint_at_most(
+        ctx: &Context,
+        param: &types::Param,
+        type_name: &str,
+        max: i8,
+    ) -> Result<num_bigint::BigInt, FacetError> {
+        let value = Self::bigint(ctx, param)?;
+        if value > num_bigint::BigInt::from(max) {
+            return Err(FacetError::InvalidInt(
+                ctx.convert_span(&param.0),
+                format!("{value} is greater than {max}, the largest {type_name}"),
+            ));
+        }
+        Ok(value)
+    }
+
+    /// The value of a bound facet of a type with no values below `min`
+    fn biguint_at_least(
+        ctx: &Context,
+        param: &types::Param,
+        type_name: &str,
+        min: u8,
+    ) -> Result<num_bigint::BigUint, FacetError> {
+        let value = Self::biguint(ctx, param)?;
+        if value < num_bigint::BigUint::from(min) {
+            return Err(FacetError::InvalidInt(
+                ctx.convert_span(&param.0),
+                format!("{value} is less than {min}, the smallest {type_name}"),
+            ));
+        }
+        Ok(value)
+    }
+
     fn biguint(ctx: &Context, param: &types::Param) -> Result<num_bigint::BigUint, FacetError> {
         param
             .3
@@ -2062,6 +3062,22 @@ impl Compiler {
             })
     }
 
+    fn duration_bound(ctx: &Context, param: &types::Param) -> Result<DurationValue, FacetError> {
+        let value = param.3.as_string_value();
+        DurationValue::parse(value.trim())
+            .ok_or_else(|| FacetError::InvalidDuration(ctx.convert_span(&param.0), value))
+    }
+
+    /// The error for a parameter which the datatype being compiled does not take
+    fn invalid_facet(ctx: &Context, param: &types::Param) -> FacetError {
+        let name = param.2.to_string();
+        FacetError::InvalidFacet {
+            span: ctx.convert_span(&param.0),
+            known: FACETS.contains(&&name[..]),
+            name,
+        }
+    }
+
     fn usize(ctx: &Context, param: &types::Param) -> Result<usize, FacetError> {
         param
             .3
@@ -2072,14 +3088,23 @@ impl Compiler {
             })
     }
 
-    fn pattern(&self, ctx: &Context, param: &types::Param) -> Result<PatternFacet, FacetError> {
-        let raw = param.3.as_string_value();
-        let translated = xsd_regex_to_rust(&raw);
-        // XSD spec: pattern facet must match the entire lexical value (implicit ^ and $).
-        let anchored = format!("^(?:{translated})$");
-        regex::Regex::new(&anchored)
-            .map(|re| PatternFacet(raw, re))
-            .map_err(|e| FacetError::InvalidPattern(ctx.convert_span(&param.0), e))
+    /// Compile a `pattern` facet, or give `None` if the policy says patterns are ignored
+    fn pattern(
+        &self,
+        ctx: &Context,
+        param: &types::Param,
+    ) -> Result<Option<PatternFacet>, FacetError> {
+        if self.policy.patterns == PatternPolicy::Ignore {
+            return Ok(None);
+        }
+        PatternFacet::new(param.3.as_string_value(), self.policy.pattern_size_limit)
+            .map(Some)
+            .map_err(|e| match e {
+                regex::Error::CompiledTooBig(limit) => {
+                    FacetError::PatternTooLarge(ctx.convert_span(&param.0), limit)
+                }
+                e => FacetError::InvalidPattern(ctx.convert_span(&param.0), e),
+            })
     }
 }
 
@@ -2269,6 +3294,155 @@ impl QNameVal {
     }
 }
 
+codec_enum!(XsdDatatypes {
+    0 => NormalizedString(facets),
+    1 => String(facets),
+    2 => Short(bounds, pattern),
+    3 => UnsignedShort(bounds, pattern),
+    4 => Long(bounds, pattern),
+    5 => Int(bounds, pattern),
+    6 => Integer(bounds, pattern),
+    7 => PositiveInteger(bounds, pattern),
+    8 => UnsignedInt(bounds, pattern),
+    9 => UnsignedLong(bounds, pattern),
+    10 => Decimal { min_max, pattern, fraction_digits, total_digits },
+    11 => Double(bounds, pattern),
+    12 => NmTokens(len),
+    13 => NmToken(len),
+    14 => NcName(len),
+    15 => Token(facets),
+    16 => Duration(bounds, pattern),
+    17 => Date(pattern),
+    18 => Datetime(pattern),
+    19 => AnyURI(pattern),
+    20 => Language(syntax, pattern),
+    21 => Boolean(pattern),
+    22 => Id(pattern),
+    23 => IdRef(pattern),
+    24 => IdRefs(len),
+    25 => Float(bounds, pattern),
+    26 => NonNegativeInteger(bounds, pattern),
+    27 => NegativeInteger(bounds, pattern),
+    28 => NonPositiveInteger(bounds, pattern),
+    29 => Byte(bounds, pattern),
+    30 => UnsignedByte(bounds, pattern),
+    31 => Base64Binary(len),
+    32 => HexBinary(len),
+    33 => GYear(pattern),
+    34 => GYearMonth(pattern),
+    35 => GMonth(pattern),
+    36 => GMonthDay(pattern),
+    37 => GDay(pattern),
+    38 => Name(len),
+    39 => QNameData,
+    40 => Entity(len),
+    41 => Entities(len),
+    42 => Time(pattern),
+});
+codec_enum!(XsdDatatypeValues {
+    0 => String(value),
+    1 => Token(value),
+    2 => QName(value),
+    3 => Double(value),
+    4 => Float(value),
+});
+codec_struct!(StringFacets { len, pattern });
+codec_enum!(LengthFacet {
+    0 => Unbounded,
+    1 => MinLength(min),
+    2 => MaxLength(max),
+    3 => MinMaxLength(min, max),
+    4 => Length(len),
+});
+codec_enum!(LanguageSyntax {
+    0 => Xsd,
+    1 => Bcp47,
+});
+codec_struct!(DurationValue { months, seconds });
+
+impl<T: PartialOrd + Encode> Encode for MinMaxFacet<T> {
+    fn encode(&self, w: &mut Writer) -> io::Result<()> {
+        match &self.min {
+            Min::Unbounded => w.tag(0)?,
+            Min::Inclusive(v) => {
+                w.tag(1)?;
+                v.encode(w)?
+            }
+            Min::Exclusive(v) => {
+                w.tag(2)?;
+                v.encode(w)?
+            }
+        }
+        match &self.max {
+            Max::Unbounded => w.tag(0),
+            Max::Inclusive(v) => {
+                w.tag(1)?;
+                v.encode(w)
+            }
+            Max::Exclusive(v) => {
+                w.tag(2)?;
+                v.encode(w)
+            }
+        }
+    }
+}
+impl<T: PartialOrd + Decode> Decode for MinMaxFacet<T> {
+    fn decode(r: &mut Reader) -> io::Result<Self> {
+        let min = match r.tag()? {
+            0 => Min::Unbounded,
+            1 => Min::Inclusive(T::decode(r)?),
+            2 => Min::Exclusive(T::decode(r)?),
+            tag => return Err(serial::unknown_tag("Min", tag)),
+        };
+        let max = match r.tag()? {
+            0 => Max::Unbounded,
+            1 => Max::Inclusive(T::decode(r)?),
+            2 => Max::Exclusive(T::decode(r)?),
+            tag => return Err(serial::unknown_tag("Max", tag)),
+        };
+        Ok(MinMaxFacet { min, max })
+    }
+}
+
+impl Encode for FloatBound {
+    fn encode(&self, w: &mut Writer) -> io::Result<()> {
+        self.0.encode(w)
+    }
+}
+impl Decode for FloatBound {
+    fn decode(r: &mut Reader) -> io::Result<Self> {
+        f64::decode(r).map(FloatBound)
+    }
+}
+
+/// Only the pattern as written in the schema and its size limit are kept, and the pattern is
+/// compiled again when read
+impl Encode for PatternFacet {
+    fn encode(&self, w: &mut Writer) -> io::Result<()> {
+        self.0.encode(w)?;
+        self.2.encode(w)
+    }
+}
+impl Decode for PatternFacet {
+    fn decode(r: &mut Reader) -> io::Result<Self> {
+        let raw = String::decode(r)?;
+        let size_limit = Option::<usize>::decode(r)?;
+        PatternFacet::new(raw, size_limit).map_err(|_| serial::invalid("bad pattern facet"))
+    }
+}
+
+impl Encode for QNameVal {
+    fn encode(&self, w: &mut Writer) -> io::Result<()> {
+        self.0.encode(w)?;
+        self.1.encode(w)
+    }
+}
+impl Decode for QNameVal {
+    fn decode(r: &mut Reader) -> io::Result<Self> {
+        Ok(QNameVal(String::decode(r)?, String::decode(r)?))
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -2281,7 +3455,7 @@ mod test {
         let mut map = CodeMap::new();
         let file = map.add_file("main.rnc".to_string(), "just testing".to_string());
         let ctx = Context::new(file);
-        let c = Compiler;
+        let c = Compiler::default();
         let name =
             types::IdentifierOrKeyword::Identifier(types::Identifier(0..0, "length".to_string()));
         let value = types::Literal(
@@ -2300,4 +3474,460 @@ mod test {
             }))
         )
     }
+
+    fn compile_no_params(name: &str) -> XsdDatatypes {
+        let mut map = CodeMap::new();
+        let file = map.add_file("main.rnc".to_string(), "just testing".to_string());
+        let ctx = Context::new(file);
+        Compiler::default()
+            .compile(&ctx, &(0..0), name, &[])
+            .unwrap()
+    }
+
+    #[test]
+    fn numeric_lexical_space() {
+        use super::super::Datatype as _;
+        let decimal = compile_no_params("decimal");
+        assert!(decimal.is_valid("1000"));
+        assert!(decimal.is_valid("-1.5"));
+        assert!(decimal.is_valid(".5"));
+        assert!(!decimal.is_valid("1E3"));
+        assert!(!decimal.is_valid("1e3"));
+
+        let integer = compile_no_params("integer");
+        assert!(integer.is_valid("+1000"));
+        assert!(!integer.is_valid("1_000"));
+        assert!(!integer.is_valid("1E3"));
+
+        for name in ["double", "float"] {
+            let dt = compile_no_params(name);
+            assert!(dt.is_valid("1E3"), "{name}");
+            assert!(dt.is_valid("1.5e-3"), "{name}");
+            assert!(dt.is_valid("INF"), "{name}");
+            assert!(dt.is_valid("-INF"), "{name}");
+            assert!(dt.is_valid("NaN"), "{name}");
+            assert!(!dt.is_valid("inf"), "{name}");
+            assert!(!dt.is_valid("infinity"), "{name}");
+            assert!(!dt.is_valid("nan"), "{name}");
+            assert!(!dt.is_valid("1E"), "{name}");
+        }
+    }
+
+    #[test]
+    fn floating_point_values() {
+        use super::super::Datatype as _;
+        let mut map = CodeMap::new();
+        let file = map.add_file("main.rnc".to_string(), "just testing".to_string());
+        let ctx = Context::new(file);
+        let c = Compiler::default();
+        for name in ["double", "float"] {
+            let one = c.compile_value(&ctx, &(0..0), name, "1.0", &[]).unwrap();
+            assert!(one.is_valid("1E0"), "{name}");
+            assert!(one.is_valid("1.00"), "{name}");
+            assert!(one.is_valid(" 1 "), "{name}");
+            assert!(!one.is_valid("1.01"), "{name}");
+            assert!(!one.is_valid("one"), "{name}");
+
+            let nan = c.compile_value(&ctx, &(0..0), name, "NaN", &[]).unwrap();
+            assert!(!nan.is_valid("NaN"), "{name}");
+
+            assert_matches!(
+                c.compile_value(&ctx, &(0..0), name, "1.0.0", &[]),
+                Err(XsdDatatypeError::InvalidValueOfType { .. })
+            );
+        }
+    }
+
+    #[test]
+    fn floating_point_bounds() {
+        use super::super::Datatype as _;
+        for name in ["double", "float"] {
+            let non_negative = compile_with_param(name, "minInclusive", "0");
+            assert!(non_negative.is_valid("0"), "{name}");
+            assert!(non_negative.is_valid("-0"), "{name}");
+            assert!(non_negative.is_valid("INF"), "{name}");
+            assert!(!non_negative.is_valid("-1E-3"), "{name}");
+            assert!(!non_negative.is_valid("-INF"), "{name}");
+            assert!(!non_negative.is_valid("NaN"), "{name}");
+
+            let below_ten = compile_with_param(name, "maxExclusive", "10");
+            assert!(below_ten.is_valid("9.5"), "{name}");
+            assert!(!below_ten.is_valid("10"), "{name}");
+            assert!(!below_ten.is_valid("INF"), "{name}");
+            assert!(!below_ten.is_valid("NaN"), "{name}");
+
+            assert!(compile_no_params(name).is_valid("NaN"), "{name}");
+        }
+        // the bound is rounded to the same float as the value
+        let float = compile_with_param("float", "maxInclusive", "0.1");
+        assert!(float.is_valid("0.1"));
+        let double = compile_with_param("double", "maxInclusive", "0.1");
+        assert!(!double.is_valid("0.10000001"));
+    }
+
+    #[test]
+    fn date_time_fields() {
+        use super::super::Datatype as _;
+        let datetime = compile_no_params("dateTime");
+        assert!(datetime.is_valid("2024-01-01T12:00:00.5Z"));
+        assert!(datetime.is_valid("2024-01-01T12:00:00.123456789+05:30"));
+        assert!(datetime.is_valid("2024-02-29T00:00:00"));
+        assert!(datetime.is_valid("-0044-03-15T12:00:00"));
+        assert!(datetime.is_valid("-0001-02-29T00:00:00"));
+        assert!(datetime.is_valid("12345-01-01T00:00:00"));
+        assert!(datetime.is_valid("2024-01-01T24:00:00"));
+        assert!(datetime.is_valid("2024-01-01T24:00:00.000"));
+        assert!(!datetime.is_valid("2024-01-01T24:00:00.5Z"));
+        assert!(!datetime.is_valid("2024-01-01T12:00:60.5Z"));
+        assert!(!datetime.is_valid("2024-01-01T12:00:00.Z"));
+        assert!(!datetime.is_valid("2023-02-29T00:00:00"));
+        assert!(!datetime.is_valid("2024-13-01T00:00:00"));
+        assert!(!datetime.is_valid("0000-01-01T00:00:00"));
+        assert!(!datetime.is_valid("02024-01-01T00:00:00"));
+        assert!(!datetime.is_valid("2024-01-01T00:00:00+14:30"));
+
+        let time = compile_no_params("time");
+        assert!(time.is_valid("23:59:59.999"));
+        assert!(time.is_valid("24:00:00"));
+        assert!(!time.is_valid("24:00:00.1"));
+        assert!(!time.is_valid("23:60:00"));
+        assert!(time.is_valid("12:00:00+14:00"));
+        assert!(time.is_valid("12:00:00-13:59"));
+        assert!(!time.is_valid("12:00:00+25:00"));
+        assert!(!time.is_valid("12:00:00-14:01"));
+        assert!(!time.is_valid("12:00:00+05:60"));
+    }
+
+    #[test]
+    fn gregorian_timezones() {
+        use super::super::Datatype as _;
+        for (name, value) in [
+            ("gYear", "2024"),
+            ("gYearMonth", "2024-01"),
+            ("gMonth", "--01"),
+            ("gMonthDay", "--01-31"),
+            ("gDay", "---31"),
+        ] {
+            let dt = compile_no_params(name);
+            assert!(dt.is_valid(value), "{name}");
+            assert!(dt.is_valid(&format!("{value}Z")), "{name}");
+            assert!(dt.is_valid(&format!("{value}-14:00")), "{name}");
+            assert!(!dt.is_valid(&format!("{value}+25:00")), "{name}");
+            assert!(!dt.is_valid(&format!("{value}+14:30")), "{name}");
+            assert!(!dt.is_valid(&format!("{value}+01:60")), "{name}");
+        }
+    }
+
+    #[test]
+    fn duration_values() {
+        use super::super::Datatype as _;
+        let duration = compile_no_params("duration");
+        for valid in [
+            "P1Y2M3DT4H5M6.5S",
+            "-P1D",
+            "PT0S",
+            "P0D",
+            "PT1.S",
+            "PT.5S",
+            "P1M",
+            // more months than an i64 can count
+            "P1000000000000000000Y",
+            "-P99999999999999999999M",
+        ] {
+            assert!(duration.is_valid(valid), "{valid}");
+        }
+        for invalid in [
+            "P", "PT", "-P", "P1DT", "P1.5D", "P1,5Y", "1D", "P1S", "PT1D", "P-1D",
+        ] {
+            assert!(!duration.is_valid(invalid), "{invalid}");
+        }
+
+        let non_negative = compile_with_param("duration", "minInclusive", "PT0S");
+        assert!(non_negative.is_valid("PT0S"));
+        assert!(non_negative.is_valid("P0D"));
+        assert!(non_negative.is_valid("-PT0S"));
+        assert!(non_negative.is_valid("P1M"));
+        assert!(!non_negative.is_valid("-PT1S"));
+        assert!(!non_negative.is_valid("-P1Y"));
+
+        let at_least_a_month = compile_with_param("duration", "minInclusive", "P1M");
+        assert!(at_least_a_month.is_valid("P1M"));
+        assert!(at_least_a_month.is_valid("P32D"));
+        assert!(at_least_a_month.is_valid("PT768H"));
+        assert!(!at_least_a_month.is_valid("P27D"));
+        // some months are shorter than 30 days and some longer, so the two are not ordered
+        assert!(!at_least_a_month.is_valid("P30D"));
+
+        let under_a_year = compile_with_param("duration", "maxExclusive", "P1Y");
+        assert!(under_a_year.is_valid("P11M"));
+        assert!(under_a_year.is_valid("P364D"));
+        assert!(!under_a_year.is_valid("P12M"));
+        assert!(!under_a_year.is_valid("P365D"));
+        assert!(!under_a_year.is_valid("P1000000000000000000Y"));
+        assert!(under_a_year.is_valid("-P1000000000000000000Y"));
+        assert!(!non_negative.is_valid("-P1000000000000000000Y"));
+
+        let mut map = CodeMap::new();
+        let file = map.add_file("main.rnc".to_string(), "just testing".to_string());
+        let ctx = Context::new(file);
+        let name = types::IdentifierOrKeyword::Identifier(types::Identifier(
+            0..0,
+            "minInclusive".to_string(),
+        ));
+        let value = types::Literal(
+            0..0,
+            vec![types::LiteralSegment {
+                body: "P".to_string(),
+            }],
+        );
+        let param = types::Param(0..0, None, name, value);
+        assert_matches!(
+            Compiler::default().compile(&ctx, &(0..0), "duration", &[param]),
+            Err(XsdDatatypeError::Facet {
+                facet: FacetError::InvalidDuration(_, value),
+                ..
+            }) if value == "P"
+        );
+    }
+
+    #[test]
+    fn language_tags() {
+        use super::super::Datatype as _;
+        let mut map = CodeMap::new();
+        let file = map.add_file("main.rnc".to_string(), "just testing".to_string());
+        let ctx = Context::new(file);
+        let mut c = Compiler::default();
+        let xsd = c.compile(&ctx, &(0..0), "language", &[]).unwrap();
+        c.set_strict_language_tags(true);
+        let bcp47 = c.compile(&ctx, &(0..0), "language", &[]).unwrap();
+
+        for dt in [&xsd, &bcp47] {
+            assert!(dt.is_valid("en-US"));
+            assert!(dt.is_valid("i-navajo"));
+            assert!(dt.is_valid("zh-Hant-TW"));
+            assert!(!dt.is_valid("123"));
+        }
+        // well-formed per XSD 1.0, but not per BCP 47
+        assert!(xsd.is_valid("a-b"));
+        assert!(!bcp47.is_valid("a-b"));
+        assert!(xsd.is_valid("en-US-x"));
+        assert!(!bcp47.is_valid("en-US-x"));
+    }
+
+    fn compile_with_param(name: &str, facet: &str, value: &str) -> XsdDatatypes {
+        try_compile_with_param(name, facet, value).unwrap()
+    }
+
+    fn try_compile_with_param(
+        name: &str,
+        facet: &str,
+        value: &str,
+    ) -> Result<XsdDatatypes, XsdDatatypeError> {
+        let mut map = CodeMap::new();
+        let file = map.add_file("main.rnc".to_string(), "just testing".to_string());
+        let ctx = Context::new(file);
+        let facet =
+            types::IdentifierOrKeyword::Identifier(types::Identifier(0..0, facet.to_string()));
+        let value = types::Literal(
+            0..0,
+            vec![types::LiteralSegment {
+                body: value.to_string(),
+            }],
+        );
+        let param = types::Param(0..0, None, facet, value);
+        Compiler::default().compile(&ctx, &(0..0), name, &[param])
+    }
+
+    #[test]
+    fn bounds_outside_type() {
+        use super::super::Datatype as _;
+        for (name, facet, value) in [
+            ("nonPositiveInteger", "minInclusive", "5"),
+            ("nonPositiveInteger", "maxExclusive", "1"),
+            ("negativeInteger", "minInclusive", "0"),
+            ("positiveInteger", "maxInclusive", "0"),
+        ] {
+            assert_matches!(
+                try_compile_with_param(name, facet, value),
+                Err(XsdDatatypeError::Facet {
+                    facet: FacetError::InvalidInt(..),
+                    ..
+                }),
+                "{name} {facet} {value}"
+            );
+        }
+        let dt = compile_with_param("nonPositiveInteger", "minInclusive", "-5");
+        assert!(dt.is_valid("-5"));
+        assert!(dt.is_valid("0"));
+        assert!(!dt.is_valid("-6"));
+        let dt = compile_with_param("negativeInteger", "maxInclusive", "-1");
+        assert!(dt.is_valid("-1"));
+        assert!(!dt.is_valid("0"));
+    }
+
+    #[test]
+    fn pattern_size_limit() {
+        let mut map = CodeMap::new();
+        let file = map.add_file("main.rnc".to_string(), "just testing".to_string());
+        let ctx = Context::new(file);
+        let facet =
+            types::IdentifierOrKeyword::Identifier(types::Identifier(0..0, "pattern".to_string()));
+        let value = types::Literal(
+            0..0,
+            vec![types::LiteralSegment {
+                body: "(\\c+-){20}".to_string(),
+            }],
+        );
+        let params = [types::Param(0..0, None, facet, value)];
+        let mut c = Compiler::default();
+        assert!(c.compile(&ctx, &(0..0), "token", &params).is_ok());
+        c.set_policy(DatatypePolicy {
+            pattern_size_limit: Some(10_000),
+            ..Default::default()
+        });
+        assert_matches!(
+            c.compile(&ctx, &(0..0), "token", &params),
+            Err(XsdDatatypeError::Facet {
+                facet: FacetError::PatternTooLarge(_, 10_000),
+                ..
+            })
+        );
+    }
+
+    #[test]
+    fn length_counts_scalar_values() {
+        use super::super::Datatype as _;
+        // U+1F600 is one scalar value, but two UTF-16 code units and four UTF-8 bytes
+        let emoji = "\u{1F600}";
+        for name in ["string", "token", "normalizedString"] {
+            let len1 = compile_with_param(name, "length", "1");
+            assert!(len1.is_valid(emoji), "{name}");
+            assert!(!len1.is_valid("ab"), "{name}");
+            let max1 = compile_with_param(name, "maxLength", "1");
+            assert!(max1.is_valid(emoji), "{name}");
+            assert!(!max1.is_valid(&format!("{emoji}{emoji}")), "{name}");
+        }
+        // 'e' followed by a combining acute accent is two scalar values
+        let len2 = compile_with_param("string", "length", "2");
+        assert!(len2.is_valid("e\u{301}"));
+
+        // binary types count decoded octets rather than characters
+        let hex = compile_with_param("hexBinary", "length", "2");
+        assert!(hex.is_valid("00FF"));
+        assert!(!hex.is_valid("00"));
+        let base64 = compile_with_param("base64Binary", "length", "3");
+        assert!(base64.is_valid("AAAA"));
+        // list types count items
+        let nmtokens = compile_with_param("NMTOKENS", "length", "2");
+        assert!(nmtokens.is_valid("abc def"));
+        assert!(!nmtokens.is_valid("abcdef"));
+    }
+
+    #[test]
+    fn zero_length() {
+        use super::super::Datatype as _;
+        for name in [
+            "string",
+            "token",
+            "normalizedString",
+            "hexBinary",
+            "base64Binary",
+        ] {
+            let min0 = compile_with_param(name, "minLength", "0");
+            assert!(min0.is_valid(""), "{name}");
+            assert!(min0.is_valid("AAAA"), "{name}");
+            let len0 = compile_with_param(name, "length", "0");
+            assert!(len0.is_valid(""), "{name}");
+            assert!(!len0.is_valid("AAAA"), "{name}");
+            let max0 = compile_with_param(name, "maxLength", "0");
+            assert!(max0.is_valid(""), "{name}");
+            assert!(!max0.is_valid("AAAA"), "{name}");
+        }
+        // whitespace collapses to the empty string, except in a string
+        assert!(compile_with_param("token", "length", "0").is_valid("  \t "));
+        assert!(!compile_with_param("string", "length", "0").is_valid(" "));
+
+        // padding counts towards the group of four characters, but not the decoded octets
+        let base64 = compile_with_param("base64Binary", "length", "1");
+        assert!(base64.is_valid("AA=="));
+        assert!(!base64.is_valid("AAA="));
+        // and is not a value on its own
+        for value in ["=", "==", "A", "AA=", "AAAAA"] {
+            assert!(!base64.is_valid(value), "{value}");
+            assert_eq!(base64.explain(value), None, "{value}");
+        }
+    }
+
+    #[test]
+    fn explain() {
+        let max = compile_with_param("integer", "maxInclusive", "10");
+        assert_eq!(max.explain("10"), None);
+        assert_eq!(
+            max.explain("12").as_deref(),
+            Some("\"12\" is greater than maxInclusive 10")
+        );
+        // not an integer at all, which no facet explains
+        assert_eq!(max.explain("ten"), None);
+
+        let digits = compile_with_param("string", "pattern", "[0-9]+");
+        assert_eq!(
+            digits.explain("ab").as_deref(),
+            Some("\"ab\" does not match pattern [0-9]+")
+        );
+        let above = compile_with_param("double", "minExclusive", "0");
+        assert_eq!(
+            above.explain("0").as_deref(),
+            Some("\"0\" is not greater than minExclusive 0")
+        );
+        let short = compile_with_param("token", "maxLength", "3");
+        assert_eq!(
+            short.explain("abcd").as_deref(),
+            Some("\"abcd\" has 4 characters, more than maxLength 3")
+        );
+        let pair = compile_with_param("NMTOKENS", "length", "2");
+        assert_eq!(
+            pair.explain("a").as_deref(),
+            Some("\"a\" has 1 token, not length 2")
+        );
+
+        // a length is not blamed for a value that is not of the type at all
+        let hex = compile_with_param("hexBinary", "length", "2");
+        assert_eq!(
+            hex.explain("ab").as_deref(),
+            Some("\"ab\" has 1 octet, not length 2")
+        );
+        for (name, facet, length, value) in [
+            ("hexBinary", "length", "2", "zzz"),
+            ("hexBinary", "length", "2", "abc"),
+            ("NCName", "maxLength", "2", "a:bc"),
+            ("ENTITY", "maxLength", "2", "a:bc"),
+            ("Name", "maxLength", "2", "1abc"),
+            ("NMTOKEN", "maxLength", "2", "a bc"),
+            ("NMTOKENS", "length", "2", "a ? b"),
+            ("IDREFS", "length", "2", "a:b"),
+            ("ENTITIES", "length", "2", "1a"),
+        ] {
+            let dt = compile_with_param(name, facet, length);
+            assert_eq!(dt.explain(value), None, "{name} {value}");
+        }
+    }
+
+    #[test]
+    fn whitespace_collapsed() {
+        use super::super::Datatype as _;
+        // surrounding whitespace, as in pretty-printed element content, is not part of the value
+        let int = compile_with_param("int", "maxInclusive", "10");
+        assert!(int.is_valid(" 5\n"));
+        assert_eq!(
+            int.explain(" 12 ").as_deref(),
+            Some("\"12\" is greater than maxInclusive 10")
+        );
+        let short = compile_with_param("token", "maxLength", "3");
+        assert!(short.is_valid("  a \t b "));
+        let date = compile_with_param("date", "pattern", "2024-.*");
+        assert!(date.is_valid("\n  2024-01-01\n"));
+        // except for string, whose whitespace is all kept
+        let string = compile_with_param("string", "maxLength", "3");
+        assert!(!string.is_valid("  a  "));
+    }
 }
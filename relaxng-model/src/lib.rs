@@ -16,13 +16,16 @@ use std::fs::File;
 use std::io;
 use std::io::Read;
 use std::ops::Range;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use std::rc::Rc;
 use std::sync::Arc;
 
+pub mod content_model;
 pub mod datatype;
 pub mod model;
 pub mod restrictions;
+mod serial;
+pub mod visit;
 
 // TODO:
 //  - Detect ambiguous grammars per https://www.kohsuke.org/relaxng/ambiguity/AmbiguousGrammarDetection.pdf
@@ -155,6 +158,12 @@ pub enum RelaxError {
         name: String,
         span: codemap::Span,
     },
+    /// An `include` or `externalRef` gives a URL rather than a file path.  Schemas are only ever
+    /// read from local files, so that compiling an untrusted schema cannot make network requests.
+    RemoteHref {
+        span: codemap::Span,
+        href: String,
+    },
     IncludedFileMustBeGrammar {
         span: codemap::Span,
     },
@@ -171,6 +180,15 @@ pub enum RelaxError {
         pattern_name: String,
         context: String,
     },
+    /// Section 7.2: members of a group or interleave with content types that cannot be combined,
+    /// such as text and a data value, or two data values outside a list
+    UngroupableContent {
+        span: codemap::Span,
+        /// `group` or `interleave`
+        operator: &'static str,
+        /// descriptions of the conflicting members, such as `a data value (from 'code')`
+        members: [String; 2],
+    },
     /// Section 7.1.1: attribute named "xmlns" is forbidden
     XmlnsAttributeForbidden,
     /// Section 7.1.1: attribute in xmlns namespace is forbidden
@@ -189,6 +207,89 @@ pub enum RelaxError {
     OverlappingElements {
         span: codemap::Span,
     },
+    /// Patterns are nested more deeply than the limit given to `Compiler::set_max_depth()`, so
+    /// the section 7 restrictions were not checked
+    NestingTooDeep {
+        /// the start rule, from which the restrictions are checked
+        span: codemap::Span,
+        limit: usize,
+    },
+}
+
+impl RelaxError {
+    /// A short, stable name for the kind of error, such as `undefined-reference`, which is shown
+    /// in diagnostics and can be looked up with `rng --explain`.  Errors in an included file
+    /// have the code of the underlying error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            RelaxError::Io(..) => "io",
+            RelaxError::IncludeError(_, err) => err.code(),
+            RelaxError::Parse(..) | RelaxError::XmlParse(..) => "syntax",
+            RelaxError::DuplicateDefinition { .. } => "duplicate-definition",
+            RelaxError::IncompatibleCombination { .. }
+            | RelaxError::DefineRulesMixesChoiceAndInterleave { .. } => "incompatible-combine",
+            RelaxError::UndefinedNamespacePrefix { .. } => "undefined-namespace-prefix",
+            RelaxError::UndefinedReference { .. } => "undefined-reference",
+            RelaxError::UndefinedDatatypePrefix { .. } => "undefined-datatype-prefix",
+            RelaxError::NamespacePrefixAlreadyDefined { .. }
+            | RelaxError::DatatypePrefixAlreadyDefined(_) => "duplicate-prefix",
+            RelaxError::DuplicateParameterName(_) => "duplicate-parameter",
+            RelaxError::StartRuleRedefined => "duplicate-start",
+            RelaxError::StartRuleNotDefined { .. } => "missing-start",
+            RelaxError::NoParentAvailable(_) => "no-parent-grammar",
+            RelaxError::DatatypeError(_) => "datatype",
+            RelaxError::RecursiveReference { .. } => "recursive-reference",
+            RelaxError::ElementAsChildOfAttribute { .. } => "element-in-attribute",
+            RelaxError::RecursiveInclude { .. } => "recursive-include",
+            RelaxError::RemoteHref { .. } => "remote-href",
+            RelaxError::IncludedFileMustBeGrammar { .. } => "include-not-grammar",
+            RelaxError::OverrideMissingFromInclude { .. } => "override-missing",
+            RelaxError::RestrictedPattern { .. } => "restricted-pattern",
+            RelaxError::UngroupableContent { .. } => "string-sequence",
+            RelaxError::XmlnsAttributeForbidden | RelaxError::XmlnsNamespaceForbidden => {
+                "xmlns-attribute"
+            }
+            RelaxError::AnyNameInExcept
+            | RelaxError::AnyNameInNsNameExcept
+            | RelaxError::NsNameInNsNameExcept => "name-class-except",
+            RelaxError::OverlappingAttributes { .. } => "overlapping-attributes",
+            RelaxError::OverlappingElements { .. } => "overlapping-elements",
+            RelaxError::NestingTooDeep { .. } => "nesting-too-deep",
+        }
+    }
+}
+
+/// A problem in the schema that does not prevent compilation, but which the schema author
+/// probably wants to know about
+#[derive(Debug)]
+pub enum RelaxWarning {
+    /// A datatype parameter was accepted, but has no effect on which values are valid
+    IgnoredFacet {
+        span: codemap::Span,
+        type_name: String,
+        facet: String,
+    },
+    /// An attribute's name class only matches names reserved for namespace declarations, so
+    /// the attribute can never be present in an instance document
+    UnsatisfiableAttribute { span: codemap::Span },
+    /// Two alternatives of a choice are elements whose name classes overlap, so an element
+    /// with one of the names they share could match either.  Only reported when enabled with
+    /// `Compiler::set_warn_ambiguous_choices()`.
+    AmbiguousChoice {
+        span: codemap::Span,
+        /// the overlapping name classes, such as `a` and `{urn:example}*`
+        names: [String; 2],
+    },
+    /// A definition is not used by the start pattern, even indirectly through other
+    /// definitions.  Only reported when enabled with `Compiler::set_warn_unused_definitions()`.
+    UnusedDefinition { span: codemap::Span, name: String },
+    /// An interleave has more members than the limit given to
+    /// `Compiler::set_max_interleave_members()`, so that validating against it may be slow
+    LargeInterleave {
+        span: codemap::Span,
+        members: usize,
+        limit: usize,
+    },
 }
 
 enum Context<'a> {
@@ -424,9 +525,7 @@ impl<'a> Context<'a> {
     }
     fn namespace_uri_for_prefix_str(&self, prefix: &str) -> Option<&str> {
         match self {
-            Context::Root { namespaces, .. } => {
-                namespaces.get(prefix).map(|s| &s[..])
-            }
+            Context::Root { namespaces, .. } => namespaces.get(prefix).map(|s| &s[..]),
             Context::Include {
                 namespaces, parent, ..
             } => namespaces
@@ -441,6 +540,38 @@ impl<'a> Context<'a> {
         }
     }
 
+    /// The namespace prefixes declared for this context, and its default namespace (with the
+    /// prefix `""`) if it has one, as the context in which `QName` values in the schema resolve
+    fn namespace_bindings(&self) -> Vec<(String, String)> {
+        let mut bindings = vec![];
+        let default = self.default_namespace_uri();
+        if !default.is_empty() {
+            bindings.push((String::new(), default.to_string()));
+        }
+        let mut this = Some(self);
+        while let Some(ctx) = this {
+            this = match ctx {
+                Context::Root { namespaces, .. } | Context::Include { namespaces, .. } => {
+                    for (prefix, uri) in namespaces {
+                        if !bindings.iter().any(|(p, _)| p == prefix) {
+                            bindings.push((prefix.clone(), uri.clone()));
+                        }
+                    }
+                    match ctx {
+                        Context::Include { parent, .. } => Some(parent),
+                        _ => None,
+                    }
+                }
+                Context::IncludeOverrides { parent, .. }
+                | Context::Grammar { parent, .. }
+                | Context::Define { parent, .. }
+                | Context::Element { parent }
+                | Context::Attribute { parent, .. } => Some(parent),
+            };
+        }
+        bindings
+    }
+
     fn declare_datatype(&mut self, prefix: String, uri: String) -> Result<(), RelaxError> {
         match self {
             Context::Root { datatypes, .. } | Context::Include { datatypes, .. } => {
@@ -698,11 +829,28 @@ impl<'a> Context<'a> {
     }
 }
 
+/// Where the compiler reads schema files from.
+///
+/// Only paths are passed to `load()`: an `include` or `externalRef` naming a URL is rejected
+/// with `RelaxError::RemoteHref` before any file is read, so no implementation is asked to fetch
+/// anything from the network.
 pub trait Files {
     fn load(&self, name: &Path) -> Result<String, RelaxError>;
+
+    /// A name for the file at the given path which is the same however the path is written, so
+    /// that a schema included from several places is read and parsed only once.  By default,
+    /// the path with `.` and `..` components resolved.
+    fn canonical(&self, name: &Path) -> PathBuf {
+        normalize_path(name)
+    }
 }
+/// Reads schema files from the local file system, and nowhere else
 pub struct FsFiles;
 impl Files for FsFiles {
+    fn canonical(&self, name: &Path) -> PathBuf {
+        std::fs::canonicalize(name).unwrap_or_else(|_| normalize_path(name))
+    }
+
     fn load(&self, name: &Path) -> Result<String, RelaxError> {
         let mut io = File::open(name).map_err(|e| RelaxError::Io(name.to_path_buf(), e))?;
         let mut data = String::new();
@@ -712,12 +860,65 @@ impl Files for FsFiles {
     }
 }
 
+/// Whether an href starts with a URI scheme, such as `http:` or `file:`, rather than being a
+/// path.  A single letter before the colon is taken to be a Windows drive.
+fn is_url(href: &str) -> bool {
+    href.split_once(':').is_some_and(|(scheme, _)| {
+        scheme.len() > 1
+            && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+    })
+}
+
+/// The path with `.` components removed, and each `..` removing the component before it, where
+/// there is one
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(result.components().next_back(), Some(Component::Normal(_))) =>
+            {
+                result.pop();
+            }
+            component => result.push(component),
+        }
+    }
+    result
+}
+
 pub struct Compiler<FS: Files> {
-    loaded: HashMap<PathBuf, (Arc<codemap::File>, Rc<Schema>)>,
+    /// the schemas read so far, by their `Files::canonical()` name, each with the path it was
+    /// first read by.  Parsed schemas are kept rather than compiled ones, since what an included
+    /// schema compiles to depends on where it is included, such as the datatype library that
+    /// it inherits.
+    loaded: HashMap<PathBuf, (PathBuf, Arc<codemap::File>, Rc<Schema>)>,
     codemap: CodeMap,
     fs: FS,
     syntax: Syntax,
     datatype_compiler: datatype::Compiler,
+    warnings: Vec<RelaxWarning>,
+    /// the directory set by `set_base_uri()`
+    base_uri: Option<PathBuf>,
+    /// the names of the schemas passed to `compile()` or `compile_types()`, whose hrefs are
+    /// resolved against `base_uri`
+    roots: HashSet<String>,
+    /// see `namespaces()`
+    namespaces: Vec<(Option<String>, String)>,
+    /// see `set_max_depth()`
+    max_depth: usize,
+    /// see `set_max_interleave_members()`
+    max_interleave_members: usize,
+    /// see `set_warn_ambiguous_choices()`
+    warn_ambiguous_choices: bool,
+    /// see `set_warn_unused_definitions()`
+    warn_unused_definitions: bool,
+    /// the definitions of every grammar in the schema being compiled, other than their `start`,
+    /// collected when `warn_unused_definitions` is set
+    definitions: Vec<(String, Rc<RefCell<Option<model::DefineRule>>>)>,
 }
 impl Default for Compiler<FsFiles> {
     fn default() -> Self {
@@ -732,12 +933,81 @@ impl<FS: Files> Compiler<FS> {
             fs,
             syntax,
             datatype_compiler: datatype::Compiler::default(),
+            warnings: Vec::new(),
+            base_uri: None,
+            roots: HashSet::new(),
+            namespaces: Vec::new(),
+            max_depth: restrictions::DEFAULT_MAX_DEPTH,
+            max_interleave_members: restrictions::DEFAULT_MAX_INTERLEAVE_MEMBERS,
+            warn_ambiguous_choices: false,
+            warn_unused_definitions: false,
+            definitions: Vec::new(),
+        }
+    }
+
+    /// Resolve the `include` and `externalRef` hrefs of the schemas passed to `compile()` or
+    /// `compile_types()` against the given directory, rather than the directory of the schema
+    /// itself.  This is needed when the schema does not come from a file, but the schemas it
+    /// refers to do.  Hrefs in those schemas are still resolved against their own directory.
+    pub fn set_base_uri(&mut self, base: PathBuf) {
+        self.base_uri = Some(base);
+    }
+
+    /// Check values of the XSD `language` datatype against the BCP 47 (RFC 5646) grammar,
+    /// rather than the more permissive lexical rule given by XML Schema.  Affects schemas
+    /// compiled after the call.
+    pub fn set_strict_language_tags(&mut self, strict: bool) {
+        self.datatype_compiler.set_strict_language_tags(strict);
+    }
+
+    /// Limit the XML Schema datatypes and facets that schemas compiled after the call may use,
+    /// for example to avoid compiling `pattern` facets from an untrusted schema
+    pub fn set_datatype_policy(&mut self, policy: datatype::xsd::DatatypePolicy) {
+        self.datatype_compiler.set_policy(policy);
+    }
+
+    /// Limit how deeply patterns may be nested, counting each reference followed as a level,
+    /// when checking the restrictions of section 7 of the specification.  Deeper schemas are
+    /// reported as `RelaxError::NestingTooDeep`, rather than overflowing the stack.  The default
+    /// is `restrictions::DEFAULT_MAX_DEPTH`.
+    pub fn set_max_depth(&mut self, depth: usize) {
+        self.max_depth = depth;
+    }
+
+    /// Warn about interleaves with more than the given number of members, which may be slow to
+    /// validate against.  The schema is still compiled.  The default is
+    /// `restrictions::DEFAULT_MAX_INTERLEAVE_MEMBERS`, and `usize::MAX` turns the warning off.
+    /// Affects schemas compiled after the call.
+    pub fn set_max_interleave_members(&mut self, limit: usize) {
+        self.max_interleave_members = limit;
+    }
+
+    /// Warn about choices between elements whose name classes overlap, such as
+    /// `element a { empty } | element a { text }`, which are allowed but often unintended.
+    /// Affects schemas compiled after the call.
+    pub fn set_warn_ambiguous_choices(&mut self, warn: bool) {
+        self.warn_ambiguous_choices = warn;
+    }
+
+    /// Warn about definitions that the start pattern never refers to, directly or through other
+    /// definitions, and which could therefore be removed.  Affects schemas compiled after the
+    /// call.
+    pub fn set_warn_unused_definitions(&mut self, warn: bool) {
+        self.warn_unused_definitions = warn;
+    }
+
+    /// Remember the definitions of a grammar that has been compiled, for
+    /// `set_warn_unused_definitions()`
+    fn note_definitions(&mut self, ctx: &Context) {
+        if self.warn_unused_definitions {
+            self.definitions
+                .extend(ctx.ref_iter().filter(|(name, _)| name != "start"));
         }
     }
 
     // Returns an iterator over the loaded schemas.
     pub fn loaded(&self) -> impl Iterator<Item = &PathBuf> {
-        self.loaded.keys()
+        self.loaded.values().map(|(name, _, _)| name)
     }
 
     // TODO: provide a simpler return-type
@@ -747,8 +1017,48 @@ impl<FS: Files> Compiler<FS> {
         name: &Path,
     ) -> Result<Rc<RefCell<Option<model::DefineRule>>>, RelaxError> {
         let (file, schema) = self.get_schema(name)?;
+        self.compile_loaded(file, schema)
+    }
+
+    /// Compile each of the given schemas, independently of one another, returning the result
+    /// for each in the same order.  A schema that fails to compile does not stop the rest from
+    /// being tried; pass the errors to `dump_diagnostic()` to report them.
+    pub fn compile_all<P: AsRef<Path>>(
+        &mut self,
+        names: &[P],
+    ) -> Vec<Result<Rc<RefCell<Option<model::DefineRule>>>, RelaxError>> {
+        names
+            .iter()
+            .map(|name| self.compile(name.as_ref()))
+            .collect()
+    }
+
+    /// Compile a schema syntax tree that was built by some means other than parsing a schema
+    /// file, for example by a code generator.
+    ///
+    /// There is no source text for the tree, so spans within it (used only for diagnostics)
+    /// should be empty ranges at offset 0.  Any `include` or `externalRef` is still loaded
+    /// using this `Compiler`'s `Files` implementation.
+    pub fn compile_types(
+        &mut self,
+        schema: Schema,
+    ) -> Result<Rc<RefCell<Option<model::DefineRule>>>, RelaxError> {
+        let file = self
+            .codemap
+            .add_file("<syntax tree>".to_string(), String::new());
+        self.compile_loaded(file, Rc::new(schema))
+    }
+
+    fn compile_loaded(
+        &mut self,
+        file: Arc<codemap::File>,
+        schema: Rc<Schema>,
+    ) -> Result<Rc<RefCell<Option<model::DefineRule>>>, RelaxError> {
+        self.roots.insert(file.name().to_string());
+        self.definitions.clear();
         let mut ctx = Context::new(file.clone());
         self.compile_schema(&mut ctx, schema)?;
+        self.note_definitions(&ctx);
         for (name, r) in ctx.ref_iter() {
             if r.borrow().is_none() {
                 //println!("Undefined {:?} :(", name);
@@ -768,7 +1078,34 @@ impl<FS: Files> Compiler<FS> {
             {
                 let borrowed = start.borrow();
                 let rule = borrowed.as_ref().unwrap();
-                restrictions::check_restrictions(rule, *rule.span())?;
+                restrictions::check_restrictions(rule, *rule.span(), self.max_depth)?;
+                self.warnings
+                    .extend(restrictions::find_unsatisfiable_attributes(
+                        rule,
+                        *rule.span(),
+                    ));
+                self.warnings.extend(restrictions::find_large_interleaves(
+                    rule,
+                    *rule.span(),
+                    self.max_interleave_members,
+                ));
+                if self.warn_ambiguous_choices {
+                    self.warnings
+                        .extend(restrictions::find_ambiguous_choices(rule, *rule.span()));
+                }
+                let definitions = std::mem::take(&mut self.definitions);
+                self.warnings
+                    .extend(restrictions::find_unused_definitions(rule, &definitions));
+                let mut uris = NamespaceUris(vec![]);
+                visit::walk_pattern(&mut uris, rule.pattern());
+                for uri in uris.0 {
+                    if !self.namespaces.iter().any(|(_, u)| *u == uri) {
+                        // the one prefix that is bound without a declaration
+                        let prefix =
+                            (uri == "http://www.w3.org/XML/1998/namespace").then_some("xml");
+                        self.note_namespace(prefix, &uri);
+                    }
+                }
             }
             Ok(start)
         } else {
@@ -819,12 +1156,144 @@ impl<FS: Files> Compiler<FS> {
     }
 
     pub fn dump_diagnostic(&self, err: &RelaxError) {
+        let (map, diagnostics) = self.to_diagnostics(err);
+        codemap_diagnostic::Emitter::stderr(codemap_diagnostic::ColorConfig::Always, Some(map))
+            .emit(&diagnostics);
+    }
+
+    /// The same report as `dump_diagnostic()` prints, as plain text without colours
+    pub fn render_diagnostic(&self, err: &RelaxError) -> String {
+        let mut out = vec![];
+        let (map, diagnostics) = self.to_diagnostics(err);
+        codemap_diagnostic::Emitter::vec(&mut out, Some(map)).emit(&diagnostics);
+        String::from_utf8_lossy(&out).into_owned()
+    }
+
+    /// The report that `dump_diagnostic()` prints, for rendering in some other format, along
+    /// with the map of the schema files that its spans are in (as `Validator::diagnostic()`
+    /// gives for validation errors)
+    pub fn to_diagnostics(
+        &self,
+        err: &RelaxError,
+    ) -> (&CodeMap, Vec<codemap_diagnostic::Diagnostic>) {
+        let mut d = self.diagnostic(err);
+        d.code = Some(err.code().to_string());
+        (&self.codemap, vec![d])
+    }
+
+    /// The namespaces that the schemas compiled so far declare or use, as (prefix, URI) pairs in
+    /// the order they were first seen.
+    ///
+    /// The prefix is `None` for a default namespace declaration, and for a namespace which
+    /// element or attribute names use without any prefix having been declared for it (as with
+    /// the `ns` attribute of the XML syntax).  The empty URI, meaning no namespace, is left out.
+    pub fn namespaces(&self) -> Vec<(Option<String>, String)> {
+        self.namespaces.clone()
+    }
+
+    fn note_namespace(&mut self, prefix: Option<&str>, uri: &str) {
+        let known = self
+            .namespaces
+            .iter()
+            .any(|(p, u)| p.as_deref() == prefix && u == uri);
+        if !uri.is_empty() && !known {
+            self.namespaces
+                .push((prefix.map(str::to_string), uri.to_string()));
+        }
+    }
+
+    /// Warnings produced by the schemas compiled so far
+    pub fn warnings(&self) -> &[RelaxWarning] {
+        &self.warnings
+    }
+
+    pub fn dump_warnings(&self) {
         let mut emitter = codemap_diagnostic::Emitter::stderr(
             codemap_diagnostic::ColorConfig::Always,
             Some(&self.codemap),
         );
-        let d = self.diagnostic(err);
-        emitter.emit(&[d]);
+        let d: Vec<_> = self
+            .warnings
+            .iter()
+            .map(|w| self.warning_diagnostic(w))
+            .collect();
+        emitter.emit(&d[..]);
+    }
+
+    fn warning_diagnostic(&self, warning: &RelaxWarning) -> codemap_diagnostic::Diagnostic {
+        match warning {
+            RelaxWarning::IgnoredFacet {
+                span,
+                type_name,
+                facet,
+            } => codemap_diagnostic::Diagnostic {
+                level: codemap_diagnostic::Level::Warning,
+                message: format!("The {facet:?} facet of {type_name} is not enforced"),
+                code: None,
+                spans: vec![codemap_diagnostic::SpanLabel {
+                    span: *span,
+                    style: codemap_diagnostic::SpanStyle::Primary,
+                    label: Some(if facet == "pattern" {
+                        "values not matching this pattern will still be accepted".to_owned()
+                    } else {
+                        "values outside this bound will still be accepted".to_owned()
+                    }),
+                }],
+            },
+            RelaxWarning::UnsatisfiableAttribute { span } => codemap_diagnostic::Diagnostic {
+                level: codemap_diagnostic::Level::Warning,
+                message: "Attribute can never match, since its names are reserved for namespace declarations".to_string(),
+                code: None,
+                spans: vec![codemap_diagnostic::SpanLabel {
+                    span: *span,
+                    style: codemap_diagnostic::SpanStyle::Primary,
+                    label: Some("in this definition".to_owned()),
+                }],
+            },
+            RelaxWarning::UnusedDefinition { span, name } => codemap_diagnostic::Diagnostic {
+                level: codemap_diagnostic::Level::Warning,
+                message: format!("{name} is defined, but never used by the start pattern"),
+                code: None,
+                spans: vec![codemap_diagnostic::SpanLabel {
+                    span: *span,
+                    style: codemap_diagnostic::SpanStyle::Primary,
+                    label: Some("unused definition".to_owned()),
+                }],
+            },
+            RelaxWarning::LargeInterleave {
+                span,
+                members,
+                limit,
+            } => codemap_diagnostic::Diagnostic {
+                level: codemap_diagnostic::Level::Warning,
+                message: format!(
+                    "An interleave has {members} members, more than {limit}, and may be slow to validate against"
+                ),
+                code: None,
+                spans: vec![codemap_diagnostic::SpanLabel {
+                    span: *span,
+                    style: codemap_diagnostic::SpanStyle::Primary,
+                    label: Some("in this definition".to_owned()),
+                }],
+            },
+            RelaxWarning::AmbiguousChoice {
+                span,
+                names: [a, b],
+            } => codemap_diagnostic::Diagnostic {
+                level: codemap_diagnostic::Level::Warning,
+                message: if a == b {
+                    format!("Two alternatives of a choice are elements named {a}")
+                } else {
+                    format!("Alternatives of a choice are elements named {a} and {b}, which overlap")
+                },
+                code: None,
+                spans: vec![codemap_diagnostic::SpanLabel {
+                    span: *span,
+                    style: codemap_diagnostic::SpanStyle::Primary,
+                    label: Some("in this definition".to_owned()),
+                }],
+            },
+        }
     }
 
     #[allow(clippy::only_used_in_recursion)]
@@ -946,16 +1415,39 @@ impl<FS: Files> Compiler<FS> {
                         spans: vec![label],
                     }
                 }
+                Errors::CompatibilityParamNotAllowed { span, name } => {
+                    let label = codemap_diagnostic::SpanLabel {
+                        span: *span,
+                        style: codemap_diagnostic::SpanStyle::Primary,
+                        label: Some(
+                            "remove this parameter, or use the XML Schema datatype of the same name"
+                                .to_string(),
+                        ),
+                    };
+                    codemap_diagnostic::Diagnostic {
+                        level: codemap_diagnostic::Level::Error,
+                        message: format!(
+                            "Parameter {name:?} not allowed: the compatibility datatypes 'ID', 'IDREF' and 'IDREFS' take no parameters"
+                        ),
+                        code: None,
+                        spans: vec![label],
+                    }
+                }
                 Errors::Relax(e) => match e {
-                    datatype::relax::Error::ParamNotAllowed { span, name: _ } => {
+                    datatype::relax::Error::ParamNotAllowed { span, name } => {
                         let label = codemap_diagnostic::SpanLabel {
                             span: *span,
                             style: codemap_diagnostic::SpanStyle::Primary,
-                            label: Some("remove this parameter".to_string()),
+                            label: Some(
+                                "remove this parameter, or use a datatype from another library"
+                                    .to_string(),
+                            ),
                         };
                         codemap_diagnostic::Diagnostic {
                             level: codemap_diagnostic::Level::Error,
-                            message: "relaxng built-in datatype supports no parameters".to_string(),
+                            message: format!(
+                                "Parameter {name:?} not allowed: the built-in datatypes 'string' and 'token' take no parameters"
+                            ),
                             code: None,
                             spans: vec![label],
                         }
@@ -980,8 +1472,24 @@ impl<FS: Files> Compiler<FS> {
                     XsdDatatypeError::Facet { type_name, facet } => {
                         let mut labels = vec![];
                         match facet {
-                            FacetError::ConflictingFacet(_name) => {
-                                unimplemented!("Support for ConflictingFacet")
+                            FacetError::ConflictingFacets(conflicts) => {
+                                for conflict in conflicts {
+                                    let [(a, a_span), (b, b_span)] = conflict.facets;
+                                    labels.push(codemap_diagnostic::SpanLabel {
+                                        span: a_span,
+                                        style: codemap_diagnostic::SpanStyle::Primary,
+                                        label: Some(conflict.reason.clone()),
+                                    });
+                                    labels.push(codemap_diagnostic::SpanLabel {
+                                        span: b_span,
+                                        style: codemap_diagnostic::SpanStyle::Secondary,
+                                        label: Some(if a == b {
+                                            format!("{b} first given here")
+                                        } else {
+                                            format!("{b} given here, conflicting with {a}")
+                                        }),
+                                    });
+                                }
                             }
                             FacetError::InvalidInt(span, msg) => {
                                 labels.push(codemap_diagnostic::SpanLabel {
@@ -997,6 +1505,13 @@ impl<FS: Files> Compiler<FS> {
                                     label: Some(format!("Invalid floating-point value: {msg}")),
                                 })
                             }
+                            FacetError::InvalidDuration(span, value) => {
+                                labels.push(codemap_diagnostic::SpanLabel {
+                                    span: *span,
+                                    style: codemap_diagnostic::SpanStyle::Primary,
+                                    label: Some(format!("Invalid duration value: {value:?}")),
+                                })
+                            }
                             FacetError::InvalidPattern(span, err) => {
                                 labels.push(codemap_diagnostic::SpanLabel {
                                     span: *span,
@@ -1004,21 +1519,74 @@ impl<FS: Files> Compiler<FS> {
                                     label: Some(format!("Invalid pattern value: {err}")),
                                 })
                             }
-                            FacetError::InvalidFacet(span, name) => {
+                            FacetError::PatternTooLarge(span, limit) => {
+                                labels.push(codemap_diagnostic::SpanLabel {
+                                    span: *span,
+                                    style: codemap_diagnostic::SpanStyle::Primary,
+                                    label: Some(format!(
+                                        "Pattern is too complex, compiling to more than {limit} bytes"
+                                    )),
+                                })
+                            }
+                            FacetError::InvalidFacet { span, name, known } => {
                                 labels.push(codemap_diagnostic::SpanLabel {
                                     span: *span,
                                     style: codemap_diagnostic::SpanStyle::Primary,
-                                    label: Some(format!("Invalid facet for type: {name}")),
+                                    label: Some(match &name[..] {
+                                        _ if *known => format!(
+                                            "facet {name} is not applicable to {type_name}"
+                                        ),
+                                        // XML Schema facets which RELAX NG leaves out
+                                        "enumeration" => format!(
+                                            "enumeration is not allowed as a parameter; use a \
+                                             choice of values, like xsd:{type_name} \"a\" | \
+                                             xsd:{type_name} \"b\""
+                                        ),
+                                        "whiteSpace" => {
+                                            "whiteSpace is not allowed as a parameter".to_string()
+                                        }
+                                        _ => format!("{name} is not a facet of any datatype"),
+                                    }),
                                 })
                             }
                         }
                         codemap_diagnostic::Diagnostic {
                             level: codemap_diagnostic::Level::Error,
-                            message: format!("Problematic facet for {type_name} type"),
+                            message: if let FacetError::ConflictingFacets(_) = facet {
+                                format!("Conflicting facets for {type_name} type")
+                            } else {
+                                format!("Problematic facet for {type_name} type")
+                            },
                             code: None,
                             spans: labels,
                         }
                     }
+                    XsdDatatypeError::DatatypeNotAllowed { span, name } => {
+                        let label = codemap_diagnostic::SpanLabel {
+                            span: *span,
+                            style: codemap_diagnostic::SpanStyle::Primary,
+                            label: Some("not allowed by the datatype policy".to_string()),
+                        };
+                        codemap_diagnostic::Diagnostic {
+                            level: codemap_diagnostic::Level::Error,
+                            message: format!("The datatype {name:?} may not be used"),
+                            code: None,
+                            spans: vec![label],
+                        }
+                    }
+                    XsdDatatypeError::PatternNotAllowed { span } => {
+                        let label = codemap_diagnostic::SpanLabel {
+                            span: *span,
+                            style: codemap_diagnostic::SpanStyle::Primary,
+                            label: Some("not allowed by the datatype policy".to_string()),
+                        };
+                        codemap_diagnostic::Diagnostic {
+                            level: codemap_diagnostic::Level::Error,
+                            message: "Pattern facets may not be used".to_string(),
+                            code: None,
+                            spans: vec![label],
+                        }
+                    }
                     XsdDatatypeError::UnsupportedDatatype { span, name } => {
                         let label = codemap_diagnostic::SpanLabel {
                             span: *span,
@@ -1178,6 +1746,19 @@ impl<FS: Files> Compiler<FS> {
                     spans: vec![label],
                 }
             }
+            RelaxError::RemoteHref { span, href } => {
+                let label = codemap_diagnostic::SpanLabel {
+                    span: *span,
+                    style: codemap_diagnostic::SpanStyle::Primary,
+                    label: Some("only local file paths can be given here".to_string()),
+                };
+                codemap_diagnostic::Diagnostic {
+                    level: codemap_diagnostic::Level::Error,
+                    message: format!("Schemas are not fetched from URLs such as {href:?}"),
+                    code: None,
+                    spans: vec![label],
+                }
+            }
             RelaxError::IncludedFileMustBeGrammar { span } => {
                 let label = codemap_diagnostic::SpanLabel {
                     span: *span,
@@ -1249,6 +1830,25 @@ impl<FS: Files> Compiler<FS> {
                     spans: vec![label],
                 }
             }
+            RelaxError::UngroupableContent {
+                span,
+                operator,
+                members: [a, b],
+            } => {
+                let label = codemap_diagnostic::SpanLabel {
+                    span: *span,
+                    style: codemap_diagnostic::SpanStyle::Primary,
+                    label: Some(format!(
+                        "only elements and text may be combined with '{operator}' (section 7.2)"
+                    )),
+                };
+                codemap_diagnostic::Diagnostic {
+                    level: codemap_diagnostic::Level::Error,
+                    message: format!("Cannot {operator} {a} with {b}"),
+                    code: None,
+                    spans: vec![label],
+                }
+            }
             RelaxError::XmlnsAttributeForbidden => codemap_diagnostic::Diagnostic {
                 level: codemap_diagnostic::Level::Error,
                 message: "Attribute named 'xmlns' is forbidden (section 7.1.1)".to_string(),
@@ -1305,26 +1905,68 @@ impl<FS: Files> Compiler<FS> {
                     spans: vec![label],
                 }
             }
+            RelaxError::NestingTooDeep { span, limit } => {
+                let label = codemap_diagnostic::SpanLabel {
+                    span: *span,
+                    style: codemap_diagnostic::SpanStyle::Primary,
+                    label: Some("checking the patterns reachable from here".to_string()),
+                };
+                codemap_diagnostic::Diagnostic {
+                    level: codemap_diagnostic::Level::Error,
+                    message: format!(
+                        "Patterns are nested more than {limit} deep, counting references"
+                    ),
+                    code: None,
+                    spans: vec![label],
+                }
+            }
             _ => panic!("{err:?}"),
         }
     }
 
     #[inline(never)]
     fn get_schema(&mut self, name: &Path) -> Result<(Arc<codemap::File>, Rc<Schema>), RelaxError> {
-        if let Some((f, s)) = self.loaded.get(name) {
+        let canonical = self.fs.canonical(name);
+        if let Some((_, f, s)) = self.loaded.get(&canonical) {
             return Ok((f.clone(), s.clone()));
         }
-        let data = self.fs.load(name)?;
+        let mut data = self.fs.load(name)?;
+        // a UTF-8 byte order mark is not part of either schema syntax
+        if data.starts_with('\u{feff}') {
+            data.drain(..'\u{feff}'.len_utf8());
+        }
         let file = self
             .codemap
             .add_file(name.to_string_lossy().to_string(), data);
         let schema = self.syntax.parse(&file)?;
         let schema = Rc::new(schema);
-        self.loaded
-            .insert(name.to_path_buf(), (file.clone(), schema.clone()));
+        self.loaded.insert(
+            canonical,
+            (name.to_path_buf(), file.clone(), schema.clone()),
+        );
         Ok((file, schema))
     }
 
+    /// The path of the schema that the given href within `file` refers to
+    fn resolve_href(
+        &self,
+        file: &codemap::File,
+        href: &str,
+        span: codemap::Span,
+    ) -> Result<PathBuf, RelaxError> {
+        if is_url(href) {
+            return Err(RelaxError::RemoteHref {
+                span,
+                href: href.to_string(),
+            });
+        }
+        let dir = match &self.base_uri {
+            Some(base) if self.roots.contains(file.name()) => base.as_path(),
+            _ => Path::new(file.name()).parent().expect("TODO: no parent?"),
+        };
+        Ok(dir.join(href))
+    }
+
     fn compile_schema(&mut self, ctx: &mut Context, schema: Rc<Schema>) -> Result<(), RelaxError> {
         for dec in schema.decls.iter() {
             self.compile_declaration(ctx, dec)?;
@@ -1421,24 +2063,28 @@ impl<FS: Files> Compiler<FS> {
     }
     fn compile_include_div(
         &mut self,
-        _ctx: &mut Context,
-        _div: &[types::IncludeContent],
+        ctx: &mut Context,
+        div: &[types::IncludeContent],
     ) -> Result<(), RelaxError> {
-        unimplemented!()
+        for item in div {
+            match item {
+                types::IncludeContent::Define(d) => self.compile_define(ctx, d)?,
+                types::IncludeContent::Div(d) => self.compile_include_div(ctx, d)?,
+                types::IncludeContent::Annotation(_) => (),
+            }
+        }
+        Ok(())
     }
     fn compile_include(
         &mut self,
         ctx: &mut Context,
         inc: &types::Include,
     ) -> Result<(), RelaxError> {
-        let path = Path::new(ctx.file().name())
-            .parent()
-            .expect("TODO: no parent?")
-            .join(inc.0.as_string_value());
         let span = ctx
             .file()
             .span
             .subspan((inc.0).0.start as u64, (inc.0).0.end as u64);
+        let path = self.resolve_href(&ctx.file(), &inc.0.as_string_value(), span)?;
         let (file, s) = self
             .get_schema(&path)
             .map_err(|e| RelaxError::IncludeError(span, Box::new(e)))?;
@@ -1479,18 +2125,8 @@ impl<FS: Files> Compiler<FS> {
         // replace any definitions for which the including file provides an override
         if let Some(ref overrides) = inc.2 {
             let mut override_ctx = inc_ctx.new_inc_overrides();
-            for o in overrides.iter() {
-                match o {
-                    types::IncludeContent::Define(d) => {
-                        self.compile_define(&mut override_ctx, d)
-                            .map_err(|e| RelaxError::IncludeError(span, Box::new(e)))?
-                    }
-                    types::IncludeContent::Div(d) => self
-                        .compile_include_div(&mut override_ctx, &d[..])
-                        .map_err(|e| RelaxError::IncludeError(span, Box::new(e)))?,
-                    types::IncludeContent::Annotation(_) => (),
-                }
-            }
+            self.compile_include_div(&mut override_ctx, overrides)
+                .map_err(|e| RelaxError::IncludeError(span, Box::new(e)))?;
         }
 
         self.compile_schema(&mut inc_ctx, s)
@@ -1757,11 +2393,8 @@ impl<FS: Files> Compiler<FS> {
         if external.1.is_some() {
             unimplemented!("inherit");
         }
-        let path = Path::new(ctx.file().name())
-            .parent()
-            .expect("TODO: no parent?")
-            .join(external.0.as_string_value());
         let span = ctx.convert_span(&(external.0).0);
+        let path = self.resolve_href(&ctx.file(), &external.0.as_string_value(), span)?;
         let (file, s) = self
             .get_schema(&path)
             .map_err(|e| RelaxError::IncludeError(span, Box::new(e)))?;
@@ -1793,6 +2426,7 @@ impl<FS: Files> Compiler<FS> {
                         });
                     }
                 }
+                self.note_definitions(&child_ctx);
                 if let Some(r) = child_ctx.get_ref("start") {
                     r.borrow_mut()
                         .take()
@@ -1817,6 +2451,7 @@ impl<FS: Files> Compiler<FS> {
 
         let content = &grammar.content[..];
         self.compile_grammar_contents(&mut child_ctx, content)?;
+        self.note_definitions(&child_ctx);
         if let Some(r) = child_ctx.get_ref("start") {
             r.borrow_mut()
                 .take()
@@ -1955,14 +2590,18 @@ impl<FS: Files> Compiler<FS> {
                 NcName(localname.0.clone(), localname.1.clone()),
             )),
         };
+        // the compact syntax leaves the bindings to the schema's namespace declarations, while the
+        // XML syntax always has at least the predeclared 'xml' prefix in scope
+        let schema_bindings;
+        let bindings = if datatype_value.2.is_empty() {
+            schema_bindings = ctx.namespace_bindings();
+            &schema_bindings
+        } else {
+            &datatype_value.2
+        };
         let datatype = self
             .datatype_compiler
-            .datatype_value(
-                ctx,
-                &name,
-                &datatype_value.1.as_string_value(),
-                &datatype_value.2,
-            )
+            .datatype_value(ctx, &name, &datatype_value.1.as_string_value(), bindings)
             .map_err(RelaxError::DatatypeError)?;
         Ok(Pattern::DatatypeValue { datatype })
     }
@@ -1997,7 +2636,7 @@ impl<FS: Files> Compiler<FS> {
         };
         let datatype = self
             .datatype_compiler
-            .datatype_name(ctx, &name, params)
+            .datatype_name(ctx, &name, params, &mut self.warnings)
             .map_err(RelaxError::DatatypeError)?;
         Ok(model::Pattern::DatatypeName {
             datatype,
@@ -2022,6 +2661,10 @@ impl<FS: Files> Compiler<FS> {
                     }
                     NamespaceUriLiteral::Uri(uri) => {
                         let ns_uri = uri.as_string_value();
+                        self.note_namespace(None, &ns_uri);
+                        if let Some(p) = prefix {
+                            self.note_namespace(Some(p), &ns_uri);
+                        }
                         ctx.set_default_namespace(ns_uri.clone());
                         if let Some(p) = prefix {
                             ctx.declare_namespace(p.clone(), ns_uri)
@@ -2036,6 +2679,7 @@ impl<FS: Files> Compiler<FS> {
                     panic!("Can't inherit namespace {prefix:?} at top level, I think?")
                 }
                 NamespaceUriLiteral::Uri(uri) => {
+                    self.note_namespace(Some(prefix), &uri.as_string_value());
                     ctx.declare_namespace(prefix.clone(), uri.as_string_value())
                 }
             },
@@ -2126,30 +2770,117 @@ enum ElemAttr {
     Attribute,
 }
 
+/// Collects the namespace URIs of the element and attribute names in a pattern
+struct NamespaceUris(Vec<String>);
+
+impl NamespaceUris {
+    fn add(&mut self, name_class: &model::NameClass) {
+        match name_class {
+            model::NameClass::Named { namespace_uri, .. } => self.add_uri(namespace_uri),
+            model::NameClass::NsName {
+                namespace_uri,
+                except,
+            } => {
+                self.add_uri(namespace_uri);
+                if let Some(except) = except {
+                    self.add(except);
+                }
+            }
+            model::NameClass::AnyName { except } => {
+                if let Some(except) = except {
+                    self.add(except);
+                }
+            }
+            model::NameClass::Alt { a, b } => {
+                self.add(a);
+                self.add(b);
+            }
+        }
+    }
+
+    fn add_uri(&mut self, uri: &str) {
+        if !self.0.iter().any(|u| u == uri) {
+            self.0.push(uri.to_string());
+        }
+    }
+}
+
+impl visit::Visitor for NamespaceUris {
+    fn visit_element(
+        &mut self,
+        walker: &mut visit::Walker,
+        name_class: &model::NameClass,
+        content: &Pattern,
+    ) {
+        self.add(name_class);
+        walker.walk(self, content)
+    }
+
+    fn visit_attribute(
+        &mut self,
+        walker: &mut visit::Walker,
+        name_class: &model::NameClass,
+        content: &Pattern,
+    ) {
+        self.add(name_class);
+        walker.walk(self, content)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use assert_matches::*;
     // TODO: maybe take tests from https://github.com/relaxng/jing-trang/blob/master/mod/rng-validate/test/spectest.xml
 
-    #[test]
-    fn refs() {
-        struct FS;
-        impl Files for FS {
-            fn load(&self, name: &Path) -> Result<String, RelaxError> {
-                let t = match name.to_str().unwrap() {
-                    "test.rnc" => {
-                        "
-                        start = foo
-                        foo = element x { foo | empty }
-                    "
-                    }
-                    other => panic!("No {other:?}"),
-                };
-                Ok(t.to_string())
+    /// Schema files held in memory for the compiler to load.  Loading a file that is not among
+    /// them fails with `NotFound`, as it would on disk.
+    #[derive(Clone, Default)]
+    struct TestFiles {
+        files: Vec<(String, String)>,
+        /// the name of each file loaded, in order
+        reads: Rc<RefCell<Vec<PathBuf>>>,
+    }
+    impl TestFiles {
+        /// The given files, each a name and its text
+        fn new(files: &[(&str, &str)]) -> TestFiles {
+            TestFiles {
+                files: files
+                    .iter()
+                    .map(|(name, text)| (name.to_string(), text.to_string()))
+                    .collect(),
+                reads: Rc::default(),
             }
         }
-        let mut c = Compiler::new(FS, Syntax::Compact);
+
+        /// A single compact-syntax schema, `main.rnc`
+        fn main(schema: &str) -> TestFiles {
+            TestFiles::new(&[("main.rnc", schema)])
+        }
+    }
+    impl Files for TestFiles {
+        fn load(&self, name: &Path) -> Result<String, RelaxError> {
+            self.reads.borrow_mut().push(name.to_path_buf());
+            match self.files.iter().find(|(file, _)| Path::new(file) == name) {
+                Some((_, text)) => Ok(text.clone()),
+                None => Err(RelaxError::Io(
+                    name.to_path_buf(),
+                    io::Error::from(io::ErrorKind::NotFound),
+                )),
+            }
+        }
+    }
+
+    #[test]
+    fn refs() {
+        let files = TestFiles::new(&[(
+            "test.rnc",
+            "
+                        start = foo
+                        foo = element x { foo | empty }
+                    ",
+        )]);
+        let mut c = Compiler::new(files, Syntax::Compact);
         let input = Path::new("test.rnc");
         let schema = match c.compile(input) {
             Err(e) => {
@@ -2177,23 +2908,14 @@ mod tests {
 
     #[test]
     fn include_self_ref() {
-        struct FS;
-        impl Files for FS {
-            fn load(&self, name: &Path) -> Result<String, RelaxError> {
-                let t = match name.to_str().unwrap() {
-                    "main.rnc" => "start = grammar { start = blocks  include 'elements.rnc' }",
-                    "elements.rnc" => "blocks = element container { blocks? }",
-                    _ => {
-                        return Err(RelaxError::Io(
-                            name.to_path_buf(),
-                            io::Error::from(io::ErrorKind::NotFound),
-                        ));
-                    }
-                };
-                Ok(t.to_string())
-            }
-        }
-        let mut c = Compiler::new(FS, Syntax::Compact);
+        let files = TestFiles::new(&[
+            (
+                "main.rnc",
+                "start = grammar { start = blocks  include 'elements.rnc' }",
+            ),
+            ("elements.rnc", "blocks = element container { blocks? }"),
+        ]);
+        let mut c = Compiler::new(files, Syntax::Compact);
         let input = Path::new("main.rnc");
         let schema = match c.compile(input) {
             Ok(s) => s,
@@ -2216,4 +2938,1203 @@ mod tests {
             })
         })
     }
+
+    #[test]
+    fn no_warning_for_floating_point_bounds() {
+        let files = TestFiles::new(&[(
+            "main.rnc",
+            "start = element a { xsd:double { minInclusive = \"0\" } }",
+        )]);
+        let mut c = Compiler::new(files, Syntax::Compact);
+        assert!(c.warnings().is_empty());
+        if let Err(e) = c.compile(Path::new("main.rnc")) {
+            c.dump_diagnostic(&e);
+            panic!("{e:?}");
+        }
+        // the bounds of double and float used to be ignored, with a warning; they are now
+        // enforced
+        assert_matches!(c.warnings(), []);
+    }
+
+    #[test]
+    fn warning_for_unsatisfiable_attribute() {
+        let files = TestFiles::new(&[(
+            "main.rnc",
+            "namespace xmlns = \"http://www.w3.org/2000/xmlns/\"\n\
+                         start = element a { decls, attribute id { text } }\n\
+                         decls = attribute xmlns:* { text }+",
+        )]);
+        let mut c = Compiler::new(files, Syntax::Compact);
+        if let Err(e) = c.compile(Path::new("main.rnc")) {
+            c.dump_diagnostic(&e);
+            panic!("{e:?}");
+        }
+        assert_matches!(c.warnings(), [RelaxWarning::UnsatisfiableAttribute { .. }]);
+    }
+
+    #[test]
+    fn warning_for_ambiguous_choice() {
+        let warnings = |schema, warn| {
+            let mut c = Compiler::new(TestFiles::main(schema), Syntax::Compact);
+            c.set_warn_ambiguous_choices(warn);
+            if let Err(e) = c.compile(Path::new("main.rnc")) {
+                c.dump_diagnostic(&e);
+                panic!("{e:?}");
+            }
+            c.warnings
+        };
+
+        let schema = "start = element a { empty } | element a { text }";
+        assert_matches!(
+            &warnings(schema, true)[..],
+            [RelaxWarning::AmbiguousChoice { names, .. }] if names == &["a", "a"]
+        );
+        assert_matches!(&warnings(schema, false)[..], []);
+        assert_matches!(
+            &warnings(
+                "namespace x = 'urn:x'
+                 start = element r { item* }
+                 item = element x:a { empty } | other
+                 other = element b { empty } | element x:* { text }",
+                true
+            )[..],
+            [RelaxWarning::AmbiguousChoice { names, .. }] if names == &["{urn:x}a", "{urn:x}*"]
+        );
+        assert_matches!(
+            &warnings("start = element a { empty } | element b { empty }", true)[..],
+            []
+        );
+    }
+
+    #[test]
+    fn warning_for_large_interleave() {
+        let warnings = |n: usize, limit: Option<usize>| {
+            let members: Vec<_> = (0..n)
+                .map(|i| format!("element e{i} {{ empty }}?"))
+                .collect();
+            let schema = format!("start = element r {{ {} }}", members.join(" & "));
+            let mut c = Compiler::new(TestFiles::main(&schema), Syntax::Compact);
+            if let Some(limit) = limit {
+                c.set_max_interleave_members(limit);
+            }
+            if let Err(e) = c.compile(Path::new("main.rnc")) {
+                c.dump_diagnostic(&e);
+                panic!("{e:?}");
+            }
+            c.warnings
+        };
+
+        assert_matches!(
+            &warnings(100, None)[..],
+            [RelaxWarning::LargeInterleave {
+                members: 100,
+                limit: 64,
+                ..
+            }]
+        );
+        assert_matches!(&warnings(64, None)[..], []);
+        assert_matches!(
+            &warnings(10, Some(5))[..],
+            [RelaxWarning::LargeInterleave { members: 10, .. }]
+        );
+        assert_matches!(&warnings(100, Some(usize::MAX))[..], []);
+    }
+
+    #[test]
+    fn warning_for_unused_definition() {
+        let unused = |schema, warn| {
+            let mut c = Compiler::new(TestFiles::main(schema), Syntax::Compact);
+            c.set_warn_unused_definitions(warn);
+            if let Err(e) = c.compile(Path::new("main.rnc")) {
+                c.dump_diagnostic(&e);
+                panic!("{e:?}");
+            }
+            c.warnings
+                .iter()
+                .map(|w| match w {
+                    RelaxWarning::UnusedDefinition { name, .. } => name.clone(),
+                    other => panic!("{other:?}"),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let schema = "start = element a { empty }  unused = element b { empty }";
+        assert_eq!(unused(schema, true), ["unused"]);
+        assert_eq!(unused(schema, false), [""; 0]);
+        // a definition used only by an unused one is unused too, while one used through a
+        // chain of references, or from a nested grammar, is not
+        assert_eq!(
+            unused(
+                "start = element a { b, grammar { start = parent c  d = empty } }
+                 b = c
+                 c = empty
+                 e = f
+                 f = element f { empty }",
+                true
+            ),
+            ["d", "e", "f"]
+        );
+    }
+
+    #[test]
+    fn deterministic_content() {
+        let ambiguous = |content| {
+            let schema = format!("start = element root {{ {content} }}\nr = element a {{ empty }}");
+            let start = Compiler::new(TestFiles::main(&schema), Syntax::Compact)
+                .compile(Path::new("main.rnc"))
+                .unwrap();
+            let start = start.borrow();
+            let Pattern::Element(_, content) = start.as_ref().unwrap().pattern() else {
+                panic!("{schema}");
+            };
+            assert_eq!(
+                restrictions::is_deterministic(content),
+                restrictions::ambiguous_particles(content).is_none()
+            );
+            restrictions::ambiguous_particles(content)
+        };
+        assert_eq!(
+            ambiguous("(element a { empty }, element a { empty })?, element a { empty }"),
+            Some(["a".to_string(), "a".to_string()])
+        );
+        for content in [
+            "element a { empty }*, element a { empty }",
+            "(element a { empty } | element b { empty })+, element b { empty }",
+            "element a { empty }?, r",
+            "element a { empty } | element * { empty }",
+            "element b { empty }, element b { empty }?, element c { empty }?, element b { empty }",
+            "(element a { empty }?, element a { empty }) & element b { empty }",
+        ] {
+            assert_matches!(ambiguous(content), Some(_), "{content}");
+        }
+        for content in [
+            "empty",
+            "element a { empty }, element a { empty }",
+            "element a { empty }, element b { empty }?, element a { empty }",
+            "element a { empty }*, element b { empty }",
+            "element a { empty } & element b { empty }* & text",
+            "(element a { empty }, element b { empty })+",
+            "r, element b { empty }",
+            "element a { element a { empty }?, element a { empty } }",
+        ] {
+            assert_matches!(ambiguous(content), None, "{content}");
+        }
+    }
+
+    #[test]
+    fn start_through_ref() {
+        let compile = |schema| {
+            Compiler::new(TestFiles::main(schema), Syntax::Compact).compile(Path::new("main.rnc"))
+        };
+        for (schema, expected) in [
+            ("start = foo\nfoo = text", "text"),
+            ("start = foo\nfoo = attribute a { text }", "attribute"),
+            ("start = foo\nfoo = xsd:int", "data"),
+            ("start = foo\nfoo = list { xsd:int }", "list"),
+            // through more than one ref, and a choice
+            (
+                "start = foo\nfoo = bar | element e { empty }\nbar = text",
+                "text",
+            ),
+        ] {
+            assert_matches!(
+                compile(schema),
+                Err(RelaxError::RestrictedPattern { pattern_name, context, .. })
+                    if pattern_name == expected && context == "start",
+                "{schema}"
+            );
+        }
+        for schema in [
+            "start = foo\nfoo = element e { empty }",
+            "start = foo\nfoo = bar | element e { empty }\nbar = element f { text }",
+        ] {
+            assert_matches!(compile(schema), Ok(_), "{schema}");
+        }
+    }
+
+    #[test]
+    fn parent_ref() {
+        let compile = |schema| {
+            Compiler::new(TestFiles::main(schema), Syntax::Compact).compile(Path::new("main.rnc"))
+        };
+        assert_matches!(
+            compile("start = element a { grammar { start = parent inner } }\ninner = text"),
+            Ok(_)
+        );
+        // there is no grammar enclosing the outermost one
+        assert_matches!(
+            compile("start = element a { parent inner }\ninner = text"),
+            Err(RelaxError::NoParentAvailable(_))
+        );
+        // the name is looked up in the parent grammar only, not the nested one
+        assert_matches!(
+            compile("start = element a { grammar { start = parent inner\ninner = text } }"),
+            Err(RelaxError::UndefinedReference { identifier, .. }) if identifier == "inner"
+        );
+    }
+
+    #[test]
+    fn text_in_both_sides_of_mixed() {
+        let compile = |schema| {
+            Compiler::new(TestFiles::main(schema), Syntax::Compact).compile(Path::new("main.rnc"))
+        };
+        for schema in [
+            "start = element a { mixed { text } }",
+            "start = element a { mixed { text* | element b { empty } } }",
+            "start = element a { mixed { mixed { element b { empty } } } }",
+            "start = element a { mixed { r } }\nr = mixed { empty }",
+            "start = element a { mixed { element b { empty }, (text & element c { empty }) } }",
+        ] {
+            assert_matches!(
+                compile(schema),
+                Err(RelaxError::RestrictedPattern { pattern_name, .. }) if pattern_name == "text",
+                "{schema}"
+            );
+        }
+        for schema in [
+            "start = element a { mixed { element b { text } } }",
+            "start = element a { mixed { empty } }",
+            "start = element a { mixed { element b { mixed { empty } }* } }",
+        ] {
+            assert_matches!(compile(schema), Ok(_), "{schema}");
+        }
+    }
+
+    #[test]
+    fn nearest_datatype_library() {
+        let files = TestFiles::new(&[(
+            "main.rng",
+            r#"<grammar xmlns="http://relaxng.org/ns/structure/1.0"
+                        datatypeLibrary="http://www.w3.org/2001/XMLSchema-datatypes">
+                      <start>
+                        <element name="a">
+                          <attribute name="inherited"><data type="token"/></attribute>
+                          <attribute name="own"><data type="token" datatypeLibrary=""/></attribute>
+                          <attribute name="value"><value datatypeLibrary="">x</value></attribute>
+                          <element name="b" datatypeLibrary="">
+                            <attribute name="parent"><data type="token"/></attribute>
+                            <attribute name="restored">
+                              <data type="token"
+                                datatypeLibrary="http://www.w3.org/2001/XMLSchema-datatypes"/>
+                            </attribute>
+                          </element>
+                        </element>
+                      </start>
+                    </grammar>"#,
+        )]);
+        /// The library of each datatype, in order, with `true` for XML Schema
+        struct Libraries(Vec<bool>);
+        impl visit::Visitor for Libraries {
+            fn visit_data(
+                &mut self,
+                _walker: &mut visit::Walker,
+                datatype: &datatype::Datatypes,
+                _except: Option<&Pattern>,
+            ) {
+                self.0.push(matches!(datatype, datatype::Datatypes::Xsd(_)));
+            }
+
+            fn visit_value(&mut self, datatype: &datatype::DatatypeValues) {
+                self.0
+                    .push(matches!(datatype, datatype::DatatypeValues::Xsd(_)));
+            }
+        }
+        let schema = Compiler::new(files, Syntax::Xml)
+            .compile(Path::new("main.rng"))
+            .unwrap();
+        let mut libraries = Libraries(vec![]);
+        visit::walk_pattern(&mut libraries, schema.borrow().as_ref().unwrap().pattern());
+        assert_eq!(libraries.0, [true, false, false, false, true]);
+    }
+
+    #[test]
+    fn visitor() {
+        let files = TestFiles::new(&[(
+            "main.rnc",
+            "start = element doc { item* }\n\
+                         item = element item { attribute id { text }, (item | element leaf { empty })* }",
+        )]);
+        struct CountElements(usize);
+        impl visit::Visitor for CountElements {
+            fn visit_element(
+                &mut self,
+                walker: &mut visit::Walker,
+                _name_class: &model::NameClass,
+                content: &Pattern,
+            ) {
+                self.0 += 1;
+                walker.walk(self, content)
+            }
+        }
+        let mut c = Compiler::new(files, Syntax::Compact);
+        let schema = match c.compile(Path::new("main.rnc")) {
+            Ok(s) => s,
+            Err(e) => {
+                c.dump_diagnostic(&e);
+                panic!("{e:?}");
+            }
+        };
+        let mut count = CountElements(0);
+        visit::walk_pattern(&mut count, schema.borrow().as_ref().unwrap().pattern());
+        // the recursive reference to 'item' is only followed once
+        assert_eq!(count.0, 3);
+    }
+
+    #[test]
+    fn element_content_model() {
+        let files = TestFiles::new(&[(
+            "atom.rnc",
+            "default namespace atom = 'http://www.w3.org/2005/Atom'\n\
+                         start = atomFeed\n\
+                         atomCommonAttributes = attribute xml:lang { xsd:language }?\n\
+                         atomText = atomCommonAttributes, attribute type { 'text' | 'html' }?, text\n\
+                         atomFeed = element feed {\n\
+                             atomCommonAttributes,\n\
+                             (atomTitle & atomId & atomUpdated & atomLink*),\n\
+                             atomEntry*\n\
+                         }\n\
+                         atomTitle = element title { atomText }\n\
+                         atomId = element id { xsd:anyURI }\n\
+                         atomUpdated = element updated { xsd:dateTime }\n\
+                         atomLink = element link { attribute href { text }, empty }\n\
+                         atomEntry = element entry { atomTitle & atomId & atomUpdated }",
+        )]);
+        let mut c = Compiler::new(files, Syntax::Compact);
+        let schema = match c.compile(Path::new("atom.rnc")) {
+            Ok(s) => s,
+            Err(e) => {
+                c.dump_diagnostic(&e);
+                panic!("{e:?}");
+            }
+        };
+        let schema = schema.borrow();
+        let start = schema.as_ref().unwrap();
+        let model = content_model::element_content_model(start, "feed", 1).unwrap();
+        assert_eq!(
+            model.to_string(),
+            "@lang?, (title & id & updated & link*), entry*"
+        );
+        for name in ["title", "id", "updated", "entry"] {
+            assert!(model.to_string().contains(name), "{name} missing");
+        }
+        // unexpanded, the model is in terms of the definitions the content refers to
+        let model = content_model::element_content_model(start, "feed", 0).unwrap();
+        assert_eq!(
+            model.to_string(),
+            "atomCommonAttributes, (atomTitle & atomId & atomUpdated & atomLink*), atomEntry*"
+        );
+        let title = "{http://www.w3.org/2005/Atom}title";
+        let model = content_model::element_content_model(start, title, 2).unwrap();
+        assert_eq!(model.to_string(), "@lang?, @type?, text");
+        let model = content_model::element_content_model(start, "updated", 2).unwrap();
+        assert_eq!(
+            model,
+            content_model::ContentModel::Data("xsd:dateTime".to_string())
+        );
+        assert!(content_model::element_content_model(start, "author", 2).is_none());
+    }
+
+    #[test]
+    fn bare_string_value_is_token() {
+        let files = TestFiles::new(&[("main.rnc", "start = element e { \"a\" | string \" b \" }")]);
+        let mut c = Compiler::new(files, Syntax::Compact);
+        let schema = match c.compile(Path::new("main.rnc")) {
+            Ok(s) => s,
+            Err(e) => {
+                c.dump_diagnostic(&e);
+                panic!("{e:?}");
+            }
+        };
+        let s = schema.borrow();
+        assert_matches!(s.as_ref().unwrap().pattern(), Pattern::Element(_, content) => {
+            assert_matches!(&**content, Pattern::Choice(alts) => {
+                assert_matches!(
+                    &alts[..],
+                    [
+                        Pattern::DatatypeValue {
+                            datatype: datatype::DatatypeValues::Relax(
+                                datatype::relax::BuiltinDatatypeValue::TokenValue(a)
+                            )
+                        },
+                        Pattern::DatatypeValue {
+                            datatype: datatype::DatatypeValues::Relax(
+                                datatype::relax::BuiltinDatatypeValue::StringValue(b)
+                            )
+                        },
+                    ] if a == "a" && b == " b "
+                )
+            })
+        })
+    }
+
+    #[test]
+    fn byte_order_mark_and_unicode_names() {
+        let files = TestFiles::new(&[
+            ("bom.rnc", "\u{feff}start = element e { empty }"),
+            (
+                "bom.rng",
+                "\u{feff}<element name=\"e\" xmlns=\"http://relaxng.org/ns/structure/1.0\"><empty/></element>",
+            ),
+            (
+                "names.rnc",
+                "start = élément\nélément = element e { empty }",
+            ),
+        ]);
+        fn assert_element_e(p: &Pattern) {
+            assert_matches!(p, Pattern::Element(model::NameClass::Named { name, .. }, _) if name == "e");
+        }
+        for (name, syntax) in [
+            ("bom.rnc", Syntax::Compact),
+            ("bom.rng", Syntax::Xml),
+            ("names.rnc", Syntax::Compact),
+        ] {
+            let mut c = Compiler::new(files.clone(), syntax);
+            let schema = match c.compile(Path::new(name)) {
+                Ok(s) => s,
+                Err(e) => {
+                    c.dump_diagnostic(&e);
+                    panic!("{name}: {e:?}");
+                }
+            };
+            let s = schema.borrow();
+            match s.as_ref().unwrap().pattern() {
+                Pattern::Ref(_, ref_name, model::PatRef(r)) => {
+                    assert_eq!(ref_name, "élément");
+                    assert_element_e(r.borrow().as_ref().unwrap().pattern());
+                }
+                p => assert_element_e(p),
+            }
+        }
+    }
+
+    #[test]
+    fn compile_all() {
+        let files = TestFiles::new(&[
+            ("good.rnc", "start = element a { empty }"),
+            ("broken.rnc", "start = element a { missing }"),
+        ]);
+        let mut c = Compiler::new(files, Syntax::Compact);
+        let results = c.compile_all(&["broken.rnc", "good.rnc"]);
+        assert_matches!(
+            &results[..],
+            [Err(RelaxError::UndefinedReference { .. }), Ok(_)]
+        );
+    }
+
+    #[test]
+    fn compile_types() {
+        // element a { empty }
+        let schema = Schema {
+            decls: vec![],
+            pattern_or_grammar: types::PatternOrGrammar::Pattern(types::Pattern::Element(
+                types::ElementPattern {
+                    span: 0..0,
+                    name_class: types::NameClass::Name(types::Name::Identifier(
+                        types::IdentifierOrKeyword::Identifier(types::Identifier(
+                            0..0,
+                            "a".to_string(),
+                        )),
+                    )),
+                    pattern: Box::new(types::Pattern::Empty),
+                },
+            )),
+        };
+        let mut c = Compiler::new(TestFiles::default(), Syntax::Compact);
+        let model = match c.compile_types(schema) {
+            Ok(m) => m,
+            Err(e) => {
+                c.dump_diagnostic(&e);
+                panic!("{e:?}");
+            }
+        };
+        let m = model.borrow();
+        assert_matches!(m.as_ref().unwrap().pattern(), Pattern::Element(model::NameClass::Named { name, .. }, p) => {
+            assert_eq!(name, "a");
+            assert_matches!(**p, Pattern::Empty);
+        });
+    }
+
+    #[test]
+    fn conflicting_facets() {
+        let conflicts = |schema| {
+            let mut c = Compiler::new(TestFiles::main(schema), Syntax::Compact);
+            match c.compile(Path::new("main.rnc")) {
+                Err(RelaxError::DatatypeError(datatype::Errors::Xsd(
+                    XsdDatatypeError::Facet {
+                        facet: FacetError::ConflictingFacets(conflicts),
+                        ..
+                    },
+                ))) => conflicts
+                    .iter()
+                    .map(|c| (c.facets[0].0, c.facets[1].0, c.reason.clone()))
+                    .collect::<Vec<_>>(),
+                other => panic!("{schema}: {other:?}"),
+            }
+        };
+
+        assert_eq!(
+            conflicts(
+                "start = element a { xsd:int { minInclusive = \"10\" maxInclusive = \"5\" } }"
+            ),
+            vec![(
+                "minInclusive",
+                "maxInclusive",
+                "minInclusive is greater than maxInclusive".to_string()
+            )]
+        );
+        // all conflicts are reported, whatever order the facets are given in
+        assert_eq!(
+            conflicts(
+                "start = element a { xsd:decimal { maxExclusive = \"1\" minExclusive = \"1\" minInclusive = \"2\" } }"
+            )
+            .len(),
+            3
+        );
+        assert_eq!(
+            conflicts("start = element a { xsd:string { maxLength = \"2\" length = \"3\" minLength = \"4\" } }")
+                .into_iter()
+                .map(|(a, b, _)| (a, b))
+                .collect::<Vec<_>>(),
+            vec![
+                ("length", "minLength"),
+                ("length", "maxLength"),
+                ("minLength", "maxLength")
+            ]
+        );
+        // a repeated facet is reported, rather than the later value silently replacing the
+        // earlier one
+        assert_eq!(
+            conflicts(
+                "start = element a { xsd:int { minInclusive = \"10\" minInclusive = \"1\" } }"
+            ),
+            vec![(
+                "minInclusive",
+                "minInclusive",
+                "minInclusive is given more than once".to_string()
+            )]
+        );
+        assert_eq!(
+            conflicts("start = element a { xsd:token { maxLength = \"2\" maxLength = \"2\" } }")
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn remote_href() {
+        let files = TestFiles::new(&[
+            ("include.rnc", "include 'http://example.com/schema.rnc'"),
+            (
+                "external.rnc",
+                "start = element a { external 'https://example.com/a.rnc' }",
+            ),
+            (
+                "include.rng",
+                "<grammar xmlns='http://relaxng.org/ns/structure/1.0'>\
+                         <include href='file:///etc/schema.rng'/></grammar>",
+            ),
+        ]);
+        for (file, syntax, url) in [
+            (
+                "include.rnc",
+                Syntax::Compact,
+                "http://example.com/schema.rnc",
+            ),
+            ("external.rnc", Syntax::Compact, "https://example.com/a.rnc"),
+            ("include.rng", Syntax::Xml, "file:///etc/schema.rng"),
+        ] {
+            let mut c = Compiler::new(files.clone(), syntax);
+            let err = c.compile(Path::new(file)).unwrap_err();
+            assert_matches!(&err, RelaxError::RemoteHref { href, .. } if href == url, "{file}");
+            assert_eq!(err.code(), "remote-href");
+        }
+        // a Windows drive letter is not mistaken for a scheme
+        assert!(!is_url("C:\\schemas\\a.rnc"));
+        assert!(!is_url("schemas/a.rnc"));
+        assert!(is_url("urn:example:a"));
+    }
+
+    #[test]
+    fn to_diagnostics() {
+        let mut c = Compiler::new(
+            TestFiles::main(
+                "start = element a { xsd:int { minInclusive = \"10\" maxInclusive = \"5\" } }",
+            ),
+            Syntax::Compact,
+        );
+        let err = c.compile(Path::new("main.rnc")).unwrap_err();
+        let (map, diagnostics) = c.to_diagnostics(&err);
+        assert_eq!(diagnostics.len(), 1);
+        let d = &diagnostics[0];
+        assert_eq!(d.code.as_deref(), Some(err.code()));
+        assert_eq!(d.message, "Conflicting facets for int type");
+        let spans: Vec<_> = d
+            .spans
+            .iter()
+            .map(|label| {
+                let file = map.find_file(label.span.low());
+                let start = (label.span.low() - file.span.low()) as usize;
+                (
+                    start..start + label.span.len() as usize,
+                    file.source_slice(label.span),
+                )
+            })
+            .collect();
+        assert_eq!(
+            spans,
+            [
+                (30..49, "minInclusive = \"10\""),
+                (50..68, "maxInclusive = \"5\"")
+            ]
+        );
+    }
+
+    #[test]
+    fn included_file_diagnostics() {
+        let files = TestFiles::new(&[
+            (
+                "main.rnc",
+                "include 'types.rnc'\nstart = element a { small }",
+            ),
+            (
+                "types.rnc",
+                "small = xsd:int { minInclusive = \"10\" maxInclusive = \"5\" }",
+            ),
+            (
+                "main.rng",
+                "<grammar xmlns='http://relaxng.org/ns/structure/1.0'>\
+                         <include href='types.rng'/>\
+                         <start><element name='a'><ref name='small'/></element></start>\
+                         </grammar>",
+            ),
+            (
+                "types.rng",
+                "<grammar xmlns='http://relaxng.org/ns/structure/1.0' \
+                         datatypeLibrary='http://www.w3.org/2001/XMLSchema-datatypes'>\
+                         <define name='small'><data type='int'>\
+                         <param name='minInclusive'>10</param>\
+                         <param name='maxInclusive'>5</param>\
+                         </data></define></grammar>",
+            ),
+        ]);
+        for (main, syntax, href) in [
+            ("main.rnc", Syntax::Compact, "'types.rnc'"),
+            ("main.rng", Syntax::Xml, "types.rng"),
+        ] {
+            let mut c = Compiler::new(files.clone(), syntax);
+            let err = c.compile(Path::new(main)).unwrap_err();
+            let (map, diagnostics) = c.to_diagnostics(&err);
+            let d = &diagnostics[0];
+            assert_eq!(d.message, "Conflicting facets for int type");
+            let spans: Vec<_> = d
+                .spans
+                .iter()
+                .map(|label| {
+                    let file = map.find_file(label.span.low());
+                    (file.name().to_string(), file.source_slice(label.span))
+                })
+                .collect();
+            // the facets are highlighted in the file which gives them, and the href in the file
+            // which includes it
+            let types = main.replace("main", "types");
+            assert_eq!(spans.len(), 3, "{main}: {spans:?}");
+            assert_eq!(spans[0].0, types, "{main}");
+            assert!(spans[0].1.contains("minInclusive"), "{main}: {spans:?}");
+            assert_eq!(spans[1].0, types, "{main}");
+            assert!(spans[1].1.contains("maxInclusive"), "{main}: {spans:?}");
+            assert_eq!(spans[2], (main.to_string(), href), "{main}");
+        }
+    }
+
+    #[test]
+    fn builtin_datatype_params() {
+        let files = |schema| {
+            TestFiles::new(&[
+                ("main.rnc", schema),
+                ("main.rng", schema),
+                (
+                    "types.rng",
+                    "<grammar xmlns='http://relaxng.org/ns/structure/1.0' datatypeLibrary='http://www.w3.org/2001/XMLSchema-datatypes'>
+                            <define name='code'><data type='token'><param name='maxLength'>3</param></data></define>
+                        </grammar>",
+                ),
+            ])
+        };
+        let compile = |schema, syntax| {
+            let main = match syntax {
+                Syntax::Compact => "main.rnc",
+                Syntax::Xml => "main.rng",
+            };
+            Compiler::new(files(schema), syntax).compile(Path::new(main))
+        };
+
+        let mut c = Compiler::new(
+            files(
+                "<element name='a' xmlns='http://relaxng.org/ns/structure/1.0' datatypeLibrary=''><data type='token'><param name='length'>3</param></data></element>",
+            ),
+            Syntax::Xml,
+        );
+        assert_matches!(
+            c.compile(Path::new("main.rng")),
+            Err(RelaxError::DatatypeError(datatype::Errors::Relax(
+                datatype::relax::Error::ParamNotAllowed { name, span }
+            ))) => {
+                assert_eq!(name, "length");
+                assert_eq!(
+                    c.codemap.find_file(span.low()).source_slice(span),
+                    "<param name='length'>3</param>"
+                );
+            }
+        );
+        assert_matches!(
+            compile(
+                "start = element a { string { length = \"3\" } }",
+                Syntax::Compact
+            ),
+            Err(RelaxError::DatatypeError(datatype::Errors::Relax(
+                datatype::relax::Error::ParamNotAllowed { .. }
+            )))
+        );
+
+        // "token" from the XML Schema library accepts facets, whether the library is given
+        // directly or inherited by way of an include
+        assert_matches!(
+            compile(
+                "<element name='a' xmlns='http://relaxng.org/ns/structure/1.0' datatypeLibrary='http://www.w3.org/2001/XMLSchema-datatypes'><data type='token'><param name='maxLength'>3</param></data></element>",
+                Syntax::Xml
+            ),
+            Ok(_)
+        );
+        assert_matches!(
+            compile(
+                "<grammar xmlns='http://relaxng.org/ns/structure/1.0'>
+                    <include href='types.rng'/>
+                    <start><element name='a'><ref name='code'/></element></start>
+                </grammar>",
+                Syntax::Xml
+            ),
+            Ok(_)
+        );
+
+        // the compatibility types take no parameters either, but for a reason of their own
+        let mut c = Compiler::new(
+            TestFiles::main(
+                "datatypes c = 'http://relaxng.org/ns/compatibility/datatypes/1.0'
+                 start = element a { attribute id { c:ID { length = \"1\" } } }",
+            ),
+            Syntax::Compact,
+        );
+        let err = c.compile(Path::new("main.rnc")).unwrap_err();
+        assert_matches!(
+            &err,
+            RelaxError::DatatypeError(datatype::Errors::CompatibilityParamNotAllowed { name, .. })
+                if name == "length"
+        );
+        let d = c.diagnostic(&err);
+        assert_eq!(
+            d.message,
+            "Parameter \"length\" not allowed: the compatibility datatypes 'ID', 'IDREF' and 'IDREFS' take no parameters"
+        );
+        assert_eq!(
+            d.spans[0].label.as_deref(),
+            Some("remove this parameter, or use the XML Schema datatype of the same name")
+        );
+    }
+
+    #[test]
+    fn invalid_facet() {
+        let label = |schema| {
+            let mut c = Compiler::new(TestFiles::main(schema), Syntax::Compact);
+            match c.compile(Path::new("main.rnc")) {
+                Err(
+                    err @ RelaxError::DatatypeError(datatype::Errors::Xsd(
+                        XsdDatatypeError::Facet {
+                            facet: FacetError::InvalidFacet { .. },
+                            ..
+                        },
+                    )),
+                ) => c.diagnostic(&err).spans[0].label.clone().unwrap(),
+                other => panic!("{schema}: {other:?}"),
+            }
+        };
+
+        assert_eq!(
+            label("start = element a { xsd:string { fractionDigits = \"2\" } }"),
+            "facet fractionDigits is not applicable to string"
+        );
+        assert_eq!(
+            label("start = element a { xsd:QName { length = \"2\" } }"),
+            "facet length is not applicable to QName"
+        );
+        assert_eq!(
+            label("start = element a { xsd:decimal { fractionDigit = \"2\" } }"),
+            "fractionDigit is not a facet of any datatype"
+        );
+        assert_eq!(
+            label("start = element a { xsd:QName { enumeration = \"foo:bar\" } }"),
+            "enumeration is not allowed as a parameter; use a choice of values, like \
+             xsd:QName \"a\" | xsd:QName \"b\""
+        );
+    }
+
+    #[test]
+    fn ungroupable_content() {
+        let message = |schema| {
+            let mut c = Compiler::new(TestFiles::main(schema), Syntax::Compact);
+            match c.compile(Path::new("main.rnc")) {
+                Err(err @ RelaxError::UngroupableContent { .. }) => c.diagnostic(&err).message,
+                other => panic!("{schema}: {other:?}"),
+            }
+        };
+
+        assert_eq!(
+            message("start = element a { text, xsd:string }"),
+            "Cannot group text with a data value"
+        );
+        assert_eq!(
+            message("start = element a { element b { empty } & \"x\" }"),
+            "Cannot interleave an element with a value"
+        );
+        // the definition through which a member's content was reached is named
+        assert_eq!(
+            message(
+                "start = element a { code, item* }
+                code = xsd:token | empty
+                item = element item { empty }"
+            ),
+            "Cannot group a data value (from 'code') with an element (from 'item')"
+        );
+        assert_eq!(
+            message("start = element a { list { xsd:int }, xsd:int }"),
+            "Cannot group a list with a data value"
+        );
+    }
+
+    #[test]
+    fn base_uri() {
+        let files = TestFiles::new(&[
+            (
+                "main.rnc",
+                "include 'common/defs.rnc' start = element a { item, external 'ext.rnc' }",
+            ),
+            ("schemas/common/defs.rnc", "include 'items.rnc'"),
+            ("schemas/common/items.rnc", "item = element item { empty }"),
+            ("schemas/ext.rnc", "element ext { empty }"),
+        ]);
+        let mut c = Compiler::new(files.clone(), Syntax::Compact);
+        assert_matches!(
+            c.compile(Path::new("main.rnc")),
+            Err(RelaxError::IncludeError(_, e)) if matches!(*e, RelaxError::Io(..))
+        );
+
+        // hrefs in main.rnc resolve against the base, and those in the included schema against
+        // its own directory
+        let mut c = Compiler::new(files, Syntax::Compact);
+        c.set_base_uri(PathBuf::from("schemas"));
+        if let Err(e) = c.compile(Path::new("main.rnc")) {
+            c.dump_diagnostic(&e);
+            panic!("{e:?}");
+        }
+        let mut loaded: Vec<_> = c.loaded().map(|p| p.to_str().unwrap()).collect();
+        loaded.sort();
+        assert_eq!(
+            loaded,
+            [
+                "main.rnc",
+                "schemas/common/defs.rnc",
+                "schemas/common/items.rnc",
+                "schemas/ext.rnc"
+            ]
+        );
+    }
+
+    #[test]
+    fn shared_include_read_once() {
+        let files = TestFiles::new(&[
+            ("a.rnc", "include 'common.rnc' start = element a { item }"),
+            (
+                "b/b.rnc",
+                "include '../common.rnc' start = element b { item }",
+            ),
+            ("common.rnc", "item = element item { xsd:int }"),
+        ]);
+        let mut c = Compiler::new(files.clone(), Syntax::Compact);
+        for schema in ["a.rnc", "b/b.rnc", "./a.rnc"] {
+            if let Err(e) = c.compile(Path::new(schema)) {
+                c.dump_diagnostic(&e);
+                panic!("{e:?}");
+            }
+        }
+        assert_eq!(
+            *files.reads.borrow(),
+            [
+                PathBuf::from("a.rnc"),
+                PathBuf::from("common.rnc"),
+                PathBuf::from("b/b.rnc")
+            ]
+        );
+        let mut loaded: Vec<_> = c.loaded().map(|p| p.to_str().unwrap()).collect();
+        loaded.sort();
+        assert_eq!(loaded, ["a.rnc", "b/b.rnc", "common.rnc"]);
+    }
+
+    #[test]
+    fn datatype_policy() {
+        use datatype::xsd::{DatatypePolicy, FacetError, PatternPolicy, XsdDatatypeError};
+        let files = TestFiles::main(
+            "start = element a { xsd:token { pattern = '[a-z]+' }, attribute n { xsd:int } }",
+        );
+        let compile = |policy| {
+            let mut c = Compiler::new(files.clone(), Syntax::Compact);
+            c.set_datatype_policy(policy);
+            let result = c.compile(Path::new("main.rnc")).map(|_| ());
+            (result, c.warnings().len())
+        };
+
+        assert_matches!(compile(DatatypePolicy::default()), (Ok(()), 0));
+        assert_matches!(
+            compile(DatatypePolicy {
+                patterns: PatternPolicy::Reject,
+                ..Default::default()
+            }),
+            (
+                Err(RelaxError::DatatypeError(datatype::Errors::Xsd(
+                    XsdDatatypeError::PatternNotAllowed { .. }
+                ))),
+                0
+            )
+        );
+        assert_matches!(
+            compile(DatatypePolicy {
+                patterns: PatternPolicy::Ignore,
+                ..Default::default()
+            }),
+            (Ok(()), 1)
+        );
+        assert_matches!(
+            compile(DatatypePolicy {
+                allowed_types: Some(vec!["token".to_string()]),
+                ..Default::default()
+            }),
+            (
+                Err(RelaxError::DatatypeError(datatype::Errors::Xsd(
+                    XsdDatatypeError::DatatypeNotAllowed { name, .. }
+                ))),
+                0
+            ) if name == "int"
+        );
+        // '[a-z]+' compiles to far more than 10 bytes
+        assert_matches!(
+            compile(DatatypePolicy {
+                pattern_size_limit: Some(10),
+                ..Default::default()
+            }),
+            (
+                Err(RelaxError::DatatypeError(datatype::Errors::Xsd(
+                    XsdDatatypeError::Facet {
+                        facet: FacetError::PatternTooLarge(_, 10),
+                        ..
+                    }
+                ))),
+                0
+            )
+        );
+    }
+
+    #[test]
+    fn check_datatype() {
+        use datatype::xsd::NAMESPACE_URI as XSD;
+        use datatype::{Namespaces, check};
+        struct Ns;
+        impl Namespaces for Ns {
+            fn resolve(&self, prefix: &str) -> Option<&str> {
+                (prefix == "ex").then_some("urn:example")
+            }
+        }
+
+        assert_matches!(check(XSD, "date", &[], "2024-01-01", &Ns), Ok(true));
+        assert_matches!(check(XSD, "date", &[], "not-a-date", &Ns), Ok(false));
+        let params = [("minInclusive", "1"), ("maxInclusive", "10")];
+        assert_matches!(check(XSD, "int", &params, "10", &Ns), Ok(true));
+        assert_matches!(check(XSD, "int", &params, "11", &Ns), Ok(false));
+        assert_matches!(check(XSD, "QName", &[], "ex:a", &Ns), Ok(true));
+        assert_matches!(check(XSD, "QName", &[], "other:a", &Ns), Ok(false));
+        assert_matches!(check("", "token", &[], " a ", &Ns), Ok(true));
+        assert_matches!(
+            check(XSD, "dat", &[], "2024-01-01", &Ns),
+            Err(datatype::Errors::Xsd(
+                datatype::xsd::XsdDatatypeError::UnsupportedDatatype { .. }
+            ))
+        );
+        assert_matches!(
+            check("urn:other", "date", &[], "2024-01-01", &Ns),
+            Err(datatype::Errors::UnsupportedDatatypeLibrary { .. })
+        );
+    }
+
+    #[test]
+    fn max_depth() {
+        let compile = |schema: String, max_depth| {
+            let mut c = Compiler::new(TestFiles::main(&schema), Syntax::Compact);
+            if let Some(max_depth) = max_depth {
+                c.set_max_depth(max_depth);
+            }
+            c.compile(Path::new("main.rnc")).map(|_| ())
+        };
+
+        // each nested group is optional, so that they are not merged into one group
+        let nested = format!(
+            "start = element a {{ {}element b {{ empty }}{} }}",
+            "(element c { empty }, ".repeat(20),
+            ")?".repeat(20)
+        );
+        assert_matches!(compile(nested.clone(), None), Ok(()));
+        assert_matches!(
+            compile(nested, Some(10)),
+            Err(RelaxError::NestingTooDeep { limit: 10, .. })
+        );
+
+        // each reference followed is a level of nesting
+        let mut chain = "start = d0\n".to_string();
+        for i in 0..20 {
+            chain.push_str(&format!("d{i} = element e{i} {{ d{} }}\n", i + 1));
+        }
+        chain.push_str("d20 = empty\n");
+        assert_matches!(compile(chain.clone(), None), Ok(()));
+        assert_matches!(
+            compile(chain, Some(30)),
+            Err(RelaxError::NestingTooDeep { limit: 30, .. })
+        );
+    }
+
+    #[test]
+    fn div_and_include_override() {
+        let files = TestFiles::new(&[
+            (
+                "plain.rnc",
+                "start = doc
+                        doc = element doc { item* }
+                        item = element item { text }",
+            ),
+            (
+                "div.rnc",
+                "namespace a = 'http://relaxng.org/ns/compatibility/annotations/1.0'
+                        div { start = doc }
+                        [ a:documentation = 'the content' ]
+                        div {
+                            doc = element doc { item* }
+                            div { item = element item { text } }
+                        }",
+            ),
+            (
+                "base.rnc",
+                "start = doc
+                        doc = element doc { item* }
+                        item = element item { empty }",
+            ),
+            (
+                "override.rnc",
+                "include 'base.rnc' { item = element item { text } }",
+            ),
+            (
+                "override_div.rnc",
+                "include 'base.rnc' {
+                            [ a:documentation = 'replaces the empty item' ]
+                            div { item = element item { text } }
+                        }",
+            ),
+        ]);
+        // the serialized form leaves out spans, so schemas which differ only in how they are
+        // arranged into divs and files serialize the same
+        let serialized = |name: &str| {
+            let mut c = Compiler::new(files.clone(), Syntax::Compact);
+            let start = match c.compile(Path::new(name)) {
+                Ok(start) => start,
+                Err(e) => {
+                    c.dump_diagnostic(&e);
+                    panic!("{name}: {e:?}");
+                }
+            };
+            let mut bytes = vec![];
+            model::serialize(&start, &mut bytes).unwrap();
+            bytes
+        };
+
+        let plain = serialized("plain.rnc");
+        assert_eq!(serialized("div.rnc"), plain);
+        assert_eq!(serialized("override.rnc"), plain);
+        assert_eq!(serialized("override_div.rnc"), plain);
+        assert_ne!(serialized("base.rnc"), plain);
+    }
+
+    #[test]
+    fn serialized_pattern_size_limit() {
+        use datatype::xsd::DatatypePolicy;
+        // '\w{400}' compiles to more than the regex crate's default size limit
+        let files = TestFiles::main("start = element a { xsd:token { pattern = '\\w{400}' } }");
+        let mut c = Compiler::new(files.clone(), Syntax::Compact);
+        assert!(c.compile(Path::new("main.rnc")).is_err());
+
+        let mut c = Compiler::new(files.clone(), Syntax::Compact);
+        c.set_datatype_policy(DatatypePolicy {
+            pattern_size_limit: Some(100 << 20),
+            ..Default::default()
+        });
+        let start = c.compile(Path::new("main.rnc")).unwrap();
+        let mut bytes = vec![];
+        model::serialize(&start, &mut bytes).unwrap();
+        let reloaded = model::deserialize(&mut &bytes[..]).unwrap();
+        let mut again = vec![];
+        model::serialize(&reloaded, &mut again).unwrap();
+        assert_eq!(bytes, again);
+    }
+
+    #[test]
+    fn namespaces() {
+        let files = TestFiles::new(&[
+            (
+                "atom.rnc",
+                "default namespace atom = 'http://www.w3.org/2005/Atom'
+                        namespace xhtml = 'http://www.w3.org/1999/xhtml'
+                        namespace local = ''
+                        start = element feed {
+                            attribute xml:lang { text }?,
+                            element title { text },
+                            element xhtml:div { text }?,
+                            external 'ext.rnc'
+                        }",
+            ),
+            ("ext.rnc", "element ext { empty }"),
+            (
+                "ns.rng",
+                "<element name='doc' ns='urn:doc' xmlns='http://relaxng.org/ns/structure/1.0'>
+                            <attribute name='id'><text/></attribute>
+                        </element>",
+            ),
+        ]);
+        let atom = "http://www.w3.org/2005/Atom".to_string();
+        let mut c = Compiler::new(files.clone(), Syntax::Compact);
+        c.compile(Path::new("atom.rnc")).unwrap();
+        // ext.rnc inherits the default namespace of the schema referring to it
+        assert_eq!(
+            c.namespaces(),
+            [
+                (None, atom.clone()),
+                (Some("atom".to_string()), atom),
+                (
+                    Some("xhtml".to_string()),
+                    "http://www.w3.org/1999/xhtml".to_string()
+                ),
+                (
+                    Some("xml".to_string()),
+                    "http://www.w3.org/XML/1998/namespace".to_string()
+                ),
+            ]
+        );
+
+        // a namespace given only by the XML syntax's ns attribute
+        let mut c = Compiler::new(files, Syntax::Xml);
+        c.compile(Path::new("ns.rng")).unwrap();
+        assert_eq!(c.namespaces(), [(None, "urn:doc".to_string())]);
+    }
 }
@@ -1,5 +1,7 @@
+use crate::serial::{self, Decode, Encode, Reader, Writer, codec_enum};
 use std::cell::RefCell;
 use std::fmt;
+use std::io;
 use std::ops::Range;
 use std::rc::Rc;
 
@@ -124,3 +126,67 @@ pub struct Param {
     pub name: String,
     pub value: String,
 }
+
+/// Write the compiled schema whose `start` definition is given, as returned by
+/// `Compiler::compile()`, in a compact binary form that `deserialize()` can read back much
+/// faster than the schema could be compiled again.
+///
+/// Spans are not written, so diagnostics for the loaded schema cannot point into its source.
+/// The form may change between versions of this crate; data written by another version is
+/// rejected by `deserialize()` rather than misread.
+pub fn serialize(
+    start: &Rc<RefCell<Option<DefineRule>>>,
+    w: &mut impl io::Write,
+) -> io::Result<()> {
+    serial::serialize(start, w)
+}
+
+/// Read a schema written by `serialize()`, returning its `start` definition, ready to be
+/// passed to the validator.  Malformed data gives an error of kind `InvalidData`.
+pub fn deserialize(r: &mut impl io::Read) -> io::Result<Rc<RefCell<Option<DefineRule>>>> {
+    serial::deserialize(r)
+}
+
+codec_enum!(DefineRule {
+    0 => AssignCombine(span, combine, pattern),
+    1 => CombineOnly(span, combine, pattern),
+});
+codec_enum!(CombineRule {
+    0 => Choice,
+    1 => Interleave,
+});
+codec_enum!(Pattern {
+    0 => Choice(members),
+    1 => Interleave(members),
+    2 => Group(members),
+    3 => Mixed(content),
+    4 => Empty,
+    5 => Text,
+    6 => NotAllowed,
+    7 => Optional(content),
+    8 => ZeroOrMore(content),
+    9 => OneOrMore(content),
+    10 => Attribute(name, content),
+    11 => Element(name, content),
+    12 => Ref(span, name, def),
+    13 => DatatypeValue { datatype },
+    14 => DatatypeName { datatype, except },
+    15 => List(content),
+});
+codec_enum!(NameClass {
+    0 => Named { namespace_uri, name },
+    1 => NsName { namespace_uri, except },
+    2 => AnyName { except },
+    3 => Alt { a, b },
+});
+
+impl Encode for PatRef {
+    fn encode(&self, w: &mut Writer) -> io::Result<()> {
+        self.0.encode(w)
+    }
+}
+impl Decode for PatRef {
+    fn decode(r: &mut Reader) -> io::Result<Self> {
+        Decode::decode(r).map(PatRef)
+    }
+}
@@ -15,32 +15,73 @@
 //!
 //! Reference: <https://relaxng.org/spec-20011203.html#section7>
 
-use crate::RelaxError;
 use crate::model::{DefineRule, NameClass, Pattern};
+use crate::visit::{Visitor, Walker, walk_pattern};
+use crate::{RelaxError, RelaxWarning};
+use std::cell::RefCell;
 use std::collections::HashSet;
+use std::rc::Rc;
+
+/// The default for how deeply patterns may be nested, counting each reference that is followed
+/// as a level, before `check_restrictions()` gives up with `RelaxError::NestingTooDeep`
+pub const DEFAULT_MAX_DEPTH: usize = 1000;
+
+/// The default for how many members an interleave may have before `find_large_interleaves()`
+/// warns about it
+pub const DEFAULT_MAX_INTERLEAVE_MEMBERS: usize = 64;
 
 /// Check all section 7 restrictions on the compiled pattern tree.
 ///
 /// Called after compilation is complete and all references are resolved.
 /// The `start_span` is the source span of the start rule, used for error reporting.
+/// Patterns nested more than `max_depth` deep are reported as an error rather than checked, so
+/// that the recursive walk cannot overflow the stack.
 pub fn check_restrictions(
     start_rule: &DefineRule,
     start_span: codemap::Span,
+    max_depth: usize,
 ) -> Result<(), RelaxError> {
     let pattern = start_rule.pattern();
+    let depth = Depth {
+        current: 0,
+        max: max_depth,
+    };
 
     // 7.1.5: start element restrictions
     let mut seen = HashSet::new();
-    check_start(pattern, start_span, &mut seen)?;
+    check_start(pattern, start_span, &mut seen, depth)?;
 
     // Walk the full pattern tree for remaining restrictions (7.1.1-7.1.4, 7.3)
     let mut seen = HashSet::new();
     let ctx = WalkContext::default();
-    check_pattern(pattern, &ctx, start_span, &mut seen)?;
+    check_pattern(pattern, &ctx, start_span, &mut seen, depth)?;
 
     Ok(())
 }
 
+/// How deeply nested the pattern being checked is
+#[derive(Clone, Copy)]
+struct Depth {
+    current: usize,
+    max: usize,
+}
+
+impl Depth {
+    /// The depth of a child of the pattern being checked
+    fn deeper(self, span: codemap::Span) -> Result<Depth, RelaxError> {
+        if self.current == self.max {
+            return Err(RelaxError::NestingTooDeep {
+                span,
+                limit: self.max,
+            });
+        }
+        Ok(Depth {
+            current: self.current + 1,
+            ..self
+        })
+    }
+}
+
 // --- Simplification-aware helpers ---
 
 /// Returns true if a pattern simplifies to notAllowed per section 4 rules.
@@ -51,13 +92,13 @@ fn is_dead(pattern: &Pattern) -> bool {
         Pattern::NotAllowed => true,
         Pattern::Group(members) | Pattern::Interleave(members) => {
             // group/interleave with any dead member is dead
-            members.iter().any(|m| is_dead(m))
+            members.iter().any(is_dead)
         }
         Pattern::OneOrMore(p) | Pattern::List(p) => is_dead(p),
         Pattern::ZeroOrMore(_) => false, // zeroOrMore(notAllowed) = choice(oneOrMore(notAllowed), empty) = empty
         Pattern::Choice(alts) => {
             // choice is dead only if ALL alternatives are dead
-            alts.iter().all(|a| is_dead(a))
+            alts.iter().all(is_dead)
         }
         Pattern::Optional(_) => false, // optional(X) = choice(X, empty), and empty is not dead
         // An attribute whose content is dead can never be satisfied
@@ -93,7 +134,9 @@ fn check_start(
     pattern: &Pattern,
     span: codemap::Span,
     seen: &mut HashSet<usize>,
+    depth: Depth,
 ) -> Result<(), RelaxError> {
+    let depth = depth.deeper(span)?;
     // Dead patterns are fine -- they simplify to notAllowed which is allowed in start
     if is_dead(pattern) {
         return Ok(());
@@ -107,7 +150,7 @@ fn check_start(
         Pattern::Choice(alternatives) => {
             for alt in alternatives {
                 if !is_dead(alt) {
-                    check_start(alt, span, seen)?;
+                    check_start(alt, span, seen, depth)?;
                 }
             }
             Ok(())
@@ -124,14 +167,14 @@ fn check_start(
             }
             seen.insert(ptr);
             if let Some(rule) = pat_ref.0.borrow().as_ref() {
-                check_start(rule.pattern(), span, seen)
+                check_start(rule.pattern(), span, seen, depth)
             } else {
                 Ok(())
             }
         }
 
         // Optional(p) = choice(p, empty) -- check p under start rules
-        Pattern::Optional(content) => check_start(content, span, seen),
+        Pattern::Optional(content) => check_start(content, span, seen, depth),
 
         // Group/Interleave with a single non-empty member simplifies to that
         // member (section 4: group(p, empty) = p, interleave(p, empty) = p).
@@ -142,7 +185,7 @@ fn check_start(
                 .filter(|m| !matches!(m, Pattern::Empty))
                 .collect();
             if non_empty.len() == 1 {
-                return check_start(non_empty[0], span, seen);
+                return check_start(non_empty[0], span, seen, depth);
             }
             // Multi-member group/interleave is forbidden under start
             if matches!(pattern, Pattern::Group(_)) {
@@ -187,7 +230,9 @@ fn check_pattern(
     ctx: &WalkContext,
     span: codemap::Span,
     seen: &mut HashSet<usize>,
+    depth: Depth,
 ) -> Result<(), RelaxError> {
+    let depth = depth.deeper(span)?;
     // Skip restriction checks on dead patterns -- they would be eliminated
     // during full simplification (section 4)
     if is_dead(pattern) {
@@ -207,7 +252,7 @@ fn check_pattern(
             // Check name class restrictions
             check_name_class(name_class)?;
             // Element creates a new context boundary -- reset all flags
-            check_pattern(content, &WalkContext::default(), span, seen)
+            check_pattern(content, &WalkContext::default(), span, seen, depth)
         }
 
         Pattern::Attribute(name_class, content) => {
@@ -246,7 +291,7 @@ fn check_pattern(
 
             let mut child_ctx = ctx.clone();
             child_ctx.in_attribute = true;
-            check_pattern(content, &child_ctx, span, seen)
+            check_pattern(content, &child_ctx, span, seen, depth)
         }
 
         Pattern::List(content) => {
@@ -260,14 +305,14 @@ fn check_pattern(
             }
             let mut child_ctx = ctx.clone();
             child_ctx.in_list = true;
-            check_pattern(content, &child_ctx, span, seen)
+            check_pattern(content, &child_ctx, span, seen, depth)
         }
 
         Pattern::DatatypeName { except, .. } => {
             if let Some(except_pat) = except {
                 let mut child_ctx = ctx.clone();
                 child_ctx.in_data_except = true;
-                check_pattern(except_pat, &child_ctx, span, seen)?;
+                check_pattern(except_pat, &child_ctx, span, seen, depth)?;
             }
             Ok(())
         }
@@ -276,7 +321,7 @@ fn check_pattern(
             for alt in alternatives {
                 // Skip dead alternatives -- they simplify away
                 if !is_dead(alt) {
-                    check_pattern(alt, ctx, span, seen)?;
+                    check_pattern(alt, ctx, span, seen, depth)?;
                 }
             }
             Ok(())
@@ -290,7 +335,7 @@ fn check_pattern(
             // 7.2: check string sequence restriction (content types must be groupable)
             // Inside list, group of data/value is allowed (whitespace-separated tokens)
             if !ctx.in_list {
-                check_string_sequence(members, span)?;
+                check_string_sequence(members, "group", span)?;
             }
             // 7.3: check for overlapping attribute name classes within the group
             check_group_attribute_overlap(members, span)?;
@@ -304,7 +349,7 @@ fn check_pattern(
             }
             for m in members {
                 if !is_dead(m) {
-                    check_pattern(m, &child_ctx, span, seen)?;
+                    check_pattern(m, &child_ctx, span, seen, depth)?;
                 }
             }
             Ok(())
@@ -320,7 +365,7 @@ fn check_pattern(
                 return Err(restricted(span, "interleave", "data/except"));
             }
             // 7.2: check string sequence restriction (content types must be groupable)
-            check_string_sequence(members, span)?;
+            check_string_sequence(members, "interleave", span)?;
             // 7.4: check for overlapping elements and duplicate text across
             // interleave branches
             check_interleave_restrictions(members, span)?;
@@ -336,7 +381,7 @@ fn check_pattern(
             }
             for m in members {
                 if !is_dead(m) {
-                    check_pattern(m, &child_ctx, span, seen)?;
+                    check_pattern(m, &child_ctx, span, seen, depth)?;
                 }
             }
             Ok(())
@@ -365,7 +410,7 @@ fn check_pattern(
             if ctx.in_one_or_more {
                 child_ctx.in_one_or_more_group = true;
             }
-            check_pattern(content, &child_ctx, span, seen)
+            check_pattern(content, &child_ctx, span, seen, depth)
         }
 
         Pattern::OneOrMore(content) => {
@@ -375,7 +420,7 @@ fn check_pattern(
             }
             let mut child_ctx = ctx.clone();
             child_ctx.in_one_or_more = true;
-            check_pattern(content, &child_ctx, span, seen)
+            check_pattern(content, &child_ctx, span, seen, depth)
         }
 
         Pattern::ZeroOrMore(content) => {
@@ -386,10 +431,10 @@ fn check_pattern(
             }
             let mut child_ctx = ctx.clone();
             child_ctx.in_one_or_more = true;
-            check_pattern(content, &child_ctx, span, seen)
+            check_pattern(content, &child_ctx, span, seen, depth)
         }
 
-        Pattern::Optional(content) => check_pattern(content, ctx, span, seen),
+        Pattern::Optional(content) => check_pattern(content, ctx, span, seen, depth),
 
         Pattern::Ref(_ref_span, _name, pat_ref) => {
             // Follow refs and check the resolved pattern in the current context.
@@ -402,7 +447,7 @@ fn check_pattern(
             }
             seen.insert(ptr);
             if let Some(rule) = pat_ref.0.borrow().as_ref() {
-                check_pattern(rule.pattern(), ctx, span, seen)
+                check_pattern(rule.pattern(), ctx, span, seen, depth)
             } else {
                 Ok(())
             }
@@ -508,6 +553,501 @@ fn check_attribute_name_class(name_class: &NameClass) -> Result<(), RelaxError>
     }
 }
 
+// --- Attributes that can never match ---
+//
+// RELAX NG forbids the "http://www.w3.org/2000/xmlns" namespace, but the
+// Namespaces in XML recommendation binds the `xmlns` prefix to the same URI
+// with a trailing slash.  A schema may legally use that namespace, yet no
+// instance attribute can ever be in it, because the parser consumes every
+// `xmlns:*` attribute as a namespace declaration.
+
+const XMLNS_DECLARATION_NAMESPACE: &str = "http://www.w3.org/2000/xmlns/";
+
+/// Find attribute patterns whose name class only matches reserved `xmlns` names, and which
+/// therefore can never be satisfied by an instance document.
+///
+/// Each warning is reported against the span of the definition containing the attribute (or
+/// `start_span` for attributes in the start rule itself).
+pub fn find_unsatisfiable_attributes(
+    start_rule: &DefineRule,
+    start_span: codemap::Span,
+) -> Vec<RelaxWarning> {
+    let mut finder = UnsatisfiableAttributes {
+        span: start_span,
+        warnings: vec![],
+    };
+    walk_pattern(&mut finder, start_rule.pattern());
+    finder.warnings
+}
+
+struct UnsatisfiableAttributes {
+    /// the span of the definition currently being walked
+    span: codemap::Span,
+    warnings: Vec<RelaxWarning>,
+}
+
+impl Visitor for UnsatisfiableAttributes {
+    fn visit_attribute(&mut self, walker: &mut Walker, name_class: &NameClass, content: &Pattern) {
+        if only_reserved_attribute_names(name_class) {
+            self.warnings
+                .push(RelaxWarning::UnsatisfiableAttribute { span: self.span });
+        }
+        walker.walk(self, content)
+    }
+
+    fn visit_ref(
+        &mut self,
+        walker: &mut Walker,
+        _span: codemap::Span,
+        _name: &str,
+        rule: &DefineRule,
+    ) {
+        let outer = std::mem::replace(&mut self.span, *rule.span());
+        walker.walk(self, rule.pattern());
+        self.span = outer;
+    }
+}
+
+// --- Ambiguous choices ---
+//
+// A choice between elements whose name classes overlap is legal, and the
+// validator accepts an element matching either one, but it is often a
+// mistake (such as the same element being declared twice with different
+// content), so this is offered as an optional lint.
+
+/// Find choices with two alternatives that are elements able to match the same name.
+///
+/// An alternative may also be a reference to such an element, or a nested choice of them.
+/// Each warning is reported against the span of the definition containing the choice (or
+/// `start_span` for choices in the start rule itself).
+pub fn find_ambiguous_choices(
+    start_rule: &DefineRule,
+    start_span: codemap::Span,
+) -> Vec<RelaxWarning> {
+    let mut finder = AmbiguousChoices {
+        span: start_span,
+        warnings: vec![],
+    };
+    walk_pattern(&mut finder, start_rule.pattern());
+    finder.warnings
+}
+
+struct AmbiguousChoices {
+    /// the span of the definition currently being walked
+    span: codemap::Span,
+    warnings: Vec<RelaxWarning>,
+}
+
+impl Visitor for AmbiguousChoices {
+    fn visit_choice(&mut self, walker: &mut Walker, alternatives: &[Pattern]) {
+        let elements: Vec<_> = alternatives
+            .iter()
+            .map(|alt| {
+                let mut out = vec![];
+                collect_choice_element_name_classes(alt, &mut out);
+                out
+            })
+            .collect();
+        'found: for (i, a) in elements.iter().enumerate() {
+            for b in &elements[i + 1..] {
+                for nc_a in a {
+                    if let Some(nc_b) = b.iter().find(|nc_b| name_classes_overlap(nc_a, nc_b)) {
+                        self.warnings.push(RelaxWarning::AmbiguousChoice {
+                            span: self.span,
+                            names: [describe_name_class(nc_a), describe_name_class(nc_b)],
+                        });
+                        break 'found;
+                    }
+                }
+            }
+        }
+        walker.walk_all(self, alternatives)
+    }
+
+    fn visit_ref(
+        &mut self,
+        walker: &mut Walker,
+        _span: codemap::Span,
+        _name: &str,
+        rule: &DefineRule,
+    ) {
+        let outer = std::mem::replace(&mut self.span, *rule.span());
+        walker.walk(self, rule.pattern());
+        self.span = outer;
+    }
+}
+
+// --- Large interleaves ---
+//
+// The derivative of an interleave is a choice with an alternative for each
+// member that could match, so validating against an interleave of very many
+// members, particularly optional ones, can be slow.  Such schemas are legal,
+// so this only warns, to give the schema author notice before a validation
+// takes too long.
+
+/// Find interleaves with more than `limit` members, counting the members of interleaves nested
+/// directly within them, as `a & (b & c)` is compiled to a single interleave.
+///
+/// Each warning is reported against the span of the definition containing the interleave (or
+/// `start_span` for interleaves in the start rule itself).
+pub fn find_large_interleaves(
+    start_rule: &DefineRule,
+    start_span: codemap::Span,
+    limit: usize,
+) -> Vec<RelaxWarning> {
+    let mut finder = LargeInterleaves {
+        span: start_span,
+        limit,
+        warnings: vec![],
+    };
+    walk_pattern(&mut finder, start_rule.pattern());
+    finder.warnings
+}
+
+struct LargeInterleaves {
+    /// the span of the definition currently being walked
+    span: codemap::Span,
+    limit: usize,
+    warnings: Vec<RelaxWarning>,
+}
+
+impl Visitor for LargeInterleaves {
+    fn visit_interleave(&mut self, walker: &mut Walker, members: &[Pattern]) {
+        if members.len() > self.limit {
+            self.warnings.push(RelaxWarning::LargeInterleave {
+                span: self.span,
+                members: members.len(),
+                limit: self.limit,
+            });
+        }
+        walker.walk_all(self, members)
+    }
+
+    fn visit_ref(
+        &mut self,
+        walker: &mut Walker,
+        _span: codemap::Span,
+        _name: &str,
+        rule: &DefineRule,
+    ) {
+        let outer = std::mem::replace(&mut self.span, *rule.span());
+        walker.walk(self, rule.pattern());
+        self.span = outer;
+    }
+}
+
+// --- Unused definitions ---
+//
+// A definition which no reference reachable from the start pattern uses is
+// legal, but is dead code in the schema: often left behind when the last
+// use of it was removed.  Also offered as an optional lint, since modular
+// schemas may define things that only some of the schemas including them use.
+
+/// Find the definitions, out of those given, that cannot be reached from the start pattern
+/// through any chain of references.  Each warning is reported against the span of the unused
+/// definition, in the order the definitions appear in the schema.
+pub fn find_unused_definitions(
+    start_rule: &DefineRule,
+    definitions: &[(String, Rc<RefCell<Option<DefineRule>>>)],
+) -> Vec<RelaxWarning> {
+    let mut reached = ReachedDefinitions(HashSet::new());
+    walk_pattern(&mut reached, start_rule.pattern());
+    let mut unused: Vec<_> = definitions
+        .iter()
+        .filter_map(|(name, rule)| {
+            let rule = rule.borrow();
+            let rule = rule.as_ref()?;
+            (!reached.0.contains(&definition_id(rule))).then(|| (name.clone(), *rule.span()))
+        })
+        .collect();
+    unused.sort_by_key(|(_, span)| span.low());
+    unused
+        .into_iter()
+        .map(|(name, span)| RelaxWarning::UnusedDefinition { span, name })
+        .collect()
+}
+
+/// Identifies a definition by its address, which is shared by every reference to it
+fn definition_id(rule: &DefineRule) -> usize {
+    rule as *const DefineRule as usize
+}
+
+struct ReachedDefinitions(HashSet<usize>);
+
+impl Visitor for ReachedDefinitions {
+    fn visit_ref(
+        &mut self,
+        walker: &mut Walker,
+        _span: codemap::Span,
+        _name: &str,
+        rule: &DefineRule,
+    ) {
+        self.0.insert(definition_id(rule));
+        walker.walk(self, rule.pattern())
+    }
+}
+
+/// Collect the name classes of the elements that a choice alternative consists of: an element,
+/// a reference to one, or a choice of these
+fn collect_choice_element_name_classes(pattern: &Pattern, out: &mut Vec<CollectedNameClass>) {
+    match pattern {
+        Pattern::Element(nc, _) => collect_name_class_entries(nc, out),
+        Pattern::Choice(alternatives) => {
+            for alt in alternatives {
+                collect_choice_element_name_classes(alt, out);
+            }
+        }
+        Pattern::Ref(_, _, pat_ref) => {
+            if let Some(rule) = pat_ref.0.borrow().as_ref() {
+                collect_choice_element_name_classes(rule.pattern(), out);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Describe a name class in Clark notation, such as `{urn:example}name` or `{urn:example}*`
+fn describe_name_class(nc: &CollectedNameClass) -> String {
+    match nc {
+        CollectedNameClass::Named {
+            namespace_uri,
+            name,
+        } if namespace_uri.is_empty() => name.clone(),
+        CollectedNameClass::Named {
+            namespace_uri,
+            name,
+        } => format!("{{{namespace_uri}}}{name}"),
+        CollectedNameClass::NsName { namespace_uri, .. } => format!("{{{namespace_uri}}}*"),
+        CollectedNameClass::AnyName { .. } => "*".to_string(),
+    }
+}
+
+// --- Deterministic content ---
+//
+// Content is deterministic when each child element can be attributed to a
+// single element pattern without looking ahead at the elements after it,
+// as required by the "unique particle attribution" rule of W3C XML Schema.
+// RELAX NG has no such rule, and the validator handles either kind of
+// content, but tooling such as error recovery can make better guesses
+// about how a document was meant to continue when the content is
+// deterministic.  The check builds the Glushkov automaton of the content,
+// taking each element pattern as one position.
+
+/// Whether the given content of an element is deterministic: at each point in a sequence of
+/// child elements, no two of the element patterns that may come next can match the same name.
+///
+/// Element patterns reached through references are part of the content, but what they contain
+/// is not, being content of its own to check separately.  Attributes, text and data are
+/// ignored.
+pub fn is_deterministic(content: &Pattern) -> bool {
+    ambiguous_particles(content).is_none()
+}
+
+/// If the given content of an element is not deterministic, the names of two element patterns
+/// which a child element could match at the same point, in Clark notation
+pub fn ambiguous_particles(content: &Pattern) -> Option<[String; 2]> {
+    let mut positions = Positions::default();
+    let edges = positions.add(content, &mut vec![]);
+    positions.overlap(&edges.first).or_else(|| {
+        positions
+            .follow
+            .iter()
+            .find_map(|follow| positions.overlap(follow))
+    })
+}
+
+/// The element patterns of some content, each known by its index
+#[derive(Default)]
+struct Positions {
+    /// the name class of each element pattern
+    name_classes: Vec<Vec<CollectedNameClass>>,
+    /// for each element pattern, the element patterns which may match the next sibling of an
+    /// element it matched
+    follow: Vec<Vec<usize>>,
+}
+
+/// The element patterns at the start and end of part of the content
+struct Edges {
+    /// whether that part of the content may match no elements at all
+    nullable: bool,
+    first: Vec<usize>,
+    last: Vec<usize>,
+}
+
+impl Edges {
+    fn empty() -> Edges {
+        Edges {
+            nullable: true,
+            first: vec![],
+            last: vec![],
+        }
+    }
+}
+
+impl Positions {
+    /// Add the element patterns of the given pattern, given the refs currently being expanded
+    fn add(&mut self, pattern: &Pattern, refs: &mut Vec<usize>) -> Edges {
+        match pattern {
+            Pattern::Element(nc, _) => {
+                let position = self.name_classes.len();
+                let mut name_classes = vec![];
+                collect_name_class_entries(nc, &mut name_classes);
+                self.name_classes.push(name_classes);
+                self.follow.push(vec![]);
+                Edges {
+                    nullable: false,
+                    first: vec![position],
+                    last: vec![position],
+                }
+            }
+            Pattern::NotAllowed => Edges {
+                nullable: false,
+                first: vec![],
+                last: vec![],
+            },
+            Pattern::Choice(alternatives) => {
+                let mut edges = Edges {
+                    nullable: false,
+                    first: vec![],
+                    last: vec![],
+                };
+                for alt in alternatives {
+                    let alt = self.add(alt, refs);
+                    edges.nullable |= alt.nullable;
+                    edges.first.extend(alt.first);
+                    edges.last.extend(alt.last);
+                }
+                edges
+            }
+            Pattern::Group(members) => {
+                let mut edges = Edges::empty();
+                for member in members {
+                    let member = self.add(member, refs);
+                    self.add_follow(&edges.last, &member.first);
+                    if edges.nullable {
+                        edges.first.extend(&member.first);
+                    }
+                    if member.nullable {
+                        edges.last.extend(member.last);
+                    } else {
+                        edges.last = member.last;
+                    }
+                    edges.nullable &= member.nullable;
+                }
+                edges
+            }
+            Pattern::Interleave(members) => {
+                // after any element of one member, the others may start
+                let mut ranges = vec![];
+                let mut edges = Edges::empty();
+                for member in members {
+                    let start = self.name_classes.len();
+                    let member = self.add(member, refs);
+                    ranges.push((start..self.name_classes.len(), member.first.clone()));
+                    edges.nullable &= member.nullable;
+                    edges.first.extend(member.first);
+                    edges.last.extend(member.last);
+                }
+                for (i, (range, _)) in ranges.iter().enumerate() {
+                    for (j, (_, first)) in ranges.iter().enumerate() {
+                        if i != j {
+                            for position in range.clone() {
+                                self.follow[position].extend(first);
+                            }
+                        }
+                    }
+                }
+                edges
+            }
+            Pattern::Mixed(content) => self.add(content, refs),
+            Pattern::Optional(content) => Edges {
+                nullable: true,
+                ..self.add(content, refs)
+            },
+            Pattern::OneOrMore(content) | Pattern::ZeroOrMore(content) => {
+                let edges = self.add(content, refs);
+                self.add_follow(&edges.last, &edges.first);
+                Edges {
+                    nullable: edges.nullable || matches!(pattern, Pattern::ZeroOrMore(_)),
+                    ..edges
+                }
+            }
+            Pattern::Ref(_, _, pat_ref) => {
+                let ptr = pat_ref.0.as_ptr() as usize;
+                // a reference back to a definition being expanded is only allowed within an
+                // element, which is not part of this content
+                if refs.contains(&ptr) {
+                    return Edges::empty();
+                }
+                let rule = pat_ref.0.borrow();
+                let Some(rule) = rule.as_ref() else {
+                    return Edges::empty();
+                };
+                refs.push(ptr);
+                let edges = self.add(rule.pattern(), refs);
+                refs.pop();
+                edges
+            }
+            Pattern::Attribute(..)
+            | Pattern::Empty
+            | Pattern::Text
+            | Pattern::DatatypeValue { .. }
+            | Pattern::DatatypeName { .. }
+            | Pattern::List(_) => Edges::empty(),
+        }
+    }
+
+    fn add_follow(&mut self, from: &[usize], to: &[usize]) {
+        for &position in from {
+            self.follow[position].extend(to);
+        }
+    }
+
+    /// The names of two different element patterns in the given set which can match the same
+    /// name
+    fn overlap(&self, set: &[usize]) -> Option<[String; 2]> {
+        for (i, &a) in set.iter().enumerate() {
+            for &b in &set[i + 1..] {
+                if a == b {
+                    continue;
+                }
+                for nc_a in &self.name_classes[a] {
+                    if let Some(nc_b) = self.name_classes[b]
+                        .iter()
+                        .find(|nc_b| name_classes_overlap(nc_a, nc_b))
+                    {
+                        return Some([describe_name_class(nc_a), describe_name_class(nc_b)]);
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+/// True if every name matched by the name class is one that cannot appear as an attribute in
+/// an instance document
+fn only_reserved_attribute_names(name_class: &NameClass) -> bool {
+    match name_class {
+        NameClass::Named {
+            namespace_uri,
+            name,
+        } => {
+            (namespace_uri.is_empty() && name == "xmlns")
+                || namespace_uri == XMLNS_NAMESPACE
+                || namespace_uri == XMLNS_DECLARATION_NAMESPACE
+        }
+        NameClass::NsName { namespace_uri, .. } => {
+            namespace_uri == XMLNS_NAMESPACE || namespace_uri == XMLNS_DECLARATION_NAMESPACE
+        }
+        // an except can never remove every other namespace, since anyName is forbidden within it
+        NameClass::AnyName { .. } => false,
+        NameClass::Alt { a, b } => {
+            only_reserved_attribute_names(a) && only_reserved_attribute_names(b)
+        }
+    }
+}
+
 // --- 7.1.1: anyName/nsName except restrictions ---
 //
 // An except inside anyName must not contain anyName descendants.
@@ -711,7 +1251,7 @@ fn has_text(pattern: &Pattern) -> bool {
         Pattern::Text => true,
         Pattern::Mixed(_) => true, // mixed = interleave(text, ...)
         Pattern::Group(members) | Pattern::Interleave(members) | Pattern::Choice(members) => {
-            members.iter().any(|m| has_text(m))
+            members.iter().any(has_text)
         }
         Pattern::OneOrMore(p) | Pattern::ZeroOrMore(p) | Pattern::Optional(p) => has_text(p),
         Pattern::Ref(_, _, pat_ref) => {
@@ -942,15 +1482,6 @@ fn name_class_is_infinite(nc: &NameClass) -> bool {
     }
 }
 
-/// Check if a pattern is effectively empty (matches only the empty string
-/// or nothing at all).
-fn content_is_empty(pattern: &Pattern) -> bool {
-    match pattern {
-        Pattern::Empty | Pattern::NotAllowed => true,
-        _ => false,
-    }
-}
-
 // --- 7.2: Content type / string sequence checking ---
 //
 // Each pattern has a "content type": empty, complex, or simple.
@@ -971,56 +1502,89 @@ enum ContentType {
     Simple,
 }
 
-/// Compute the content type of a pattern (for section 7.2 checking).
+/// The pattern which gives a pattern its content type, to name in error messages
+#[derive(Debug, Clone)]
+struct ContentSource {
+    /// what the pattern is, such as `text` or `a data value`
+    kind: &'static str,
+    /// the outermost definition through which the pattern was reached, if any
+    define: Option<String>,
+}
+
+impl ContentSource {
+    fn new(kind: &'static str) -> Option<ContentSource> {
+        Some(ContentSource { kind, define: None })
+    }
+
+    fn describe(&self) -> String {
+        match &self.define {
+            Some(name) => format!("{} (from '{}')", self.kind, name),
+            None => self.kind.to_string(),
+        }
+    }
+}
+
+/// Compute the content type of a pattern (for section 7.2 checking), together with the pattern
+/// responsible for it (`None` for empty content).
 /// Per spec: text=complex, element=complex, data/value/list=simple,
 /// empty/attribute=empty, notAllowed=empty.
 /// Refs are followed to their resolved content type (with cycle detection).
-fn content_type(pattern: &Pattern) -> ContentType {
+fn content_type(pattern: &Pattern) -> (ContentType, Option<ContentSource>) {
     let mut seen = HashSet::new();
     content_type_impl(pattern, &mut seen)
 }
 
-fn content_type_impl(pattern: &Pattern, seen: &mut HashSet<usize>) -> ContentType {
+fn content_type_impl(
+    pattern: &Pattern,
+    seen: &mut HashSet<usize>,
+) -> (ContentType, Option<ContentSource>) {
     if is_dead(pattern) {
-        return ContentType::Empty;
+        return (ContentType::Empty, None);
     }
     match pattern {
-        Pattern::Empty | Pattern::NotAllowed => ContentType::Empty,
-        Pattern::Element(_, _) => ContentType::Complex,
-        Pattern::Text => ContentType::Complex,
-        Pattern::Ref(_, _, pat_ref) => {
+        Pattern::Empty | Pattern::NotAllowed => (ContentType::Empty, None),
+        Pattern::Element(_, _) => (ContentType::Complex, ContentSource::new("an element")),
+        Pattern::Text => (ContentType::Complex, ContentSource::new("text")),
+        Pattern::Ref(_, name, pat_ref) => {
             let ptr = pat_ref.0.as_ptr() as usize;
-            if seen.contains(&ptr) {
+            let (ct, source) = if seen.contains(&ptr) {
                 // Cycle detected. Any cycle reachable from content context implies
                 // the pattern eventually contains elements → Complex.
-                return ContentType::Complex;
-            }
-            seen.insert(ptr);
-            if let Some(rule) = pat_ref.0.borrow().as_ref() {
-                content_type_impl(rule.pattern(), seen)
+                (ContentType::Complex, ContentSource::new("an element"))
             } else {
-                ContentType::Empty
-            }
-        }
-        Pattern::DatatypeValue { .. } | Pattern::DatatypeName { .. } | Pattern::List(_) => {
-            ContentType::Simple
+                seen.insert(ptr);
+                if let Some(rule) = pat_ref.0.borrow().as_ref() {
+                    content_type_impl(rule.pattern(), seen)
+                } else {
+                    (ContentType::Empty, None)
+                }
+            };
+            let source = source.map(|source| ContentSource {
+                define: Some(name.clone()),
+                ..source
+            });
+            (ct, source)
         }
-        Pattern::Attribute(_, _) => ContentType::Empty,
+        Pattern::DatatypeValue { .. } => (ContentType::Simple, ContentSource::new("a value")),
+        Pattern::DatatypeName { .. } => (ContentType::Simple, ContentSource::new("a data value")),
+        Pattern::List(_) => (ContentType::Simple, ContentSource::new("a list")),
+        Pattern::Attribute(_, _) => (ContentType::Empty, None),
         Pattern::Group(members) | Pattern::Interleave(members) => members
             .iter()
             .map(|m| content_type_impl(m, seen))
-            .max()
-            .unwrap_or(ContentType::Empty),
+            .max_by_key(|(ct, _)| *ct)
+            .unwrap_or((ContentType::Empty, None)),
         Pattern::Choice(alts) => alts
             .iter()
             .filter(|a| !is_dead(a))
             .map(|a| content_type_impl(a, seen))
-            .max()
-            .unwrap_or(ContentType::Empty),
+            .max_by_key(|(ct, _)| *ct)
+            .unwrap_or((ContentType::Empty, None)),
         Pattern::OneOrMore(p) | Pattern::ZeroOrMore(p) | Pattern::Optional(p) => {
             content_type_impl(p, seen)
         }
-        Pattern::Mixed(_) => ContentType::Complex, // mixed = interleave(text, ...) and text is complex
+        // mixed = interleave(text, ...) and text is complex
+        Pattern::Mixed(_) => (ContentType::Complex, ContentSource::new("mixed content")),
     }
 }
 
@@ -1034,16 +1598,29 @@ fn groupable(ct1: ContentType, ct2: ContentType) -> bool {
 }
 
 /// Check that all members of a group/interleave have groupable content types.
-fn check_string_sequence(members: &[Pattern], span: codemap::Span) -> Result<(), RelaxError> {
-    let types: Vec<ContentType> = members
+fn check_string_sequence(
+    members: &[Pattern],
+    operator: &'static str,
+    span: codemap::Span,
+) -> Result<(), RelaxError> {
+    let types: Vec<_> = members
         .iter()
         .filter(|m| !is_dead(m))
-        .map(|m| content_type(m))
+        .map(content_type)
         .collect();
     for i in 0..types.len() {
         for j in (i + 1)..types.len() {
-            if !groupable(types[i], types[j]) {
-                return Err(restricted(span, "string sequence", "group/interleave"));
+            if !groupable(types[i].0, types[j].0) {
+                let describe = |source: &Option<ContentSource>| {
+                    source
+                        .as_ref()
+                        .map_or_else(|| "empty content".to_string(), ContentSource::describe)
+                };
+                return Err(RelaxError::UngroupableContent {
+                    span,
+                    operator,
+                    members: [describe(&types[i].1), describe(&types[j].1)],
+                });
             }
         }
     }
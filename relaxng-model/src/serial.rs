@@ -0,0 +1,418 @@
+//! The compact binary form of a compiled schema written by `model::serialize()` and read back
+//! by `model::deserialize()`.
+//!
+//! Each type in the model implements `Encode` and `Decode`, mostly by way of the `codec_enum!`
+//! and `codec_struct!` macros, which write an enum's variant as a one-byte tag followed by its
+//! fields in order.  Integers are written as LEB128 varints (zigzag encoded when signed), and
+//! big numbers as their decimal text.  Definitions are numbered in the order that references
+//! to them are first written, starting with the `start` definition, and each `ref` is written
+//! as that number; the bodies of the definitions follow one another in the same order.
+
+use crate::model::DefineRule;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{self, Read as _};
+use std::rc::Rc;
+use std::str::FromStr;
+
+/// Written at the start of the data, so that something which is not a serialized schema, or
+/// was written by an incompatible version, is rejected
+const MAGIC: &[u8; 4] = b"RNGm";
+const VERSION: u8 = 3;
+
+type Define = Rc<RefCell<Option<DefineRule>>>;
+
+pub(crate) struct Writer<'a> {
+    out: &'a mut dyn io::Write,
+    /// the number given to each definition reached so far
+    ids: HashMap<*const RefCell<Option<DefineRule>>, u64>,
+    /// the definitions reached so far, in the order they were numbered
+    defines: Vec<Define>,
+}
+
+impl Writer<'_> {
+    pub(crate) fn tag(&mut self, tag: u8) -> io::Result<()> {
+        self.out.write_all(&[tag])
+    }
+
+    fn uint(&mut self, mut v: u64) -> io::Result<()> {
+        loop {
+            let byte = (v & 0x7f) as u8;
+            v >>= 7;
+            if v == 0 {
+                return self.out.write_all(&[byte]);
+            }
+            self.out.write_all(&[byte | 0x80])?;
+        }
+    }
+
+    fn bytes(&mut self, b: &[u8]) -> io::Result<()> {
+        self.uint(b.len() as u64)?;
+        self.out.write_all(b)
+    }
+
+    /// The number of the given definition, numbering it now if it has not been seen before
+    fn define_id(&mut self, define: &Define) -> u64 {
+        let next = self.defines.len() as u64;
+        *self.ids.entry(Rc::as_ptr(define)).or_insert_with(|| {
+            self.defines.push(define.clone());
+            next
+        })
+    }
+}
+
+pub(crate) struct Reader<'a> {
+    input: &'a mut dyn io::Read,
+    /// the span given to everything read, since the source of the schema is not kept
+    span: codemap::Span,
+    /// the definitions numbered so far, whose bodies are filled in as they are read
+    defines: Vec<Define>,
+}
+
+impl Reader<'_> {
+    pub(crate) fn tag(&mut self) -> io::Result<u8> {
+        let mut b = [0];
+        self.input.read_exact(&mut b)?;
+        Ok(b[0])
+    }
+
+    fn uint(&mut self) -> io::Result<u64> {
+        let mut v = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.tag()?;
+            v |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(v);
+            }
+        }
+        Err(invalid("integer too long"))
+    }
+
+    fn bytes(&mut self) -> io::Result<Vec<u8>> {
+        let len = self.uint()?;
+        let mut b = vec![];
+        // read in pieces, so that a corrupt length cannot make us allocate a huge buffer
+        let read = io::Read::take(&mut self.input, len).read_to_end(&mut b)?;
+        if read as u64 != len {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+        }
+        Ok(b)
+    }
+
+    /// The definition with the given number, which may only be the next unused number if it
+    /// has not been seen before
+    fn define(&mut self, id: u64) -> io::Result<Define> {
+        let id = usize::try_from(id).map_err(|_| invalid("bad definition number"))?;
+        if id == self.defines.len() {
+            self.defines.push(Rc::new(RefCell::new(None)));
+        }
+        self.defines
+            .get(id)
+            .cloned()
+            .ok_or_else(|| invalid("bad definition number"))
+    }
+}
+
+pub(crate) fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub(crate) fn unknown_tag(type_name: &str, tag: u8) -> io::Error {
+    invalid(&format!("unknown {type_name} tag {tag}"))
+}
+
+pub(crate) trait Encode {
+    fn encode(&self, w: &mut Writer) -> io::Result<()>;
+}
+
+pub(crate) trait Decode: Sized {
+    fn decode(r: &mut Reader) -> io::Result<Self>;
+}
+
+pub(crate) fn serialize(start: &Define, out: &mut dyn io::Write) -> io::Result<()> {
+    out.write_all(MAGIC)?;
+    out.write_all(&[VERSION])?;
+    let mut w = Writer {
+        out,
+        ids: HashMap::new(),
+        defines: vec![],
+    };
+    w.define_id(start);
+    // writing a definition can number more of them, which are then written in turn
+    let mut i = 0;
+    while let Some(define) = w.defines.get(i).cloned() {
+        define.borrow().encode(&mut w)?;
+        i += 1;
+    }
+    Ok(())
+}
+
+pub(crate) fn deserialize(input: &mut dyn io::Read) -> io::Result<Define> {
+    let mut header = [0; 5];
+    input.read_exact(&mut header)?;
+    if &header[..4] != MAGIC {
+        return Err(invalid("not a serialized schema"));
+    }
+    if header[4] != VERSION {
+        return Err(invalid(&format!(
+            "serialized schema has format version {}, but only {VERSION} is supported",
+            header[4]
+        )));
+    }
+    let mut map = codemap::CodeMap::new();
+    let span = map
+        .add_file("<serialized schema>".to_string(), String::new())
+        .span;
+    let mut r = Reader {
+        input,
+        span,
+        defines: vec![],
+    };
+    let start = r.define(0)?;
+    // the writer wrote the body of every definition it numbered, in order
+    let mut i = 0;
+    while let Some(define) = r.defines.get(i).cloned() {
+        *define.borrow_mut() = Decode::decode(&mut r)?;
+        i += 1;
+    }
+    Ok(start)
+}
+
+/// Implement `Encode` and `Decode` for an enum, given a distinct tag for each variant and the
+/// names of the variant's fields (for tuple variants, any names, used only by the macro)
+macro_rules! codec_enum {
+    ($ty:ident { $($tag:literal => $variant:ident $(( $($f:ident),* ))? $({ $($sf:ident),* })?),* $(,)? }) => {
+        impl $crate::serial::Encode for $ty {
+            fn encode(&self, w: &mut $crate::serial::Writer) -> std::io::Result<()> {
+                match self {
+                    $($ty::$variant $(( $($f),* ))? $({ $($sf),* })? => {
+                        w.tag($tag)?;
+                        $($($crate::serial::Encode::encode($f, w)?;)*)?
+                        $($($crate::serial::Encode::encode($sf, w)?;)*)?
+                    })*
+                }
+                Ok(())
+            }
+        }
+        impl $crate::serial::Decode for $ty {
+            fn decode(r: &mut $crate::serial::Reader) -> std::io::Result<Self> {
+                Ok(match r.tag()? {
+                    $($tag => $ty::$variant
+                        $(( $({
+                            let $f = $crate::serial::Decode::decode(r)?;
+                            $f
+                        }),* ))?
+                        $({ $($sf: $crate::serial::Decode::decode(r)?),* })?,)*
+                    tag => return Err($crate::serial::unknown_tag(stringify!($ty), tag)),
+                })
+            }
+        }
+    };
+}
+pub(crate) use codec_enum;
+
+/// Implement `Encode` and `Decode` for a struct with named fields
+macro_rules! codec_struct {
+    ($ty:ident { $($f:ident),* $(,)? }) => {
+        impl $crate::serial::Encode for $ty {
+            fn encode(&self, w: &mut $crate::serial::Writer) -> std::io::Result<()> {
+                $($crate::serial::Encode::encode(&self.$f, w)?;)*
+                Ok(())
+            }
+        }
+        impl $crate::serial::Decode for $ty {
+            fn decode(r: &mut $crate::serial::Reader) -> std::io::Result<Self> {
+                Ok($ty {
+                    $($f: $crate::serial::Decode::decode(r)?,)*
+                })
+            }
+        }
+    };
+}
+pub(crate) use codec_struct;
+
+macro_rules! codec_unsigned {
+    ($($ty:ty),*) => {$(
+        impl Encode for $ty {
+            fn encode(&self, w: &mut Writer) -> io::Result<()> {
+                w.uint(*self as u64)
+            }
+        }
+        impl Decode for $ty {
+            fn decode(r: &mut Reader) -> io::Result<Self> {
+                <$ty>::try_from(r.uint()?).map_err(|_| invalid("integer out of range"))
+            }
+        }
+    )*};
+}
+codec_unsigned!(u8, u16, u32, u64, usize);
+
+macro_rules! codec_signed {
+    ($($ty:ty),*) => {$(
+        impl Encode for $ty {
+            fn encode(&self, w: &mut Writer) -> io::Result<()> {
+                let v = *self as i64;
+                w.uint(((v << 1) ^ (v >> 63)) as u64)
+            }
+        }
+        impl Decode for $ty {
+            fn decode(r: &mut Reader) -> io::Result<Self> {
+                let v = r.uint()?;
+                let v = ((v >> 1) as i64) ^ -((v & 1) as i64);
+                <$ty>::try_from(v).map_err(|_| invalid("integer out of range"))
+            }
+        }
+    )*};
+}
+codec_signed!(i8, i16, i32, i64);
+
+impl Encode for bool {
+    fn encode(&self, w: &mut Writer) -> io::Result<()> {
+        w.tag(u8::from(*self))
+    }
+}
+impl Decode for bool {
+    fn decode(r: &mut Reader) -> io::Result<Self> {
+        match r.tag()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            tag => Err(unknown_tag("bool", tag)),
+        }
+    }
+}
+
+impl Encode for f64 {
+    fn encode(&self, w: &mut Writer) -> io::Result<()> {
+        w.out.write_all(&self.to_bits().to_le_bytes())
+    }
+}
+impl Decode for f64 {
+    fn decode(r: &mut Reader) -> io::Result<Self> {
+        let mut b = [0; 8];
+        r.input.read_exact(&mut b)?;
+        Ok(f64::from_bits(u64::from_le_bytes(b)))
+    }
+}
+
+impl Encode for f32 {
+    fn encode(&self, w: &mut Writer) -> io::Result<()> {
+        w.out.write_all(&self.to_bits().to_le_bytes())
+    }
+}
+impl Decode for f32 {
+    fn decode(r: &mut Reader) -> io::Result<Self> {
+        let mut b = [0; 4];
+        r.input.read_exact(&mut b)?;
+        Ok(f32::from_bits(u32::from_le_bytes(b)))
+    }
+}
+
+impl Encode for String {
+    fn encode(&self, w: &mut Writer) -> io::Result<()> {
+        w.bytes(self.as_bytes())
+    }
+}
+impl Decode for String {
+    fn decode(r: &mut Reader) -> io::Result<Self> {
+        String::from_utf8(r.bytes()?).map_err(|_| invalid("string is not UTF-8"))
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode(&self, w: &mut Writer) -> io::Result<()> {
+        match self {
+            None => w.tag(0),
+            Some(v) => {
+                w.tag(1)?;
+                v.encode(w)
+            }
+        }
+    }
+}
+impl<T: Decode> Decode for Option<T> {
+    fn decode(r: &mut Reader) -> io::Result<Self> {
+        match r.tag()? {
+            0 => Ok(None),
+            1 => Ok(Some(T::decode(r)?)),
+            tag => Err(unknown_tag("Option", tag)),
+        }
+    }
+}
+
+impl<T: Encode> Encode for Box<T> {
+    fn encode(&self, w: &mut Writer) -> io::Result<()> {
+        (**self).encode(w)
+    }
+}
+impl<T: Decode> Decode for Box<T> {
+    fn decode(r: &mut Reader) -> io::Result<Self> {
+        T::decode(r).map(Box::new)
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode(&self, w: &mut Writer) -> io::Result<()> {
+        w.uint(self.len() as u64)?;
+        for item in self {
+            item.encode(w)?;
+        }
+        Ok(())
+    }
+}
+impl<T: Decode> Decode for Vec<T> {
+    fn decode(r: &mut Reader) -> io::Result<Self> {
+        let len = r.uint()?;
+        // not preallocated, so that a corrupt length cannot make us allocate a huge buffer
+        let mut items = vec![];
+        for _ in 0..len {
+            items.push(T::decode(r)?);
+        }
+        Ok(items)
+    }
+}
+
+/// Spans are not written, since they are only meaningful alongside the source of the schema
+impl Encode for codemap::Span {
+    fn encode(&self, _w: &mut Writer) -> io::Result<()> {
+        Ok(())
+    }
+}
+impl Decode for codemap::Span {
+    fn decode(r: &mut Reader) -> io::Result<Self> {
+        Ok(r.span)
+    }
+}
+
+impl Encode for Define {
+    fn encode(&self, w: &mut Writer) -> io::Result<()> {
+        let id = w.define_id(self);
+        w.uint(id)
+    }
+}
+impl Decode for Define {
+    fn decode(r: &mut Reader) -> io::Result<Self> {
+        let id = r.uint()?;
+        r.define(id)
+    }
+}
+
+/// Write a number which has no fixed size as its decimal text
+macro_rules! codec_decimal_text {
+    ($($ty:ty),*) => {$(
+        impl Encode for $ty {
+            fn encode(&self, w: &mut Writer) -> io::Result<()> {
+                self.to_string().encode(w)
+            }
+        }
+        impl Decode for $ty {
+            fn decode(r: &mut Reader) -> io::Result<Self> {
+                <$ty>::from_str(&String::decode(r)?).map_err(|_| invalid("bad number"))
+            }
+        }
+    )*};
+}
+codec_decimal_text!(
+    num_bigint::BigInt,
+    num_bigint::BigUint,
+    bigdecimal::BigDecimal
+);
@@ -0,0 +1,145 @@
+//! Traversal of a compiled `model::Pattern` tree.
+//!
+//! Implement `Visitor`, overriding the methods for the patterns of interest, and pass it to
+//! `walk_pattern()`.  Each default method continues the walk into the pattern's children, so an
+//! overriding method should call `walker.walk()` (or the default behaviour it replaces) if it
+//! wants the walk to carry on below that point.
+//!
+//! A `ref` is followed into its definition only the first time the walk reaches that
+//! definition, so recursive grammars are walked once and the walk always terminates.
+
+use crate::datatype::{DatatypeValues, Datatypes};
+use crate::model::{DefineRule, NameClass, Pattern};
+use std::collections::HashSet;
+
+/// Callbacks for each kind of pattern encountered by a `Walker`
+pub trait Visitor {
+    fn visit_element(&mut self, walker: &mut Walker, name_class: &NameClass, content: &Pattern) {
+        let _ = name_class;
+        walker.walk(self, content)
+    }
+
+    fn visit_attribute(&mut self, walker: &mut Walker, name_class: &NameClass, content: &Pattern) {
+        let _ = name_class;
+        walker.walk(self, content)
+    }
+
+    fn visit_choice(&mut self, walker: &mut Walker, alternatives: &[Pattern]) {
+        walker.walk_all(self, alternatives)
+    }
+
+    fn visit_interleave(&mut self, walker: &mut Walker, members: &[Pattern]) {
+        walker.walk_all(self, members)
+    }
+
+    fn visit_group(&mut self, walker: &mut Walker, members: &[Pattern]) {
+        walker.walk_all(self, members)
+    }
+
+    fn visit_mixed(&mut self, walker: &mut Walker, content: &Pattern) {
+        walker.walk(self, content)
+    }
+
+    fn visit_optional(&mut self, walker: &mut Walker, content: &Pattern) {
+        walker.walk(self, content)
+    }
+
+    fn visit_zero_or_more(&mut self, walker: &mut Walker, content: &Pattern) {
+        walker.walk(self, content)
+    }
+
+    fn visit_one_or_more(&mut self, walker: &mut Walker, content: &Pattern) {
+        walker.walk(self, content)
+    }
+
+    fn visit_list(&mut self, walker: &mut Walker, content: &Pattern) {
+        walker.walk(self, content)
+    }
+
+    /// Called the first time the walk reaches a given definition; later references to the
+    /// same definition (including recursive ones) are skipped.  `span` and `name` are those
+    /// of the reference.
+    fn visit_ref(
+        &mut self,
+        walker: &mut Walker,
+        span: codemap::Span,
+        name: &str,
+        rule: &DefineRule,
+    ) {
+        let _ = (span, name);
+        walker.walk(self, rule.pattern())
+    }
+
+    fn visit_data(&mut self, walker: &mut Walker, datatype: &Datatypes, except: Option<&Pattern>) {
+        let _ = datatype;
+        if let Some(except) = except {
+            walker.walk(self, except)
+        }
+    }
+
+    fn visit_value(&mut self, datatype: &DatatypeValues) {
+        let _ = datatype;
+    }
+
+    fn visit_text(&mut self) {}
+
+    fn visit_empty(&mut self) {}
+
+    fn visit_not_allowed(&mut self) {}
+}
+
+/// Drives a `Visitor` over a pattern tree, remembering which definitions have been entered
+#[derive(Default)]
+pub struct Walker {
+    seen: HashSet<usize>,
+}
+
+impl Walker {
+    /// Calls the visitor method corresponding to the given pattern
+    pub fn walk<V: Visitor + ?Sized>(&mut self, visitor: &mut V, pattern: &Pattern) {
+        match pattern {
+            Pattern::Element(name_class, content) => {
+                visitor.visit_element(self, name_class, content)
+            }
+            Pattern::Attribute(name_class, content) => {
+                visitor.visit_attribute(self, name_class, content)
+            }
+            Pattern::Choice(alternatives) => visitor.visit_choice(self, alternatives),
+            Pattern::Interleave(members) => visitor.visit_interleave(self, members),
+            Pattern::Group(members) => visitor.visit_group(self, members),
+            Pattern::Mixed(content) => visitor.visit_mixed(self, content),
+            Pattern::Optional(content) => visitor.visit_optional(self, content),
+            Pattern::ZeroOrMore(content) => visitor.visit_zero_or_more(self, content),
+            Pattern::OneOrMore(content) => visitor.visit_one_or_more(self, content),
+            Pattern::List(content) => visitor.visit_list(self, content),
+            Pattern::Ref(span, name, pat_ref) => {
+                let ptr = pat_ref.0.as_ptr() as usize;
+                if !self.seen.insert(ptr) {
+                    return;
+                }
+                if let Some(rule) = pat_ref.0.borrow().as_ref() {
+                    visitor.visit_ref(self, *span, name, rule)
+                }
+            }
+            Pattern::DatatypeName { datatype, except } => {
+                visitor.visit_data(self, datatype, except.as_deref())
+            }
+            Pattern::DatatypeValue { datatype } => visitor.visit_value(datatype),
+            Pattern::Text => visitor.visit_text(),
+            Pattern::Empty => visitor.visit_empty(),
+            Pattern::NotAllowed => visitor.visit_not_allowed(),
+        }
+    }
+
+    /// Walks each of the given patterns in turn
+    pub fn walk_all<V: Visitor + ?Sized>(&mut self, visitor: &mut V, patterns: &[Pattern]) {
+        for pattern in patterns {
+            self.walk(visitor, pattern)
+        }
+    }
+}
+
+/// Walks the given pattern, and everything reachable from it, with a fresh `Walker`
+pub fn walk_pattern<V: Visitor + ?Sized>(visitor: &mut V, pattern: &Pattern) {
+    Walker::default().walk(visitor, pattern)
+}
@@ -102,7 +102,8 @@ pub struct DatatypeValuePattern(
     pub Option<DatatypeName>,
     pub Literal,
     /// In-scope namespace bindings (prefix, uri) for QName value resolution.
-    /// Uses "" as prefix for the default namespace.
+    /// Uses "" as prefix for the default namespace.  Empty in the compact syntax, where the
+    /// bindings come from the schema's namespace declarations instead.
     pub Vec<(String, String)>,
 );
 
@@ -0,0 +1,274 @@
+//! Longer explanations of the error codes shown in diagnostics, printed by `rng --explain`
+
+/// Each error code, with a paragraph describing what the error means and how it is usually fixed
+static EXPLANATIONS: &[(&str, &str)] = &[
+    // errors found while validating a document
+    (
+        "xml",
+        "The document is not well-formed XML, so it could not be validated at all.  Typical \
+         causes are a missing end tag, mismatched quotes around an attribute value, or a stray \
+         '<' or '&' in text (write these as '&lt;' and '&amp;').",
+    ),
+    (
+        "trailing-content",
+        "Something other than whitespace, comments or processing instructions follows the end of \
+         the document element, such as stray text or a second root element.  An XML document \
+         has exactly one root element, so move the content inside it, or remove it.",
+    ),
+    (
+        "misplaced-declaration",
+        "An XML declaration such as `<?xml version=\"1.0\"?>` appears somewhere other than at the \
+         very start of the document.  A document may have only one declaration, and nothing, not \
+         even whitespace or a comment, may come before it.  This often happens when documents \
+         are concatenated; remove the extra declaration.",
+    ),
+    (
+        "not-allowed",
+        "The document contains an element, attribute or piece of text which the schema does not \
+         allow at that point.  The help text lists what the schema would have accepted instead.  \
+         Check the spelling and namespace of the name, the order of the element's children, and \
+         whether a required child or attribute is missing.",
+    ),
+    (
+        "undefined-namespace-prefix",
+        "A name uses a namespace prefix that has not been declared.  In a document, add an \
+         'xmlns:prefix' declaration to the element or one of its ancestors, usually the root \
+         element.  In a compact syntax schema, add a 'namespace prefix = \"uri\"' declaration \
+         (or an xmlns attribute in the XML syntax).  Otherwise, correct the prefix.",
+    ),
+    (
+        "undefined-entity",
+        "The document refers to an entity such as '&name;' which is neither one of the five \
+         predefined XML entities nor declared in the document's DTD internal subset.  Declare the \
+         entity, or replace the reference with the character it stands for or a numeric \
+         character reference like '&#xA0;'.",
+    ),
+    (
+        "invalid-entity-reference",
+        "A '&' in text or an attribute value does not begin a complete entity or character \
+         reference ending in ';'.  A literal ampersand must be written as '&amp;'.",
+    ),
+    (
+        "illegal-character-reference",
+        "A numeric character reference such as '&#0;' refers to a code point which may not \
+         appear in an XML document, for example most control characters or a UTF-16 \
+         surrogate.  A character outside the Basic Multilingual Plane must be referred to by its \
+         own code point, as in '&#x1F600;', and not by a pair of surrogates like \
+         '&#xD83D;&#xDE00;'.  Remove the reference or replace it with a legal character.",
+    ),
+    (
+        "duplicate-namespace-declaration",
+        "The same namespace prefix (or the default namespace) is declared twice on a single \
+         element.  Remove one of the declarations.",
+    ),
+    (
+        "empty-namespace-declaration",
+        "A prefixed namespace declaration like 'xmlns:p=\"\"' gives an empty namespace URI, which \
+         XML namespaces only permit for the default namespace.  Give the prefix a URI, or remove \
+         the declaration.",
+    ),
+    (
+        "duplicate-attribute",
+        "An element has two attributes with the same name, which XML does not allow.  Two \
+         prefixes bound to the same namespace URI count as the same name, so 'p:a' and 'q:a' \
+         clash when p and q refer to one namespace.  Remove one of the attributes.",
+    ),
+    (
+        "duplicate-id",
+        "Two values of a datatype with ID semantics (such as xsd:ID) are the same, but ID values \
+         must be unique within the document (or across all documents, with --shared-ids).  \
+         Rename one of them, and update any IDREF values which referred to it.",
+    ),
+    (
+        "dangling-idref",
+        "A value of a datatype with IDREF semantics (such as xsd:IDREF) refers to an ID which no \
+         attribute in the document has (or, with --shared-ids, no attribute in this or an \
+         earlier document).  Correct the reference, or add the missing ID.",
+    ),
+    (
+        "invalid-list-value",
+        "A value matched by a 'list' pattern has the wrong number of whitespace-separated items, \
+         or one of its items is not of the expected datatype.  Check for missing or extra items, \
+         and for stray separators such as commas, which are not treated as whitespace.",
+    ),
+    (
+        "invalid-value",
+        "A value is not of the datatype (or is not the fixed value) that the schema expects, or \
+         is of the datatype but fails one of the facets given for it, such as a maxInclusive \
+         bound, a maxLength, or a pattern.  The message names the facet, or else what was \
+         expected; change the value to satisfy it.",
+    ),
+    (
+        "xinclude",
+        "An XInclude 'include' element could not be replaced by the document it refers to.  Only \
+         whole XML documents named by a relative 'href' can be included, so 'parse=\"text\"', \
+         'xpointer' and URLs are not supported.  Check that the file exists relative to the \
+         document containing the 'include', and that it does not include itself.",
+    ),
+    (
+        "missing-required-content",
+        "An element ends before all of the content that the schema requires of it has been \
+         seen, most often because a required child element is missing.  The elements that could \
+         have come next are listed; an optional one may be among them, so check the schema for \
+         which is required.  Add the missing content before the end tag.",
+    ),
+    // errors found while compiling a schema
+    (
+        "io",
+        "A schema file, or a file it includes or refers to with 'external', could not be read.  \
+         Check that the path is correct relative to the including file, and that it is readable.",
+    ),
+    (
+        "syntax",
+        "A schema file could not be parsed, either as the compact syntax (.rnc) or as the XML \
+         syntax (.rng), which is chosen from the file extension.  The diagnostic points at the \
+         first place the parser could not make sense of.",
+    ),
+    (
+        "duplicate-definition",
+        "The same name is defined more than once in a grammar without a combine method.  Either \
+         remove one definition, or use '|=' or '&=' (combine=\"choice\" or \
+         combine=\"interleave\" in the XML syntax) to say how the definitions are merged.",
+    ),
+    (
+        "incompatible-combine",
+        "Definitions of the same name use different combine methods, for example one with '|=' \
+         and another with '&=', or more than one of them has no combine method.  All but at most \
+         one definition of a name must give the same method.",
+    ),
+    (
+        "undefined-reference",
+        "A pattern refers to a name which is not defined anywhere in the grammar.  Check the \
+         spelling, and whether the definition lives in a file which should be included.  A \
+         'parent' reference must name a definition in the enclosing grammar.",
+    ),
+    (
+        "undefined-datatype-prefix",
+        "A datatype such as 'xsd:integer' uses a prefix that has not been declared with \
+         'datatypes'.  The 'xsd' prefix is predeclared; other datatype libraries must be \
+         declared before use.",
+    ),
+    (
+        "duplicate-prefix",
+        "A namespace or datatypes prefix is declared twice in the same schema file with \
+         different URIs.  Remove or rename one of the declarations.",
+    ),
+    (
+        "duplicate-parameter",
+        "A datatype is given the same parameter (facet) more than once, for example two \
+         'maxLength' parameters.  Only the 'pattern' parameter may be repeated.",
+    ),
+    (
+        "duplicate-start",
+        "A grammar has more than one 'start' definition without a combine method.  Remove one, \
+         or combine them with '|=' or '&='.",
+    ),
+    (
+        "missing-start",
+        "A grammar has no 'start' definition, so there is no pattern for the document element to \
+         match.  Add 'start = ...' naming the allowed root element(s).",
+    ),
+    (
+        "no-parent-grammar",
+        "A 'parent' reference is used in the outermost grammar, which has no parent.  Use an \
+         ordinary reference instead, or nest the grammar.",
+    ),
+    (
+        "datatype",
+        "A datatype or one of its parameters is not valid, for example an unknown datatype name, \
+         a facet that does not apply to that datatype, a facet value that is not a valid \
+         instance of the datatype, or facets which contradict each other.",
+    ),
+    (
+        "recursive-reference",
+        "A definition refers to itself without an intervening 'element' pattern, so expanding it \
+         would never finish.  Recursion in RELAX NG must pass through an element, for example \
+         'list = element item { text }, list?' rather than 'list = text, list?'.",
+    ),
+    (
+        "element-in-attribute",
+        "An 'element' pattern appears within the content of an 'attribute' pattern.  Attribute \
+         values are plain text, so they cannot contain elements.",
+    ),
+    (
+        "recursive-include",
+        "A schema file includes itself, directly or through other included files.  Break the \
+         cycle, moving the shared definitions into a file that both include.",
+    ),
+    (
+        "remote-href",
+        "An 'include' or 'external' names a URL, such as one starting 'http:'.  Schemas are only \
+         read from local files, so that compiling a schema never makes network requests.  \
+         Download the schema and refer to it by a path relative to the including file.",
+    ),
+    (
+        "include-not-grammar",
+        "A file named by 'include' must contain a grammar, but this one contains a bare pattern.  \
+         Wrap its content in 'start = ...', or refer to it with 'external' instead.",
+    ),
+    (
+        "override-missing",
+        "The body of an 'include' overrides a definition (or 'start') which the included file \
+         does not define.  Check the name, or add the definition to the main grammar instead of \
+         the include body.",
+    ),
+    (
+        "restricted-pattern",
+        "A pattern appears somewhere the RELAX NG specification forbids it (section 7, \
+         'Restrictions'), for example an attribute inside 'list', text or an element inside \
+         'list' or 'data', or 'text' on both sides of an interleave.  Restructure the schema so \
+         that the pattern only appears where it is allowed.",
+    ),
+    (
+        "string-sequence",
+        "A 'group' or 'interleave' (including ',' and '&' in the compact syntax) combines a data \
+         value, 'value' or 'list' with text, an element, or another data value (section 7.2).  \
+         The content of an element is either a single data value or a mixture of text and \
+         elements, so these cannot be combined.  Use 'list' to give a sequence of data values, \
+         or wrap the data value in an element or attribute of its own.",
+    ),
+    (
+        "xmlns-attribute",
+        "The schema declares an attribute named 'xmlns', or an attribute in the \
+         'http://www.w3.org/2000/xmlns' namespace.  Namespace declarations are not attributes \
+         as far as RELAX NG is concerned, so they are never matched and need not be declared.",
+    ),
+    (
+        "name-class-except",
+        "The 'except' of a name class contains a name class which could never be excluded: \
+         'anyName' inside the except of 'anyName' or 'nsName', or 'nsName' inside the except of \
+         'nsName'.  List the specific names or namespaces to exclude instead.",
+    ),
+    (
+        "overlapping-attributes",
+        "Two attribute patterns which may both apply to the same element have name classes that \
+         could match the same attribute name.  An element cannot have the same attribute twice, \
+         so make the name classes disjoint, for example with an 'except'.",
+    ),
+    (
+        "overlapping-elements",
+        "Two element patterns on either side of an 'interleave' could match an element with the \
+         same name, so the validator could not tell which side it belongs to.  Make the name \
+         classes disjoint, or restructure the content model.",
+    ),
+    (
+        "nesting-too-deep",
+        "Patterns in the schema are nested too deeply to be checked against the restrictions \
+         of the RELAX NG specification, counting each reference to a definition as one level of \
+         nesting.  Such schemas are usually generated; split the deeply nested patterns into \
+         separate definitions that are referred to in sequence rather than one inside another.",
+    ),
+];
+
+/// The explanation for the given error code, if it is one we know about
+pub fn explanation(code: &str) -> Option<&'static str> {
+    EXPLANATIONS
+        .iter()
+        .find(|(c, _)| *c == code)
+        .map(|(_, text)| *text)
+}
+
+/// All the error codes which have an explanation
+pub fn codes() -> impl Iterator<Item = &'static str> {
+    EXPLANATIONS.iter().map(|(code, _)| *code)
+}
@@ -0,0 +1,70 @@
+//! JUnit XML reports of validation results, printed by `rng validate --format junit` for CI
+//! systems to display
+
+use relaxng_validator::OwnedValidatorError;
+use std::io;
+use std::io::Write;
+
+/// The result of validating one document, which is reported as one `testcase`
+pub enum Outcome {
+    Passed,
+    /// the errors found, of which there is at least one
+    Failed(Vec<OwnedValidatorError>),
+    /// not validated, because an earlier document failed and `--keep-going` was not given
+    Skipped,
+}
+
+/// Write a `testsuite` named after the schema, with a `testcase` for each document
+pub fn write(out: &mut impl Write, schema: &str, cases: &[(String, Outcome)]) -> io::Result<()> {
+    let count = |f: fn(&Outcome) -> bool| cases.iter().filter(|(_, o)| f(o)).count();
+    writeln!(out, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(
+        out,
+        "<testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"0\" skipped=\"{}\">",
+        escape(schema),
+        cases.len(),
+        count(|o| matches!(o, Outcome::Failed(_))),
+        count(|o| matches!(o, Outcome::Skipped)),
+    )?;
+    for (doc, outcome) in cases {
+        let start = format!(
+            "  <testcase name=\"{}\" classname=\"{}\"",
+            escape(doc),
+            escape(schema)
+        );
+        match outcome {
+            Outcome::Passed => writeln!(out, "{start}/>")?,
+            Outcome::Skipped => writeln!(out, "{start}>\n    <skipped/>\n  </testcase>")?,
+            Outcome::Failed(errors) => {
+                writeln!(out, "{start}>")?;
+                write!(
+                    out,
+                    "    <failure message=\"{}\" type=\"{}\">",
+                    escape(&errors[0].to_string()),
+                    errors[0].code()
+                )?;
+                for err in errors {
+                    writeln!(out, "{}:{}", escape(doc), escape(&err.to_string()))?;
+                }
+                writeln!(out, "</failure>\n  </testcase>")?;
+            }
+        }
+    }
+    writeln!(out, "</testsuite>")
+}
+
+/// Escape text for use in either element content or a double-quoted attribute value
+fn escape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\n' => result.push_str("&#10;"),
+            c => result.push(c),
+        }
+    }
+    result
+}
@@ -1,30 +1,203 @@
+mod explain;
+mod junit;
+
 use relaxng_model::{Compiler, Syntax};
-use relaxng_validator::Validator;
+use relaxng_validator::subtree::{Selector, Subtrees};
+use relaxng_validator::{IdScope, OwnedValidatorError, Validator};
 
+use relaxng_model::model::DefineRule;
+use std::cell::{Cell, RefCell};
 use std::fs::File;
-use std::io::Read;
-use std::path::PathBuf;
+use std::io;
+use std::io::{Read, Write};
+use std::panic;
+use std::path::{Path, PathBuf};
 use std::process::exit;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
 
 use structopt::StructOpt;
+use xmlparser::{ElementEnd, Token};
 
 #[derive(Debug, StructOpt)]
 enum Cli {
-    Validate { schema: PathBuf, xml: Vec<PathBuf> },
+    Validate(ValidateArgs),
+    /// Print the pattern that a schema compiles to, as used by the validator
+    Dump {
+        schema: PathBuf,
+    },
+    /// Compile each of the given schemas, without validating any documents, and report which
+    /// fail.  A directory stands for all the .rnc and .rng files directly within it
+    Check {
+        schemas: Vec<PathBuf>,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+struct ValidateArgs {
+    /// Print any warnings produced while compiling the schema, including for definitions which
+    /// the schema never uses
+    #[structopt(short = "W", long)]
+    warnings: bool,
+    /// Validate only the subtrees rooted at elements with this name, given as `local` or
+    /// `{namespace-uri}local`, each one separately against the schema's start pattern
+    #[structopt(long)]
+    select: Option<Selector>,
+    /// Require xsd:ID values to be unique across all the given documents, not just within each
+    #[structopt(long)]
+    shared_ids: bool,
+    /// Report up to this many errors in each document (0 for no limit).  Errors after the first
+    /// are found by skipping past the problem, and may be consequences of the earlier ones.  An
+    /// undeclared namespace prefix is reported, and then treated as mapping to no namespace
+    #[structopt(long, default_value = "1")]
+    max_errors: usize,
+    /// After a document fails validation, go on to validate the remaining documents rather than
+    /// exiting immediately.  The exit status still reports the failure
+    #[structopt(long)]
+    keep_going: bool,
+    /// Validate only the first n elements of each document (of each subtree, with --select), as
+    /// a quick check of very large documents.  Finding no errors in them does not show that the
+    /// whole document is valid
+    #[structopt(long, value_name = "n")]
+    element_budget: Option<usize>,
+    /// Validate this many documents at a time, each on its own thread (0 for one per CPU).  The
+    /// results are still reported in the order the documents were given
+    #[structopt(long, default_value = "1", conflicts_with = "shared-ids")]
+    jobs: usize,
+    /// How to report the results: `human` prints diagnostics, while `junit` also prints a JUnit
+    /// XML report to stdout, with a testcase for each document
+    #[structopt(long, default_value = "human", possible_values = &["human", "junit"])]
+    format: Format,
+    /// Print a line for each start and end tag as it is validated, saying whether the schema
+    /// accepted it and, after a start tag, whether the element's content is required
+    #[structopt(long)]
+    verbose: bool,
+    schema: PathBuf,
+    xml: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Human,
+    Junit,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(Format::Human),
+            "junit" => Ok(Format::Junit),
+            _ => Err(format!("unknown format {s:?}")),
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+struct Opts {
+    /// Print a longer explanation of the error with the given code, as shown in square brackets
+    /// after `error` in a diagnostic (e.g. `not-allowed`)
+    #[structopt(long, value_name = "code")]
+    explain: Option<String>,
+    #[structopt(subcommand)]
+    command: Option<Cli>,
 }
 
 fn main() {
-    match Cli::from_args() {
-        Cli::Validate { schema, xml } => validate(schema, xml),
+    let opts = Opts::from_args();
+    if let Some(code) = opts.explain {
+        return explain(&code);
+    }
+    match opts.command {
+        Some(Cli::Validate(args)) => validate(args),
+        Some(Cli::Dump { schema }) => dump(schema),
+        Some(Cli::Check { schemas }) => check(schemas),
+        None => {
+            Opts::clap().print_help().unwrap();
+            println!();
+            exit(1);
+        }
     }
 }
 
-fn validate(schema: PathBuf, xmls: Vec<PathBuf>) {
-    let syntax = match schema.extension().and_then(|e| e.to_str()) {
+fn explain(code: &str) {
+    match explain::explanation(code) {
+        Some(text) => println!("{text}"),
+        None => {
+            eprintln!("No explanation for error code {code:?}; known codes are:");
+            for code in explain::codes() {
+                eprintln!("  {code}");
+            }
+            exit(1);
+        }
+    }
+}
+
+fn syntax(schema: &Path) -> Syntax {
+    match schema.extension().and_then(|e| e.to_str()) {
         Some("rng") => Syntax::Xml,
         _ => Syntax::Compact,
-    };
-    let mut compiler = Compiler::new(relaxng_model::FsFiles, syntax);
+    }
+}
+
+fn compile(schema: &Path) -> Compiler<relaxng_model::FsFiles> {
+    Compiler::new(relaxng_model::FsFiles, syntax(schema))
+}
+
+fn check(schemas: Vec<PathBuf>) {
+    let mut compact = vec![];
+    let mut xml = vec![];
+    for path in schemas {
+        let files = if path.is_dir() {
+            let entries = path
+                .read_dir()
+                .and_then(|dir| dir.map(|entry| entry.map(|e| e.path())).collect());
+            let mut files: Vec<PathBuf> = match entries {
+                Ok(files) => files,
+                Err(err) => {
+                    eprintln!("Failed to read directory {path:?}: {err}");
+                    exit(1);
+                }
+            };
+            files.retain(|f| matches!(f.extension().and_then(|e| e.to_str()), Some("rnc" | "rng")));
+            files.sort();
+            files
+        } else {
+            vec![path]
+        };
+        for file in files {
+            match syntax(&file) {
+                Syntax::Compact => compact.push(file),
+                Syntax::Xml => xml.push(file),
+            }
+        }
+    }
+    let mut failed = 0;
+    for (syntax, files) in [(Syntax::Compact, compact), (Syntax::Xml, xml)] {
+        let mut compiler = Compiler::new(relaxng_model::FsFiles, syntax);
+        for (file, result) in files.iter().zip(compiler.compile_all(&files)) {
+            match result {
+                Ok(_) => println!("ok     {}", file.display()),
+                Err(err) => {
+                    println!("FAILED {}", file.display());
+                    compiler.dump_diagnostic(&err);
+                    failed += 1;
+                }
+            }
+        }
+    }
+    if failed > 0 {
+        eprintln!("{failed} schema(s) failed to compile");
+        exit(1);
+    }
+}
+
+fn dump(schema: PathBuf) {
+    let mut compiler = compile(&schema);
     let model = match compiler.compile(&schema) {
         Ok(m) => m,
         Err(err) => {
@@ -32,29 +205,359 @@ fn validate(schema: PathBuf, xmls: Vec<PathBuf>) {
             exit(1);
         }
     };
-    for xml in xmls {
-        let mut f = File::open(&xml).expect("open example xml");
-        let mut doc = String::new();
-        f.read_to_string(&mut doc).expect("read xml");
-        let src = doc.clone();
-        let reader = xmlparser::Tokenizer::from(&src[..]);
-        let mut v = Validator::new(model.clone(), reader);
-        //v.assert_health();
-        eprintln!("Validating {xml:?}");
-        loop {
-            match v.validate_next() {
-                Some(Ok(())) => {}
-                Some(Err(err)) => {
-                    let (map, d) = v.diagnostic(xml.to_string_lossy().to_string(), doc, &err);
-                    let mut emitter = codemap_diagnostic::Emitter::stderr(
-                        codemap_diagnostic::ColorConfig::Auto,
-                        Some(&map),
+    // building the validator's pattern arena is known to panic for some schemas, so report
+    // that as a failure rather than aborting with a backtrace
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        let v = Validator::new(model, xmlparser::Tokenizer::from(""));
+        let mut out = io::stdout().lock();
+        v.dump_pattern(&mut out)?;
+        writeln!(out, "{} patterns", v.pattern_count())
+    }));
+    match result {
+        Ok(Ok(())) => {}
+        Ok(Err(err)) => {
+            eprintln!("Failed to write dump: {err}");
+            exit(1);
+        }
+        Err(_) => {
+            eprintln!("Internal error while building the pattern for {schema:?}");
+            exit(3);
+        }
+    }
+}
+
+fn validate(args: ValidateArgs) {
+    let mut compiler = compile(&args.schema);
+    compiler.set_warn_unused_definitions(args.warnings);
+    let model = match compiler.compile(&args.schema) {
+        Ok(m) => m,
+        Err(err) => {
+            compiler.dump_diagnostic(&err);
+            exit(1);
+        }
+    };
+    if args.warnings {
+        compiler.dump_warnings();
+    }
+    let jobs = match args.jobs {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        jobs => jobs,
+    };
+    let mut parallel = (jobs > 1).then(|| validate_parallel(&args, &model, jobs));
+    let mut shared_ids = args.shared_ids.then(IdScope::new);
+    let mut failed = false;
+    let mut cases = vec![];
+    for (i, xml) in args.xml.iter().enumerate() {
+        if failed && !args.keep_going {
+            cases.push((xml.display().to_string(), junit::Outcome::Skipped));
+            continue;
+        }
+        let errors = match parallel.as_mut() {
+            Some(results) => {
+                let (report, errors) = results[i].take().unwrap();
+                io::stderr().write_all(&report).unwrap();
+                errors
+            }
+            None => validate_file(
+                &args,
+                model.clone(),
+                xml,
+                &mut shared_ids,
+                &mut Report::Stderr,
+            ),
+        };
+        let outcome = if errors.is_empty() {
+            junit::Outcome::Passed
+        } else {
+            failed = true;
+            junit::Outcome::Failed(errors)
+        };
+        cases.push((xml.display().to_string(), outcome));
+    }
+    if args.format == Format::Junit {
+        let schema = args.schema.display().to_string();
+        if let Err(err) = junit::write(&mut io::stdout().lock(), &schema, &cases) {
+            eprintln!("Failed to write report: {err}");
+            exit(1);
+        }
+    }
+    if failed {
+        exit(2);
+    }
+}
+
+/// Validate all the documents using the given number of threads, giving for each document the
+/// messages to print about it and the errors found.  Without `--keep-going`, the documents after
+/// one that fails are validated anyway, but their results are not reported.
+fn validate_parallel(
+    args: &ValidateArgs,
+    model: &Rc<RefCell<Option<DefineRule>>>,
+    jobs: usize,
+) -> Vec<Option<(Vec<u8>, Vec<OwnedValidatorError>)>> {
+    // the model cannot be shared between threads, so each thread reads its own copy
+    let mut schema = vec![];
+    relaxng_model::model::serialize(model, &mut schema).expect("serialize schema");
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(vec![None; args.xml.len()]);
+    thread::scope(|scope| {
+        for _ in 0..jobs.min(args.xml.len()) {
+            scope.spawn(|| {
+                let model = relaxng_model::model::deserialize(&mut &schema[..])
+                    .expect("deserialize schema");
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(xml) = args.xml.get(i) else {
+                        break;
+                    };
+                    let mut report = vec![];
+                    let errors = validate_file(
+                        args,
+                        model.clone(),
+                        xml,
+                        &mut None,
+                        &mut Report::Buffer(&mut report),
                     );
-                    emitter.emit(&d[..]);
-                    exit(2);
+                    results.lock().unwrap()[i] = Some((report, errors));
+                }
+            });
+        }
+    });
+    results.into_inner().unwrap()
+}
+
+/// Where the messages about a document are printed
+enum Report<'a> {
+    Stderr,
+    /// a buffer, whose contents are printed to stderr once the documents before this one have
+    /// been reported
+    Buffer(&'a mut Vec<u8>),
+}
+
+impl Report<'_> {
+    fn line(&mut self, message: &str) {
+        match self {
+            Report::Stderr => eprintln!("{message}"),
+            Report::Buffer(buf) => writeln!(buf, "{message}").unwrap(),
+        }
+    }
+
+    fn emit(&mut self, map: &codemap::CodeMap, diagnostics: &[codemap_diagnostic::Diagnostic]) {
+        let mut emitter = match self {
+            Report::Stderr => codemap_diagnostic::Emitter::stderr(
+                codemap_diagnostic::ColorConfig::Auto,
+                Some(map),
+            ),
+            Report::Buffer(buf) => codemap_diagnostic::Emitter::vec(buf, Some(map)),
+        };
+        emitter.emit(diagnostics);
+    }
+}
+
+/// Validate one document, returning the errors reported
+fn validate_file(
+    args: &ValidateArgs,
+    model: Rc<RefCell<Option<DefineRule>>>,
+    xml: &Path,
+    shared_ids: &mut Option<IdScope>,
+    report: &mut Report,
+) -> Vec<OwnedValidatorError> {
+    let limit = (args.max_errors > 0).then_some(args.max_errors);
+    let mut f = File::open(xml).expect("open example xml");
+    let mut doc = String::new();
+    f.read_to_string(&mut doc).expect("read xml");
+    let src = doc.clone();
+    let reader = xmlparser::Tokenizer::from(&src[..]);
+    report.line(&format!("Validating {xml:?}"));
+    let mut errors = vec![];
+    if let Some(ref selector) = args.select {
+        let mut count = 0;
+        for subtree in Subtrees::new(reader, selector.clone()) {
+            count += 1;
+            let tokens: Vec<_> = match subtree {
+                Ok(tokens) => tokens.into_iter().map(Ok).collect(),
+                Err(err) => vec![Err(err)],
+            };
+            let trail = args.verbose.then(Trail::default);
+            let tokens = watched(tokens, trail.as_ref());
+            let v = Validator::from_tokens(model.clone(), tokens).with_source(&src);
+            let v = recovering(with_budget(v, args.element_budget), limit);
+            let remaining = limit.map(|max| max - errors.len());
+            errors.extend(validate_tokens(
+                xml,
+                &doc,
+                with_ids(v, shared_ids),
+                remaining,
+                trail,
+                report,
+            ));
+            if limit.is_some_and(|max| errors.len() >= max) {
+                break;
+            }
+        }
+        if count == 0 {
+            report.line(&format!(
+                "No {selector} elements found in {xml:?}; nothing validated"
+            ));
+        }
+    } else {
+        let trail = args.verbose.then(Trail::default);
+        let tokens = watched(reader, trail.as_ref());
+        let v = Validator::from_tokens(model, tokens).with_source(&src);
+        let v = recovering(with_budget(v, args.element_budget), limit);
+        errors = validate_tokens(xml, &doc, with_ids(v, shared_ids), limit, trail, report);
+    }
+    errors
+}
+
+/// Enable the recovery options that only matter when more than one error will be reported
+fn recovering(v: Validator, limit: Option<usize>) -> Validator {
+    if limit == Some(1) {
+        v
+    } else {
+        v.with_undefined_prefix_recovery()
+    }
+}
+
+fn with_budget(v: Validator, element_budget: Option<usize>) -> Validator {
+    match element_budget {
+        Some(elements) => v.with_element_budget(elements),
+        None => v,
+    }
+}
+
+fn with_ids<'a>(v: Validator<'a>, shared_ids: &'a mut Option<IdScope>) -> Validator<'a> {
+    match shared_ids {
+        Some(ids) => v.with_id_scope(ids),
+        None => v,
+    }
+}
+
+/// Prints diagnostics for up to `max_errors` errors (or all of them, given `None`), returning
+/// the errors printed
+fn validate_tokens(
+    xml: &Path,
+    doc: &str,
+    mut v: Validator,
+    max_errors: Option<usize>,
+    mut trail: Option<Trail>,
+    report: &mut Report,
+) -> Vec<OwnedValidatorError> {
+    //v.assert_health();
+    let mut errors = vec![];
+    while let Some(result) = v.validate_next() {
+        if let Some(trail) = &mut trail {
+            trail.describe(&v, result.is_ok(), report);
+        }
+        if let Err(err) = result {
+            let (map, mut d) =
+                v.diagnostic(xml.to_string_lossy().to_string(), doc.to_string(), &err);
+            if !v.error_path().is_empty() {
+                d.push(codemap_diagnostic::Diagnostic {
+                    level: codemap_diagnostic::Level::Note,
+                    message: format!("in element {}", v.error_path()),
+                    code: None,
+                    spans: vec![],
+                });
+            }
+            report.emit(&map, &d[..]);
+            errors.push(err.into_owned(doc));
+            if max_errors.is_some_and(|max| errors.len() >= max) {
+                break;
+            }
+        }
+    }
+    if v.stopped_early() {
+        report.line(&format!(
+            "Stopped at the element budget; the rest of {xml:?} was not validated"
+        ));
+    }
+    errors
+}
+
+/// Pass on the tokens, recording each in the trail, if there is one, as the validator reads it
+fn watched<'a>(
+    tokens: impl IntoIterator<Item = Result<Token<'a>, xmlparser::Error>> + 'a,
+    trail: Option<&Trail<'a>>,
+) -> impl Iterator<Item = Result<Token<'a>, xmlparser::Error>> + 'a {
+    let last = trail.map(|trail| trail.last.clone());
+    tokens.into_iter().inspect(move |tok| {
+        if let (Some(last), Ok(tok)) = (&last, tok) {
+            last.set(Some(*tok));
+        }
+    })
+}
+
+/// The elements of a document as they are validated, which `--verbose` describes tag by tag
+#[derive(Default)]
+struct Trail<'a> {
+    /// the token most recently read by the validator, and not yet described
+    last: Rc<Cell<Option<Token<'a>>>>,
+    /// the names of the open elements, each with whether the validator is skipping its content
+    /// after an error
+    open: Vec<(String, bool)>,
+    /// the name of the element whose start tag is being read, and whether its attributes have
+    /// been accepted so far
+    start: Option<(String, bool)>,
+}
+
+impl Trail<'_> {
+    /// Describe the token just validated, if it ends a tag
+    fn describe(&mut self, v: &Validator, ok: bool, report: &mut Report) {
+        let Some(tok) = self.last.take() else {
+            // an error left over from an earlier token
+            return;
+        };
+        let skipping = self.open.last().is_some_and(|(_, skipping)| *skipping);
+        let status = |ok| match (skipping, ok) {
+            (true, _) => "skipped",
+            (false, true) => "accepted",
+            (false, false) => "rejected",
+        };
+        match tok {
+            Token::ElementStart { prefix, local, .. } => {
+                // any error here is in text of the parent element, which is flushed now
+                let name = match prefix.as_str() {
+                    "" => local.to_string(),
+                    prefix => format!("{prefix}:{local}"),
+                };
+                self.start = Some((name, true));
+            }
+            Token::Attribute { .. } => {
+                if let Some((_, accepted)) = &mut self.start {
+                    *accepted &= ok;
+                }
+            }
+            Token::ElementEnd { end, .. } => {
+                let mut indent = "  ".repeat(self.open.len());
+                match end {
+                    ElementEnd::Open => {
+                        let (name, accepted) = self.start.take().unwrap_or_default();
+                        let detail = match (skipping, accepted, ok) {
+                            (true, _, _) => "",
+                            (false, _, false) => ", content skipped",
+                            (false, false, true) => "",
+                            (false, true, true) if v.can_close() => ", content optional",
+                            (false, true, true) => ", content required",
+                        };
+                        report.line(&format!(
+                            "{indent}<{name}> {}{detail}",
+                            status(accepted && ok)
+                        ));
+                        self.open.push((name, skipping || !ok));
+                    }
+                    ElementEnd::Empty => {
+                        let (name, accepted) = self.start.take().unwrap_or_default();
+                        report.line(&format!("{indent}<{name}/> {}", status(accepted && ok)));
+                    }
+                    ElementEnd::Close(..) => {
+                        let (name, _) = self.open.pop().unwrap_or_default();
+                        indent.truncate(indent.len().saturating_sub(2));
+                        // the end tag of a rejected element is skipped along with its content
+                        report.line(&format!("{indent}</{name}> {}", status(ok)));
+                    }
                 }
-                None => break,
             }
+            _ => {}
         }
     }
 }
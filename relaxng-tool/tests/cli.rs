@@ -0,0 +1,312 @@
+// Tests running the `rng` binary against small schemas and documents written to a temp dir
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+const SCHEMA: &str = "start = element doc { item+ }\n\
+                      item = element item { attribute n { xsd:integer }, element title { text } }";
+
+// four invalid items
+const DOC: &str = "<doc>\
+                   <item n=\"one\"><title>a</title></item>\
+                   <item n=\"two\"><title>b</title></item>\
+                   <item n=\"3\"></item>\
+                   <bogus/>\
+                   </doc>";
+
+fn rng(dir: &Path, args: &[&str]) -> Output {
+    fs::write(dir.join("schema.rnc"), SCHEMA).unwrap();
+    fs::write(dir.join("doc.xml"), DOC).unwrap();
+    fs::write(dir.join("other.xml"), DOC).unwrap();
+    Command::new(env!("CARGO_BIN_EXE_rng"))
+        .current_dir(dir)
+        .env("NO_COLOR", "1")
+        .args(args)
+        .output()
+        .expect("run rng")
+}
+
+fn error_count(output: &Output) -> usize {
+    String::from_utf8_lossy(&output.stderr)
+        .lines()
+        .filter(|l| l.contains("error"))
+        .count()
+}
+
+#[test]
+fn max_errors() {
+    let dir = tempfile::tempdir().expect("create temp dir");
+
+    let out = rng(dir.path(), &["validate", "schema.rnc", "doc.xml"]);
+    assert_eq!(out.status.code(), Some(2));
+    assert_eq!(error_count(&out), 1);
+
+    let out = rng(
+        dir.path(),
+        &["validate", "--max-errors", "3", "schema.rnc", "doc.xml"],
+    );
+    assert_eq!(out.status.code(), Some(2));
+    assert_eq!(error_count(&out), 3);
+
+    let out = rng(
+        dir.path(),
+        &["validate", "--max-errors", "0", "schema.rnc", "doc.xml"],
+    );
+    assert_eq!(error_count(&out), 4);
+}
+
+#[test]
+fn keep_going() {
+    let dir = tempfile::tempdir().expect("create temp dir");
+    let args = [
+        "validate",
+        "--max-errors",
+        "3",
+        "schema.rnc",
+        "doc.xml",
+        "other.xml",
+    ];
+
+    let out = rng(dir.path(), &args);
+    assert_eq!(out.status.code(), Some(2));
+    assert_eq!(error_count(&out), 3);
+
+    let out = rng(
+        dir.path(),
+        &[&args[..1], &["--keep-going"], &args[1..]].concat(),
+    );
+    assert_eq!(out.status.code(), Some(2));
+    assert_eq!(error_count(&out), 6);
+}
+
+#[test]
+fn check() {
+    let dir = tempfile::tempdir().expect("create temp dir");
+    let schemas = dir.path().join("schemas");
+    fs::create_dir(&schemas).unwrap();
+    fs::write(schemas.join("good.rnc"), SCHEMA).unwrap();
+    fs::write(schemas.join("broken.rnc"), "start = element a { missing }").unwrap();
+    fs::write(schemas.join("notes.txt"), "not a schema").unwrap();
+
+    let out = rng(dir.path(), &["check", "schemas"]);
+    assert_eq!(out.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    let lines: Vec<_> = stdout.lines().collect();
+    assert_eq!(lines.len(), 2, "{stdout}");
+    assert!(lines[0].starts_with("FAILED") && lines[0].ends_with("broken.rnc"));
+    assert!(lines[1].starts_with("ok") && lines[1].ends_with("good.rnc"));
+
+    let out = rng(dir.path(), &["check", "schema.rnc", "schemas/good.rnc"]);
+    assert_eq!(out.status.code(), Some(0));
+}
+
+#[test]
+fn warnings() {
+    let dir = tempfile::tempdir().expect("create temp dir");
+    fs::write(
+        dir.path().join("unused.rnc"),
+        "start = element doc { item+ }\n\
+         item = element item { text }\n\
+         old = element old { empty }",
+    )
+    .unwrap();
+    fs::write(dir.path().join("ok.xml"), "<doc><item/></doc>").unwrap();
+
+    let out = rng(dir.path(), &["validate", "-W", "unused.rnc", "ok.xml"]);
+    assert_eq!(out.status.code(), Some(0));
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains("old is defined, but never used by the start pattern"),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("item is defined"), "{stderr}");
+
+    let out = rng(dir.path(), &["validate", "unused.rnc", "ok.xml"]);
+    assert!(!String::from_utf8_lossy(&out.stderr).contains("is defined"));
+}
+
+#[test]
+fn explain() {
+    let dir = tempfile::tempdir().expect("create temp dir");
+
+    let output = rng(dir.path(), &["--explain", "not-allowed"]);
+    assert!(output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stdout).trim().is_empty());
+
+    let output = rng(dir.path(), &["--explain", "no-such-code"]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn junit() {
+    let dir = tempfile::tempdir().expect("create temp dir");
+    fs::write(
+        dir.path().join("valid.xml"),
+        "<doc><item n=\"1\"><title>a</title></item></doc>",
+    )
+    .unwrap();
+    let args = ["validate", "--format", "junit", "schema.rnc", "valid.xml"];
+
+    let out = rng(dir.path(), &[&args[..], &["doc.xml", "other.xml"]].concat());
+    assert_eq!(out.status.code(), Some(2));
+    let report = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        report.contains(
+            "<testsuite name=\"schema.rnc\" tests=\"3\" failures=\"1\" errors=\"0\" skipped=\"1\">"
+        ),
+        "{report}"
+    );
+    assert_eq!(report.matches("<testcase ").count(), 3, "{report}");
+    assert!(report.contains("<testcase name=\"valid.xml\" classname=\"schema.rnc\"/>"));
+    let failures: Vec<_> = report.match_indices("<failure ").collect();
+    assert_eq!(failures.len(), 1, "{report}");
+    assert!(report[failures[0].0..].contains("doc.xml:1:"), "{report}");
+    assert_eq!(report.matches("<skipped/>").count(), 1, "{report}");
+
+    // with --keep-going, every document is validated
+    let out = rng(
+        dir.path(),
+        &[&args[..], &["--keep-going", "doc.xml", "other.xml"]].concat(),
+    );
+    let report = String::from_utf8_lossy(&out.stdout);
+    assert_eq!(report.matches("<failure ").count(), 2, "{report}");
+    assert!(!report.contains("<skipped/>"), "{report}");
+
+    let out = rng(dir.path(), &args);
+    assert_eq!(out.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&out.stdout).contains("failures=\"0\""));
+}
+
+#[test]
+fn jobs() {
+    let dir = tempfile::tempdir().expect("create temp dir");
+    let mut docs = vec![];
+    for i in 0..8 {
+        let name = format!("doc{i}.xml");
+        let doc = if i % 3 == 0 {
+            DOC.to_string()
+        } else {
+            format!("<doc><item n=\"{i}\"><title>t</title></item></doc>")
+        };
+        fs::write(dir.path().join(&name), doc).unwrap();
+        docs.push(name);
+    }
+    let docs: Vec<_> = docs.iter().map(String::as_str).collect();
+
+    for options in [
+        &["--keep-going", "--max-errors", "2", "--format", "junit"][..],
+        &[],
+    ] {
+        let args = [&["validate"], options, &["schema.rnc"], &docs[..]].concat();
+        let sequential = rng(dir.path(), &args);
+        let parallel = rng(
+            dir.path(),
+            &[&args[..1], &["--jobs", "4"], &args[1..]].concat(),
+        );
+        assert_eq!(parallel.status.code(), Some(2));
+        assert!(error_count(&parallel) > 0);
+        assert_eq!(parallel.status.code(), sequential.status.code());
+        assert_eq!(
+            String::from_utf8_lossy(&parallel.stderr),
+            String::from_utf8_lossy(&sequential.stderr)
+        );
+        assert_eq!(
+            String::from_utf8_lossy(&parallel.stdout),
+            String::from_utf8_lossy(&sequential.stdout)
+        );
+    }
+}
+
+#[test]
+fn verbose() {
+    let dir = tempfile::tempdir().expect("create temp dir");
+    fs::write(
+        dir.path().join("valid.xml"),
+        "<doc><item n=\"1\"><title>t</title></item></doc>",
+    )
+    .unwrap();
+
+    let out = rng(
+        dir.path(),
+        &["validate", "--verbose", "schema.rnc", "valid.xml"],
+    );
+    assert_eq!(out.status.code(), Some(0));
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    let lines: Vec<_> = stderr.lines().skip(1).collect();
+    assert_eq!(
+        lines,
+        [
+            "<doc> accepted, content required",
+            "  <item> accepted, content required",
+            "    <title> accepted, content optional",
+            "    </title> accepted",
+            "  </item> accepted",
+            "</doc> accepted",
+        ]
+    );
+
+    // the content of a rejected element is skipped, not validated
+    let out = rng(
+        dir.path(),
+        &[
+            "validate",
+            "--verbose",
+            "--max-errors",
+            "0",
+            "schema.rnc",
+            "doc.xml",
+        ],
+    );
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("  <item> rejected, content skipped\n"));
+    assert!(stderr.contains("    <title> skipped\n"));
+    assert!(stderr.contains("  <bogus/> rejected\n"));
+
+    let out = rng(dir.path(), &["validate", "schema.rnc", "valid.xml"]);
+    assert!(!String::from_utf8_lossy(&out.stderr).contains("<doc>"));
+}
+
+#[test]
+fn element_budget() {
+    let dir = tempfile::tempdir().expect("create temp dir");
+
+    // the first error is in the second element
+    let out = rng(
+        dir.path(),
+        &["validate", "--element-budget", "1", "schema.rnc", "doc.xml"],
+    );
+    assert_eq!(out.status.code(), Some(0));
+    assert!(
+        String::from_utf8_lossy(&out.stderr)
+            .contains("Stopped at the element budget; the rest of \"doc.xml\" was not validated")
+    );
+
+    let out = rng(
+        dir.path(),
+        &["validate", "--element-budget", "2", "schema.rnc", "doc.xml"],
+    );
+    assert_eq!(out.status.code(), Some(2));
+    assert_eq!(error_count(&out), 1);
+}
+
+#[test]
+fn misplaced_declaration() {
+    let dir = tempfile::tempdir().expect("create temp dir");
+    fs::write(
+        dir.path().join("decl.xml"),
+        "<doc><?xml version=\"1.0\"?><item n=\"1\"><title/></item></doc>",
+    )
+    .unwrap();
+
+    for args in [
+        &["validate", "schema.rnc", "decl.xml"][..],
+        &["validate", "--select", "item", "schema.rnc", "decl.xml"][..],
+    ] {
+        let out = rng(dir.path(), args);
+        assert_eq!(out.status.code(), Some(2));
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        assert!(stderr.contains("error[misplaced-declaration]"), "{stderr}");
+        assert!(!stderr.contains("error[xml]"), "{stderr}");
+    }
+}
@@ -0,0 +1,146 @@
+//! Benchmarks of compiling a schema and of validating documents whose cost grows with the size of
+//! the schema or the document.
+//!
+//! Run with `cargo bench -p relaxng-validator`, optionally naming a group such as `interleave`.
+//! Criterion reports each time with the change since the previous run, so run once before a
+//! change and once after; a change flagged as a regression of more than a few percent is worth
+//! investigating.  Before measuring anything, each benchmark validates its document once and
+//! panics if the validator allocates more patterns than expected (see `ValidationStats`), which
+//! catches a return of the exponential blowup however fast the machine.
+
+use criterion::*;
+use relaxng_model::{Compiler, Files, RelaxError, Syntax};
+use relaxng_validator::{ValidationStats, Validator};
+use std::cell::RefCell;
+use std::fmt::Write;
+use std::path::Path;
+use std::rc::Rc;
+use xmlparser::Tokenizer;
+
+#[path = "../tests/generators/mod.rs"]
+mod generators;
+
+use generators::{gen_choice_doc, gen_choice_schema, gen_interleave_doc, gen_interleave_schema};
+
+/// Serves a single generated schema, whatever name is asked for
+struct Generated(String);
+
+impl Files for Generated {
+    fn load(&self, _name: &Path) -> Result<String, RelaxError> {
+        Ok(self.0.clone())
+    }
+}
+
+type Model = Rc<RefCell<Option<relaxng_model::model::DefineRule>>>;
+
+fn compile(schema: &str, syntax: Syntax) -> Model {
+    Compiler::new(Generated(schema.to_string()), syntax)
+        .compile(Path::new("schema"))
+        .expect("compile")
+}
+
+/// Validate the whole document, which must be valid, returning the validator's counters
+fn validate(model: &Model, doc: &str) -> ValidationStats {
+    let mut v = Validator::new(model.clone(), Tokenizer::from(doc));
+    while let Some(result) = v.validate_next() {
+        result.expect("valid document");
+    }
+    v.stats()
+}
+
+/// Fail unless validating the document allocates at most the given number of patterns
+fn check_patterns(name: &str, model: &Model, doc: &str, max_patterns: usize) {
+    let stats = validate(model, doc);
+    assert!(
+        stats.patterns <= max_patterns,
+        "{name}: {} patterns allocated, but expected at most {max_patterns}",
+        stats.patterns
+    );
+}
+
+/// A cut down Atom schema, small enough that compiling it is dominated by the fixed costs of
+/// the compiler rather than by the size of the schema
+const ATOM_SCHEMA: &str = r#"
+default namespace = "http://www.w3.org/2005/Atom"
+start = element feed { common, entry* }
+common = title & id & updated & link* & author*
+title = element title { text }
+id = element id { xsd:anyURI }
+updated = element updated { xsd:dateTime }
+link = element link { attribute href { xsd:anyURI }, attribute rel { text }?, empty }
+author = element author { element name { text } & element email { text }? }
+entry = element entry { common & element summary { text }? }
+"#;
+
+fn atom_doc(entries: usize) -> String {
+    let mut xml = String::from(
+        "<feed xmlns='http://www.w3.org/2005/Atom'><title>Feed</title><id>urn:feed</id>\
+         <updated>2024-01-01T00:00:00Z</updated>",
+    );
+    for i in 0..entries {
+        write!(
+            xml,
+            "<entry><updated>2024-01-01T00:00:00Z</updated><id>urn:entry:{i}</id>\
+             <title>Entry {i}</title><link href='http://example.com/{i}'/>\
+             <author><name>Author</name></author><summary>Summary {i}</summary></entry>"
+        )
+        .unwrap();
+    }
+    xml.push_str("</feed>");
+    xml
+}
+
+fn compile_small_schema(c: &mut Criterion) {
+    c.bench_function("compile_small_schema", |b| {
+        b.iter(|| compile(ATOM_SCHEMA, Syntax::Compact));
+    });
+}
+
+/// Validation of a feed, reported per entry, where each entry's content is an interleave
+fn atom_feed(c: &mut Criterion) {
+    const ENTRIES: usize = 200;
+    let model = compile(ATOM_SCHEMA, Syntax::Compact);
+    let doc = atom_doc(ENTRIES);
+    // once the first entry has been validated, the derivatives for the rest are all memoized
+    check_patterns("atom_feed", &model, &doc, 200);
+    let mut group = c.benchmark_group("atom_feed");
+    group.throughput(Throughput::Elements(ENTRIES as u64));
+    group.bench_function("entries", |b| b.iter(|| validate(&model, &doc)));
+    group.finish();
+}
+
+/// Validation of a document against an interleave of n optional elements, given in the reverse
+/// of the order they are declared in, where most of the time goes into finding the member that
+/// each element matches and rebuilding the interleave around its derivative
+fn interleave(c: &mut Criterion) {
+    let mut group = c.benchmark_group("interleave");
+    for n in [50, 100, 200] {
+        let model = compile(&gen_interleave_schema(n), Syntax::Xml);
+        let doc = gen_interleave_doc(n);
+        // about 6 per member
+        check_patterns("interleave", &model, &doc, 8 * n);
+        group.bench_with_input(BenchmarkId::from_parameter(n), &doc, |b, doc| {
+            b.iter(|| validate(&model, doc))
+        });
+    }
+    group.finish();
+}
+
+/// Validation of a document against a choice between n elements, where most of the time goes
+/// into building (and deduplicating) the choices of the derivatives
+fn choice(c: &mut Criterion) {
+    let mut group = c.benchmark_group("choice");
+    for n in [25, 50, 100] {
+        let model = compile(&gen_choice_schema(n), Syntax::Xml);
+        let doc = gen_choice_doc(n, 500);
+        // about 2 per branch, however many children there are
+        check_patterns("choice", &model, &doc, 4 * n);
+        group.bench_with_input(BenchmarkId::from_parameter(n), &doc, |b, doc| {
+            b.iter(|| validate(&model, doc))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, compile_small_schema, atom_feed, interleave, choice);
+criterion_main!(benches);
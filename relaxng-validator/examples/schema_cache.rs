@@ -0,0 +1,48 @@
+//! Validates two configuration documents against a schema that is compiled once, from a string
+//! constant embedded in the program.
+//!
+//!     cargo run -p relaxng-validator --example schema_cache
+
+use relaxng_model::Syntax;
+use relaxng_validator::cache::SchemaCache;
+
+const SCHEMA: &str = r#"
+start = element config {
+  attribute version { xsd:positiveInteger },
+  element server {
+    attribute host { xsd:token },
+    attribute port { xsd:unsignedShort }
+  }+
+}
+"#;
+
+const DOCUMENTS: [(&str, &str); 2] = [
+    (
+        "good.xml",
+        r#"<config version="2"><server host="example.com" port="8080"/></config>"#,
+    ),
+    (
+        "bad.xml",
+        r#"<config version="2"><server host="example.com" port="80000"/></config>"#,
+    ),
+];
+
+fn main() {
+    let schema = match SchemaCache::compile(SCHEMA, Syntax::Compact) {
+        Ok(schema) => schema,
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+    };
+    for (name, document) in DOCUMENTS {
+        let outcome = schema.validate(document);
+        if outcome.ok {
+            println!("{name}: valid");
+        } else {
+            for err in &outcome.errors {
+                println!("{name}: {err}");
+            }
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! A schema compiled once from text held in memory, such as a string constant embedded in an
+//! application, for validating any number of documents
+
+use crate::{ValidationOutcome, Validator};
+use alloc::rc::Rc;
+use core::cell::RefCell;
+use core::fmt;
+use relaxng_model::model::DefineRule;
+use relaxng_model::{Compiler, Files, RelaxError, Syntax};
+use std::io;
+use std::path::Path;
+use xmlparser::Tokenizer;
+
+/// The name under which the schema text is compiled, and which appears in error reports
+const SCHEMA_NAME: &str = "schema";
+
+/// Serves the schema text, and refuses to load anything else (so a schema using `include` or
+/// `externalRef` fails to compile)
+struct Text<'a>(&'a str);
+
+impl Files for Text<'_> {
+    fn load(&self, name: &Path) -> Result<String, RelaxError> {
+        if name == Path::new(SCHEMA_NAME) {
+            Ok(self.0.to_string())
+        } else {
+            Err(RelaxError::Io(
+                name.to_path_buf(),
+                io::Error::from(io::ErrorKind::NotFound),
+            ))
+        }
+    }
+}
+
+/// A schema compiled from a string, from which a `Validator` can be created for each document
+///
+/// ```
+/// use relaxng_model::Syntax;
+/// use relaxng_validator::cache::SchemaCache;
+///
+/// let schema = SchemaCache::compile("start = element config { empty }", Syntax::Compact).unwrap();
+/// assert!(schema.validate("<config/>").ok);
+/// assert!(!schema.validate("<other/>").ok);
+/// ```
+pub struct SchemaCache {
+    model: Rc<RefCell<Option<DefineRule>>>,
+}
+
+impl SchemaCache {
+    /// Compile the given schema text, written in the given syntax
+    pub fn compile(schema: &str, syntax: Syntax) -> Result<SchemaCache, SchemaError> {
+        let mut compiler = Compiler::new(Text(schema), syntax);
+        match compiler.compile(Path::new(SCHEMA_NAME)) {
+            Ok(model) => Ok(SchemaCache { model }),
+            Err(error) => Err(SchemaError {
+                report: compiler.render_diagnostic(&error),
+                error,
+            }),
+        }
+    }
+
+    /// A validator for the given document, to be driven with `Validator::validate_next()`
+    pub fn validator<'a>(&self, document: &'a str) -> Validator<'a> {
+        Validator::new(self.model.clone(), Tokenizer::from(document))
+    }
+
+    /// Validate the whole of the given document, as with `crate::validate()`
+    pub fn validate(&self, document: &str) -> ValidationOutcome {
+        crate::validate(self.model.clone(), document)
+    }
+
+    /// The compiled schema
+    pub fn model(&self) -> Rc<RefCell<Option<DefineRule>>> {
+        self.model.clone()
+    }
+}
+
+/// The reason a `SchemaCache` could not be created
+#[derive(Debug)]
+pub struct SchemaError {
+    pub error: RelaxError,
+    /// a description of the error quoting the relevant part of the schema, as `rng` would print
+    /// it
+    pub report: String,
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.report.trim_end())
+    }
+}
+
+impl core::error::Error for SchemaError {}
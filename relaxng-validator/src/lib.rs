@@ -1,26 +1,760 @@
+extern crate alloc;
+
+use alloc::borrow::Cow;
+use alloc::collections::VecDeque;
+use alloc::rc::Rc;
+use core::cell::RefCell;
+use relaxng_model::datatype::xsd::XsdDatatypes;
 use relaxng_model::datatype::{Datatype, Namespaces};
 use relaxng_model::model::NameClass;
 use relaxng_model::{datatype, model};
-use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
+#[cfg(feature = "std")]
 use std::io;
-use std::rc::Rc;
+use std::path::{Path, PathBuf};
 use xmlparser::{ElementEnd, EntityDefinition, StrSpan, Token, Tokenizer};
 
+#[cfg(feature = "std")]
+pub mod cache;
+pub mod names;
+pub mod schema_set;
+pub mod subtree;
+#[cfg(feature = "std")]
+pub mod xinclude;
+
 #[derive(Debug)]
 pub enum ValidatorError<'a> {
     Xml(xmlparser::Error),
+    /// Something other than whitespace, comments or processing instructions follows the end of
+    /// the document element, such as text or a second root element
+    TrailingContent(xmlparser::TextPos),
+    /// An XML declaration `<?xml ...?>` somewhere other than at the very start of the document,
+    /// such as a second declaration, or one in the middle of the content
+    MisplacedDeclaration {
+        span: core::ops::Range<usize>,
+    },
     NotAllowed(Token<'a>),
     UndefinedNamespacePrefix {
         prefix: StrSpan<'a>,
     },
     UndefinedEntity {
         name: &'a str,
-        span: std::ops::Range<usize>,
+        span: core::ops::Range<usize>,
+    },
+    InvalidOrUnclosedEntity {
+        span: core::ops::Range<usize>,
+    },
+    /// A character reference names a code point that is not a legal XML `Char`
+    IllegalCharacterReference {
+        code_point: u32,
+        span: core::ops::Range<usize>,
+    },
+    /// The same namespace prefix (or the default namespace) is declared twice on one element
+    DuplicateNamespaceDeclaration {
+        prefix: StrSpan<'a>,
+    },
+    /// `xmlns:p=""` -- only the default namespace may be undeclared (in XML 1.0)
+    EmptyNamespaceDeclaration {
+        prefix: StrSpan<'a>,
+    },
+    /// An attribute with the same namespace and local name as an earlier one on the same
+    /// element, even if written with a different prefix
+    DuplicateAttribute {
+        /// the name of the second attribute, as written
+        name: &'a str,
+        /// the whole of the second attribute
+        span: StrSpan<'a>,
+    },
+    /// The value of an attribute of type `xsd:ID` was already used by another attribute in the
+    /// same `IdScope`
+    DuplicateId {
+        value: StrSpan<'a>,
+    },
+    /// An attribute of type `xsd:IDREF` or `xsd:IDREFS` refers to an ID which no attribute in
+    /// the document (or in the same `IdScope`) has, reported once the whole document is read
+    DanglingIdRef {
+        /// the ID referred to
+        id: String,
+        /// the attribute's value
+        value: StrSpan<'a>,
+    },
+    /// Text or an attribute value did not match the `list` pattern expected for it
+    InvalidListValue {
+        /// what the list required, such as `exactly 2 items but found 1`
+        expected_description: String,
+        /// the value, with any entity references expanded
+        value: String,
+        span: core::ops::Range<usize>,
+    },
+    /// Text or an attribute value is not of the one datatype or value expected for it, or is of
+    /// the datatype but fails one of its facets
+    InvalidValue {
+        /// which facet the value fails, such as `"12" is greater than maxInclusive 10`, or else
+        /// what was expected, such as `expected xsd:date but found "today"`
+        reason: String,
+        span: core::ops::Range<usize>,
+    },
+    /// An XInclude `include` element could not be expanded (see `Validator::with_xinclude()`)
+    XInclude {
+        message: String,
+        /// the start tag of the `include` element
+        span: core::ops::Range<usize>,
+    },
+    /// An element ended before all of the content that the schema requires of it had been seen
+    MissingRequiredContent {
+        /// the names of the elements that could have come next, any of which may be the missing
+        /// one, or nothing if what is missing is text or a value rather than an element
+        expected: Vec<String>,
+        /// the end tag, or the `/>` of an empty element tag
+        span: StrSpan<'a>,
+    },
+    /// An error found in a document included by way of `Validator::with_xinclude()`, whose
+    /// spans are offsets into that document rather than the one being validated
+    Included {
+        path: &'a Path,
+        /// the text of the included document
+        text: &'a str,
+        error: Box<ValidatorError<'a>>,
+    },
+}
+
+impl<'a> ValidatorError<'a> {
+    /// A short, stable name for the kind of error, such as `not-allowed`, which is shown in
+    /// diagnostics and can be looked up with `rng --explain`
+    pub fn code(&self) -> &'static str {
+        match self {
+            ValidatorError::Xml(_) => "xml",
+            ValidatorError::TrailingContent(_) => "trailing-content",
+            ValidatorError::MisplacedDeclaration { .. } => "misplaced-declaration",
+            ValidatorError::NotAllowed(_) => "not-allowed",
+            ValidatorError::UndefinedNamespacePrefix { .. } => "undefined-namespace-prefix",
+            ValidatorError::UndefinedEntity { .. } => "undefined-entity",
+            ValidatorError::InvalidOrUnclosedEntity { .. } => "invalid-entity-reference",
+            ValidatorError::IllegalCharacterReference { .. } => "illegal-character-reference",
+            ValidatorError::DuplicateNamespaceDeclaration { .. } => {
+                "duplicate-namespace-declaration"
+            }
+            ValidatorError::EmptyNamespaceDeclaration { .. } => "empty-namespace-declaration",
+            ValidatorError::DuplicateAttribute { .. } => "duplicate-attribute",
+            ValidatorError::DuplicateId { .. } => "duplicate-id",
+            ValidatorError::DanglingIdRef { .. } => "dangling-idref",
+            ValidatorError::InvalidListValue { .. } => "invalid-list-value",
+            ValidatorError::InvalidValue { .. } => "invalid-value",
+            ValidatorError::XInclude { .. } => "xinclude",
+            ValidatorError::MissingRequiredContent { .. } => "missing-required-content",
+            ValidatorError::Included { error, .. } => error.code(),
+        }
+    }
+
+    /// The part of the document that the error is about, for those errors which keep it
+    fn text(&self) -> Option<&'a str> {
+        match self {
+            ValidatorError::NotAllowed(tok) => Some(token_span(*tok).as_str()),
+            ValidatorError::UndefinedNamespacePrefix { prefix }
+            | ValidatorError::DuplicateNamespaceDeclaration { prefix }
+            | ValidatorError::EmptyNamespaceDeclaration { prefix } => Some(prefix.as_str()),
+            ValidatorError::DuplicateAttribute { span, .. }
+            | ValidatorError::MissingRequiredContent { span, .. } => Some(span.as_str()),
+            ValidatorError::DuplicateId { value } | ValidatorError::DanglingIdRef { value, .. } => {
+                Some(value.as_str())
+            }
+            _ => None,
+        }
+    }
+
+    /// Copy the details of the error out of the document, so that it can outlive the document
+    /// and be sent between threads.  `source` must be the text of the document that was
+    /// validated, and is used to find line and column numbers (those of an error in an included
+    /// document are found in the text of that document instead).
+    pub fn into_owned(self, source: &str) -> OwnedValidatorError {
+        let at = |span: core::ops::Range<usize>| SourceLocation::new(source, span);
+        let at_str = |s: StrSpan| at(s.start()..s.end());
+        match self {
+            ValidatorError::Xml(err) => {
+                let pos = err.pos();
+                let offset = SourceLocation::offset(source, pos.row as usize, pos.col as usize);
+                OwnedValidatorError::Xml {
+                    message: err.to_string(),
+                    location: at(offset..offset),
+                }
+            }
+            ValidatorError::TrailingContent(pos) => {
+                let offset = SourceLocation::offset(source, pos.row as usize, pos.col as usize);
+                OwnedValidatorError::TrailingContent {
+                    location: at(offset..offset),
+                }
+            }
+            ValidatorError::MisplacedDeclaration { span } => {
+                OwnedValidatorError::MisplacedDeclaration { location: at(span) }
+            }
+            ValidatorError::NotAllowed(tok) => {
+                let (kind, span) = match tok {
+                    Token::Declaration { span, .. } => ("declaration", span),
+                    Token::ProcessingInstruction { span, .. } => ("processing-instruction", span),
+                    Token::Comment { span, .. } => ("comment", span),
+                    Token::DtdStart { span, .. } => ("dtd-start", span),
+                    Token::EmptyDtd { span, .. } => ("empty-dtd", span),
+                    Token::EntityDeclaration { span, .. } => ("entity-declaration", span),
+                    Token::DtdEnd { span, .. } => ("dtd-end", span),
+                    Token::ElementStart { span, .. } => ("element-start", span),
+                    Token::Attribute { span, .. } => ("attribute", span),
+                    Token::ElementEnd { span, .. } => ("element-end", span),
+                    Token::Text { text } => ("text", text),
+                    Token::Cdata { span, .. } => ("cdata", span),
+                };
+                OwnedValidatorError::NotAllowed {
+                    kind,
+                    text: span.as_str().to_string(),
+                    location: at_str(span),
+                }
+            }
+            ValidatorError::UndefinedNamespacePrefix { prefix } => {
+                OwnedValidatorError::UndefinedNamespacePrefix {
+                    prefix: prefix.as_str().to_string(),
+                    location: at_str(prefix),
+                }
+            }
+            ValidatorError::UndefinedEntity { name, span } => {
+                OwnedValidatorError::UndefinedEntity {
+                    name: name.to_string(),
+                    location: at(span),
+                }
+            }
+            ValidatorError::InvalidOrUnclosedEntity { span } => {
+                OwnedValidatorError::InvalidOrUnclosedEntity { location: at(span) }
+            }
+            ValidatorError::IllegalCharacterReference { code_point, span } => {
+                OwnedValidatorError::IllegalCharacterReference {
+                    code_point,
+                    location: at(span),
+                }
+            }
+            ValidatorError::DuplicateNamespaceDeclaration { prefix } => {
+                OwnedValidatorError::DuplicateNamespaceDeclaration {
+                    prefix: prefix.as_str().to_string(),
+                    location: at_str(prefix),
+                }
+            }
+            ValidatorError::EmptyNamespaceDeclaration { prefix } => {
+                OwnedValidatorError::EmptyNamespaceDeclaration {
+                    prefix: prefix.as_str().to_string(),
+                    location: at_str(prefix),
+                }
+            }
+            ValidatorError::DuplicateAttribute { name, span } => {
+                OwnedValidatorError::DuplicateAttribute {
+                    name: name.to_string(),
+                    location: at_str(span),
+                }
+            }
+            ValidatorError::DuplicateId { value } => OwnedValidatorError::DuplicateId {
+                value: value.as_str().to_string(),
+                location: at_str(value),
+            },
+            ValidatorError::DanglingIdRef { id, value } => OwnedValidatorError::DanglingIdRef {
+                id,
+                location: at_str(value),
+            },
+            ValidatorError::InvalidListValue {
+                expected_description,
+                value,
+                span,
+            } => OwnedValidatorError::InvalidListValue {
+                expected_description,
+                value,
+                location: at(span),
+            },
+            ValidatorError::InvalidValue { reason, span } => OwnedValidatorError::InvalidValue {
+                reason,
+                location: at(span),
+            },
+            ValidatorError::XInclude { message, span } => OwnedValidatorError::XInclude {
+                message,
+                location: at(span),
+            },
+            ValidatorError::MissingRequiredContent { expected, span } => {
+                OwnedValidatorError::MissingRequiredContent {
+                    expected,
+                    location: at_str(span),
+                }
+            }
+            ValidatorError::Included { path, text, error } => OwnedValidatorError::Included {
+                path: path.to_path_buf(),
+                error: Box::new(error.into_owned(text)),
+            },
+        }
+    }
+}
+
+/// The whole of the given token
+fn token_span(tok: Token) -> StrSpan {
+    match tok {
+        Token::Declaration { span, .. }
+        | Token::ProcessingInstruction { span, .. }
+        | Token::Comment { span, .. }
+        | Token::DtdStart { span, .. }
+        | Token::EmptyDtd { span, .. }
+        | Token::EntityDeclaration { span, .. }
+        | Token::DtdEnd { span, .. }
+        | Token::ElementStart { span, .. }
+        | Token::Attribute { span, .. }
+        | Token::ElementEnd { span, .. }
+        | Token::Cdata { span, .. } => span,
+        Token::Text { text } => text,
+    }
+}
+
+/// The message for `ValidatorError::MissingRequiredContent`
+fn missing_content_message(expected: &[String]) -> String {
+    match expected {
+        [] => "The element ends before its required content".to_string(),
+        [name] => format!("The element ends before its required content: expected {name}"),
+        names => format!(
+            "The element ends before its required content: expected one of {}",
+            names.join(", ")
+        ),
+    }
+}
+
+/// Where in a document an error was found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    /// byte offsets into the document
+    pub span: core::ops::Range<usize>,
+    /// 1-based line number of the start of the span
+    pub line: usize,
+    /// 1-based column of the start of the span, counted in characters
+    pub column: usize,
+}
+
+impl SourceLocation {
+    /// The byte offset of the given 1-based line and (character) column
+    fn offset(source: &str, line: usize, column: usize) -> usize {
+        let mut lines = source.split_inclusive('\n');
+        let line_start: usize = lines
+            .by_ref()
+            .take(line.saturating_sub(1))
+            .map(str::len)
+            .sum();
+        let within_line: usize = lines
+            .next()
+            .unwrap_or("")
+            .chars()
+            .take(column.saturating_sub(1))
+            .map(char::len_utf8)
+            .sum();
+        line_start + within_line
+    }
+
+    fn new(source: &str, span: core::ops::Range<usize>) -> SourceLocation {
+        let before = &source[..span.start.min(source.len())];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        SourceLocation {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+            span,
+        }
+    }
+}
+
+/// A `ValidatorError` which does not borrow from the document, produced by
+/// `ValidatorError::into_owned()`
+///
+/// It is `Send + Sync + 'static` and implements `Error`, so it can be returned with `?` from a
+/// function giving `Box<dyn Error>` or `anyhow::Result`:
+///
+/// ```
+/// # #[cfg(feature = "std")] {
+/// # use relaxng_validator::cache::SchemaCache;
+/// # use relaxng_model::Syntax;
+/// fn check(schema: &SchemaCache, doc: &str) -> anyhow::Result<()> {
+///     let mut v = schema.validator(doc);
+///     while let Some(result) = v.validate_next() {
+///         result.map_err(|err| err.into_owned(doc))?;
+///     }
+///     Ok(())
+/// }
+/// let schema = SchemaCache::compile("start = element a { empty }", Syntax::Compact).unwrap();
+/// assert!(check(&schema, "<a/>").is_ok());
+/// let err = check(&schema, "<b/>").unwrap_err();
+/// assert_eq!(err.to_string(), "1:2: element-start not expected here");
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OwnedValidatorError {
+    Xml {
+        message: String,
+        location: SourceLocation,
+    },
+    TrailingContent {
+        location: SourceLocation,
+    },
+    MisplacedDeclaration {
+        location: SourceLocation,
+    },
+    NotAllowed {
+        /// the kind of token, such as `element-start` or `text`
+        kind: &'static str,
+        /// the source text of the token
+        text: String,
+        location: SourceLocation,
+    },
+    UndefinedNamespacePrefix {
+        prefix: String,
+        location: SourceLocation,
+    },
+    UndefinedEntity {
+        name: String,
+        location: SourceLocation,
     },
     InvalidOrUnclosedEntity {
-        span: std::ops::Range<usize>,
+        location: SourceLocation,
     },
+    IllegalCharacterReference {
+        code_point: u32,
+        location: SourceLocation,
+    },
+    DuplicateNamespaceDeclaration {
+        prefix: String,
+        location: SourceLocation,
+    },
+    EmptyNamespaceDeclaration {
+        prefix: String,
+        location: SourceLocation,
+    },
+    DuplicateAttribute {
+        name: String,
+        location: SourceLocation,
+    },
+    DuplicateId {
+        value: String,
+        location: SourceLocation,
+    },
+    DanglingIdRef {
+        id: String,
+        location: SourceLocation,
+    },
+    InvalidListValue {
+        expected_description: String,
+        value: String,
+        location: SourceLocation,
+    },
+    InvalidValue {
+        reason: String,
+        location: SourceLocation,
+    },
+    XInclude {
+        message: String,
+        location: SourceLocation,
+    },
+    MissingRequiredContent {
+        expected: Vec<String>,
+        location: SourceLocation,
+    },
+    /// An error in a document included by way of XInclude, located within that document
+    Included {
+        path: PathBuf,
+        error: Box<OwnedValidatorError>,
+    },
+}
+
+impl OwnedValidatorError {
+    /// The same code as `ValidatorError::code()` gives for the original error
+    pub fn code(&self) -> &'static str {
+        match self {
+            OwnedValidatorError::Xml { .. } => "xml",
+            OwnedValidatorError::TrailingContent { .. } => "trailing-content",
+            OwnedValidatorError::MisplacedDeclaration { .. } => "misplaced-declaration",
+            OwnedValidatorError::NotAllowed { .. } => "not-allowed",
+            OwnedValidatorError::UndefinedNamespacePrefix { .. } => "undefined-namespace-prefix",
+            OwnedValidatorError::UndefinedEntity { .. } => "undefined-entity",
+            OwnedValidatorError::InvalidOrUnclosedEntity { .. } => "invalid-entity-reference",
+            OwnedValidatorError::IllegalCharacterReference { .. } => "illegal-character-reference",
+            OwnedValidatorError::DuplicateNamespaceDeclaration { .. } => {
+                "duplicate-namespace-declaration"
+            }
+            OwnedValidatorError::EmptyNamespaceDeclaration { .. } => "empty-namespace-declaration",
+            OwnedValidatorError::DuplicateAttribute { .. } => "duplicate-attribute",
+            OwnedValidatorError::DuplicateId { .. } => "duplicate-id",
+            OwnedValidatorError::DanglingIdRef { .. } => "dangling-idref",
+            OwnedValidatorError::InvalidListValue { .. } => "invalid-list-value",
+            OwnedValidatorError::InvalidValue { .. } => "invalid-value",
+            OwnedValidatorError::XInclude { .. } => "xinclude",
+            OwnedValidatorError::MissingRequiredContent { .. } => "missing-required-content",
+            OwnedValidatorError::Included { error, .. } => error.code(),
+        }
+    }
+
+    pub fn location(&self) -> &SourceLocation {
+        match self {
+            OwnedValidatorError::Xml { location, .. }
+            | OwnedValidatorError::TrailingContent { location }
+            | OwnedValidatorError::MisplacedDeclaration { location }
+            | OwnedValidatorError::NotAllowed { location, .. }
+            | OwnedValidatorError::UndefinedNamespacePrefix { location, .. }
+            | OwnedValidatorError::UndefinedEntity { location, .. }
+            | OwnedValidatorError::InvalidOrUnclosedEntity { location }
+            | OwnedValidatorError::IllegalCharacterReference { location, .. }
+            | OwnedValidatorError::DuplicateNamespaceDeclaration { location, .. }
+            | OwnedValidatorError::EmptyNamespaceDeclaration { location, .. }
+            | OwnedValidatorError::DuplicateAttribute { location, .. }
+            | OwnedValidatorError::DuplicateId { location, .. }
+            | OwnedValidatorError::DanglingIdRef { location, .. }
+            | OwnedValidatorError::InvalidListValue { location, .. }
+            | OwnedValidatorError::InvalidValue { location, .. }
+            | OwnedValidatorError::XInclude { location, .. }
+            | OwnedValidatorError::MissingRequiredContent { location, .. } => location,
+            OwnedValidatorError::Included { error, .. } => error.location(),
+        }
+    }
+}
+
+impl core::fmt::Display for OwnedValidatorError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if let OwnedValidatorError::Included { path, error } = self {
+            return write!(f, "{}:{error}", path.display());
+        }
+        let loc = self.location();
+        write!(f, "{}:{}: ", loc.line, loc.column)?;
+        match self {
+            OwnedValidatorError::Xml { message, .. } => write!(f, "{message}"),
+            OwnedValidatorError::TrailingContent { .. } => {
+                write!(
+                    f,
+                    "Content is not allowed after the end of the document element"
+                )
+            }
+            OwnedValidatorError::MisplacedDeclaration { .. } => write!(
+                f,
+                "An XML declaration is only allowed at the very start of the document"
+            ),
+            OwnedValidatorError::NotAllowed { kind, .. } => write!(f, "{kind} not expected here"),
+            OwnedValidatorError::UndefinedNamespacePrefix { prefix, .. } => {
+                write!(f, "The prefix {prefix:?} is not defined")
+            }
+            OwnedValidatorError::UndefinedEntity { name, .. } => {
+                write!(f, "The entity &{name}; is not defined")
+            }
+            OwnedValidatorError::InvalidOrUnclosedEntity { .. } => {
+                write!(f, "Invalid or unclosed entity reference")
+            }
+            OwnedValidatorError::IllegalCharacterReference { code_point, .. } => {
+                write!(
+                    f,
+                    "Character reference to illegal code point U+{code_point:04X}"
+                )?;
+                if is_surrogate(*code_point) {
+                    write!(f, " (a UTF-16 surrogate, not a whole character)")?;
+                }
+                Ok(())
+            }
+            OwnedValidatorError::DuplicateNamespaceDeclaration { prefix, .. } => {
+                write!(
+                    f,
+                    "The namespace prefix {prefix:?} is declared twice on one element"
+                )
+            }
+            OwnedValidatorError::EmptyNamespaceDeclaration { prefix, .. } => write!(
+                f,
+                "The prefix {prefix:?} may not be declared with an empty namespace name"
+            ),
+            OwnedValidatorError::DuplicateAttribute { name, .. } => {
+                write!(f, "The attribute {name:?} appears twice on one element")
+            }
+            OwnedValidatorError::DuplicateId { value, .. } => write!(f, "Duplicate ID {value:?}"),
+            OwnedValidatorError::DanglingIdRef { id, .. } => {
+                write!(f, "No element has the ID {id:?} referred to here")
+            }
+            OwnedValidatorError::InvalidListValue {
+                expected_description,
+                value,
+                ..
+            } => write!(
+                f,
+                "Invalid list value {value:?}: expected {expected_description}"
+            ),
+            OwnedValidatorError::InvalidValue { reason, .. } => {
+                write!(f, "Invalid value: {reason}")
+            }
+            OwnedValidatorError::XInclude { message, .. } => {
+                write!(f, "XInclude failed: {message}")
+            }
+            OwnedValidatorError::MissingRequiredContent { expected, .. } => {
+                write!(f, "{}", missing_content_message(expected))
+            }
+            OwnedValidatorError::Included { .. } => unreachable!("written above"),
+        }
+    }
+}
+
+impl core::error::Error for OwnedValidatorError {}
+
+/// A remark about a value in a document which does not make the document invalid, such as a
+/// value that was accepted only because its datatype is checked leniently
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Note {
+    pub message: &'static str,
+    /// the value, with any entity references expanded
+    pub value: String,
+    /// byte offsets of the value in the document
+    pub span: core::ops::Range<usize>,
+}
+
+/// How far validation has got through a document, as passed to the callback given to
+/// `Validator::with_progress()`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ValidationProgress {
+    /// the offset in the document just past the furthest token validated so far
+    pub bytes: usize,
+    /// the number of start tags seen, including those of empty elements like `<br/>`
+    pub elements_opened: usize,
+    /// the number of elements ended, by an end tag or by being empty
+    pub elements_closed: usize,
+    /// the number of elements currently open
+    pub depth: usize,
+}
+
+/// Counters of the work a `Validator` has done, as returned by `Validator::stats()`, for
+/// noticing when a change to the validator or to a schema makes validation more costly
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ValidationStats {
+    /// the number of tokens of the document validated so far
+    pub tokens: usize,
+    /// the number of distinct patterns allocated, including those created as derivatives while
+    /// validating (the same as `Validator::pattern_count()`)
+    pub patterns: usize,
+    /// the number of times a pattern was built that had been allocated already, so that the
+    /// existing one was used instead
+    pub patterns_reused: usize,
+}
+
+/// The number of tokens between calls to the `with_progress()` callback
+const PROGRESS_INTERVAL: usize = 10_000;
+
+struct Progress<'a> {
+    counts: ValidationProgress,
+    /// tokens seen since the callback was last called
+    tokens: usize,
+    callback: Box<dyn FnMut(ValidationProgress) + 'a>,
+}
+
+impl Progress<'_> {
+    fn count(&mut self, evt: Token) {
+        let counts = &mut self.counts;
+        let span = match evt {
+            Token::ElementStart { span, .. } => {
+                counts.elements_opened += 1;
+                counts.depth += 1;
+                span
+            }
+            Token::ElementEnd { end, span } => {
+                if let ElementEnd::Close(..) | ElementEnd::Empty = end {
+                    counts.elements_closed += 1;
+                    counts.depth = counts.depth.saturating_sub(1);
+                }
+                span
+            }
+            Token::Declaration { span, .. }
+            | Token::ProcessingInstruction { span, .. }
+            | Token::Comment { span, .. }
+            | Token::DtdStart { span, .. }
+            | Token::EmptyDtd { span, .. }
+            | Token::EntityDeclaration { span, .. }
+            | Token::DtdEnd { span, .. }
+            | Token::Attribute { span, .. }
+            | Token::Cdata { span, .. } => span,
+            Token::Text { text } => text,
+        };
+        counts.bytes = counts.bytes.max(span.end());
+        self.tokens += 1;
+        if self.tokens == PROGRESS_INTERVAL {
+            self.tokens = 0;
+            (self.callback)(*counts);
+        }
+    }
+}
+
+/// The result of `validate()`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationOutcome {
+    /// `true` if the document is valid, in which case `errors` is empty
+    pub ok: bool,
+    pub errors: Vec<OwnedValidatorError>,
+    pub notes: Vec<Note>,
+}
+
+/// Validate the whole of the given document, continuing past any errors (see
+/// `Validator::validate_next()`) and collecting notes about values which are valid but
+/// questionable
+pub fn validate(model: Rc<RefCell<Option<model::DefineRule>>>, source: &str) -> ValidationOutcome {
+    let mut v = Validator::new(model, Tokenizer::from(source)).with_notes();
+    let errors: Vec<_> = core::iter::from_fn(|| v.validate_next())
+        .filter_map(|r| r.err().map(|e| e.into_owned(source)))
+        .collect();
+    ValidationOutcome {
+        ok: errors.is_empty(),
+        errors,
+        notes: v.take_notes(),
+    }
+}
+
+/// Validate the whole of the given document, as with `validate()`, against the schema that the
+/// set holds for the namespace of the document's root element
+pub fn validate_with_set(
+    set: &schema_set::SchemaSet,
+    source: &str,
+) -> Result<ValidationOutcome, schema_set::NoMatchingSchema> {
+    set.validate(source)
+}
+
+/// The set of `xsd:ID` values seen so far, within which each ID must be unique.
+///
+/// By default each `Validator` has its own scope, so that IDs need only be unique within one
+/// document.  To require IDs to be unique across a batch of documents, create a single scope
+/// and pass it to `Validator::with_id_scope()` for each of them.
+#[derive(Default, Debug)]
+pub struct IdScope {
+    ids: HashSet<String>,
+}
+
+impl IdScope {
+    pub fn new() -> IdScope {
+        IdScope::default()
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.ids.contains(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.ids.clear()
+    }
+
+    /// returns false if the id was already present
+    fn insert(&mut self, id: &str) -> bool {
+        self.ids.insert(id.to_string())
+    }
+}
+
+enum Ids<'a> {
+    Local(IdScope),
+    Shared(&'a mut IdScope),
+}
+impl Ids<'_> {
+    fn scope(&mut self) -> &mut IdScope {
+        match self {
+            Ids::Local(scope) => scope,
+            Ids::Shared(scope) => scope,
+        }
+    }
 }
 
 #[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
@@ -31,11 +765,14 @@ struct PatId(u32);
 //       2) excludes 'Placeholder', and includes nullability flags and 'After'
 
 // Large types (NameClass, Datatypes, DatatypeValues) are boxed to keep the Pat
-// enum small (~16 bytes) so that cloning during derivative computation is cheap.
+// enum small (~24 bytes) so that cloning during derivative computation is cheap.
 #[derive(PartialEq, Eq, Hash, Clone, Debug)]
 enum Pat {
     Choice(PatId, PatId, bool),
-    Interleave(PatId, PatId, bool),
+    /// An interleave of two or more members, none of them `Empty`, `NotAllowed` or another
+    /// interleave, each in its current state.  A derivative replaces the one member that it
+    /// advances and shares the rest (see `Schema::interleave_replacing()`).
+    Interleave(Rc<[PatId]>, bool),
     Group(PatId, PatId, bool),
     OneOrMore(PatId, bool),
     Empty,
@@ -55,7 +792,7 @@ impl Pat {
     pub fn is_nullable(&self) -> bool {
         match self {
             Pat::Choice(_, _, nullable) => *nullable,
-            Pat::Interleave(_, _, nullable) => *nullable,
+            Pat::Interleave(_, nullable) => *nullable,
             Pat::Group(_, _, nullable) => *nullable,
             Pat::OneOrMore(_, nullable) => *nullable,
             Pat::Empty => true,
@@ -77,71 +814,41 @@ impl Pat {
 struct Inner {
     memo: HashMap<Pat, PatId>,
     patterns: Vec<Pat>,
+    /// the number of patterns pushed that were found in `memo`
+    memo_hits: usize,
     refs: HashMap<*const Option<relaxng_model::model::DefineRule>, PatId>,
     deferred_resolutions: Vec<(PatId, PatId)>,
 }
-#[derive(Default)]
-struct Schema {
-    inner: RefCell<Inner>,
-}
-impl Schema {
-    fn push(&self, p: Pat) -> PatId {
-        let mut inner = self.inner.borrow_mut();
+impl Inner {
+    fn push(&mut self, p: Pat) -> PatId {
         // Guard against exponential blowup in interleave/choice derivatives.
         // 2^16 (65535) is sufficient for almost all real-world schemas that don't
         // trigger pathological blowup. Schemas that do blowup would hang forever
         // without this check; this gives a clean diagnostic instead.
-        if inner.patterns.len() > 0x0fff_ffff {
+        if self.patterns.len() > 0x0fff_ffff {
             panic!("Schema derivative complexity limit exceeded (pattern count > 268M)")
         }
-        if let Some(id) = inner.memo.get(&p) {
+        if let Some(id) = self.memo.get(&p) {
+            self.memo_hits += 1;
             *id
         } else {
-            let id = PatId(inner.patterns.len() as u32);
-            inner.memo.insert(p.clone(), id);
-            inner.patterns.push(p);
+            let id = PatId(self.patterns.len() as u32);
+            self.memo.insert(p.clone(), id);
+            self.patterns.push(p);
             id
         }
     }
-    pub fn choice(&self, left: PatId, right: PatId) -> PatId {
-        // Eliminate redundant choice-leaves to avoid exponential blowup.
-        // See https://relaxng.org/jclark/derivative.html#Avoiding_exponential_blowup
-        //
-        // Walk the choice-leaves of the left operand into a hash-set, then
-        // filter the right operand, removing any leaf already present in the
-        // left.  This keeps the choice-tree linear in the number of unique
-        // leaves.
-        if self.is_not_allowed(left) {
-            return right;
-        }
-        if self.is_not_allowed(right) {
-            return left;
-        }
-        if left == right {
-            return left;
-        }
-        let mut left_leaves = fnv::FnvHashSet::default();
-        self.collect_choice_leaves(left, &mut left_leaves);
-        match self.filter_choice(right, &left_leaves) {
-            None => left, // every right leaf was a duplicate
-            Some(filtered_right) => {
-                let nullable = self.nullable(left) || self.nullable(filtered_right);
-                self.push(Pat::Choice(left, filtered_right, nullable))
-            }
-        }
+
+    fn pat(&self, id: PatId) -> &Pat {
+        &self.patterns[id.0 as usize]
     }
 
     /// Collect all non-Choice leaf PatIds from a choice tree.
     fn collect_choice_leaves(&self, id: PatId, leaves: &mut fnv::FnvHashSet<PatId>) {
-        let inner = self.inner.borrow();
-        Self::collect_leaves_inner(&inner.patterns, id, leaves);
-    }
-
-    fn collect_leaves_inner(patterns: &[Pat], id: PatId, leaves: &mut fnv::FnvHashSet<PatId>) {
-        match &patterns[id.0 as usize] {
+        match self.pat(id) {
             Pat::Choice(l, r, _) => {
-                Self::collect_leaves_inner(patterns, *l, leaves);
-                Self::collect_leaves_inner(patterns, *r, leaves);
+                self.collect_choice_leaves(*l, leaves);
+                self.collect_choice_leaves(*r, leaves);
             }
             _ => {
                 leaves.insert(id);
@@ -151,31 +858,66 @@ impl Schema {
 
     /// Walk a choice tree and remove any leaf whose PatId is in `exclude`.
     /// Returns `None` if every leaf was removed.
-    fn filter_choice(&self, id: PatId, exclude: &fnv::FnvHashSet<PatId>) -> Option<PatId> {
+    fn filter_choice(&mut self, id: PatId, exclude: &fnv::FnvHashSet<PatId>) -> Option<PatId> {
         if exclude.contains(&id) {
             return None;
         }
-        let children = {
-            let inner = self.inner.borrow();
-            match &inner.patterns[id.0 as usize] {
-                Pat::Choice(l, r, _) => Some((*l, *r)),
-                _ => None,
-            }
+        let &Pat::Choice(l, r, _) = self.pat(id) else {
+            return Some(id); // non-Choice leaf, not excluded
         };
-        match children {
-            Some((l, r)) => {
-                let fl = self.filter_choice(l, exclude);
-                let fr = self.filter_choice(r, exclude);
-                match (fl, fr) {
-                    (None, None) => None,
-                    (Some(kept), None) | (None, Some(kept)) => Some(kept),
-                    (Some(fl), Some(fr)) => {
-                        let nullable = self.nullable(fl) || self.nullable(fr);
-                        Some(self.push(Pat::Choice(fl, fr, nullable)))
-                    }
-                }
+        let fl = self.filter_choice(l, exclude);
+        let fr = self.filter_choice(r, exclude);
+        match (fl, fr) {
+            // nothing removed below here, so the existing choice can be reused as-is
+            (Some(fl), Some(fr)) if fl == l && fr == r => Some(id),
+            (None, None) => None,
+            (Some(kept), None) | (None, Some(kept)) => Some(kept),
+            (Some(fl), Some(fr)) => {
+                let nullable = self.pat(fl).is_nullable() || self.pat(fr).is_nullable();
+                Some(self.push(Pat::Choice(fl, fr, nullable)))
+            }
+        }
+    }
+}
+#[derive(Default)]
+struct Schema {
+    inner: RefCell<Inner>,
+    /// Accept any text where a datatype is expected, as set by
+    /// `Validator::with_datatype_checks(false)`
+    skip_datatype_checks: bool,
+}
+impl Schema {
+    fn push(&self, p: Pat) -> PatId {
+        self.inner.borrow_mut().push(p)
+    }
+    pub fn choice(&self, left: PatId, right: PatId) -> PatId {
+        // Eliminate redundant choice-leaves to avoid exponential blowup.
+        // See https://relaxng.org/jclark/derivative.html#Avoiding_exponential_blowup
+        //
+        // Walk the choice-leaves of the left operand into a hash-set, then
+        // filter the right operand, removing any leaf already present in the
+        // left.  This keeps the choice-tree linear in the number of unique
+        // leaves.  The whole operation happens under a single borrow of the
+        // pattern arena.
+        let mut inner = self.inner.borrow_mut();
+        if matches!(inner.pat(left), Pat::NotAllowed) {
+            return right;
+        }
+        if matches!(inner.pat(right), Pat::NotAllowed) {
+            return left;
+        }
+        if left == right {
+            return left;
+        }
+        let mut left_leaves = fnv::FnvHashSet::default();
+        inner.collect_choice_leaves(left, &mut left_leaves);
+        match inner.filter_choice(right, &left_leaves) {
+            None => left, // every right leaf was a duplicate
+            Some(filtered_right) => {
+                let nullable =
+                    inner.pat(left).is_nullable() || inner.pat(filtered_right).is_nullable();
+                inner.push(Pat::Choice(left, filtered_right, nullable))
             }
-            None => Some(id), // non-Choice leaf, not excluded
         }
     }
 
@@ -194,18 +936,49 @@ impl Schema {
         matches!(self.inner.borrow().patterns[id.0 as usize], Pat::Empty)
     }
     pub fn interleave(&self, left: PatId, right: PatId) -> PatId {
-        match (self.patt(left), self.patt(right)) {
-            (Pat::NotAllowed, _) => self.not_allowed(),
-            (_, Pat::NotAllowed) => self.not_allowed(),
-            (Pat::Empty, _) => right,
-            (_, Pat::Empty) => left,
-            (l, r) => self.push(Pat::Interleave(
-                left,
-                right,
-                l.is_nullable() && r.is_nullable(),
-            )),
-        }
+        self.interleave_all(&[left, right])
     }
+
+    /// An interleave of the given patterns, with the members of any that are themselves
+    /// interleaves taken as members of this one, since interleave is associative
+    fn interleave_all(&self, members: &[PatId]) -> PatId {
+        let mut flat = Vec::with_capacity(members.len());
+        let mut nullable = true;
+        {
+            let inner = self.inner.borrow();
+            for &id in members {
+                match inner.pat(id) {
+                    Pat::NotAllowed => {
+                        flat.clear();
+                        flat.push(id);
+                        break;
+                    }
+                    Pat::Empty => {}
+                    Pat::Interleave(nested, n) => {
+                        flat.extend_from_slice(nested);
+                        nullable &= n;
+                    }
+                    p => {
+                        flat.push(id);
+                        nullable &= p.is_nullable();
+                    }
+                }
+            }
+        }
+        match flat[..] {
+            [] => self.empty(),
+            [one] => one,
+            _ => self.push(Pat::Interleave(flat.into(), nullable)),
+        }
+    }
+
+    /// The interleave of the given members with the one at `index` replaced by `pat`
+    fn interleave_replacing(&self, members: &[PatId], index: usize, pat: PatId) -> PatId {
+        let mut members = members.to_vec();
+        members[index] = pat;
+        self.interleave_all(&members)
+    }
+
     pub fn group(&self, left: PatId, right: PatId) -> PatId {
         match (self.patt(left), self.patt(right)) {
             (Pat::NotAllowed, _) => self.not_allowed(),
@@ -364,7 +1137,12 @@ impl Schema {
                 self.check_choice(l, seen, seen_choices);
                 self.check_choice(r, seen, seen_choices);
             }
-            Pat::Interleave(l, r, _) | Pat::Group(l, r, _) => {
+            Pat::Interleave(members, _) => {
+                for &member in members.iter() {
+                    self.check_choices(member, seen);
+                }
+            }
+            Pat::Group(l, r, _) => {
                 self.check_choices(l, seen);
                 self.check_choices(r, seen);
             }
@@ -391,7 +1169,12 @@ impl Schema {
                 let mut seen_choices = vec![];
                 self.check_choice(id, seen, &mut seen_choices);
             }
-            Pat::Interleave(l, r, _) | Pat::Group(l, r, _) => {
+            Pat::Interleave(members, _) => {
+                for &member in members.iter() {
+                    self.check_choices(member, seen);
+                }
+            }
+            Pat::Group(l, r, _) => {
                 self.check_choices(l, seen);
                 self.check_choices(r, seen);
             }
@@ -405,16 +1188,19 @@ impl Schema {
         }
     }
 
+    #[cfg(feature = "std")]
     #[allow(unused)]
     pub fn d(&self, pat: PatId) -> Result<(), io::Error> {
         let mut o = io::stdout();
         self.dumpy(pat, &mut o)
     }
 
+    #[cfg(feature = "std")]
     pub fn dumpy<W: io::Write>(&self, pat: PatId, w: &mut W) -> Result<(), io::Error> {
         let mut seen = HashSet::new();
         self.dumpy_dump(0, pat, w, &mut seen)
     }
+    #[cfg(feature = "std")]
     pub fn dumpy_dump<W: io::Write>(
         &self,
         depth: usize,
@@ -436,10 +1222,11 @@ impl Schema {
                     }
                     writeln!(w, ")")
                 }
-                Pat::Interleave(p1, p2, _) => {
+                Pat::Interleave(members, _) => {
                     writeln!(w, "Interleave{}(", pat.0)?;
-                    self.dumpy_dump(depth + 1, p1, w, seen)?;
-                    self.dumpy_dump(depth + 1, p2, w, seen)?;
+                    for &member in members.iter() {
+                        self.dumpy_dump(depth + 1, member, w, seen)?;
+                    }
                     for _ in 0..depth {
                         w.write_all(b"  ")?;
                     }
@@ -523,7 +1310,7 @@ impl Schema {
                 Pat::Choice(_p1, _p2, _) => {
                     writeln!(w, "Choice{}!", pat.0)
                 }
-                Pat::Interleave(_p1, _p2, _) => {
+                Pat::Interleave(_, _) => {
                     writeln!(w, "Interleave{}!", pat.0)
                 }
                 Pat::Group(_p1, _p2, _) => {
@@ -575,6 +1362,53 @@ fn is_ns_match(namespace_uri: &str, target_namespace: Option<&StrSpan>) -> bool
         namespace_uri.is_empty()
     }
 }
+/// Find the name classes of attributes declared with `xsd:ID` content, and of those declared
+/// with `xsd:IDREF` or `xsd:IDREFS` content (the types of the same names in the DTD
+/// compatibility library being compiled to these)
+fn collect_id_attributes(
+    p: &model::Pattern,
+    seen: &mut HashSet<*const Option<model::DefineRule>>,
+    ids: &mut Vec<NameClass>,
+    refs: &mut Vec<NameClass>,
+) {
+    match p {
+        model::Pattern::Choice(v) | model::Pattern::Interleave(v) | model::Pattern::Group(v) => {
+            for p in v {
+                collect_id_attributes(p, seen, ids, refs);
+            }
+        }
+        model::Pattern::Mixed(p)
+        | model::Pattern::Optional(p)
+        | model::Pattern::ZeroOrMore(p)
+        | model::Pattern::OneOrMore(p)
+        | model::Pattern::List(p)
+        | model::Pattern::Element(_, p) => collect_id_attributes(p, seen, ids, refs),
+        model::Pattern::Attribute(name, p) => match **p {
+            model::Pattern::DatatypeName {
+                datatype: datatype::Datatypes::Xsd(XsdDatatypes::Id(_)),
+                ..
+            } => ids.push(name.clone()),
+            model::Pattern::DatatypeName {
+                datatype: datatype::Datatypes::Xsd(XsdDatatypes::IdRef(_) | XsdDatatypes::IdRefs(_)),
+                ..
+            } => refs.push(name.clone()),
+            _ => {}
+        },
+        model::Pattern::Ref(_, _, r) => {
+            if seen.insert(r.0.as_ptr())
+                && let Some(rule) = Rc::as_ref(&r.0).borrow().as_ref()
+            {
+                collect_id_attributes(rule.pattern(), seen, ids, refs);
+            }
+        }
+        model::Pattern::Empty
+        | model::Pattern::Text
+        | model::Pattern::NotAllowed
+        | model::Pattern::DatatypeValue { .. }
+        | model::Pattern::DatatypeName { .. } => {}
+    }
+}
+
 fn contains(nc: &model::NameClass, target_name: QualifiedName) -> bool {
     match nc {
         NameClass::Named {
@@ -616,16 +1450,191 @@ struct QualifiedName<'a> {
     local_name: StrSpan<'a>,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug)]
 struct Attr<'a> {
     name: QualifiedName<'a>,
     value: StrSpan<'a>,
+    /// the value with any entity and character references replaced
+    text: Cow<'a, str>,
     span: StrSpan<'a>,
 }
 
+/// The tokens of the document being validated, with any errors found in producing them
+type Tokens<'a> = Box<dyn Iterator<Item = Result<Token<'a>, ValidatorError<'a>>> + 'a>;
+
+/// The options of a `Validator`, set one at a time before building validators for documents.
+///
+/// Each setter corresponds to one of the `Validator::with_*()` methods, which remain available
+/// for configuring a single option; `Validator::new()` is the same as building with no options
+/// set.
+///
+/// ```
+/// # #[cfg(feature = "std")] {
+/// use relaxng_model::Syntax;
+/// use relaxng_validator::cache::SchemaCache;
+/// use relaxng_validator::{IdScope, Validator};
+///
+/// let schema = SchemaCache::compile(
+///     "start = element a { attribute id { xsd:ID }, xsd:integer }",
+///     Syntax::Compact,
+/// )
+/// .unwrap();
+/// let mut scope = IdScope::new();
+/// let mut v = Validator::builder(schema.model())
+///     .datatype_checks(false)
+///     .id_scope(&mut scope)
+///     .build(xmlparser::Tokenizer::from("<a id='x'>one</a>"));
+/// while let Some(result) = v.validate_next() {
+///     assert!(result.is_ok());
+/// }
+/// # }
+/// ```
+pub struct ValidatorBuilder<'a> {
+    model: Rc<RefCell<Option<model::DefineRule>>>,
+    undefined_prefix_recovery: bool,
+    datatype_checks: bool,
+    attribute_normalization: bool,
+    notes: bool,
+    progress: Option<Box<dyn FnMut(ValidationProgress) + 'a>>,
+    element_budget: Option<usize>,
+    id_scope: Option<&'a mut IdScope>,
+    #[cfg(feature = "std")]
+    xinclude: Option<&'a xinclude::XIncludeFiles>,
+    #[cfg(feature = "trace")]
+    trace: Option<Box<dyn io::Write + 'a>>,
+}
+
+impl<'a> ValidatorBuilder<'a> {
+    pub fn new(model: Rc<RefCell<Option<model::DefineRule>>>) -> ValidatorBuilder<'a> {
+        ValidatorBuilder {
+            model,
+            undefined_prefix_recovery: false,
+            datatype_checks: true,
+            attribute_normalization: true,
+            notes: false,
+            progress: None,
+            element_budget: None,
+            id_scope: None,
+            #[cfg(feature = "std")]
+            xinclude: None,
+            #[cfg(feature = "trace")]
+            trace: None,
+        }
+    }
+
+    /// See `Validator::with_undefined_prefix_recovery()`
+    pub fn undefined_prefix_recovery(mut self, enabled: bool) -> ValidatorBuilder<'a> {
+        self.undefined_prefix_recovery = enabled;
+        self
+    }
+
+    /// See `Validator::with_datatype_checks()`
+    pub fn datatype_checks(mut self, enabled: bool) -> ValidatorBuilder<'a> {
+        self.datatype_checks = enabled;
+        self
+    }
+
+    /// See `Validator::with_attribute_normalization()`
+    pub fn attribute_normalization(mut self, enabled: bool) -> ValidatorBuilder<'a> {
+        self.attribute_normalization = enabled;
+        self
+    }
+
+    /// See `Validator::with_notes()`
+    pub fn notes(mut self, enabled: bool) -> ValidatorBuilder<'a> {
+        self.notes = enabled;
+        self
+    }
+
+    /// See `Validator::with_progress()`
+    pub fn progress(
+        mut self,
+        callback: impl FnMut(ValidationProgress) + 'a,
+    ) -> ValidatorBuilder<'a> {
+        self.progress = Some(Box::new(callback));
+        self
+    }
+
+    /// See `Validator::with_element_budget()`
+    pub fn element_budget(mut self, elements: usize) -> ValidatorBuilder<'a> {
+        self.element_budget = Some(elements);
+        self
+    }
+
+    /// See `Validator::with_id_scope()`
+    pub fn id_scope(mut self, scope: &'a mut IdScope) -> ValidatorBuilder<'a> {
+        self.id_scope = Some(scope);
+        self
+    }
+
+    /// See `Validator::with_xinclude()`
+    #[cfg(feature = "std")]
+    pub fn xinclude(mut self, files: &'a xinclude::XIncludeFiles) -> ValidatorBuilder<'a> {
+        self.xinclude = Some(files);
+        self
+    }
+
+    /// See `Validator::with_trace()`
+    #[cfg(feature = "trace")]
+    pub fn trace(mut self, writer: impl io::Write + 'a) -> ValidatorBuilder<'a> {
+        self.trace = Some(Box::new(writer));
+        self
+    }
+
+    /// A validator for the document read by the given tokenizer
+    pub fn build(self, tokenizer: Tokenizer<'a>) -> Validator<'a> {
+        let source = tokenizer.stream().span().as_str();
+        let mut v = self.build_from_tokens(tokenizer);
+        v.source = Some(source);
+        v
+    }
+
+    /// A validator for a fragment of XML with any number of top-level elements, as with
+    /// `Validator::new_fragment()`
+    pub fn build_fragment(self, fragment: &'a str) -> Validator<'a> {
+        let mut v = self.build(Tokenizer::from_fragment(fragment, 0..fragment.len()));
+        let repeated = v.schema.one_or_more(v.current_step);
+        v.current_step = v.schema.choice(repeated, v.schema.empty());
+        v.fragment = true;
+        v
+    }
+
+    /// A validator for a stream of tokens, as with `Validator::from_tokens()`
+    pub fn build_from_tokens(
+        self,
+        tokens: impl IntoIterator<Item = Result<Token<'a>, xmlparser::Error>> + 'a,
+    ) -> Validator<'a> {
+        let mut v = Validator::from_tokens(self.model, tokens)
+            .with_datatype_checks(self.datatype_checks)
+            .with_attribute_normalization(self.attribute_normalization);
+        v.recover_undefined_prefixes = self.undefined_prefix_recovery;
+        if self.notes {
+            v = v.with_notes();
+        }
+        if let Some(callback) = self.progress {
+            v = v.with_progress(callback);
+        }
+        if let Some(elements) = self.element_budget {
+            v = v.with_element_budget(elements);
+        }
+        if let Some(scope) = self.id_scope {
+            v = v.with_id_scope(scope);
+        }
+        #[cfg(feature = "std")]
+        if let Some(files) = self.xinclude {
+            v = v.with_xinclude(files);
+        }
+        #[cfg(feature = "trace")]
+        if let Some(writer) = self.trace {
+            v = v.with_trace(writer);
+        }
+        v
+    }
+}
+
 pub struct Validator<'a> {
     schema: Schema,
-    tokenizer: Tokenizer<'a>,
+    tokenizer: Tokens<'a>,
     current_step: PatId,
     last_was_start_element: bool,
     stack: ElementStack<'a>,
@@ -633,12 +1642,106 @@ pub struct Validator<'a> {
     /// Buffer for accumulating text content that may be split by processing
     /// instructions or CDATA sections. Flushed before non-text events.
     text_buffer: String,
+    /// the extent in the document of the text in `text_buffer`
+    text_span: Option<core::ops::Range<usize>>,
+    /// the first of the `Text` or `Cdata` tokens whose text is in `text_buffer`, reported if the
+    /// text turns out not to be allowed
+    text_token: Option<Token<'a>>,
+    /// Names of the attributes that the schema declares to have type `xsd:ID` somewhere, so
+    /// that `check_ids()` need only look for the type of attributes with these names
+    id_attributes: Vec<NameClass>,
+    /// Names of the attributes that the schema declares to have type `xsd:IDREF` or
+    /// `xsd:IDREFS` somewhere
+    idref_attributes: Vec<NameClass>,
+    ids: Ids<'a>,
+    /// The IDs referred to so far, each with the attribute value referring to it and the path of
+    /// its element, checked against `ids` at the end of the document
+    idrefs: Vec<(String, StrSpan<'a>, String)>,
+    /// The number of open elements being skipped following an error
+    skip_depth: usize,
+    /// Whether a token has been read, after which an XML declaration is out of place
+    started: bool,
+    /// How many more elements may be started before validation stops, if limited by
+    /// `with_element_budget()`
+    element_budget: Option<usize>,
+    /// Whether validation stopped at the element budget, before the end of the document
+    stopped_early: bool,
+    /// The text of the document, when validating the tokens of a `Tokenizer` or when given by
+    /// `with_source()`
+    source: Option<&'a str>,
+    /// Treat undeclared prefixes as bound to no namespace, rather than skipping the element
+    recover_undefined_prefixes: bool,
+    /// Errors found while recovering, still to be returned from `validate_next()`, each with
+    /// the element path where it was found
+    pending_errors: VecDeque<(ValidatorError<'a>, String)>,
+    /// The element path of the error most recently returned by `validate_next()`
+    error_path: String,
+    /// Notes about accepted values, if requested with `with_notes()`
+    notes: Option<Vec<Note>>,
+    progress: Option<Progress<'a>>,
+    /// The number of tokens validated, for `stats()`
+    tokens: usize,
+    /// Whether validating a fragment created by `new_fragment()`, in which text can follow the
+    /// last top-level element
+    fragment: bool,
+    /// Whether whitespace in attribute values is replaced with spaces before they are checked
+    normalize_attributes: bool,
+    /// The path and text of each document included so far by way of `with_xinclude()`
+    included: Rc<RefCell<Vec<(&'a Path, &'a str)>>>,
+    /// The first token of text that was found not to be allowed when it was flushed, which an
+    /// error without a span of its own is then located by
+    failed_text: Option<Token<'a>>,
+    #[cfg(feature = "trace")]
+    trace: Option<Box<dyn io::Write + 'a>>,
 }
 
 impl<'a> Validator<'a> {
     pub fn new(
         model: Rc<RefCell<Option<model::DefineRule>>>,
         tokenizer: Tokenizer<'a>,
+    ) -> Validator<'a> {
+        let source = tokenizer.stream().span().as_str();
+        let mut v = Self::from_tokens(model, tokenizer);
+        v.source = Some(source);
+        v
+    }
+
+    /// Validate a fragment of XML, such as an external parsed entity, which may hold any number
+    /// of top-level elements (including none) rather than a single document element
+    ///
+    /// Each top-level element must match the schema's start pattern, and text between them
+    /// may only be whitespace.  The fragment cannot have an XML declaration or a DTD.  To set
+    /// other options as well, use `ValidatorBuilder::build_fragment()`.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use relaxng_model::Syntax;
+    /// # use relaxng_validator::Validator;
+    /// # use relaxng_validator::cache::SchemaCache;
+    /// let schema = SchemaCache::compile("start = element a { empty }", Syntax::Compact).unwrap();
+    /// let mut v = Validator::new_fragment(schema.model(), "<a/>\n<a/>\n");
+    /// while let Some(result) = v.validate_next() {
+    ///     assert!(result.is_ok());
+    /// }
+    /// # }
+    /// ```
+    pub fn new_fragment(
+        model: Rc<RefCell<Option<model::DefineRule>>>,
+        fragment: &'a str,
+    ) -> Validator<'a> {
+        Self::builder(model).build_fragment(fragment)
+    }
+
+    /// A builder for validators using the given schema, for setting several options at once
+    pub fn builder(model: Rc<RefCell<Option<model::DefineRule>>>) -> ValidatorBuilder<'a> {
+        ValidatorBuilder::new(model)
+    }
+
+    /// Validate a stream of tokens from some source other than a `Tokenizer` over the whole
+    /// document, such as one of the `subtree::Subtrees`
+    pub fn from_tokens(
+        model: Rc<RefCell<Option<model::DefineRule>>>,
+        tokens: impl IntoIterator<Item = Result<Token<'a>, xmlparser::Error>> + 'a,
     ) -> Validator<'a> {
         let schema = Schema::default();
         let start = Self::compile(
@@ -646,6 +1749,14 @@ impl<'a> Validator<'a> {
             Rc::as_ref(&model).borrow().as_ref().unwrap().pattern(),
         );
         schema.resolve_deferred();
+        let mut id_attributes = vec![];
+        let mut idref_attributes = vec![];
+        collect_id_attributes(
+            Rc::as_ref(&model).borrow().as_ref().unwrap().pattern(),
+            &mut HashSet::new(),
+            &mut id_attributes,
+            &mut idref_attributes,
+        );
         let mut entity_definitions = HashMap::default();
         entity_definitions.insert("lt".to_string(), "<".to_string());
         entity_definitions.insert("gt".to_string(), ">".to_string());
@@ -654,14 +1765,200 @@ impl<'a> Validator<'a> {
         entity_definitions.insert("quot".to_string(), "\"".to_string());
         Validator {
             schema,
-            tokenizer,
+            tokenizer: Box::new(tokens.into_iter().map(|r| r.map_err(ValidatorError::Xml))),
             current_step: start,
             last_was_start_element: false,
             stack: ElementStack::default(),
             entity_definitions,
             text_buffer: String::new(),
+            text_span: None,
+            text_token: None,
+            id_attributes,
+            idref_attributes,
+            ids: Ids::Local(IdScope::default()),
+            idrefs: vec![],
+            skip_depth: 0,
+            started: false,
+            element_budget: None,
+            stopped_early: false,
+            source: None,
+            recover_undefined_prefixes: false,
+            pending_errors: VecDeque::new(),
+            error_path: String::new(),
+            notes: None,
+            progress: None,
+            tokens: 0,
+            fragment: false,
+            normalize_attributes: true,
+            included: Rc::default(),
+            failed_text: None,
+            #[cfg(feature = "trace")]
+            trace: None,
+        }
+    }
+
+    /// Write one line of JSON to the given writer for each token validated, giving the kind of
+    /// token, any name it has, the ids of the current pattern before and after the token was
+    /// processed, and whether the derivative was computed entirely from already-existing
+    /// patterns (`memo_hit`).  Errors writing the trace are ignored.
+    #[cfg(feature = "trace")]
+    pub fn with_trace(mut self, writer: impl io::Write + 'a) -> Validator<'a> {
+        self.trace = Some(Box::new(writer));
+        self
+    }
+
+    #[cfg(feature = "trace")]
+    fn validate_traced(&mut self, evt: Token<'a>) -> Result<(), ValidatorError<'a>> {
+        let before = self.current_step;
+        let count_before = self.pattern_count();
+        let result = self.validate(evt);
+        let memo_hit = self.pattern_count() == count_before;
+        let (kind, name) = match evt {
+            Token::Declaration { .. } => ("Declaration", None),
+            Token::ProcessingInstruction { target, .. } => {
+                ("ProcessingInstruction", Some(("", target)))
+            }
+            Token::Comment { .. } => ("Comment", None),
+            Token::DtdStart { name, .. } => ("DtdStart", Some(("", name))),
+            Token::EmptyDtd { name, .. } => ("EmptyDtd", Some(("", name))),
+            Token::EntityDeclaration { name, .. } => ("EntityDeclaration", Some(("", name))),
+            Token::DtdEnd { .. } => ("DtdEnd", None),
+            Token::ElementStart { prefix, local, .. } => {
+                ("ElementStart", Some((prefix.as_str(), local)))
+            }
+            Token::Attribute { prefix, local, .. } => ("Attribute", Some((prefix.as_str(), local))),
+            Token::ElementEnd {
+                end: ElementEnd::Open,
+                ..
+            } => ("ElementEndOpen", None),
+            Token::ElementEnd {
+                end: ElementEnd::Close(prefix, local),
+                ..
+            } => ("ElementEndClose", Some((prefix.as_str(), local))),
+            Token::ElementEnd {
+                end: ElementEnd::Empty,
+                ..
+            } => ("ElementEndEmpty", None),
+            Token::Text { .. } => ("Text", None),
+            Token::Cdata { .. } => ("Cdata", None),
+        };
+        let name = match name {
+            Some(("", local)) => format!(",\"name\":\"{local}\""),
+            Some((prefix, local)) => format!(",\"name\":\"{prefix}:{local}\""),
+            None => String::new(),
+        };
+        if let Some(trace) = self.trace.as_mut() {
+            let _ = writeln!(
+                trace,
+                "{{\"event\":\"{kind}\"{name},\"before\":{},\"after\":{},\"memo_hit\":{memo_hit},\"ok\":{}}}",
+                before.0,
+                self.current_step.0,
+                result.is_ok()
+            );
+        }
+        result
+    }
+
+    /// Give the text of the document that the tokens passed to `from_tokens()` were read from,
+    /// which `new()` finds for itself.  With it, an XML declaration out of place in the
+    /// document is reported as `MisplacedDeclaration` rather than as a tokenizer error.
+    pub fn with_source(mut self, source: &'a str) -> Validator<'a> {
+        self.source = Some(source);
+        self
+    }
+
+    /// When an element or attribute uses a namespace prefix that has not been declared, report
+    /// the `UndefinedNamespacePrefix` error and then carry on as if the prefix were declared
+    /// on that element with no namespace, instead of skipping the element.  The remainder of
+    /// the element's content is then still checked against the schema.
+    pub fn with_undefined_prefix_recovery(mut self) -> Validator<'a> {
+        self.recover_undefined_prefixes = true;
+        self
+    }
+
+    /// With `false`, check only the structure of the document, accepting any text wherever the
+    /// schema expects a datatype or value (including within a `list`).  Checking is on by
+    /// default.
+    pub fn with_datatype_checks(mut self, enabled: bool) -> Validator<'a> {
+        self.schema.skip_datatype_checks = !enabled;
+        self
+    }
+
+    /// With `false`, check attribute values as they are written (with references expanded),
+    /// rather than first replacing each tab and line end in them with a space as XML's
+    /// attribute-value normalization requires, which matters to datatypes such as `string` that
+    /// keep whitespace.  Normalization is on by default.
+    pub fn with_attribute_normalization(mut self, enabled: bool) -> Validator<'a> {
+        self.normalize_attributes = enabled;
+        self
+    }
+
+    /// Collect a `Note` for each value that is accepted but questionable, to be retrieved with
+    /// `take_notes()`
+    pub fn with_notes(mut self) -> Validator<'a> {
+        self.notes = Some(vec![]);
+        self
+    }
+
+    /// The notes collected since the last call, if enabled with `with_notes()`
+    pub fn take_notes(&mut self) -> Vec<Note> {
+        self.notes.as_mut().map(core::mem::take).unwrap_or_default()
+    }
+
+    /// Validate only the first `elements` elements of the document, for a quick check of a large
+    /// one: `validate_next()` returns `None` at the start tag of the next element, as if the
+    /// document had ended there, and `stopped_early()` becomes true.  No error is reported for
+    /// the elements left open, so finding no errors does not mean that the document is valid.
+    pub fn with_element_budget(mut self, elements: usize) -> Validator<'a> {
+        self.element_budget = Some(elements);
+        self
+    }
+
+    /// Whether validation stopped at the limit set by `with_element_budget()`, before the end of
+    /// the document.  The errors returned so far are real, but the rest of the document was
+    /// not validated.
+    pub fn stopped_early(&self) -> bool {
+        self.stopped_early
+    }
+
+    /// Call the given function every few thousand tokens with counts of how much of the document
+    /// has been validated, and once more when the end of the document is reached
+    pub fn with_progress(mut self, callback: impl FnMut(ValidationProgress) + 'a) -> Validator<'a> {
+        self.progress = Some(Progress {
+            counts: ValidationProgress::default(),
+            tokens: 0,
+            callback: Box::new(callback),
+        });
+        self
+    }
+
+    /// Report the final counts to the progress callback, if there is one
+    fn finish_progress(&mut self) {
+        if let Some(mut progress) = self.progress.take() {
+            (progress.callback)(progress.counts);
         }
     }
+
+    /// Check `xsd:ID` values for uniqueness against the given scope, which may be shared with
+    /// other validators, rather than against the IDs of this document alone
+    pub fn with_id_scope(mut self, scope: &'a mut IdScope) -> Validator<'a> {
+        self.ids = Ids::Shared(scope);
+        self
+    }
+
+    /// Replace each XInclude `include` element with the content of the document it refers to,
+    /// read by way of the given `XIncludeFiles`, and validate the result.
+    ///
+    /// The positions given by errors found within an included document, and by errors about an
+    /// `include` element within one, are offsets into that document rather than the one being
+    /// validated.
+    #[cfg(feature = "std")]
+    pub fn with_xinclude(mut self, files: &'a xinclude::XIncludeFiles) -> Validator<'a> {
+        let tokens = core::mem::replace(&mut self.tokenizer, Box::new(core::iter::empty()));
+        self.tokenizer = Box::new(xinclude::Expand::new(tokens, files, self.included.clone()));
+        self
+    }
+
     fn compile(s: &Schema, p: &model::Pattern) -> PatId {
         match p {
             model::Pattern::Choice(v) => {
@@ -673,12 +1970,8 @@ impl<'a> Validator<'a> {
                 right
             }
             model::Pattern::Interleave(v) => {
-                let mut iter = v.iter().rev();
-                let mut right = Self::compile(s, iter.next().unwrap());
-                for left in iter {
-                    right = s.interleave(Self::compile(s, left), right)
-                }
-                right
+                let members: Vec<_> = v.iter().map(|p| Self::compile(s, p)).collect();
+                s.interleave_all(&members)
             }
             model::Pattern::Group(v) => {
                 let mut iter = v.iter().rev();
@@ -692,6 +1985,10 @@ impl<'a> Validator<'a> {
             model::Pattern::Empty => s.empty(),
             model::Pattern::Text => s.text(),
             model::Pattern::NotAllowed => s.not_allowed(),
+            // A native optional pattern was tried, with the derivative of its content as its own
+            // derivative, but on the EPUB XHTML schema it saved under 1% of the patterns and
+            // made no measurable difference to validation time: the derivative of the `Empty`
+            // alternative is `NotAllowed`, which `choice()` drops without adding a pattern
             model::Pattern::Optional(p) => s.choice(Self::compile(s, p), s.empty()),
             model::Pattern::ZeroOrMore(p) => {
                 s.choice(s.one_or_more(Self::compile(s, p)), s.empty())
@@ -723,11 +2020,289 @@ impl<'a> Validator<'a> {
         }
     }
 
+    /// Write a description of the pattern that the remainder of the document must match (before
+    /// any input has been validated, this is the compiled form of the schema's start pattern)
+    #[cfg(feature = "std")]
+    pub fn dump_pattern<W: io::Write>(&self, w: &mut W) -> Result<(), io::Error> {
+        self.schema.dumpy(self.current_step, w)
+    }
+
+    /// The number of distinct patterns allocated so far, including those created as derivatives
+    /// while validating
+    pub fn pattern_count(&self) -> usize {
+        self.schema.inner.borrow().patterns.len()
+    }
+
+    /// Counters of the work done so far, including compiling the schema's start pattern
+    pub fn stats(&self) -> ValidationStats {
+        let inner = self.schema.inner.borrow();
+        ValidationStats {
+            tokens: self.tokens,
+            patterns: inner.patterns.len(),
+            patterns_reused: inner.memo_hits,
+        }
+    }
+
+    /// Validate the next token of the document, returning `None` once all tokens are consumed.
+    ///
+    /// Validation may continue after an error is returned, so that further errors can be
+    /// reported.  To carry on, the validator makes the smallest repair it can: an element that
+    /// is not allowed (or whose attributes are not) is skipped together with its content, text
+    /// that is not allowed is ignored, and an end tag that arrives before required content is
+    /// accepted as if that content had been present.  Errors following the first may be a
+    /// consequence of these repairs rather than separate problems in the document.  An
+    /// `Xml` error ends the document.
     pub fn validate_next(&mut self) -> Option<Result<(), ValidatorError<'a>>> {
-        match self.tokenizer.next() {
-            Some(Ok(evt)) => Some(self.validate(evt)),
-            Some(Err(err)) => Some(Err(ValidatorError::Xml(err))),
-            None => None,
+        if let Some(err) = self.pending_errors.pop_front() {
+            return Some(Err(self.returning(err)));
+        }
+        if self.stopped_early {
+            return None;
+        }
+        let evt = match self.tokenizer.next() {
+            Some(Ok(evt)) => evt,
+            Some(Err(err)) => {
+                let err = match err {
+                    ValidatorError::Xml(xmlparser::Error::UnknownToken(pos)) => {
+                        match self.declaration_at(pos) {
+                            Some(span) => ValidatorError::MisplacedDeclaration { span },
+                            // xmlparser accepts only comments, processing instructions and
+                            // whitespace after the document element
+                            None if self.stack.root_closed() => {
+                                ValidatorError::TrailingContent(pos)
+                            }
+                            None => err,
+                        }
+                    }
+                    err => err,
+                };
+                let path = self.stack.path();
+                return Some(Err(self.returning((err, path))));
+            }
+            None => {
+                if self.fragment
+                    && let Some(token) = self.text_token
+                    && let Err(err) = self.flush_text_buffer(token)
+                {
+                    let err = self.locate(err, Some(token));
+                    self.pending_errors.push_back((err, self.stack.path()));
+                }
+                self.check_idrefs();
+                if let Some(err) = self.pending_errors.pop_front() {
+                    return Some(Err(self.returning(err)));
+                }
+                self.finish_progress();
+                return None;
+            }
+        };
+        if let (Token::ElementStart { .. }, Some(budget)) = (evt, &mut self.element_budget) {
+            if *budget == 0 {
+                self.stopped_early = true;
+                self.finish_progress();
+                return None;
+            }
+            *budget -= 1;
+        }
+        self.tokens += 1;
+        if let Some(progress) = &mut self.progress {
+            progress.count(evt);
+        }
+        if self.skip_depth > 0 {
+            self.skip(evt);
+            return Some(Ok(()));
+        }
+        self.failed_text = None;
+        let mut result = self.validate_event(evt);
+        self.started = true;
+        if self.recover_undefined_prefixes {
+            // declare each missing prefix in turn and retry the event, which fails without side
+            // effects when a prefix is undefined
+            while let Err(ValidatorError::UndefinedNamespacePrefix { prefix }) = result {
+                let err = self.locate(ValidatorError::UndefinedNamespacePrefix { prefix }, None);
+                self.pending_errors.push_back((err, self.stack.path()));
+                self.stack.assume_no_namespace(prefix);
+                result = self.validate_event(evt);
+            }
+        }
+        if let Err(err) = result {
+            let path = match evt {
+                // text is flushed when the next element starts, by which time that element has
+                // been pushed, but any error belongs to the parent
+                Token::ElementStart { .. } => self.stack.parent_path(),
+                _ => self.stack.path(),
+            };
+            self.recover(evt);
+            let token = self.failed_text.take().unwrap_or(evt);
+            let err = self.locate(err, Some(token));
+            self.pending_errors.push_back((err, path));
+        }
+        if let Token::ElementEnd {
+            end: ElementEnd::Close(..) | ElementEnd::Empty,
+            ..
+        } = evt
+            && self.skip_depth == 0
+        {
+            self.stack.pop();
+        }
+        Some(match self.pending_errors.pop_front() {
+            Some(err) => Err(self.returning(err)),
+            None => Ok(()),
+        })
+    }
+
+    /// The error, as found in a document included by way of `with_xinclude()` if that is where
+    /// its span, or else the given token, comes from
+    fn locate(&self, err: ValidatorError<'a>, token: Option<Token<'a>>) -> ValidatorError<'a> {
+        let included = self.included.borrow();
+        let Some(text) = err.text().or(token.map(|tok| token_span(tok).as_str())) else {
+            return err;
+        };
+        let within = |document: &str| {
+            let document = document.as_bytes().as_ptr_range();
+            document.contains(&text.as_ptr()) || document.end == text.as_ptr()
+        };
+        match included.iter().find(|(_, document)| within(document)) {
+            Some(&(path, text)) => ValidatorError::Included {
+                path,
+                text,
+                error: Box::new(err),
+            },
+            None => err,
+        }
+    }
+
+    fn returning(&mut self, (err, path): (ValidatorError<'a>, String)) -> ValidatorError<'a> {
+        self.error_path = path;
+        err
+    }
+
+    /// The path from the document element to the element in which the error most recently
+    /// returned by `validate_next()` was found, like `/book/chapter[3]/section/para`.
+    ///
+    /// Each step is an element name as written in the document, followed by its position
+    /// among preceding siblings of the same name when it is not the first.  Empty until an
+    /// error has been returned.
+    pub fn error_path(&self) -> &str {
+        &self.error_path
+    }
+
+    /// The extent of the XML declaration starting at the given position, where the tokenizer
+    /// found a token it did not expect
+    fn declaration_at(&self, pos: xmlparser::TextPos) -> Option<core::ops::Range<usize>> {
+        let source = self.source?;
+        let start = SourceLocation::offset(source, pos.row as usize, pos.col as usize);
+        // the test the tokenizer makes when it finds a declaration in the wrong place
+        if !source[start..].starts_with("<?xml ") {
+            return None;
+        }
+        let end = source[start..]
+            .find("?>")
+            .map_or(source.len(), |i| start + i + 2);
+        Some(start..end)
+    }
+
+    /// Whether the innermost open element could be closed now: whether the content seen since
+    /// its start tag, including any text since the last tag, is all that the element requires.
+    /// False when no element is open.
+    ///
+    /// This does not change the state of validation, although it may add to the patterns
+    /// counted by `pattern_count()`.
+    pub fn can_close(&self) -> bool {
+        let step = if !self.text_buffer.is_empty() {
+            Self::text_deriv_id(
+                &mut HashMap::new(),
+                self.current_step,
+                &self.schema,
+                &self.text_buffer,
+                &self.stack,
+            )
+        } else if self.last_was_start_element {
+            // an element with no content, treated as having an empty text node, as when
+            // `ElementEnd::Close` is validated
+            Self::text_deriv_id(
+                &mut HashMap::new(),
+                self.current_step,
+                &self.schema,
+                "",
+                &self.stack,
+            )
+        } else {
+            self.current_step
+        };
+        let end = Self::end_tag_deriv(self.schema.patt(step), &self.schema);
+        !self.schema.is_not_allowed(end)
+    }
+
+    fn validate_event(&mut self, evt: Token<'a>) -> Result<(), ValidatorError<'a>> {
+        #[cfg(feature = "trace")]
+        if self.trace.is_some() {
+            return self.validate_traced(evt);
+        }
+        self.validate(evt)
+    }
+
+    /// Put the validator back into a state from which validation of the remaining tokens can
+    /// continue, after the given token failed to validate
+    fn recover(&mut self, evt: Token<'a>) {
+        self.text_buffer.clear();
+        self.text_span = None;
+        self.text_token = None;
+        match evt {
+            Token::ElementEnd {
+                end: ElementEnd::Open,
+                ..
+            } => {
+                // current_step is still that from before the element began
+                self.skip_depth = 1;
+                self.last_was_start_element = false;
+            }
+            Token::ElementEnd {
+                end: ElementEnd::Close(..),
+                ..
+            } => {
+                let next = self.force_end_tag(self.current_step);
+                if !self.schema.is_not_allowed(next) {
+                    self.current_step = next;
+                }
+                self.last_was_start_element = false;
+            }
+            // anything else failed without changing current_step, and so is just ignored
+            _ => {}
+        }
+    }
+
+    /// Ignore the tokens of an element being skipped by `recover()`, until its end
+    fn skip(&mut self, evt: Token<'a>) {
+        match evt {
+            Token::ElementEnd {
+                end: ElementEnd::Open,
+                ..
+            } => self.skip_depth += 1,
+            Token::ElementEnd {
+                end: ElementEnd::Close(..),
+                ..
+            } => {
+                self.skip_depth -= 1;
+                if self.skip_depth == 0 {
+                    // the end of the skipped element, which was on the stack
+                    self.stack.pop();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Like `end_tag_deriv()`, but allows the element to end even if required content is
+    /// missing
+    fn force_end_tag(&mut self, id: PatId) -> PatId {
+        match self.schema.patt(id) {
+            Pat::Choice(p1, p2, _) => {
+                let c1 = self.force_end_tag(p1);
+                let c2 = self.force_end_tag(p2);
+                self.schema.choice(c1, c2)
+            }
+            Pat::After(_, p2) => p2,
+            _ => self.schema.not_allowed(),
         }
     }
 
@@ -748,9 +2323,24 @@ impl<'a> Validator<'a> {
         self.schema.check_choices(self.current_step, &mut seen);
     }
 
+    /// Record that the given text from the document, part of the given token, was added to
+    /// `text_buffer`
+    fn extend_text_span(&mut self, token: Token<'a>, text: StrSpan) {
+        self.text_token.get_or_insert(token);
+        let start = self
+            .text_span
+            .as_ref()
+            .map_or(text.start(), |span| span.start);
+        self.text_span = Some(start..text.end());
+    }
+
     /// Flush any buffered text content by applying text_deriv to the current pattern.
-    /// Returns Err if the text is not allowed by the current pattern.
-    fn flush_text_buffer(&mut self) -> Result<(), ()> {
+    /// Returns Err if the text is not allowed by the current pattern, reporting the token holding
+    /// the start of the text (rather than `evt`, the token following it) unless there is a more
+    /// specific explanation.
+    fn flush_text_buffer(&mut self, evt: Token<'a>) -> Result<(), ValidatorError<'a>> {
+        let span = self.text_span.take();
+        let text_token = self.text_token.take();
         if self.text_buffer.is_empty() {
             return Ok(());
         }
@@ -758,20 +2348,54 @@ impl<'a> Validator<'a> {
         let next_id = Self::text_deriv_id(
             &mut memo,
             self.current_step,
-            &mut self.schema,
+            &self.schema,
             &self.text_buffer,
             &self.stack,
         );
-        self.text_buffer.clear();
+        let text = core::mem::take(&mut self.text_buffer);
         if self.schema.is_not_allowed(next_id) {
-            Err(())
+            let step = self.current_step;
+            self.failed_text = text_token;
+            Err(span
+                .and_then(|span| self.value_error(step, text, span))
+                .unwrap_or(ValidatorError::NotAllowed(text_token.unwrap_or(evt))))
         } else {
+            if self.notes.is_some()
+                && let Some(span) = span
+            {
+                let mut values = vec![];
+                Self::text_values(&self.schema, self.current_step, &mut values);
+                self.add_notes(&values, &text, span);
+            }
             self.current_step = next_id;
             self.last_was_start_element = false;
             Ok(())
         }
     }
 
+    /// If text that was matched against the given pattern failed because it does not fit the
+    /// `list` or the `data` pattern that was expected, an error describing what was required
+    fn value_error(
+        &mut self,
+        pat: PatId,
+        text: String,
+        span: core::ops::Range<usize>,
+    ) -> Option<ValidatorError<'a>> {
+        let mut values = vec![];
+        Self::text_values(&self.schema, pat, &mut values);
+        let Some(list) = Self::only_list(&self.schema, &values) else {
+            let reason = Self::explain_data(&self.schema, &values, &text)?;
+            return Some(ValidatorError::InvalidValue { reason, span });
+        };
+        let expected_description =
+            Self::describe_list_mismatch(&mut self.schema, list, &text, &self.stack)?;
+        Some(ValidatorError::InvalidListValue {
+            expected_description,
+            value: text,
+            span,
+        })
+    }
+
     fn validate(&mut self, evt: Token<'a>) -> Result<(), ValidatorError<'a>> {
         let pat = self.schema.patt(self.current_step);
         let new = match evt {
@@ -788,10 +2412,12 @@ impl<'a> Validator<'a> {
                 local,
                 span,
             } => {
-                // Flush any buffered text before processing a new element
-                self.flush_text_buffer()
-                    .map_err(|()| ValidatorError::NotAllowed(evt))?;
+                // The element is pushed before flushing any buffered text, so that if the text
+                // is not allowed the element is still tracked when validation continues.  The
+                // new element has no namespace declarations yet, so this doesn't change how
+                // prefixes in the text are resolved.
                 self.stack.push(prefix, local, span);
+                self.flush_text_buffer(evt)?;
                 // does not change current_step state
                 return Ok(());
             }
@@ -801,22 +2427,29 @@ impl<'a> Validator<'a> {
                 value,
                 span,
             } => {
-                self.stack.add_attr(prefix, local, value, span);
+                let text = self.expand_references(value, self.normalize_attributes)?;
+                self.stack.add_attr(prefix, local, value, text, span)?;
                 // does not change current_step state
                 return Ok(());
             }
-            Token::ElementEnd { end, span: _ } => {
+            Token::ElementEnd { end, span } => {
                 match end {
                     ElementEnd::Open => {
                         let result =
                             Self::close_element_start(&self.stack, &mut self.schema, evt, pat)?;
+                        self.check_ids()?;
+                        self.note_attributes()?;
                         self.last_was_start_element = true;
                         result
                     }
                     ElementEnd::Close(_, _) => {
-                        // Flush any buffered text before processing end tag
-                        self.flush_text_buffer()
-                            .map_err(|()| ValidatorError::NotAllowed(evt))?;
+                        // Flush any buffered text before processing end tag, keeping a copy in
+                        // case the text turns out to be an incomplete list
+                        let text = self
+                            .text_span
+                            .clone()
+                            .map(|span| (self.current_step, self.text_buffer.clone(), span));
+                        self.flush_text_buffer(evt)?;
                         let next_id = if self.last_was_start_element {
                             // The last event was the opening of an element with no child elements
                             // or child text nodes.
@@ -832,7 +2465,7 @@ impl<'a> Validator<'a> {
                             Self::text_deriv_id(
                                 &mut memo,
                                 self.current_step,
-                                &mut self.schema,
+                                &self.schema,
                                 "",
                                 &self.stack,
                             )
@@ -840,75 +2473,51 @@ impl<'a> Validator<'a> {
                             self.current_step
                         };
                         let next_pat = self.schema.patt(next_id);
-                        Self::end_tag_deriv(next_pat, &mut self.schema)
+                        let end_id = Self::end_tag_deriv(next_pat, &self.schema);
+                        if self.schema.is_not_allowed(end_id) {
+                            if let Some((step, text, span)) = text
+                                && let Some(err) = self.value_error(step, text, span)
+                            {
+                                return Err(err);
+                            }
+                            self.check_missing_content(next_id, span)?;
+                        }
+                        end_id
                     }
                     ElementEnd::Empty => {
                         let next_id =
                             Self::close_element_start(&self.stack, &mut self.schema, evt, pat)?;
+                        self.check_ids()?;
+                        self.note_attributes()?;
                         // Self-closing elements like <foo/> always have no children.
                         // Per https://relaxng.org/jclark/derivative.html ,
                         //     "The case where the list of children is empty is
                         //      treated as if there were a text node whose value
                         //      were the empty string."
                         let mut memo = HashMap::new();
-                        let p = Self::text_deriv_id(
-                            &mut memo,
-                            next_id,
-                            &mut self.schema,
-                            "",
-                            &self.stack,
-                        );
+                        let p =
+                            Self::text_deriv_id(&mut memo, next_id, &self.schema, "", &self.stack);
                         let next_pat = self.schema.patt(p);
-                        Self::end_tag_deriv(next_pat, &mut self.schema)
+                        let end_id = Self::end_tag_deriv(next_pat, &self.schema);
+                        if self.schema.is_not_allowed(end_id) {
+                            self.check_missing_content(p, span)?;
+                        }
+                        end_id
                     }
                 }
             }
             Token::Cdata { text, span: _ } => {
                 // Buffer CDATA text to be flushed with other text content
                 self.text_buffer.push_str(&text);
+                self.extend_text_span(evt, text);
                 self.last_was_start_element = false;
                 return Ok(());
             }
             Token::Text { text } => {
                 // Buffer text content (with entity resolution) for later flushing
-                let mut buffer = String::new();
-                for val in parse_entities(text.start(), text.as_str()) {
-                    match val {
-                        Ok(val) => {
-                            let txt = match val {
-                                Txt::Text(_pos, val) => val,
-                                Txt::Entity(pos, name) => {
-                                    if let Some(txt) = self.entity_definitions.get(name) {
-                                        txt
-                                    } else {
-                                        return Err(ValidatorError::UndefinedEntity {
-                                            name,
-                                            span: pos..pos + name.len(),
-                                        });
-                                    }
-                                }
-                                Txt::Char(_pos, val) => {
-                                    buffer.push(val);
-                                    continue;
-                                }
-                            };
-                            if txt.len() == text.len() {
-                                break;
-                            } else {
-                                buffer.push_str(txt);
-                            }
-                        }
-                        Err(err) => {
-                            return Err(err);
-                        }
-                    }
-                }
-                let data = if buffer.is_empty() {
-                    text.as_str()
-                } else {
-                    &buffer[..]
-                };
-                self.text_buffer.push_str(data);
+                let data = self.expand_references(text, false)?;
+                self.text_buffer.push_str(&data);
+                self.extend_text_span(evt, text);
                 self.last_was_start_element = false;
                 return Ok(());
             }
@@ -931,7 +2540,18 @@ impl<'a> Validator<'a> {
                     }
                 }
             }
-            Token::Declaration { .. } | Token::DtdStart { .. } | Token::DtdEnd { .. } => {
+            Token::Declaration { span, .. } => {
+                // the tokenizer rejects a misplaced declaration itself, but other sources of
+                // tokens may not
+                if self.started {
+                    return Err(ValidatorError::MisplacedDeclaration {
+                        span: span.start()..span.end(),
+                    });
+                }
+                // does not change current_step state
+                return Ok(());
+            }
+            Token::DtdStart { .. } | Token::DtdEnd { .. } => {
                 // does not change current_step state
                 return Ok(());
             }
@@ -956,18 +2576,170 @@ impl<'a> Validator<'a> {
         }
     }
 
-    fn close_element_start<'b: 'a>(
-        stack: &ElementStack<'b>,
-        schema: &mut Schema,
-        evt: Token<'b>,
-        pat: Pat,
-    ) -> Result<PatId, ValidatorError<'b>> {
-        let name = stack.current_element()?;
-        let next_pat = Self::start_tag_open_deriv(pat, schema, name);
-        // TODO: refactor early-returns
-        let next_pat = match schema.patt(next_pat) {
-            Pat::NotAllowed => {
-                return Err(ValidatorError::NotAllowed(Token::ElementStart {
+    /// Replace entity and character references in text from the document with the text they
+    /// stand for
+    ///
+    /// With `normalize`, as for an attribute value, each whitespace character other than those
+    /// given by character references is also replaced with a space, following XML's
+    /// attribute-value normalization.
+    fn expand_references(
+        &self,
+        text: StrSpan<'a>,
+        normalize: bool,
+    ) -> Result<Cow<'a, str>, ValidatorError<'a>> {
+        let special: &[char] = if normalize {
+            &['&', '\t', '\n', '\r']
+        } else {
+            &['&']
+        };
+        if !text.as_str().contains(special) {
+            return Ok(Cow::Borrowed(text.as_str()));
+        }
+        let push = if normalize {
+            push_normalized
+        } else {
+            String::push_str
+        };
+        let mut buffer = String::new();
+        for val in parse_entities(text.start(), text.as_str()) {
+            match val? {
+                Txt::Text(_pos, val) => push(&mut buffer, val),
+                Txt::Entity(pos, name) => match self.entity_definitions.get(name) {
+                    Some(val) => push(&mut buffer, val),
+                    None => {
+                        return Err(ValidatorError::UndefinedEntity {
+                            name,
+                            span: pos..pos + name.len(),
+                        });
+                    }
+                },
+                Txt::Char(_pos, val) => buffer.push(val),
+            }
+        }
+        Ok(Cow::Owned(buffer))
+    }
+
+    /// Record the values of any ID attributes of the current element, failing if a value was
+    /// seen before, and the IDs that any IDREF attributes refer to
+    ///
+    /// An attribute is an ID or IDREF if the schema gives it that type where it matched, on
+    /// this element, rather than by its name alone, since an attribute of the same name may
+    /// have some other type on other elements.
+    fn check_ids(&mut self) -> Result<(), ValidatorError<'a>> {
+        if self.id_attributes.is_empty() && self.idref_attributes.is_empty() {
+            return Ok(());
+        }
+        let element = self.stack.current_element()?;
+        let mut open = None;
+        for att in self.stack.current_attributes()? {
+            let maybe_id = self.id_attributes.iter().any(|nc| contains(nc, att.name));
+            let maybe_idref = self
+                .idref_attributes
+                .iter()
+                .any(|nc| contains(nc, att.name));
+            if !maybe_id && !maybe_idref {
+                continue;
+            }
+            let open = *open.get_or_insert_with(|| {
+                let pat = self.schema.patt(self.current_step);
+                Self::start_tag_open_deriv(pat, &mut self.schema, element)
+            });
+            let mut values = vec![];
+            Self::attribute_values(&self.schema, open, att.name, &mut values);
+            let has_type = |is_type: fn(&XsdDatatypes) -> bool| {
+                values.iter().any(|&id| match self.schema.patt(id) {
+                    Pat::Datatype(dt) | Pat::DatatypeExcept(dt, _) => {
+                        matches!(*dt, datatype::Datatypes::Xsd(ref dt) if is_type(dt))
+                    }
+                    _ => false,
+                })
+            };
+            if maybe_id && has_type(|dt| matches!(dt, XsdDatatypes::Id(_))) {
+                let id = datatype::relax::normalize_whitespace(&att.text);
+                if !self.ids.scope().insert(&id) {
+                    return Err(ValidatorError::DuplicateId { value: att.value });
+                }
+            }
+            if maybe_idref
+                && has_type(|dt| matches!(dt, XsdDatatypes::IdRef(_) | XsdDatatypes::IdRefs(_)))
+            {
+                for id in att.text.split_ascii_whitespace() {
+                    self.idrefs
+                        .push((id.to_string(), att.value, self.stack.path()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Queue an error for each reference to an ID that the document did not define
+    ///
+    /// With a shared `IdScope`, references may also be to the IDs of documents validated
+    /// earlier, but not to those of documents validated later.
+    fn check_idrefs(&mut self) {
+        for (id, value, path) in core::mem::take(&mut self.idrefs) {
+            if !self.ids.scope().contains(&id) {
+                let err = self.locate(ValidatorError::DanglingIdRef { id, value }, None);
+                self.pending_errors.push_back((err, path));
+            }
+        }
+    }
+
+    /// Add notes for the values of the current element's attributes, if notes are enabled
+    fn note_attributes(&mut self) -> Result<(), ValidatorError<'a>> {
+        if self.notes.is_none() {
+            return Ok(());
+        }
+        let name = self.stack.current_element()?;
+        let pat = self.schema.patt(self.current_step);
+        let open = Self::start_tag_open_deriv(pat, &mut self.schema, name);
+        for att in self.stack.current_attributes()? {
+            let mut values = vec![];
+            Self::attribute_values(&self.schema, open, att.name, &mut values);
+            self.add_notes(&values, &att.text, att.value.start()..att.value.end());
+        }
+        Ok(())
+    }
+
+    /// Add a note for each of the given `data` patterns which accepts the value, but has
+    /// something to say about it
+    fn add_notes(&mut self, values: &[PatId], value: &str, span: core::ops::Range<usize>) {
+        let Some(notes) = &mut self.notes else {
+            return;
+        };
+        for &id in values {
+            if let Pat::Datatype(dt) | Pat::DatatypeExcept(dt, _) = self.schema.patt(id)
+                && dt.is_valid(value)
+                && let Some(message) = dt.note(value)
+                // notes are added in document order, so any note already given for this value
+                // is among the last ones
+                && !notes
+                    .iter()
+                    .rev()
+                    .take_while(|n| n.span == span)
+                    .any(|n| n.message == message)
+            {
+                notes.push(Note {
+                    message,
+                    value: value.to_string(),
+                    span: span.clone(),
+                });
+            }
+        }
+    }
+
+    fn close_element_start<'b: 'a>(
+        stack: &ElementStack<'b>,
+        schema: &mut Schema,
+        evt: Token<'b>,
+        pat: Pat,
+    ) -> Result<PatId, ValidatorError<'b>> {
+        let name = stack.current_element()?;
+        let next_pat = Self::start_tag_open_deriv(pat, schema, name);
+        // TODO: refactor early-returns
+        let next_pat = match schema.patt(next_pat) {
+            Pat::NotAllowed => {
+                return Err(ValidatorError::NotAllowed(Token::ElementStart {
                     prefix: name.namespace_uri.unwrap_or_else(|| StrSpan::from("")),
                     local: name.local_name,
                     span: name.local_name,
@@ -978,8 +2750,13 @@ impl<'a> Validator<'a> {
                 let mut pat = next_pat;
                 for att in attributes {
                     let mut memo = HashMap::new();
-                    pat = Self::att_deriv(&mut memo, pat, schema, att, stack);
+                    let before = pat;
+                    pat = Self::att_deriv(&mut memo, pat, schema, &att, stack);
                     if let Pat::NotAllowed = schema.patt(pat) {
+                        if let Some(err) = Self::attribute_value_error(stack, schema, before, &att)
+                        {
+                            return Err(err);
+                        }
                         return Err(ValidatorError::NotAllowed(Token::Attribute {
                             prefix: att.name.namespace_uri.unwrap_or_else(|| StrSpan::from("")),
                             local: att.name.local_name,
@@ -1001,6 +2778,186 @@ impl<'a> Validator<'a> {
         })
     }
 
+    /// If the given attribute failed to match because its value does not fit the `list` or the
+    /// `data` pattern that was expected for it, an error describing what was required
+    fn attribute_value_error<'b>(
+        stack: &ElementStack<'b>,
+        schema: &mut Schema,
+        pat: PatId,
+        att: &Attr<'b>,
+    ) -> Option<ValidatorError<'b>> {
+        let mut values = vec![];
+        Self::attribute_values(schema, pat, att.name, &mut values);
+        let Some(list) = Self::only_list(schema, &values) else {
+            let reason = Self::explain_data(schema, &values, &att.text)?;
+            return Some(ValidatorError::InvalidValue {
+                reason,
+                span: att.value.start()..att.value.end(),
+            });
+        };
+        let expected_description = Self::describe_list_mismatch(schema, list, &att.text, stack)?;
+        Some(ValidatorError::InvalidListValue {
+            expected_description,
+            value: att.text.to_string(),
+            span: att.value.start()..att.value.end(),
+        })
+    }
+
+    /// Collects the patterns that text could be matched against as a whole at the given point:
+    /// each `list`, `data` and `value`
+    fn text_values(schema: &Schema, id: PatId, result: &mut Vec<PatId>) {
+        match schema.patt(id) {
+            Pat::Choice(p1, p2, _) => {
+                Self::text_values(schema, p1, result);
+                Self::text_values(schema, p2, result);
+            }
+            Pat::Interleave(members, _) => {
+                for &member in members.iter() {
+                    Self::text_values(schema, member, result);
+                }
+            }
+            Pat::Group(p1, p2, _) => {
+                Self::text_values(schema, p1, result);
+                if schema.nullable(p1) {
+                    Self::text_values(schema, p2, result);
+                }
+            }
+            Pat::OneOrMore(p, _) | Pat::After(p, _) => Self::text_values(schema, p, result),
+            Pat::List(_) | Pat::Datatype(_) | Pat::DatatypeExcept(_, _) | Pat::DatatypeValue(_)
+                if !result.contains(&id) =>
+            {
+                result.push(id)
+            }
+            _ => {}
+        }
+    }
+
+    /// Collects the patterns that the value of an attribute with the given name could be
+    /// matched against as a whole (see `text_values()`)
+    fn attribute_values(schema: &Schema, id: PatId, name: QualifiedName, result: &mut Vec<PatId>) {
+        match schema.patt(id) {
+            Pat::Choice(p1, p2, _) | Pat::Group(p1, p2, _) => {
+                Self::attribute_values(schema, p1, name, result);
+                Self::attribute_values(schema, p2, name, result);
+            }
+            Pat::Interleave(members, _) => {
+                for &member in members.iter() {
+                    Self::attribute_values(schema, member, name, result);
+                }
+            }
+            Pat::OneOrMore(p, _) | Pat::After(p, _) => {
+                Self::attribute_values(schema, p, name, result)
+            }
+            Pat::Attribute(nc, p) if contains(&nc, name) => Self::text_values(schema, p, result),
+            _ => {}
+        }
+    }
+
+    /// The content of the only `list` among the given patterns, if there is exactly one
+    fn only_list(schema: &Schema, values: &[PatId]) -> Option<PatId> {
+        let mut lists = values.iter().filter_map(|&id| match schema.patt(id) {
+            Pat::List(p) => Some(p),
+            _ => None,
+        });
+        let list = lists.next()?;
+        lists.next().is_none().then_some(list)
+    }
+
+    /// Why the value was rejected by the only one of the given patterns, if that is a `data`
+    /// pattern (naming the facet that the value fails, or else the datatype) or a `value`
+    /// pattern
+    fn explain_data(schema: &Schema, values: &[PatId], value: &str) -> Option<String> {
+        let [id] = values else {
+            return None;
+        };
+        match schema.patt(*id) {
+            Pat::Datatype(dt) | Pat::DatatypeExcept(dt, _) => dt.explain(value).or_else(|| {
+                (!dt.is_valid(value)).then(|| format!("expected {} but found {value:?}", dt.name()))
+            }),
+            Pat::DatatypeValue(dt) => Some(format!(
+                "expected {} {:?} but found {value:?}",
+                dt.name(),
+                dt.lexical()
+            )),
+            _ => None,
+        }
+    }
+
+    /// Explains why `value` does not match the content of a `list` pattern, or returns `None`
+    /// if it does match
+    fn describe_list_mismatch(
+        schema: &mut Schema,
+        list: PatId,
+        value: &str,
+        ns: &dyn Namespaces,
+    ) -> Option<String> {
+        let items: Vec<&str> = value.split_whitespace().collect();
+        let found = items.len();
+        if let Some((min, max)) = Self::list_item_count(schema, list)
+            && (found < min || max.is_some_and(|max| found > max))
+        {
+            let expected = match max {
+                Some(max) if max == min => plural(min, "item"),
+                Some(max) => format!("between {min} and {max} items"),
+                None => format!("at least {}", plural(min, "item")),
+            };
+            return Some(format!("{expected} but found {found}"));
+        }
+        let mut p = list;
+        for (i, item) in items.iter().enumerate() {
+            let mut memo = HashMap::new();
+            p = Self::text_deriv_memo(&mut memo, p, schema, item, ns);
+            if schema.is_not_allowed(p) {
+                return Some(format!(
+                    "a different value for item {} than {item:?}",
+                    i + 1
+                ));
+            }
+        }
+        if schema.nullable(p) {
+            None
+        } else {
+            Some(format!("more items after the {}", plural(found, "item")))
+        }
+    }
+
+    /// The least and greatest number of items that the content of a `list` pattern can match
+    /// (`None` for no upper limit), or `None` if it can match nothing at all
+    fn list_item_count(schema: &Schema, id: PatId) -> Option<(usize, Option<usize>)> {
+        Some(match schema.patt(id) {
+            Pat::Empty => (0, Some(0)),
+            Pat::NotAllowed => return None,
+            Pat::Datatype(_) | Pat::DatatypeValue(_) | Pat::DatatypeExcept(_, _) => (1, Some(1)),
+            Pat::Choice(p1, p2, _) => match (
+                Self::list_item_count(schema, p1),
+                Self::list_item_count(schema, p2),
+            ) {
+                (Some((min1, max1)), Some((min2, max2))) => {
+                    (min1.min(min2), max1.zip(max2).map(|(a, b)| a.max(b)))
+                }
+                (count, None) | (None, count) => return count,
+            },
+            Pat::Group(p1, p2, _) => {
+                let (min1, max1) = Self::list_item_count(schema, p1)?;
+                let (min2, max2) = Self::list_item_count(schema, p2)?;
+                (min1 + min2, max1.zip(max2).map(|(a, b)| a + b))
+            }
+            Pat::Interleave(members, _) => {
+                let mut count = (0, Some(0));
+                for &member in members.iter() {
+                    let (min, max) = Self::list_item_count(schema, member)?;
+                    count = (count.0 + min, count.1.zip(max).map(|(a, b)| a + b));
+                }
+                count
+            }
+            Pat::OneOrMore(p, _) => match Self::list_item_count(schema, p)? {
+                (min, Some(0)) => (min, Some(0)),
+                (min, _) => (min, None),
+            },
+            _ => (0, None),
+        })
+    }
+
     fn text_deriv(current: Pat, schema: &mut Schema, text: &str, ns: &dyn Namespaces) -> PatId {
         let mut memo = HashMap::new();
         let id = schema.push(current);
@@ -1010,7 +2967,7 @@ impl<'a> Validator<'a> {
     fn text_deriv_id(
         memo: &mut HashMap<PatId, PatId>,
         id: PatId,
-        schema: &mut Schema,
+        schema: &Schema,
         text: &str,
         ns: &dyn Namespaces,
     ) -> PatId {
@@ -1020,7 +2977,7 @@ impl<'a> Validator<'a> {
     fn text_deriv_memo(
         memo: &mut HashMap<PatId, PatId>,
         id: PatId,
-        schema: &mut Schema,
+        schema: &Schema,
         text: &str,
         ns: &dyn Namespaces,
     ) -> PatId {
@@ -1034,13 +2991,24 @@ impl<'a> Validator<'a> {
                 let b = Self::text_deriv_memo(memo, p2, schema, text, ns);
                 schema.choice(a, b)
             }
-            Pat::Interleave(p1, p2, _) => {
-                let d1 = Self::text_deriv_memo(memo, p1, schema, text, ns);
-                let a = schema.interleave(d1, p2);
-
-                let d2 = Self::text_deriv_memo(memo, p2, schema, text, ns);
-                let b = schema.interleave(p1, d2);
-                schema.choice(a, b)
+            Pat::Interleave(members, _) => {
+                let mut result = schema.not_allowed();
+                // whitespace leaves most members as they were, and so the interleave too
+                let mut unchanged = false;
+                for (i, &member) in members.iter().enumerate() {
+                    let d = Self::text_deriv_memo(memo, member, schema, text, ns);
+                    if d == member {
+                        unchanged = true;
+                    } else if !schema.is_not_allowed(d) {
+                        let advanced = schema.interleave_replacing(&members, i, d);
+                        result = schema.choice(result, advanced);
+                    }
+                }
+                if unchanged {
+                    schema.choice(result, id)
+                } else {
+                    result
+                }
             }
             Pat::Group(p1, p2, _) => {
                 let nullable = schema.nullable(p1);
@@ -1062,6 +3030,11 @@ impl<'a> Validator<'a> {
                 schema.group(d, schema.choice(schema.one_or_more(p), schema.empty()))
             }
             Pat::Text => schema.text(),
+            Pat::Datatype(_) | Pat::DatatypeValue(_) | Pat::DatatypeExcept(_, _)
+                if schema.skip_datatype_checks =>
+            {
+                schema.empty()
+            }
             Pat::Datatype(dt) => {
                 if dt.is_valid(text) {
                     schema.empty()
@@ -1086,8 +3059,12 @@ impl<'a> Validator<'a> {
             }
             Pat::List(p) => {
                 let mut p = p;
+                let mut item_memo = HashMap::new();
                 for item in text.split_whitespace() {
-                    p = Self::text_deriv_memo(memo, p, schema, item, ns);
+                    // the memo is only valid for a single text value, so is emptied for each
+                    // item (keeping its capacity, which matters for lists of many items)
+                    item_memo.clear();
+                    p = Self::text_deriv_memo(&mut item_memo, p, schema, item, ns);
                     if schema.is_not_allowed(p) {
                         return p;
                     }
@@ -1149,16 +3126,22 @@ impl<'a> Validator<'a> {
                     schema.group(pat, schema.choice(schema.one_or_more(pid), schema.empty()))
                 })
             }
-            Pat::Interleave(pid1, pid2, _) => {
-                let d1 = Self::start_tag_open_deriv_memo(memo, pid1, schema, name);
-                let c1 = Self::apply_after(schema.patt(d1), schema, |pat, schema| {
-                    schema.interleave(pat, pid2)
-                });
-                let d2 = Self::start_tag_open_deriv_memo(memo, pid2, schema, name);
-                let c2 = Self::apply_after(schema.patt(d2), schema, |pat, schema| {
-                    schema.interleave(pid1, pat)
-                });
-                schema.choice(c1, c2)
+            Pat::Interleave(members, _) => {
+                // Usually the element can only start in one member, so only that member is
+                // advanced, and the others are shared with the current interleave
+                let mut result = schema.not_allowed();
+                for (i, &member) in members.iter().enumerate() {
+                    let d = Self::start_tag_open_deriv_memo(memo, member, schema, name);
+                    if !schema.is_not_allowed(d) {
+                        let members = members.clone();
+                        let advanced =
+                            Self::apply_after(schema.patt(d), schema, move |pat, schema| {
+                                schema.interleave_replacing(&members, i, pat)
+                            });
+                        result = schema.choice(result, advanced);
+                    }
+                }
+                result
             }
             Pat::Group(pid1, pid2, _) => {
                 let nullable = schema.nullable(pid1);
@@ -1231,7 +3214,7 @@ impl<'a> Validator<'a> {
         memo: &mut HashMap<PatId, PatId>,
         pat: PatId,
         schema: &mut Schema,
-        att: Attr,
+        att: &Attr,
         ns: &dyn Namespaces,
     ) -> PatId {
         if let Some(result) = memo.get(&pat) {
@@ -1257,12 +3240,16 @@ impl<'a> Validator<'a> {
                 let s2 = schema.group(p1, d2);
                 schema.choice(s1, s2)
             }
-            Pat::Interleave(p1, p2, _) => {
-                let d1 = Self::att_deriv(memo, p1, schema, att, ns);
-                let i1 = schema.interleave(d1, p2);
-                let d2 = Self::att_deriv(memo, p2, schema, att, ns);
-                let i2 = schema.interleave(p1, d2);
-                schema.choice(i1, i2)
+            Pat::Interleave(members, _) => {
+                let mut result = schema.not_allowed();
+                for (i, &member) in members.iter().enumerate() {
+                    let d = Self::att_deriv(memo, member, schema, att, ns);
+                    if !schema.is_not_allowed(d) {
+                        let advanced = schema.interleave_replacing(&members, i, d);
+                        result = schema.choice(result, advanced);
+                    }
+                }
+                result
             }
             Pat::OneOrMore(p, _) => {
                 let s1 = Self::att_deriv(memo, p, schema, att, ns);
@@ -1271,7 +3258,7 @@ impl<'a> Validator<'a> {
             }
             Pat::Attribute(ref nc, p) => {
                 let att_pat = schema.patt(p);
-                if contains(nc, att.name) && Self::value_match(att_pat, schema, &att.value, ns) {
+                if contains(nc, att.name) && Self::value_match(att_pat, schema, &att.text, ns) {
                     schema.empty()
                 } else {
                     schema.not_allowed()
@@ -1309,10 +3296,16 @@ impl<'a> Validator<'a> {
                 let c2 = Self::start_tag_close_deriv(p2, schema);
                 schema.group(c1, c2)
             }
-            Pat::Interleave(p1, p2, _) => {
-                let c1 = Self::start_tag_close_deriv(p1, schema);
-                let c2 = Self::start_tag_close_deriv(p2, schema);
-                schema.interleave(c1, c2)
+            Pat::Interleave(members, _) => {
+                let closed: Vec<_> = members
+                    .iter()
+                    .map(|&member| Self::start_tag_close_deriv(member, schema))
+                    .collect();
+                if *closed == *members {
+                    pid
+                } else {
+                    schema.interleave_all(&closed)
+                }
             }
             Pat::OneOrMore(p, _) => {
                 let _o = Self::start_tag_close_deriv(p, schema);
@@ -1323,7 +3316,7 @@ impl<'a> Validator<'a> {
         }
     }
 
-    fn end_tag_deriv(pat: Pat, schema: &mut Schema) -> PatId {
+    fn end_tag_deriv(pat: Pat, schema: &Schema) -> PatId {
         match pat {
             Pat::Choice(p1, p2, _) => {
                 let p1 = schema.patt(p1);
@@ -1359,15 +3352,14 @@ impl<'a> Validator<'a> {
                 self.head(result, p1);
                 self.head(result, p2);
             }
-            Pat::Interleave(p1, p2, _) => {
-                self.head(result, p1);
-                self.head(result, p2);
+            Pat::Interleave(members, _) => {
+                for &member in members.iter() {
+                    self.head(result, member);
+                }
             }
             Pat::Group(p1, p2, _) => {
+                self.head(result, p1);
                 if self.schema.patt(p1).is_nullable() {
-                    self.head(result, p1);
-                } else {
-                    self.head(result, p1);
                     self.head(result, p2);
                 }
             }
@@ -1396,45 +3388,65 @@ impl<'a> Validator<'a> {
         }
     }
 
-    fn describe_expected(&self, expected_patt: PatId) -> String {
+    /// The names of the elements which could come next, given the pattern that the content
+    /// seen so far leaves to be matched
+    fn expected_elements(&self, expected_patt: PatId) -> Vec<String> {
         #[allow(clippy::mutable_key_type)] // false-positive
         let heads = self.heads(expected_patt);
-        let mut result = String::new();
-        const MAX_ELEMENTS: usize = 4;
-        let mut rest = 0;
-        for (i, nameclass) in heads
+        let mut names: Vec<String> = heads
             .iter()
             .filter_map(|p| {
                 if let Pat::Element(nameclass, _) = p {
-                    Some(nameclass)
+                    // TODO: also provide namespace information; grouping by namespace to make
+                    //       the information more succinct
+                    let mut desc = String::new();
+                    self.describe_nameclass(nameclass, &mut desc);
+                    Some(desc)
                 } else {
                     None
                 }
             })
-            .enumerate()
-        {
-            if i == 0 {
-                result.push_str("Element ");
-            }
-            if i >= MAX_ELEMENTS {
-                rest += 1;
-            } else {
-                if i > 0 {
-                    result.push(' ');
-                }
-                // TODO: also provide namespace information; grouping by namespace to make the
-                //       information more succinct
-                let mut desc = String::new();
-                self.describe_nameclass(nameclass, &mut desc);
-                result.push_str(&desc);
-            }
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    #[cfg(feature = "std")]
+    fn describe_expected(&self, expected_patt: PatId) -> String {
+        let names = self.expected_elements(expected_patt);
+        let mut result = String::new();
+        const MAX_ELEMENTS: usize = 4;
+        if !names.is_empty() {
+            result.push_str("Element ");
+            result.push_str(&names[..names.len().min(MAX_ELEMENTS)].join(" "));
         }
-        if rest > 0 {
-            result.push_str(&format!(" .. or one of {rest} more"))
+        if names.len() > MAX_ELEMENTS {
+            result.push_str(&format!(
+                " .. or one of {} more",
+                names.len() - MAX_ELEMENTS
+            ))
         }
         // TODO: plus attributes and everything else
         result
     }
+
+    /// An element whose content is matched by `content` is ending but may not: unless `content`
+    /// could match nothing more anyway, report what it still requires
+    fn check_missing_content(
+        &self,
+        content: PatId,
+        span: StrSpan<'a>,
+    ) -> Result<(), ValidatorError<'a>> {
+        if self.schema.is_not_allowed(content) {
+            return Ok(());
+        }
+        Err(ValidatorError::MissingRequiredContent {
+            expected: self.expected_elements(content),
+            span,
+        })
+    }
+
     #[allow(clippy::only_used_in_recursion)]
     fn describe_nameclass(&self, nc: &NameClass, desc: &mut String) {
         match nc {
@@ -1470,6 +3482,7 @@ impl<'a> Validator<'a> {
         }
     }
 
+    #[cfg(feature = "std")]
     pub fn diagnostic(
         &self,
         name: String,
@@ -1479,13 +3492,33 @@ impl<'a> Validator<'a> {
         let mut map = codemap::CodeMap::new();
         let file = map.add_file(name, source);
         let mut diagnostics = vec![];
+        self.push_diagnostics(&mut map, &file, err, &mut diagnostics);
+        if let Some(d) = diagnostics
+            .iter_mut()
+            .find(|d| d.level == codemap_diagnostic::Level::Error)
+        {
+            d.code = Some(err.code().to_string());
+        }
+        (map, diagnostics)
+    }
+
+    /// Add the diagnostics for the given error, found in the given file, to `diagnostics`,
+    /// adding any document included by way of XInclude that it was found in to the map
+    #[cfg(feature = "std")]
+    fn push_diagnostics(
+        &self,
+        map: &mut codemap::CodeMap,
+        file: &codemap::File,
+        err: &ValidatorError,
+        diagnostics: &mut Vec<codemap_diagnostic::Diagnostic>,
+    ) {
         match err {
             ValidatorError::Xml(err) => {
-                let line = file.line_span(err.pos().row as _);
-                let span = line.subspan(err.pos().row as _, err.pos().row as _);
-
+                let pos = err.pos();
+                let offset =
+                    SourceLocation::offset(file.source(), pos.row as usize, pos.col as usize);
                 let label = codemap_diagnostic::SpanLabel {
-                    span,
+                    span: file.span.subspan(offset as _, offset as _),
                     label: None,
                     style: codemap_diagnostic::SpanStyle::Primary,
                 };
@@ -1497,6 +3530,40 @@ impl<'a> Validator<'a> {
                     spans: vec![label],
                 });
             }
+            ValidatorError::TrailingContent(pos) => {
+                let offset =
+                    SourceLocation::offset(file.source(), pos.row as usize, pos.col as usize);
+                let label = codemap_diagnostic::SpanLabel {
+                    span: file.span.subspan(offset as _, offset as _),
+                    label: Some("remove this, or move it inside the document element".to_string()),
+                    style: codemap_diagnostic::SpanStyle::Primary,
+                };
+
+                diagnostics.push(codemap_diagnostic::Diagnostic {
+                    level: codemap_diagnostic::Level::Error,
+                    message: "Content is not allowed after the end of the document element"
+                        .to_string(),
+                    code: None,
+                    spans: vec![label],
+                });
+            }
+            ValidatorError::MisplacedDeclaration { span } => {
+                let label = codemap_diagnostic::SpanLabel {
+                    span: file.span.subspan(span.start as _, span.end as _),
+                    label: Some(
+                        "remove this; nothing may come before an XML declaration".to_string(),
+                    ),
+                    style: codemap_diagnostic::SpanStyle::Primary,
+                };
+
+                diagnostics.push(codemap_diagnostic::Diagnostic {
+                    level: codemap_diagnostic::Level::Error,
+                    message: "An XML declaration is only allowed at the very start of the document"
+                        .to_string(),
+                    code: None,
+                    spans: vec![label],
+                });
+            }
             ValidatorError::NotAllowed(tok) => {
                 let span = match tok {
                     Token::Declaration { span, .. }
@@ -1597,46 +3664,221 @@ impl<'a> Validator<'a> {
                     spans: vec![label],
                 })
             }
-        }
-        (map, diagnostics)
-    }
-}
+            ValidatorError::DuplicateNamespaceDeclaration { prefix } => {
+                let label = codemap_diagnostic::SpanLabel {
+                    span: file.span.subspan(prefix.start() as _, prefix.end() as _),
+                    label: Some("declared again here".to_string()),
+                    style: codemap_diagnostic::SpanStyle::Primary,
+                };
 
-#[derive(Debug)]
-enum Txt<'a> {
-    Text(usize, &'a str),
-    Entity(usize, &'a str),
-    Char(usize, char),
-}
+                diagnostics.push(codemap_diagnostic::Diagnostic {
+                    level: codemap_diagnostic::Level::Error,
+                    message: "The same namespace prefix is declared twice on one element"
+                        .to_string(),
+                    code: None,
+                    spans: vec![label],
+                })
+            }
+            ValidatorError::EmptyNamespaceDeclaration { prefix } => {
+                let label = codemap_diagnostic::SpanLabel {
+                    span: file.span.subspan(prefix.start() as _, prefix.end() as _),
+                    label: Some("bound to an empty namespace name".to_string()),
+                    style: codemap_diagnostic::SpanStyle::Primary,
+                };
 
-fn parse_entities(
-    pos: usize,
-    text: &str,
-) -> impl Iterator<Item = Result<Txt<'_>, ValidatorError<'_>>> {
-    struct Entities<'a> {
-        text: &'a str,
-        pos: usize,
-        offset: usize,
-        in_entity: bool,
-    }
-    impl<'a> Iterator for Entities<'a> {
-        type Item = Result<Txt<'a>, ValidatorError<'a>>;
+                diagnostics.push(codemap_diagnostic::Diagnostic {
+                    level: codemap_diagnostic::Level::Error,
+                    message: format!(
+                        "The prefix {:?} may not be declared with an empty namespace name",
+                        prefix.as_str()
+                    ),
+                    code: None,
+                    spans: vec![label],
+                })
+            }
+            ValidatorError::DuplicateAttribute { name, span } => {
+                let label = codemap_diagnostic::SpanLabel {
+                    span: file.span.subspan(span.start() as _, span.end() as _),
+                    label: Some("given again here".to_string()),
+                    style: codemap_diagnostic::SpanStyle::Primary,
+                };
 
-        fn next(&mut self) -> Option<Self::Item> {
-            if self.offset == self.text.len() {
-                return None;
+                diagnostics.push(codemap_diagnostic::Diagnostic {
+                    level: codemap_diagnostic::Level::Error,
+                    message: format!("The attribute {name:?} appears twice on one element"),
+                    code: None,
+                    spans: vec![label],
+                })
             }
-            for (i, c) in self.text[self.offset..].char_indices() {
-                if self.in_entity {
-                    if c == ';' {
-                        self.in_entity = false;
-                        let text = &self.text[self.offset..self.offset + i];
-                        let result = if let Some(text) = text.strip_prefix('#') {
-                            numeric_entity(self.offset, text)
-                        } else {
-                            Ok(Txt::Entity(self.offset + self.pos, text))
-                        };
-                        self.offset += i + 1;
+            ValidatorError::DuplicateId { value } => {
+                let label = codemap_diagnostic::SpanLabel {
+                    span: file.span.subspan(value.start() as _, value.end() as _),
+                    label: Some("already used".to_string()),
+                    style: codemap_diagnostic::SpanStyle::Primary,
+                };
+
+                diagnostics.push(codemap_diagnostic::Diagnostic {
+                    level: codemap_diagnostic::Level::Error,
+                    message: format!("Duplicate ID {:?}", value.as_str()),
+                    code: None,
+                    spans: vec![label],
+                })
+            }
+            ValidatorError::DanglingIdRef { id, value } => {
+                let label = codemap_diagnostic::SpanLabel {
+                    span: file.span.subspan(value.start() as _, value.end() as _),
+                    label: Some("referred to here".to_string()),
+                    style: codemap_diagnostic::SpanStyle::Primary,
+                };
+
+                diagnostics.push(codemap_diagnostic::Diagnostic {
+                    level: codemap_diagnostic::Level::Error,
+                    message: format!("No element has the ID {id:?}"),
+                    code: None,
+                    spans: vec![label],
+                })
+            }
+            ValidatorError::InvalidListValue {
+                expected_description,
+                value,
+                span,
+            } => {
+                let label = codemap_diagnostic::SpanLabel {
+                    span: file.span.subspan(span.start as _, span.end as _),
+                    label: Some("does not match the list pattern".to_string()),
+                    style: codemap_diagnostic::SpanStyle::Primary,
+                };
+
+                diagnostics.push(codemap_diagnostic::Diagnostic {
+                    level: codemap_diagnostic::Level::Error,
+                    message: format!(
+                        "Invalid list value {value:?}: expected {expected_description}"
+                    ),
+                    code: None,
+                    spans: vec![label],
+                })
+            }
+            ValidatorError::InvalidValue { reason, span } => {
+                let label = codemap_diagnostic::SpanLabel {
+                    span: file.span.subspan(span.start as _, span.end as _),
+                    label: Some("not allowed by the datatype's facets".to_string()),
+                    style: codemap_diagnostic::SpanStyle::Primary,
+                };
+
+                diagnostics.push(codemap_diagnostic::Diagnostic {
+                    level: codemap_diagnostic::Level::Error,
+                    message: format!("Invalid value: {reason}"),
+                    code: None,
+                    spans: vec![label],
+                })
+            }
+            ValidatorError::IllegalCharacterReference { code_point, span } => {
+                let label = codemap_diagnostic::SpanLabel {
+                    span: file.span.subspan(span.start as _, span.end as _),
+                    label: Some(if is_surrogate(*code_point) {
+                        "a UTF-16 surrogate; refer to the whole character instead".to_string()
+                    } else {
+                        "not a legal XML character".to_string()
+                    }),
+                    style: codemap_diagnostic::SpanStyle::Primary,
+                };
+
+                diagnostics.push(codemap_diagnostic::Diagnostic {
+                    level: codemap_diagnostic::Level::Error,
+                    message: format!(
+                        "Character reference to illegal code point U+{code_point:04X}"
+                    ),
+                    code: None,
+                    spans: vec![label],
+                })
+            }
+            ValidatorError::XInclude { message, span } => {
+                let label = codemap_diagnostic::SpanLabel {
+                    span: file.span.subspan(span.start as _, span.end as _),
+                    label: None,
+                    style: codemap_diagnostic::SpanStyle::Primary,
+                };
+
+                diagnostics.push(codemap_diagnostic::Diagnostic {
+                    level: codemap_diagnostic::Level::Error,
+                    message: format!("XInclude failed: {message}"),
+                    code: None,
+                    spans: vec![label],
+                })
+            }
+            ValidatorError::MissingRequiredContent { expected, span } => {
+                let label = codemap_diagnostic::SpanLabel {
+                    span: file.span.subspan(span.start() as _, span.end() as _),
+                    label: Some("the element ends here".to_string()),
+                    style: codemap_diagnostic::SpanStyle::Primary,
+                };
+
+                diagnostics.push(codemap_diagnostic::Diagnostic {
+                    level: codemap_diagnostic::Level::Error,
+                    message: missing_content_message(expected),
+                    code: None,
+                    spans: vec![label],
+                })
+            }
+            ValidatorError::Included { path, text, error } => {
+                let included = map.add_file(path.display().to_string(), text.to_string());
+                self.push_diagnostics(map, &included, error, diagnostics);
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Txt<'a> {
+    Text(usize, &'a str),
+    Entity(usize, &'a str),
+    Char(usize, char),
+}
+
+/// Append text from an attribute value with each whitespace character replaced by a space, and
+/// each `\r\n` line end by a single space
+fn push_normalized(buffer: &mut String, text: &str) {
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\r' => {
+                chars.next_if_eq(&'\n');
+                buffer.push(' ');
+            }
+            '\t' | '\n' => buffer.push(' '),
+            c => buffer.push(c),
+        }
+    }
+}
+
+fn parse_entities(
+    pos: usize,
+    text: &str,
+) -> impl Iterator<Item = Result<Txt<'_>, ValidatorError<'_>>> {
+    struct Entities<'a> {
+        text: &'a str,
+        pos: usize,
+        offset: usize,
+        in_entity: bool,
+    }
+    impl<'a> Iterator for Entities<'a> {
+        type Item = Result<Txt<'a>, ValidatorError<'a>>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            if self.offset == self.text.len() {
+                return None;
+            }
+            for (i, c) in self.text[self.offset..].char_indices() {
+                if self.in_entity {
+                    if c == ';' {
+                        self.in_entity = false;
+                        let text = &self.text[self.offset..self.offset + i];
+                        let result = if let Some(text) = text.strip_prefix('#') {
+                            numeric_entity(self.offset + self.pos, text)
+                        } else {
+                            Ok(Txt::Entity(self.offset + self.pos, text))
+                        };
+                        self.offset += i + 1;
                         return Some(result);
                     }
                 } else if c == '&' {
@@ -1675,9 +3917,15 @@ fn parse_entities(
             text.parse()
                 .map_err(|_e| ValidatorError::InvalidOrUnclosedEntity { span: pos..pos })?
         };
+        if !is_xml_char(c) {
+            return Err(ValidatorError::IllegalCharacterReference {
+                code_point: c,
+                span: pos..pos,
+            });
+        }
         Ok(Txt::Char(
             pos,
-            std::char::from_u32(c)
+            core::char::from_u32(c)
                 .ok_or(ValidatorError::InvalidOrUnclosedEntity { span: pos..pos })?,
         ))
     }
@@ -1689,10 +3937,31 @@ fn parse_entities(
     }
 }
 
+/// The XML 1.0 `Char` production, which excludes most C0 controls, surrogates and the
+/// non-characters U+FFFE / U+FFFF
+fn is_xml_char(c: u32) -> bool {
+    matches!(c, 0x9 | 0xA | 0xD | 0x20..=0xD7FF | 0xE000..=0xFFFD | 0x10000..=0x10FFFF)
+}
+
+/// Half of a UTF-16 surrogate pair, which a character reference may not name even when paired
+/// with a reference to the other half, since each reference must be a whole code point
+fn is_surrogate(c: u32) -> bool {
+    (0xD800..=0xDFFF).contains(&c)
+}
+
 fn is_whitespace_char(c: char) -> bool {
     ['\x20', '\x09', '\x0d', '\x0a'].contains(&c)
 }
 
+/// `"1 item"`, `"2 items"` etc.
+fn plural(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("{count} {noun}")
+    } else {
+        format!("{count} {noun}s")
+    }
+}
+
 fn is_whitespace_str(s: &str) -> bool {
     s.chars().all(is_whitespace_char)
 }
@@ -1700,9 +3969,16 @@ fn is_whitespace_str(s: &str) -> bool {
 #[derive(Default)]
 struct ElementStack<'a> {
     elements: Vec<ElementState<'a>>,
+    /// Counts the document element, of which well-formed XML has just one
+    roots: SiblingCounts<'a>,
 }
 
 impl<'a> ElementStack<'a> {
+    /// Whether the document element has been seen, and has ended
+    fn root_closed(&self) -> bool {
+        self.elements.is_empty() && !self.roots.0.is_empty()
+    }
+
     fn lookup_namespace_uri(&self, prefix: &str) -> Option<StrSpan<'a>> {
         self.elements
             .iter()
@@ -1722,8 +3998,6 @@ impl<'a> ElementStack<'a> {
                 _ => Ok(None),
             }
         } else if prefix.as_str() == "xml" {
-            // The xml: prefix is always predefined per XML Namespaces spec
-            static XML_NS: &str = "http://www.w3.org/XML/1998/namespace";
             Ok(Some(StrSpan::from(XML_NS)))
         } else {
             Ok(Some(self.lookup_namespace_uri(&prefix).ok_or(
@@ -1733,30 +4007,61 @@ impl<'a> ElementStack<'a> {
     }
 
     fn push(&mut self, prefix: StrSpan<'a>, local: StrSpan<'a>, _span: StrSpan<'a>) {
+        let siblings = match self.elements.last_mut() {
+            Some(parent) => &mut parent.children,
+            None => &mut self.roots,
+        };
+        let position = siblings.add(prefix, local);
         self.elements.push(ElementState {
             prefix,
             local,
+            position,
+            children: SiblingCounts::default(),
             namespaces: vec![],
             attributes: vec![],
         })
     }
+    fn pop(&mut self) {
+        self.elements.pop();
+    }
+    /// The path of names of the open elements, e.g. `/book/chapter[3]/para`
+    fn path(&self) -> String {
+        Self::path_of(&self.elements)
+    }
+    /// The path of the parent of the most recently started element
+    fn parent_path(&self) -> String {
+        Self::path_of(&self.elements[..self.elements.len().saturating_sub(1)])
+    }
+    fn path_of(elements: &[ElementState<'a>]) -> String {
+        let mut path = String::new();
+        for elem in elements {
+            path.push('/');
+            if !elem.prefix.is_empty() {
+                path.push_str(&elem.prefix);
+                path.push(':');
+            }
+            path.push_str(&elem.local);
+            if elem.position > 1 {
+                path.push_str(&format!("[{}]", elem.position));
+            }
+        }
+        path
+    }
     fn add_attr(
         &mut self,
         prefix: StrSpan<'a>,
         local: StrSpan<'a>,
         value: StrSpan<'a>,
+        text: Cow<'a, str>,
         span: StrSpan<'a>,
-    ) {
+    ) -> Result<(), ValidatorError<'a>> {
         if prefix.as_str() == "xmlns" {
-            self.elements.last_mut().unwrap().namespaces.push(Ns {
-                prefix: local,
-                namespace_uri: value,
-            })
+            if value.as_str().is_empty() {
+                return Err(ValidatorError::EmptyNamespaceDeclaration { prefix: local });
+            }
+            self.add_namespace(local, value, span)
         } else if prefix.as_str() == "" && local.as_str() == "xmlns" {
-            self.elements.last_mut().unwrap().namespaces.push(Ns {
-                prefix,
-                namespace_uri: value,
-            })
+            self.add_namespace(prefix, value, span)
         } else {
             self.elements
                 .last_mut()
@@ -1766,9 +4071,40 @@ impl<'a> ElementStack<'a> {
                     prefix,
                     local,
                     value,
+                    text,
                     span,
-                })
+                });
+            Ok(())
+        }
+    }
+    fn add_namespace(
+        &mut self,
+        prefix: StrSpan<'a>,
+        namespace_uri: StrSpan<'a>,
+        span: StrSpan<'a>,
+    ) -> Result<(), ValidatorError<'a>> {
+        let namespaces = &mut self.elements.last_mut().unwrap().namespaces;
+        if namespaces
+            .iter()
+            .any(|ns| ns.prefix.as_str() == prefix.as_str())
+        {
+            // for the default namespace, report the whole 'xmlns' attribute
+            let prefix = if prefix.is_empty() { span } else { prefix };
+            return Err(ValidatorError::DuplicateNamespaceDeclaration { prefix });
         }
+        namespaces.push(Ns {
+            prefix,
+            namespace_uri,
+        });
+        Ok(())
+    }
+    /// Declare the prefix on the current element as mapping to no namespace, so that uses of an
+    /// undeclared prefix can be resolved after the error has been reported
+    fn assume_no_namespace(&mut self, prefix: StrSpan<'a>) {
+        self.elements.last_mut().unwrap().namespaces.push(Ns {
+            prefix,
+            namespace_uri: StrSpan::from(""),
+        });
     }
     fn current_element(&self) -> Result<QualifiedName<'a>, ValidatorError<'a>> {
         let curr = self.elements.last().unwrap();
@@ -1779,33 +4115,48 @@ impl<'a> ElementStack<'a> {
         })
     }
     fn current_attributes(&self) -> Result<Vec<Attr<'a>>, ValidatorError<'a>> {
-        self.elements
-            .last()
-            .unwrap()
-            .attributes
-            .iter()
-            .map(move |unresolved| {
-                let namespace_uri = if unresolved.prefix.as_str() == "" {
-                    None
-                } else {
-                    self.try_lookup_namespace_uri(unresolved.prefix)?
-                };
-                Ok(Attr {
-                    name: QualifiedName {
-                        namespace_uri,
-                        local_name: unresolved.local,
-                    },
-                    value: unresolved.value,
-                    span: unresolved.span,
-                })
-            })
-            .collect()
+        let mut attributes: Vec<Attr<'a>> = vec![];
+        for unresolved in &self.elements.last().unwrap().attributes {
+            let namespace_uri = if unresolved.prefix.as_str() == "" {
+                None
+            } else {
+                self.try_lookup_namespace_uri(unresolved.prefix)?
+            };
+            let name = QualifiedName {
+                namespace_uri,
+                local_name: unresolved.local,
+            };
+            let same_name = |att: &Attr| {
+                att.name.local_name.as_str() == name.local_name.as_str()
+                    && att.name.namespace_uri.map(|ns| ns.as_str())
+                        == name.namespace_uri.map(|ns| ns.as_str())
+            };
+            if attributes.iter().any(same_name) {
+                let span = unresolved.span;
+                let name = span.as_str().split('=').next().unwrap_or_default();
+                return Err(ValidatorError::DuplicateAttribute {
+                    name: name.trim_end(),
+                    span,
+                });
+            }
+            attributes.push(Attr {
+                name,
+                value: unresolved.value,
+                text: unresolved.text.clone(),
+                span: unresolved.span,
+            });
+        }
+        Ok(attributes)
     }
 }
 
 impl<'a> Namespaces for ElementStack<'a> {
     fn resolve(&self, prefix: &str) -> Option<&str> {
-        self.lookup_namespace_uri(prefix).map(|s| s.as_str())
+        if prefix == "xml" {
+            Some(XML_NS)
+        } else {
+            self.lookup_namespace_uri(prefix).map(|s| s.as_str())
+        }
     }
 }
 
@@ -1813,15 +4164,44 @@ struct UnresolvedAttr<'a> {
     prefix: StrSpan<'a>,
     local: StrSpan<'a>,
     value: StrSpan<'a>,
+    text: Cow<'a, str>,
     span: StrSpan<'a>,
 }
 struct ElementState<'a> {
     prefix: StrSpan<'a>,
     local: StrSpan<'a>,
+    /// 1 for the first child of the parent with this name, 2 for the second, and so on
+    position: usize,
+    children: SiblingCounts<'a>,
     namespaces: Vec<Ns<'a>>,
     attributes: Vec<UnresolvedAttr<'a>>,
 }
 
+/// The number of child elements with each name (as written, prefix included) seen so far
+#[derive(Default)]
+struct SiblingCounts<'a>(Vec<(StrSpan<'a>, StrSpan<'a>, usize)>);
+
+impl<'a> SiblingCounts<'a> {
+    /// Count another element with the given name, returning its position among those so far
+    fn add(&mut self, prefix: StrSpan<'a>, local: StrSpan<'a>) -> usize {
+        let name = (prefix.as_str(), local.as_str());
+        match self
+            .0
+            .iter_mut()
+            .find(|(p, l, _)| (p.as_str(), l.as_str()) == name)
+        {
+            Some((_, _, count)) => {
+                *count += 1;
+                *count
+            }
+            None => {
+                self.0.push((prefix, local, 1));
+                1
+            }
+        }
+    }
+}
+
 impl<'a> ElementState<'a> {
     fn lookup_namespace_uri(&self, prefix: &str) -> Option<StrSpan<'a>> {
         self.namespaces
@@ -1831,6 +4211,9 @@ impl<'a> ElementState<'a> {
     }
 }
 
+/// The namespace of the `xml:` prefix, which is always predefined per the XML Namespaces spec
+static XML_NS: &str = "http://www.w3.org/XML/1998/namespace";
+
 struct Ns<'a> {
     prefix: StrSpan<'a>,
     namespace_uri: StrSpan<'a>,
@@ -1838,7 +4221,7 @@ struct Ns<'a> {
 
 #[cfg(test)]
 mod tests {
-    use crate::Validator;
+    use crate::{IdScope, Validator};
     use assert_matches::assert_matches;
     use relaxng_model::model::DefineRule;
     use relaxng_model::{Compiler, Files, RelaxError, Syntax};
@@ -1882,12 +4265,15 @@ mod tests {
             let mut v = Validator::new(self.schema.clone(), reader);
             while let Some(i) = v.validate_next() {
                 if let Err(err) = i {
-                    let (map, d) = v.diagnostic("valid.xml".to_string(), xml.to_string(), &err);
-                    let mut emitter = codemap_diagnostic::Emitter::stderr(
-                        codemap_diagnostic::ColorConfig::Auto,
-                        Some(&map),
-                    );
-                    emitter.emit(&d[..]);
+                    #[cfg(feature = "std")]
+                    {
+                        let (map, d) = v.diagnostic("valid.xml".to_string(), xml.to_string(), &err);
+                        let mut emitter = codemap_diagnostic::Emitter::stderr(
+                            codemap_diagnostic::ColorConfig::Auto,
+                            Some(&map),
+                        );
+                        emitter.emit(&d[..]);
+                    }
                     panic!("{err:?}");
                 }
             }
@@ -1903,6 +4289,30 @@ mod tests {
             }
             panic!("Invalid input was accepted by the validator")
         }
+
+        /// The errors found in the document, continuing past each one
+        fn errors(&self, doc: &str) -> Vec<crate::OwnedValidatorError> {
+            errors_of(
+                Validator::new(self.schema.clone(), xmlparser::Tokenizer::from(doc)),
+                doc,
+            )
+        }
+
+        /// The first error found in the document, if any
+        fn first_error(&self, doc: &str) -> Option<crate::OwnedValidatorError> {
+            let mut v = Validator::new(self.schema.clone(), xmlparser::Tokenizer::from(doc));
+            std::iter::from_fn(|| v.validate_next())
+                .find_map(|r| r.err())
+                .map(|e| e.into_owned(doc))
+        }
+    }
+
+    /// The errors that the validator finds in the given document, which it must be validating
+    fn errors_of<'a>(mut v: Validator<'a>, doc: &'a str) -> Vec<crate::OwnedValidatorError> {
+        std::iter::from_fn(|| v.validate_next())
+            .filter_map(|r| r.err())
+            .map(|e| e.into_owned(doc))
+            .collect()
     }
 
     fn check_simple(schema: &str, doc: &str) -> Result<(), String> {
@@ -1933,9 +4343,12 @@ mod tests {
 
         let reader = xmlparser::Tokenizer::from(doc);
         let mut v = Validator::new(schema, reader);
-        println!("====");
-        v.schema.d(v.current_step).unwrap();
-        println!("====");
+        #[cfg(feature = "std")]
+        {
+            println!("====");
+            v.schema.d(v.current_step).unwrap();
+            println!("====");
+        }
         let mut fail = None;
         while let Some(i) = v.validate_next() {
             if let Err(err) = i {
@@ -1995,6 +4408,23 @@ mod tests {
         assert_matches!(res, Err(_));
     }
 
+    #[test]
+    fn value_choice() {
+        for schema in [
+            "start = element e { \"a\" | \"b\" }",
+            "start = element e { xsd:token \"a\" | xsd:token \"b\" }",
+        ] {
+            let fixture = Fixture::correct(schema);
+            fixture.valid("<e>a</e>");
+            fixture.valid("<e>b</e>");
+            // token values compare after whitespace normalisation
+            fixture.valid("<e> b </e>");
+            fixture.invalid("<e>c</e>");
+            fixture.invalid("<e>a b</e>");
+            fixture.invalid("<e/>");
+        }
+    }
+
     #[test]
     fn elem_text_empty() {
         // if the only child of an element is whitespace, this should still match 'empty'
@@ -2005,6 +4435,67 @@ mod tests {
         assert_matches!(res, Ok(()));
     }
 
+    #[test]
+    fn elem_comment_or_pi_empty() {
+        // a comment or processing instruction is not content, so an element containing only
+        // one still matches 'empty', and still lacks a required child element
+        for doc in [
+            "<e><!-- c --></e>",
+            "<e><?pi?></e>",
+            "<e> <!-- c --> <?pi?> </e>",
+        ] {
+            assert_matches!(
+                check_simple("start = element e { empty }", doc),
+                Ok(()),
+                "{doc}"
+            );
+            assert_matches!(
+                check_simple("start = element e { element b { empty } }", doc),
+                Err(e) if e.contains("MissingRequiredContent"),
+                "{doc}"
+            );
+        }
+        assert_matches!(
+            check_simple("start = element e { token 'a' }", "<e><!-- c --></e>"),
+            Err(_)
+        );
+        assert_matches!(
+            check_simple("start = element e { token '' }", "<e><?pi?></e>"),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn cdata_datatype_value() {
+        // text from CDATA sections is checked against datatypes just as other text is, and
+        // joined with any text around it
+        let schema = "start = element e { xsd:date }";
+        for doc in [
+            "<e><![CDATA[2024-01-01]]></e>",
+            "<e>2024<![CDATA[-01-01]]></e>",
+            "<e><![CDATA[2024-]]>01<![CDATA[-01]]></e>",
+            "<e> 2024-01-01 </e>",
+            "<e> <![CDATA[2024-01-01]]> </e>",
+        ] {
+            assert_matches!(check_simple(schema, doc), Ok(()), "{doc}");
+        }
+        for doc in ["<e><![CDATA[bad]]></e>", "<e>2024<![CDATA[-01-01x]]></e>"] {
+            assert_matches!(check_simple(schema, doc), Err(_), "{doc}");
+        }
+    }
+
+    #[test]
+    fn zero_length_values() {
+        // an element with no content holds the empty string
+        let min0 = "start = element e { xsd:string { minLength = \"0\" } }";
+        assert_matches!(check_simple(min0, "<e/>"), Ok(()));
+        assert_matches!(check_simple(min0, "<e></e>"), Ok(()));
+        let len0 = "start = element e { xsd:base64Binary { length = \"0\" } }";
+        assert_matches!(check_simple(len0, "<e/>"), Ok(()));
+        assert_matches!(check_simple(len0, "<e> </e>"), Ok(()));
+        assert_matches!(check_simple(len0, "<e>AA==</e>"), Err(_));
+    }
+
     #[test]
     fn elem_only_children_whitespace() {
         // if the only child of an element is whitespace, this should still match 'empty'
@@ -2086,6 +4577,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn unprefixed_attribute_in_no_namespace() {
+        // an unprefixed attribute is in no namespace, whatever the default namespace of its
+        // element, and so matches an attribute name with no namespace
+        let fixture = Fixture::correct(
+            "default namespace = \"urn:x\"
+             namespace x = \"urn:x\"
+             start = element e { attribute foo { text } }",
+        );
+        fixture.valid("<e xmlns='urn:x' foo='v'/>");
+        fixture.valid("<x:e xmlns:x='urn:x' foo='v'/>");
+        fixture.invalid("<e xmlns='urn:x' xmlns:x='urn:x' x:foo='v'/>");
+
+        let fixture = Fixture::correct(
+            "namespace x = \"urn:x\"
+             start = element x:e { attribute x:foo { text } }",
+        );
+        fixture.valid("<e xmlns='urn:x' xmlns:x='urn:x' x:foo='v'/>");
+        fixture.invalid("<e xmlns='urn:x' foo='v'/>");
+    }
+
     #[test]
     fn blowup() {
         // https://relaxng.org/jclark/derivative.html#Avoiding_exponential_blowup
@@ -2120,4 +4632,1560 @@ mod tests {
         assert_matches!(iter.next(), Some(Ok(super::Txt::Entity(5, "bar"))));
         assert_matches!(iter.next(), Some(Ok(super::Txt::Text(9, " blat"))));
     }
+
+    #[test]
+    fn illegal_character_references() {
+        let mut iter = super::parse_entities(0, "&#0;");
+        assert_matches!(iter.next(), Some(Ok(super::Txt::Text(0, ""))));
+        assert_matches!(
+            iter.next(),
+            Some(Err(super::ValidatorError::IllegalCharacterReference {
+                code_point: 0,
+                ..
+            }))
+        );
+        let mut iter = super::parse_entities(0, "&#xB;");
+        assert_matches!(iter.next(), Some(Ok(super::Txt::Text(0, ""))));
+        assert_matches!(
+            iter.next(),
+            Some(Err(super::ValidatorError::IllegalCharacterReference {
+                code_point: 0xB,
+                ..
+            }))
+        );
+
+        // each half of a surrogate pair is rejected, rather than being combined into one char
+        let mut iter = super::parse_entities(10, "&#xD83D;&#xDE00;");
+        assert_matches!(iter.next(), Some(Ok(super::Txt::Text(10, ""))));
+        assert_matches!(
+            iter.next(),
+            Some(Err(super::ValidatorError::IllegalCharacterReference {
+                code_point: 0xD83D,
+                span,
+            })) if span == (11..11)
+        );
+        let mut iter = super::parse_entities(10, "&#x1F600;");
+        assert_matches!(iter.next(), Some(Ok(super::Txt::Text(10, ""))));
+        assert_matches!(iter.next(), Some(Ok(super::Txt::Char(11, '\u{1F600}'))));
+
+        let fixture = Fixture::correct("start = element a { text }");
+        fixture.invalid("<a>&#xD83D;</a>");
+        fixture.invalid("<a>&#xD83D;&#xDE00;</a>");
+        fixture.valid("<a>&#x1F600;</a>");
+        fixture.valid("<a>&#128512;</a>");
+        fixture.invalid("<a>&#0;</a>");
+        fixture.invalid("<a>&#xB;</a>");
+        fixture.invalid("<a>&#xD800;</a>");
+        fixture.valid("<a>&#x9;</a>");
+    }
+
+    #[test]
+    fn subtrees() {
+        use crate::subtree::{Selector, Subtrees};
+        let fixture = Fixture::correct(
+            "default namespace = \"http://www.w3.org/2005/Atom\" \
+             start = element entry { element title { text } }",
+        );
+        let doc = "<!DOCTYPE feed [<!ENTITY t \"Title\">]>\
+            <feed xmlns=\"http://www.w3.org/2005/Atom\">\
+              <title>not checked</title>\
+              <entry><title>&t;</title></entry>\
+              <x:entry xmlns:x=\"urn:other\"/>\
+              <entry><bad/></entry>\
+            </feed>";
+        let results: Vec<bool> = Subtrees::new(
+            xmlparser::Tokenizer::from(doc),
+            Selector::new("entry").with_namespace("http://www.w3.org/2005/Atom"),
+        )
+        .map(|tokens| {
+            let mut v =
+                Validator::from_tokens(fixture.schema.clone(), tokens.unwrap().into_iter().map(Ok));
+            std::iter::from_fn(|| v.validate_next()).all(|r| r.is_ok())
+        })
+        .collect();
+        assert_eq!(results, vec![true, false]);
+
+        // the 'outermost' match only, when matches are nested
+        let nested: Vec<_> = Subtrees::new(
+            xmlparser::Tokenizer::from("<a><entry><entry/></entry><entry/></a>"),
+            Selector::new("entry"),
+        )
+        .collect();
+        assert_eq!(nested.len(), 2);
+
+        let none = Subtrees::new(
+            xmlparser::Tokenizer::from("<a><b/></a>"),
+            Selector::new("entry"),
+        );
+        assert_eq!(none.count(), 0);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn dump_pattern() {
+        let fixture = Fixture::correct("start = element a { attribute b { text }, empty }");
+        let v = Validator::new(fixture.schema.clone(), xmlparser::Tokenizer::from(""));
+        let mut out = vec![];
+        v.dump_pattern(&mut out).unwrap();
+        let dump = String::from_utf8(out).unwrap();
+        assert!(dump.starts_with("Elem"), "{dump}");
+        assert!(dump.contains("Att"), "{dump}");
+        assert!(dump.contains("Text"), "{dump}");
+        assert!(v.pattern_count() > 0);
+    }
+
+    #[test]
+    fn recovery() {
+        let fixture = Fixture::correct(
+            "start = element doc { item+ }\n\
+             item = element item { attribute n { xsd:integer }, element title { text } }",
+        );
+        assert!(
+            fixture
+                .errors("<doc><item n=\"1\"><title>a</title></item></doc>")
+                .is_empty()
+        );
+        let found = fixture.errors(concat!(
+            "<doc>",
+            // an element that is not allowed is skipped with its content
+            "<bogus><item/></bogus>",
+            // as is one with an invalid attribute
+            "<item n=\"one\"><title>b</title></item>",
+            // a missing child is reported at the end tag
+            "<item n=\"3\"></item>",
+            "<item n=\"4\"><title>d</title></item>",
+            "</doc>",
+        ));
+        let codes: Vec<_> = found.iter().map(|e| e.code()).collect();
+        assert_eq!(
+            codes,
+            ["not-allowed", "invalid-value", "missing-required-content"],
+            "{found:#?}"
+        );
+    }
+
+    #[test]
+    fn undefined_prefix_recovery() {
+        let fixture = Fixture::correct(
+            "start = element doc { element item { attribute n { xsd:integer } }* }",
+        );
+        let doc = "<doc><x:item n=\"1\"/><item n=\"two\"/><item n=\"3\"/></doc>";
+        let codes = |found: &[crate::OwnedValidatorError]| {
+            found.iter().map(|e| e.code()).collect::<Vec<_>>()
+        };
+
+        // without the option, the element with the undefined prefix is skipped
+        let found = fixture.errors(doc);
+        assert_eq!(found.len(), 2, "{found:#?}");
+        assert_eq!(codes(&found)[0], "undefined-namespace-prefix");
+
+        // with it, x:item is checked as an 'item' in no namespace, and the rest of the document
+        // is still validated
+        let v = Validator::new(fixture.schema.clone(), xmlparser::Tokenizer::from(doc))
+            .with_undefined_prefix_recovery();
+        let found = errors_of(v, doc);
+        assert_eq!(
+            codes(&found),
+            ["undefined-namespace-prefix", "invalid-value"],
+            "{found:#?}"
+        );
+
+        let doc = "<doc><x:item x:n=\"1\"/></doc>";
+        let v = Validator::new(fixture.schema.clone(), xmlparser::Tokenizer::from(doc))
+            .with_undefined_prefix_recovery();
+        let found = errors_of(v, doc);
+        // reported once, since the prefix counts as declared after the first use
+        assert_eq!(found.len(), 1, "{found:#?}");
+    }
+
+    #[test]
+    fn owned_errors() {
+        use crate::{OwnedValidatorError, SourceLocation};
+        fn assert_send<T: std::error::Error + Send + Sync + 'static>(_: &T) {}
+
+        let fixture =
+            Fixture::correct("start = a  a = element a { attribute id { xsd:ID }?, mixed { a* } }");
+        let first_error = |doc: &str| {
+            let owned = fixture
+                .first_error(doc)
+                .unwrap_or_else(|| panic!("no error for {doc:?}"));
+            assert_send(&owned);
+            assert!(!owned.to_string().is_empty());
+            owned
+        };
+        assert_matches!(
+            first_error("<a>\n<a x></a></a>"),
+            OwnedValidatorError::Xml {
+                location: SourceLocation {
+                    line: 2,
+                    column: 3,
+                    span,
+                },
+                ..
+            } if span == (6..6)
+        );
+        assert_matches!(
+            first_error("<a>\n  <b/></a>"),
+            OwnedValidatorError::NotAllowed {
+                kind: "element-start",
+                text,
+                location: SourceLocation { line: 2, column: 4, .. },
+            } if text == "b"
+        );
+        assert_matches!(
+            first_error("<x:a/>"),
+            OwnedValidatorError::UndefinedNamespacePrefix { prefix, location }
+                if prefix == "x" && location.span == (1..2)
+        );
+        assert_matches!(
+            first_error("<a><a>&foo;</a></a>"),
+            OwnedValidatorError::UndefinedEntity { name, .. } if name == "foo"
+        );
+        assert_matches!(
+            first_error("<a><a>&foo</a></a>"),
+            OwnedValidatorError::InvalidOrUnclosedEntity { .. }
+        );
+        assert_matches!(
+            first_error("<a><a>&#0;</a></a>"),
+            OwnedValidatorError::IllegalCharacterReference { code_point: 0, .. }
+        );
+        assert_matches!(
+            first_error("<a><a>&#xD83D;&#xDE00;</a></a>"),
+            OwnedValidatorError::IllegalCharacterReference { code_point: 0xD83D, location }
+                if location.span == (7..7)
+        );
+        assert_matches!(
+            first_error("<a xmlns:p='x' xmlns:p='y'/>"),
+            OwnedValidatorError::DuplicateNamespaceDeclaration { prefix, .. } if prefix == "p"
+        );
+        assert_matches!(
+            first_error("<a xmlns:p=''/>"),
+            OwnedValidatorError::EmptyNamespaceDeclaration { prefix, .. } if prefix == "p"
+        );
+        assert_matches!(
+            first_error("<a id='x'><a>\u{1F600}</a><a id='x'/></a>"),
+            OwnedValidatorError::DuplicateId { value, location }
+                if value == "x" && location.span == (28..29) && location.column == 26
+        );
+    }
+
+    #[test]
+    fn attribute_entity_references() {
+        let fixture = Fixture::correct("start = element e { attribute a { \"v\" } }");
+        fixture.valid("<!DOCTYPE e [<!ENTITY x \"v\">]><e a=\"&x;\"/>");
+        fixture.valid("<e a=\"&#x76;\"/>");
+        fixture.invalid("<!DOCTYPE e [<!ENTITY x \"w\">]><e a=\"&x;\"/>");
+
+        let fixture = Fixture::correct("start = element e { attribute a { \"<&>\" } }");
+        fixture.valid("<e a=\"&lt;&amp;&gt;\"/>");
+
+        let fixture = Fixture::correct("start = element e { attribute a { text } }");
+        let doc = "<e a=\"&y;\"/>";
+        let mut v = Validator::new(fixture.schema.clone(), xmlparser::Tokenizer::from(doc));
+        let err = std::iter::from_fn(|| v.validate_next()).find_map(|r| r.err());
+        assert_matches!(err, Some(super::ValidatorError::UndefinedEntity { name: "y", span }) if span == (7..8));
+    }
+
+    #[test]
+    fn invalid_list_value() {
+        use crate::OwnedValidatorError;
+
+        let fixture = Fixture::correct("start = element e { list { \"a\", \"b\" } }");
+        fixture.valid("<e>a b</e>");
+        let err = fixture.first_error("<e> a </e>").unwrap();
+        assert_eq!(
+            err.to_string(),
+            "1:4: Invalid list value \" a \": expected 2 items but found 1"
+        );
+        assert_eq!(err.location().span, 3..6);
+        let err = fixture.first_error("<e>a <![CDATA[c]]></e>").unwrap();
+        assert_eq!(
+            err.to_string(),
+            "1:4: Invalid list value \"a c\": expected a different value for item 2 than \"c\""
+        );
+
+        let fixture =
+            Fixture::correct("start = element e { attribute a { list { xsd:int, xsd:int? } } }");
+        fixture.valid("<e a=\"1 2\"/>");
+        assert_matches!(
+            fixture.first_error("<e a=\"1 2 3\"/>"),
+            Some(OwnedValidatorError::InvalidListValue { expected_description, value, .. })
+                if expected_description == "between 1 and 2 items but found 3" && value == "1 2 3"
+        );
+        assert_matches!(
+            fixture.first_error("<e a=\"1 x\"/>"),
+            Some(OwnedValidatorError::InvalidListValue { .. })
+        );
+
+        let fixture = Fixture::correct("start = element e { list { xsd:int+ } }");
+        fixture.invalid("<e>1 x</e>");
+        assert_matches!(
+            fixture.first_error("<e> </e>"),
+            Some(OwnedValidatorError::InvalidListValue { expected_description, .. })
+                if expected_description == "at least 1 item but found 0"
+        );
+    }
+
+    #[test]
+    fn invalid_value() {
+        use crate::OwnedValidatorError;
+
+        let fixture = Fixture::correct(
+            "start = element e {
+                attribute code { xsd:string { pattern = \"[0-9]+\" } },
+                xsd:integer { maxInclusive = \"10\" }
+            }",
+        );
+        fixture.valid("<e code='42'>10</e>");
+        let err = fixture.first_error("<e code='42'>12</e>").unwrap();
+        assert_eq!(
+            err.to_string(),
+            "1:14: Invalid value: \"12\" is greater than maxInclusive 10"
+        );
+        assert_eq!(err.location().span, 13..15);
+        let err = fixture.first_error("<e code='ab'>1</e>").unwrap();
+        assert_eq!(
+            err.to_string(),
+            "1:10: Invalid value: \"ab\" does not match pattern [0-9]+"
+        );
+        // a value which is not of the datatype at all fails no particular facet, so the type
+        // is named instead
+        let err = fixture.first_error("<e code='42'>ten</e>").unwrap();
+        assert_eq!(
+            err.to_string(),
+            "1:14: Invalid value: expected xsd:integer but found \"ten\""
+        );
+        let fixture = Fixture::correct("start = element e { xsd:date }");
+        let err = fixture.first_error("<e>2024-13-01</e>").unwrap();
+        assert_eq!(
+            err.to_string(),
+            "1:4: Invalid value: expected xsd:date but found \"2024-13-01\""
+        );
+        let fixture = Fixture::correct("start = element e { attribute a { \"yes\" } }");
+        let err = fixture.first_error("<e a='no'/>").unwrap();
+        assert_eq!(
+            err.to_string(),
+            "1:7: Invalid value: expected token \"yes\" but found \"no\""
+        );
+
+        // with a choice of patterns, none of them is singled out
+        let fixture = Fixture::correct(
+            "start = element e { xsd:integer { maxInclusive = \"10\" } | \"many\" }",
+        );
+        assert_matches!(
+            fixture.first_error("<e>12</e>"),
+            Some(OwnedValidatorError::NotAllowed { .. })
+        );
+    }
+
+    #[test]
+    fn list_ordered_pair() {
+        // e.g. a coordinate pair: exactly two items, each of which must be an int
+        let fixture = Fixture::correct("start = element e { list { xsd:int, xsd:int } }");
+        fixture.valid("<e>1 2</e>");
+        fixture.valid("<e>\n -1\t+2 </e>");
+        fixture.invalid("<e>1</e>");
+        fixture.invalid("<e>1 2 3</e>");
+        fixture.invalid("<e>1 x</e>");
+        fixture.invalid("<e>x 1</e>");
+
+        // items are matched in order against differently typed members
+        let fixture = Fixture::correct("start = element e { list { xsd:int, xsd:boolean } }");
+        fixture.valid("<e>1 true</e>");
+        fixture.invalid("<e>true 1</e>");
+    }
+
+    #[test]
+    fn missing_required_content() {
+        use crate::OwnedValidatorError;
+
+        let fixture =
+            Fixture::correct("start = element a { element b { empty }, element c { empty } }");
+        fixture.valid("<a><b/><c/></a>");
+        let err = fixture.first_error("<a><b/></a>").unwrap();
+        assert_eq!(
+            err.to_string(),
+            "1:8: The element ends before its required content: expected c"
+        );
+        assert_eq!(err.code(), "missing-required-content");
+        assert_matches!(
+            fixture.first_error("<a/>"),
+            Some(OwnedValidatorError::MissingRequiredContent { expected, .. }) if expected == ["b"]
+        );
+
+        // an optional element might have been the one to come next
+        let fixture = Fixture::correct(
+            "start = element a { element b { empty }, element x { empty }?, element c { empty } }",
+        );
+        assert_matches!(
+            fixture.first_error("<a><b/></a>"),
+            Some(OwnedValidatorError::MissingRequiredContent { expected, .. })
+                if expected == ["c", "x"]
+        );
+    }
+
+    #[test]
+    fn schema_set() {
+        use crate::schema_set::{NoMatchingSchema, SchemaSet};
+
+        let atom = Fixture::correct(
+            "default namespace = 'http://www.w3.org/2005/Atom'
+            start = element feed { element title { text }, element entry { element id { text } }* }",
+        );
+        let notes = Fixture::correct(
+            "namespace n = 'urn:example:notes'
+            start = element n:notes { element n:note { text }* }",
+        );
+        let mut set = SchemaSet::new();
+        set.insert("http://www.w3.org/2005/Atom", atom.schema.clone());
+        set.insert("urn:example:notes", notes.schema.clone());
+
+        let outcome = crate::validate_with_set(
+            &set,
+            "<feed xmlns='http://www.w3.org/2005/Atom'><title>t</title><entry><id>1</id></entry></feed>",
+        )
+        .unwrap();
+        assert!(outcome.ok, "{:?}", outcome.errors);
+        let outcome = crate::validate_with_set(
+            &set,
+            "<?xml version='1.0'?><!-- mixed --><n:notes xmlns:n='urn:example:notes'><n:note>a</n:note></n:notes>",
+        )
+        .unwrap();
+        assert!(outcome.ok, "{:?}", outcome.errors);
+        // the document is validated against the schema for its namespace
+        let outcome = crate::validate_with_set(
+            &set,
+            "<feed xmlns='http://www.w3.org/2005/Atom'><n:note xmlns:n='urn:example:notes'/></feed>",
+        )
+        .unwrap();
+        assert!(!outcome.ok);
+
+        assert_eq!(
+            crate::validate_with_set(&set, "<feed/>").unwrap_err(),
+            NoMatchingSchema {
+                namespace_uri: Some(String::new())
+            }
+        );
+        assert_eq!(
+            crate::validate_with_set(&set, "<x:feed/>").unwrap_err(),
+            NoMatchingSchema {
+                namespace_uri: None
+            }
+        );
+        assert!(set.validator("<feed xmlns='urn:other'/>").is_err());
+    }
+
+    #[test]
+    fn list_lengths() {
+        // a facet of a datatype within a list applies to each item
+        let fixture =
+            Fixture::correct("start = element e { list { xsd:string { minLength = \"3\" }+ } }");
+        fixture.valid("<e>abc</e>");
+        fixture.valid("<e> abc  defg </e>");
+        fixture.invalid("<e>abc de</e>");
+
+        // while the length of an XSD list type is its number of items
+        let fixture = Fixture::correct("start = element e { xsd:NMTOKENS { minLength = \"3\" } }");
+        fixture.valid("<e>a b c</e>");
+        fixture.invalid("<e>abc defg</e>");
+        let fixture = Fixture::correct("start = element e { xsd:ENTITIES { maxLength = \"2\" } }");
+        fixture.valid("<e>a bc</e>");
+        fixture.invalid("<e>a b c</e>");
+        let fixture = Fixture::correct("start = element e { xsd:ENTITY { maxLength = \"2\" } }");
+        fixture.valid("<e>ab</e>");
+        fixture.invalid("<e>a b</e>");
+
+        let fixture = Fixture::correct(
+            "start = element e { xsd:string { maxLength = \"3\" minLength = \"2\" } }",
+        );
+        fixture.valid("<e>abc</e>");
+        fixture.invalid("<e>a</e>");
+        fixture.invalid("<e>abcd</e>");
+    }
+
+    #[test]
+    fn notes() {
+        let fixture = Fixture::correct(
+            "start = element e { attribute href { xsd:anyURI }, (xsd:anyURI | xsd:int) }",
+        );
+        let doc = "<e href=\"http://example.com/a b\">http://example.com/</e>";
+        let outcome = super::validate(fixture.schema.clone(), doc);
+        assert!(outcome.ok);
+        assert_matches!(&outcome.notes[..], [super::Note { value, span, .. }] => {
+            assert_eq!(value, "http://example.com/a b");
+            assert_eq!(&doc[span.clone()], "http://example.com/a b");
+        });
+
+        let doc = "<e href=\"#top\">{x}</e>";
+        let outcome = super::validate(fixture.schema.clone(), doc);
+        assert!(outcome.ok);
+        assert_matches!(&outcome.notes[..], [super::Note { value, .. }] if value == "{x}");
+
+        let outcome = super::validate(fixture.schema.clone(), "<e href=\"a b\"><f/></e>");
+        assert!(!outcome.ok);
+        assert_eq!(outcome.errors.len(), 1);
+        assert_eq!(outcome.notes.len(), 1);
+
+        // one note for each questionable value, even where the value could match more than
+        // one of the datatypes giving it
+        let fixture = Fixture::correct(
+            "start = element e { attribute href { xsd:anyURI | xsd:anyURI { pattern = '.* .*' } }, \
+             xsd:anyURI }",
+        );
+        let outcome = super::validate(fixture.schema.clone(), "<e href=\"a b\">a b</e>");
+        assert!(outcome.ok);
+        assert_eq!(outcome.notes.len(), 2);
+    }
+
+    #[test]
+    fn datatype_checks() {
+        let fixture = Fixture::correct(
+            "start = element a { attribute when { xsd:dateTime }, element b { xsd:int }* }",
+        );
+        let doc = "<a when=\"not-a-date\"><b>x</b><b>1</b></a>";
+        fixture.invalid(doc);
+
+        assert!(!fixture.errors(doc).is_empty());
+        let v = Validator::new(fixture.schema.clone(), xmlparser::Tokenizer::from(doc))
+            .with_datatype_checks(false);
+        assert_eq!(errors_of(v, doc), []);
+
+        // the structure is still checked
+        let doc = "<a><b>1</b></a>";
+        let v = Validator::new(fixture.schema.clone(), xmlparser::Tokenizer::from(doc))
+            .with_datatype_checks(false);
+        assert_eq!(errors_of(v, doc).len(), 1);
+    }
+
+    #[test]
+    fn id_scope() {
+        let fixture =
+            Fixture::correct("start = element a { attribute id { xsd:ID }, element b { text }* }");
+        let doc1 = "<a id=\"x\"/>";
+        let doc2 = "<a id=\" x \"><b>x</b></a>";
+        fixture.valid(doc1);
+        fixture.valid(doc2);
+        fixture.invalid("<a id=\"x\"><a id=\"x\"/></a>");
+
+        fn run(v: &mut Validator) -> Result<(), String> {
+            while let Some(r) = v.validate_next() {
+                r.map_err(|e| format!("{e:?}"))?;
+            }
+            Ok(())
+        }
+        let mut scope = IdScope::new();
+        {
+            let mut v = Validator::new(fixture.schema.clone(), xmlparser::Tokenizer::from(doc1))
+                .with_id_scope(&mut scope);
+            assert_matches!(run(&mut v), Ok(()));
+        }
+        assert!(scope.contains("x"));
+        {
+            let mut v = Validator::new(fixture.schema.clone(), xmlparser::Tokenizer::from(doc2))
+                .with_id_scope(&mut scope);
+            assert_matches!(run(&mut v), Err(e) if e.contains("DuplicateId"));
+        }
+    }
+
+    #[test]
+    fn ids_depend_on_element() {
+        let fixture = Fixture::correct(
+            "start = element r {
+                 element a { attribute id { xsd:ID } }*,
+                 element b { attribute id { text } }*
+             }",
+        );
+        // only the attributes declared as IDs, on a, need to be unique
+        fixture.valid("<r><a id=\"x\"/><b id=\"x\"/><b id=\"x\"/></r>");
+        fixture.invalid("<r><a id=\"x\"/><a id=\"x\"/></r>");
+    }
+
+    #[test]
+    fn compatibility_ids() {
+        use crate::OwnedValidatorError;
+
+        let fixture = Fixture::correct(
+            "datatypes c = 'http://relaxng.org/ns/compatibility/datatypes/1.0'
+             start = element doc { item* }
+             item = element item { attribute id { c:ID }, attribute refs { c:IDREFS }? }",
+        );
+        fixture.valid("<doc><item id='a' refs='b'/><item id='b' refs='a b'/></doc>");
+        fixture.invalid("<doc><item id='a'/><item id=' a'/></doc>");
+
+        let doc = "<doc><item id='a' refs='a x'/></doc>";
+        let outcome = super::validate(fixture.schema.clone(), doc);
+        assert_matches!(&outcome.errors[..], [OwnedValidatorError::DanglingIdRef { id, location }] => {
+            assert_eq!(id, "x");
+            assert_eq!(location.column, 25);
+        });
+
+        // an attribute named like an IDREF on another element is not a reference
+        let fixture = Fixture::correct(
+            "datatypes c = 'http://relaxng.org/ns/compatibility/datatypes/1.0'
+             start = element doc { element item { attribute id { c:ID } }*, link* }
+             link = element link { attribute ref { c:IDREF } } | element note { attribute ref { text } }",
+        );
+        fixture.valid("<doc><item id='a'/><link ref='a'/><note ref='elsewhere'/></doc>");
+        fixture.invalid("<doc><item id='a'/><link ref='elsewhere'/></doc>");
+    }
+
+    #[test]
+    fn interned_names() {
+        use crate::names::{Event, SchemaNames};
+
+        let fixture = Fixture::correct(
+            "namespace a = 'urn:a'
+             start = element a:doc { attribute xml:lang { text }?, (element a:p { text } | element note { empty })* }",
+        );
+        let names = SchemaNames::new(&fixture.schema);
+        let doc = names.get("urn:a", "doc").unwrap();
+        let p = names.get("urn:a", "p").unwrap();
+        let note = names.get("", "note").unwrap();
+        let lang = names
+            .get("http://www.w3.org/XML/1998/namespace", "lang")
+            .unwrap();
+        // names are checked against the schema when they are interned
+        assert_eq!(names.get("", "p"), None);
+        assert_eq!(names.name(p), ("ns1", "p"));
+
+        let errors = |events: &[Event]| {
+            let mut v =
+                Validator::from_tokens(fixture.schema.clone(), names.tokens(events.to_vec()));
+            let mut errors = vec![];
+            while let Some(result) = v.validate_next() {
+                if let Err(err) = result {
+                    errors.push(err.code());
+                }
+            }
+            errors
+        };
+        assert_eq!(
+            errors(&[
+                Event::Start(doc),
+                Event::Attribute(lang, "en"),
+                Event::Start(p),
+                Event::Text("one &amp; two"),
+                Event::End,
+                Event::Start(note),
+                Event::End,
+                Event::End,
+            ]),
+            [""; 0]
+        );
+        assert_eq!(
+            errors(&[Event::Start(doc), Event::Start(doc), Event::End, Event::End]),
+            ["not-allowed"]
+        );
+    }
+
+    #[test]
+    fn attribute_normalization() {
+        let fixture = Fixture::correct(
+            "start = element e { attribute a { string \"x y\" }, attribute b { token \"p q\" }? }",
+        );
+        fixture.valid("<e a='x\ny'/>");
+        fixture.valid("<e a='x\r\ny'/>");
+        fixture.valid("<e a='x\ty' b='p\nq'/>");
+        // whitespace given by a character reference is kept
+        fixture.invalid("<e a='x&#10;y'/>");
+
+        let doc = "<e a='x\ny'/>";
+        let mut v = Validator::new(fixture.schema.clone(), xmlparser::Tokenizer::from(doc))
+            .with_attribute_normalization(false);
+        let mut errors = 0;
+        while let Some(result) = v.validate_next() {
+            errors += result.is_err() as usize;
+        }
+        assert_eq!(errors, 1);
+    }
+
+    #[test]
+    fn fragment() {
+        let fixture = Fixture::correct("start = element a { empty } | element b { text }");
+        let errors = |fragment: &str| {
+            let mut v = Validator::new_fragment(fixture.schema.clone(), fragment);
+            let mut errors = vec![];
+            while let Some(result) = v.validate_next() {
+                if let Err(err) = result {
+                    errors.push((err.code(), v.error_path().to_string()));
+                }
+            }
+            errors
+        };
+        assert_eq!(errors("<a/><a/>"), []);
+        assert_eq!(errors("\n  <a/>\n  <b>x</b>\n  <a></a>\n"), []);
+        assert_eq!(errors(""), []);
+        assert_eq!(errors("<a/>x<a/>"), [("not-allowed", String::new())]);
+        assert_eq!(errors("<a/><a/>x"), [("not-allowed", String::new())]);
+        assert_eq!(
+            errors("<a/><c/><a>x</a>"),
+            [
+                ("not-allowed", "/c".to_string()),
+                ("not-allowed", "/a[2]".to_string())
+            ]
+        );
+
+        // a whole document is still required to have a single root
+        fixture.invalid("<a/><a/>");
+    }
+
+    #[test]
+    fn stats() {
+        let fixture = Fixture::correct("start = element a { element b { text }* }");
+        let doc = "<a><b>1</b><b>2</b></a>";
+        let mut v = Validator::new(fixture.schema.clone(), xmlparser::Tokenizer::from(doc));
+        let before = v.stats();
+        assert_eq!(before.tokens, 0);
+        assert_eq!(before.patterns, v.pattern_count());
+        while let Some(result) = v.validate_next() {
+            assert_matches!(result, Ok(()));
+        }
+        let after = v.stats();
+        // <a, >, <b, >, 1, </b>, <b, >, 2, </b>, </a>
+        assert_eq!(after.tokens, 11);
+        assert!(after.patterns > before.patterns);
+        // the second <b> has the same derivatives as the first
+        assert!(after.patterns_reused > before.patterns_reused);
+    }
+
+    #[test]
+    fn can_close() {
+        let fixture =
+            Fixture::correct("start = element a { element b { empty }, element c { xsd:token }? }");
+        let doc = "<a><b/><c>x</c></a>";
+        let mut v = Validator::new(fixture.schema.clone(), xmlparser::Tokenizer::from(doc));
+        let mut seen = vec![];
+        // the state after each token
+        while let Some(result) = v.validate_next() {
+            assert_matches!(result, Ok(()));
+            seen.push(v.can_close());
+        }
+        // <a, >, <b, />, <c, >, x, </c>, </a>
+        assert_eq!(
+            seen,
+            [false, false, false, true, true, true, true, true, false]
+        );
+
+        // text not yet followed by a tag is taken into account
+        let fixture = Fixture::correct("start = element a { xsd:integer }");
+        let mut v = Validator::new(fixture.schema.clone(), xmlparser::Tokenizer::from("<a>5"));
+        v.validate_next();
+        v.validate_next();
+        assert!(!v.can_close());
+        assert_matches!(v.validate_next(), Some(Ok(())));
+        assert!(v.can_close());
+    }
+
+    #[test]
+    fn builder() {
+        let fixture =
+            Fixture::correct("start = element a { attribute id { xsd:ID }, xsd:integer }");
+        let doc = "<a id='x'>one</a>";
+        fixture.invalid(doc);
+
+        let mut scope = IdScope::new();
+        let mut v = Validator::builder(fixture.schema.clone())
+            .datatype_checks(false)
+            .id_scope(&mut scope)
+            .build(xmlparser::Tokenizer::from(doc));
+        while let Some(result) = v.validate_next() {
+            assert_matches!(result, Ok(()));
+        }
+        drop(v);
+        assert!(scope.contains("x"));
+
+        // options apply to fragments too
+        let mut scope = IdScope::new();
+        let mut v = Validator::builder(fixture.schema.clone())
+            .datatype_checks(false)
+            .id_scope(&mut scope)
+            .build_fragment("<a id='y'>one</a>\n<a id='z'>two</a>");
+        while let Some(result) = v.validate_next() {
+            assert_matches!(result, Ok(()));
+        }
+        drop(v);
+        assert!(scope.contains("y") && scope.contains("z"));
+    }
+
+    #[test]
+    fn duplicate_namespace_declaration() {
+        let fixture = Fixture::correct("start = element * { empty }");
+        fixture.valid("<e xmlns:p=\"a\" xmlns:q=\"a\"/>");
+        assert_matches!(
+            check_simple("start = element * { empty }", "<e xmlns:p=\"a\" xmlns:p=\"b\"/>"),
+            Err(e) if e.contains("DuplicateNamespaceDeclaration")
+        );
+        assert_matches!(
+            check_simple("start = element * { empty }", "<e xmlns=\"a\" xmlns=\"a\"/>"),
+            Err(e) if e.contains("DuplicateNamespaceDeclaration")
+        );
+    }
+
+    #[test]
+    fn duplicate_attribute() {
+        use crate::OwnedValidatorError;
+
+        let fixture = Fixture::correct("start = element e { attribute * { text }* }");
+        fixture.valid("<e a='1' p:a='2' xmlns:p='urn:p'/>");
+        assert_matches!(
+            fixture.first_error("<e a='1' b='2' a='3'/>"),
+            Some(OwnedValidatorError::DuplicateAttribute { name, location })
+                if name == "a" && location.column == 16
+        );
+        // different prefixes for the same namespace give the same name
+        assert_matches!(
+            fixture.first_error("<e xmlns:p='urn:p' xmlns:q='urn:p' p:a='1' q:a='2'/>"),
+            Some(OwnedValidatorError::DuplicateAttribute { name, .. }) if name == "q:a"
+        );
+    }
+
+    #[test]
+    fn empty_prefix_declaration() {
+        let fixture = Fixture::correct("start = element * { element * { empty } }");
+        // un-declaring the default namespace is allowed
+        fixture.valid("<e xmlns=\"a\"><f xmlns=\"\"/></e>");
+        assert_matches!(
+            check_simple("start = element * { empty }", "<e xmlns:p=\"\"/>"),
+            Err(e) if e.contains("EmptyNamespaceDeclaration")
+        );
+    }
+
+    #[test]
+    fn parent_ref_in_nested_grammar() {
+        // `item` in the nested grammar is a different definition from `item` in the outer one,
+        // which is only reachable with `parent`
+        let fixture = Fixture::correct(
+            "start = element list { grammar { start = item* item = element entry { parent item } } }\n\
+             item = element name { text }",
+        );
+        fixture.valid("<list/>");
+        fixture.valid("<list><entry><name>a</name></entry><entry><name>b</name></entry></list>");
+        fixture.invalid("<list><entry/></list>");
+        fixture.invalid("<list><entry><entry/></entry></list>");
+        fixture.invalid("<list><name>a</name></list>");
+    }
+
+    #[test]
+    fn undeclared_default_namespace() {
+        let fixture =
+            Fixture::correct("namespace x = 'urn:x' start = element x:a { element b { empty } }");
+        fixture.valid("<a xmlns='urn:x'><b xmlns=''/></a>");
+        fixture.valid("<a xmlns='urn:x'><b xmlns=''></b></a>");
+        fixture.invalid("<a xmlns='urn:x'><b/></a>");
+        // the undeclaration only lasts until the end of the element that makes it
+        let fixture = Fixture::correct(
+            "namespace x = 'urn:x' start = element x:a { element b { element c { empty } }, element x:d { empty } }",
+        );
+        fixture.valid("<a xmlns='urn:x'><b xmlns=''><c/></b><d/></a>");
+        fixture.invalid("<a xmlns='urn:x'><b xmlns=''><c/></b><d xmlns=''/></a>");
+    }
+
+    #[cfg(feature = "trace")]
+    #[test]
+    fn trace() {
+        let fixture = Fixture::correct("start = element a { attribute b { text }, text }");
+        let doc = "<a b=\"1\">text</a>";
+        let mut out = vec![];
+        {
+            let mut v = Validator::new(fixture.schema.clone(), xmlparser::Tokenizer::from(doc))
+                .with_trace(&mut out);
+            while let Some(r) = v.validate_next() {
+                r.unwrap();
+            }
+        }
+        let trace = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = trace.lines().collect();
+        // ElementStart, Attribute, ElementEnd::Open, Text, ElementEnd::Close
+        assert_eq!(lines.len(), 5, "{trace}");
+        assert!(lines[0].starts_with("{\"event\":\"ElementStart\",\"name\":\"a\""));
+        assert!(lines[1].contains("\"name\":\"b\""));
+        assert!(lines.iter().all(|l| l.contains("\"ok\":true")));
+    }
+
+    #[test]
+    fn error_path() {
+        fn error_paths(fixture: &Fixture, doc: &str) -> Vec<String> {
+            let mut v = Validator::new(fixture.schema.clone(), xmlparser::Tokenizer::from(doc));
+            let mut paths = vec![];
+            while let Some(result) = v.validate_next() {
+                if result.is_err() {
+                    paths.push(v.error_path().to_string());
+                }
+            }
+            paths
+        }
+
+        let fixture = Fixture::correct(
+            "start = element book { chapter+ }
+            chapter = element chapter { section* }
+            section = element section { element para { empty }* }",
+        );
+        assert_eq!(
+            error_paths(
+                &fixture,
+                "<book><chapter/><chapter/><chapter><section/><section><para>x</para></section></chapter></book>"
+            ),
+            vec!["/book/chapter[3]/section[2]/para"]
+        );
+        // text is found to be out of place when the next element starts, but belongs to its parent
+        assert_eq!(
+            error_paths(
+                &fixture,
+                "<book><chapter><section>x<para/></section></chapter></book>"
+            ),
+            vec!["/book/chapter/section"]
+        );
+        // later errors, after skipping the element in error, have paths of their own
+        assert_eq!(
+            error_paths(
+                &fixture,
+                "<book><chapter><oops><section/></oops><section><para/><para>x</para></section></chapter></book>"
+            ),
+            vec!["/book/chapter/oops", "/book/chapter/section/para[2]"]
+        );
+
+        // a namespace declared on an earlier sibling is out of scope once that element ends
+        let fixture = Fixture::correct("start = element a { element b { empty }* }");
+        let doc = "<a><b xmlns:x='urn:x'/><x:b/></a>";
+        let mut v = Validator::new(fixture.schema.clone(), xmlparser::Tokenizer::from(doc));
+        assert_matches!(
+            std::iter::from_fn(|| v.validate_next()).find_map(|r| r.err()),
+            Some(crate::ValidatorError::UndefinedNamespacePrefix { .. })
+        );
+        assert_eq!(v.error_path(), "/a/x:b");
+    }
+
+    #[test]
+    fn any_name_element() {
+        let fixture = Fixture::correct("start = element root { element * { text }* }");
+        fixture.valid("<root><foo>x</foo><bar>y</bar></root>");
+        fixture.valid("<root><foo/><p:bar xmlns:p='urn:p'>y</p:bar><baz xmlns='urn:q'/></root>");
+        fixture.valid("<root/>");
+        // the wildcard matches any name, but not any content
+        fixture.invalid("<root><foo><bar/></foo></root>");
+        fixture.invalid("<root><foo a='1'/></root>");
+
+        let fixture = Fixture::correct(
+            "namespace ext = 'urn:ext'
+            start = element root { element ext:* { text }* }",
+        );
+        fixture.valid("<root><e:foo xmlns:e='urn:ext'>x</e:foo><bar xmlns='urn:ext'/></root>");
+        fixture.invalid("<root><foo>x</foo></root>");
+        fixture.invalid("<root><o:foo xmlns:o='urn:other'/></root>");
+
+        let fixture = Fixture::correct(
+            "namespace ext = 'urn:ext'
+            start = element root { element * - (ext:* | root) { empty }* }",
+        );
+        fixture.valid("<root><foo/><o:bar xmlns:o='urn:other'/></root>");
+        fixture.invalid("<root><e:foo xmlns:e='urn:ext'/></root>");
+        fixture.invalid("<root><root/></root>");
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn schema_cache() {
+        use crate::cache::SchemaCache;
+        use relaxng_model::Syntax;
+
+        let schema = SchemaCache::compile(
+            "<element name='a' xmlns='http://relaxng.org/ns/structure/1.0'><empty/></element>",
+            Syntax::Xml,
+        )
+        .unwrap();
+        assert!(schema.validate("<a/>").ok);
+        assert!(!schema.validate("<a>x</a>").ok);
+        let mut v = schema.validator("<b/>");
+        assert!(std::iter::from_fn(|| v.validate_next()).any(|r| r.is_err()));
+
+        let err = SchemaCache::compile("start = element a { b }", Syntax::Compact)
+            .err()
+            .unwrap();
+        assert_matches!(err.error, RelaxError::UndefinedReference { .. });
+        assert!(err.to_string().contains("error[undefined-reference]"));
+        assert!(err.to_string().contains("start = element a { b }"));
+    }
+
+    #[test]
+    fn attributes_grouped_with_content() {
+        let fixture =
+            Fixture::correct("start = element e { (attribute a { text }, element c { empty }) }");
+        fixture.valid("<e a='x'><c/></e>");
+        fixture.invalid("<e><c/></e>");
+        fixture.invalid("<e a='x'/>");
+
+        // attributes are unordered, so an attribute after content in the schema is still matched
+        // when the start tag closes
+        let fixture =
+            Fixture::correct("start = element e { element c { empty }, attribute a { text } }");
+        fixture.valid("<e a='x'><c/></e>");
+        fixture.invalid("<e><c/></e>");
+
+        let fixture = Fixture::correct(
+            "start = element e { attribute a { text }, element c { empty }, attribute b { text }?, element d { empty }* }",
+        );
+        fixture.valid("<e b='y' a='x'><c/><d/></e>");
+        fixture.valid("<e a='x'><c/></e>");
+        fixture.invalid("<e b='y'><c/></e>");
+        fixture.invalid("<e a='x'><d/><c/></e>");
+
+        let fixture = Fixture::correct(
+            "start = element e { (attribute a { text }, element c { empty }) | (attribute b { text }, element d { empty }) }",
+        );
+        fixture.valid("<e a='x'><c/></e>");
+        fixture.valid("<e b='x'><d/></e>");
+        fixture.invalid("<e a='x'><d/></e>");
+        fixture.invalid("<e a='x' b='y'><c/></e>");
+    }
+
+    #[test]
+    fn attributes_interleaved_with_content() {
+        let fixture = Fixture::correct(
+            "start = element e { attribute a { text } & element c { empty } & attribute b { text } }",
+        );
+        fixture.valid("<e a='x' b='y'><c/></e>");
+        fixture.valid("<e b='y' a='x'><c/></e>");
+        fixture.invalid("<e a='x'><c/></e>");
+        fixture.invalid("<e b='y'><c/></e>");
+        fixture.invalid("<e b='y' a='x'/>");
+        fixture.invalid("<e b='y' a='x'><c/><c/></e>");
+
+        // attributes grouped within one branch of the interleave are still unordered
+        let fixture = Fixture::correct(
+            "start = element e { (element c { empty }, attribute b { text }) & attribute a { text } }",
+        );
+        fixture.valid("<e a='x' b='y'><c/></e>");
+        fixture.valid("<e b='y' a='x'><c/></e>");
+        fixture.invalid("<e a='x'><c/></e>");
+
+        let fixture = Fixture::correct(
+            "start = element e { element c { empty }+ & attribute b { text }? & attribute a { text } }",
+        );
+        fixture.valid("<e a='x'><c/></e>");
+        fixture.valid("<e b='y' a='x'><c/><c/></e>");
+        fixture.invalid("<e b='y'><c/></e>");
+    }
+
+    #[test]
+    fn interleave_members() {
+        // the nested interleave is flattened into the outer one
+        let fixture = Fixture::correct(
+            "start = element e { element a { empty } & (element b { empty } & element c { empty }) & element d { empty }? }",
+        );
+        fixture.valid("<e><a/><b/><c/></e>");
+        fixture.valid("<e><c/><d/><b/><a/></e>");
+        fixture.valid("<e>\n  <b/>\n  <a/>\n  <c/>\n</e>");
+        fixture.invalid("<e><a/><c/></e>");
+        fixture.invalid("<e><a/><b/><c/><b/></e>");
+        fixture.invalid("<e><d/><a/><b/><c/><d/></e>");
+
+        // a member that is part-way through is left where it got to while others advance
+        let fixture = Fixture::correct(
+            "start = element e { (element a { empty }, element b { empty }) & element c { empty }* & text }",
+        );
+        fixture.valid("<e><a/>x<c/><b/><c/></e>");
+        fixture.valid("<e><c/><a/><b/>x</e>");
+        fixture.invalid("<e><b/><a/></e>");
+        fixture.invalid("<e><a/><c/></e>");
+    }
+
+    #[test]
+    fn serialized_model() {
+        use relaxng_model::model;
+
+        let fixture = Fixture::correct(
+            "namespace x = \"urn:x\"
+            start = element doc { attribute version { \"1\" | \"2\" }, section* }
+            section = element section {
+                attribute id { xsd:ID },
+                attribute x:* { xsd:token { pattern = \"[a-z]+\" } }*,
+                (section | para | element size { list { xsd:int { minInclusive = \"0\" }+ } })*
+            }
+            para = element para { mixed { element em { text }* } }
+              | element when { xsd:dateTime - \"2000-01-01T00:00:00\" }
+              | element took { xsd:duration { maxExclusive = \"P1D\" } }
+              | element ratio { xsd:decimal { minExclusive = \"0.5\" } | xsd:double }",
+        );
+        let mut data = vec![];
+        model::serialize(&fixture.schema, &mut data).unwrap();
+        let reloaded = Fixture {
+            schema: model::deserialize(&mut &data[..]).unwrap(),
+        };
+
+        let docs = [
+            "<doc version='1'/>",
+            "<doc version='3'/>",
+            "<doc version='2'><section id='a' xmlns:x='urn:x' x:k='abc'><para>hi <em>there</em></para>\
+             <section id='b'><size>1 2 3</size></section></section></doc>",
+            "<doc version='2'><section id='a' xmlns:x='urn:x' x:k='ABC'/></doc>",
+            "<doc version='2'><section id='a'/><section id='a'/></doc>",
+            "<doc version='2'><section id='a'><size>1 -2</size></section></doc>",
+            "<doc version='2'><section id='a'><when>2001-01-01T00:00:00</when></section></doc>",
+            "<doc version='2'><section id='a'><when>2000-01-01T00:00:00</when></section></doc>",
+            "<doc version='2'><section id='a'><took>PT23H</took><took>P1D</took></section></doc>",
+            "<doc version='2'><section id='a'><ratio>0.75</ratio><ratio>1E3</ratio></section></doc>",
+            "<doc version='2'><section id='a'><ratio>0.5</ratio></section></doc>",
+            "<doc version='2'><other/></doc>",
+        ];
+        for doc in docs {
+            assert_eq!(fixture.errors(doc), reloaded.errors(doc), "{doc}");
+        }
+        assert!(reloaded.errors(docs[2]).is_empty());
+        assert!(!reloaded.errors(docs[3]).is_empty());
+
+        // a define referred to from several places is still shared once reloaded
+        let mut again = vec![];
+        model::serialize(&reloaded.schema, &mut again).unwrap();
+        assert_eq!(data, again);
+
+        assert_matches!(
+            model::deserialize(&mut &b"not a schema"[..]),
+            Err(e) if e.kind() == io::ErrorKind::InvalidData
+        );
+        assert_matches!(
+            model::deserialize(&mut &data[..data.len() - 1]),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn unexpected_text() {
+        fn text_errors(fixture: &Fixture, doc: &str) -> Vec<String> {
+            fixture
+                .errors(doc)
+                .into_iter()
+                .map(|e| match e {
+                    crate::OwnedValidatorError::NotAllowed {
+                        kind: "text" | "cdata",
+                        text,
+                        ..
+                    } => text,
+                    e => panic!("unexpected error {e:?}"),
+                })
+                .collect()
+        }
+
+        let fixture = Fixture::correct("start = element a { element b { empty } }");
+        // the text is reported, rather than the element which follows it
+        assert_eq!(text_errors(&fixture, "<a>junk<b/></a>"), vec!["junk"]);
+        assert_eq!(text_errors(&fixture, "<a><b/>junk</a>"), vec!["junk"]);
+        // text split by a CDATA section is reported from where it starts
+        assert_eq!(
+            text_errors(&fixture, "<a>junk<![CDATA[more]]><b/></a>"),
+            vec!["junk"]
+        );
+        assert_eq!(
+            text_errors(&fixture, "<a><![CDATA[more]]>junk<b/></a>"),
+            vec!["<![CDATA[more]]>"]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn xinclude() {
+        use crate::OwnedValidatorError;
+        use crate::xinclude::XIncludeFiles;
+
+        fn errors(fixture: &Fixture, files: &XIncludeFiles, doc: &str) -> Vec<String> {
+            let v = Validator::new(fixture.schema.clone(), xmlparser::Tokenizer::from(doc))
+                .with_xinclude(files);
+            errors_of(v, doc)
+                .into_iter()
+                .map(|e| match e {
+                    OwnedValidatorError::Included { error, .. } => *error,
+                    e => e,
+                })
+                .map(|e| match e {
+                    OwnedValidatorError::XInclude { message, .. } => message,
+                    e => e.code().to_string(),
+                })
+                .collect()
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("chapters")).unwrap();
+        std::fs::write(
+            dir.path().join("chapters/one.xml"),
+            "<?xml version='1.0'?>\n<chapter><title>One</title></chapter>",
+        )
+        .unwrap();
+        // resolved against the directory of the including file
+        std::fs::write(
+            dir.path().join("chapters/two.xml"),
+            "<chapter xmlns:xi='http://www.w3.org/2001/XInclude'><title>Two</title><xi:include href='para.xml'/></chapter>",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("chapters/para.xml"), "<para/>").unwrap();
+        std::fs::write(
+            dir.path().join("loop.xml"),
+            "<xi:include href='loop.xml' xmlns:xi='http://www.w3.org/2001/XInclude'/>",
+        )
+        .unwrap();
+        let files = XIncludeFiles::new(dir.path());
+
+        let fixture = Fixture::correct(
+            "start = element book { element chapter { element title { text }, element para { empty }* }+ }",
+        );
+        assert!(
+            errors(
+                &fixture,
+                &files,
+                "<book xmlns:xi='http://www.w3.org/2001/XInclude'>
+                <xi:include href='chapters/one.xml'/>
+                <xi:include href='chapters/two.xml'><xi:fallback/></xi:include>
+            </book>"
+            )
+            .is_empty()
+        );
+        // the included content is validated as part of the document
+        assert_eq!(
+            errors(
+                &fixture,
+                &files,
+                "<book xmlns:xi='http://www.w3.org/2001/XInclude'><chapter><xi:include href='chapters/para.xml'/></chapter></book>"
+            ),
+            vec!["not-allowed", "missing-required-content"]
+        );
+        // an include element in some other namespace is left alone
+        assert_eq!(
+            errors(
+                &fixture,
+                &files,
+                "<book><include href='chapters/one.xml'/></book>"
+            ),
+            vec!["not-allowed", "missing-required-content"]
+        );
+        // the included root element does not take on the default namespace where it is included
+        assert_eq!(
+            errors(
+                &fixture,
+                &files,
+                "<book xmlns:xi='http://www.w3.org/2001/XInclude' xmlns='urn:x'><xi:include href='chapters/one.xml'/></book>"
+            ),
+            vec!["not-allowed"]
+        );
+
+        let fixture = Fixture::correct("start = element book { element chapter { text }* }");
+        let doc = |include: &str| {
+            format!("<book xmlns:xi='http://www.w3.org/2001/XInclude'>{include}</book>")
+        };
+        assert_eq!(
+            errors(&fixture, &files, &doc("<xi:include href='missing.xml'/>")),
+            vec![format!(
+                "could not read {}: {}",
+                dir.path().join("missing.xml").display(),
+                std::fs::read(dir.path().join("missing.xml")).unwrap_err()
+            )]
+        );
+        assert_eq!(
+            errors(&fixture, &files, &doc("<xi:include href='loop.xml'/>")),
+            vec![format!(
+                "{} includes itself",
+                dir.path().join("loop.xml").display()
+            )]
+        );
+        assert_eq!(
+            errors(
+                &fixture,
+                &files,
+                &doc("<xi:include href='one.txt' parse='text'/>")
+            ),
+            vec!["parse=\"text\" is not supported, only \"xml\""]
+        );
+        assert_eq!(
+            errors(
+                &fixture,
+                &files,
+                &doc("<xi:include href='chapters/one.xml' xpointer='element(/1)'/>")
+            ),
+            vec!["xpointer is not supported"]
+        );
+        assert_eq!(
+            errors(
+                &fixture,
+                &files,
+                &doc("<xi:include href='http://example.com/one.xml'/>")
+            ),
+            vec!["href \"http://example.com/one.xml\" is not supported, only a relative file path"]
+        );
+        assert_eq!(
+            errors(&fixture, &files, &doc("<xi:include/>")),
+            vec!["href must give the file to include"]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn xinclude_error_locations() {
+        use crate::OwnedValidatorError;
+        use crate::xinclude::XIncludeFiles;
+
+        let dir = tempfile::tempdir().unwrap();
+        // longer than the including document, and with text that is not ASCII, so that offsets
+        // into it would be out of range or inside a character if taken as offsets into that
+        let mut chapter = String::from("<chapter>\n");
+        for i in 0..20 {
+            chapter.push_str(&format!("  <para>Paragraphe numéro {i}</para>\n"));
+        }
+        chapter.push_str("  <figure/>\n</chapter>");
+        std::fs::write(dir.path().join("chapter.xml"), &chapter).unwrap();
+        let files = XIncludeFiles::new(dir.path());
+
+        let fixture = Fixture::correct(
+            "start = element book { element chapter { element para { text }* }+ }",
+        );
+        let doc = "<book xmlns:xi='http://www.w3.org/2001/XInclude'>\
+                   <xi:include href='chapter.xml'/></book>";
+        let mut v = Validator::new(fixture.schema.clone(), xmlparser::Tokenizer::from(doc))
+            .with_xinclude(&files);
+        let err = std::iter::from_fn(|| v.validate_next())
+            .find_map(|r| r.err())
+            .unwrap();
+        let path = dir.path().join("chapter.xml");
+        assert_matches!(&err, super::ValidatorError::Included { path: p, .. } if *p == path);
+        assert_eq!(err.code(), "not-allowed");
+
+        let (map, diagnostics) = v.diagnostic("book.xml".to_string(), doc.to_string(), &err);
+        let label = &diagnostics[0].spans[0];
+        let file = map.find_file(label.span.low());
+        assert_eq!(file.name(), path.display().to_string());
+        assert_eq!(file.source_slice(label.span), "figure");
+
+        let owned = err.into_owned(doc);
+        assert_matches!(&owned, OwnedValidatorError::Included { error, .. } => {
+            assert_matches!(**error, OwnedValidatorError::NotAllowed { .. });
+        });
+        assert_eq!((owned.location().line, owned.location().column), (22, 4));
+        assert_eq!(
+            owned.to_string(),
+            format!("{}:22:4: element-start not expected here", path.display())
+        );
+    }
+
+    #[test]
+    fn progress() {
+        let fixture = Fixture::correct(
+            "start = element list { element item { attribute n { xsd:int }, text }* }",
+        );
+        let mut doc = "<list>".to_string();
+        for n in 0..20_000 {
+            doc.push_str(&format!("<item n='{n}'>item {n}</item>"));
+        }
+        doc.push_str("</list>");
+
+        let mut reports = vec![];
+        let mut v = Validator::new(fixture.schema.clone(), xmlparser::Tokenizer::from(&doc[..]))
+            .with_progress(|p| reports.push(p));
+        while let Some(result) = v.validate_next() {
+            result.unwrap();
+        }
+        drop(v);
+
+        // 5 tokens per item, reported every 10,000 tokens, plus the final report
+        assert_eq!(reports.len(), 11);
+        for pair in reports.windows(2) {
+            assert!(pair[0].bytes < pair[1].bytes);
+            assert!(pair[0].elements_opened <= pair[1].elements_opened);
+            assert!(pair[0].elements_closed <= pair[1].elements_closed);
+        }
+        assert_eq!(reports[0].depth, 2);
+        assert_eq!(
+            reports.last(),
+            Some(&super::ValidationProgress {
+                bytes: doc.len(),
+                elements_opened: 20_001,
+                elements_closed: 20_001,
+                depth: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn element_budget() {
+        let fixture = Fixture::correct(
+            "start = element list { element item { attribute n { xsd:int }, text }+ }",
+        );
+        let mut doc = "<list>".to_string();
+        for n in 0..20_000 {
+            doc.push_str(&format!("<item n='{n}'>item {n}</item>"));
+        }
+        doc.push_str("</list>");
+
+        let mut reports = vec![];
+        let mut v = Validator::builder(fixture.schema.clone())
+            .element_budget(10)
+            .progress(|p| reports.push(p))
+            .build(xmlparser::Tokenizer::from(&doc[..]));
+        while let Some(result) = v.validate_next() {
+            result.unwrap();
+        }
+        assert!(v.stopped_early());
+        assert!(v.validate_next().is_none());
+        drop(v);
+        // the list and nine items
+        assert_eq!(reports.last().unwrap().elements_opened, 10);
+
+        // errors within the budget are still found
+        doc.insert_str(doc.find("</item>").unwrap(), "<bad/>");
+        let mut v = Validator::new(fixture.schema.clone(), xmlparser::Tokenizer::from(&doc[..]))
+            .with_element_budget(10);
+        assert!(std::iter::from_fn(|| v.validate_next()).any(|r| r.is_err()));
+
+        // a document that ends within the budget is validated in full
+        let mut v = Validator::new(
+            fixture.schema.clone(),
+            xmlparser::Tokenizer::from("<list><item n='1'>a</item></list>"),
+        )
+        .with_element_budget(10);
+        while let Some(result) = v.validate_next() {
+            result.unwrap();
+        }
+        assert!(!v.stopped_early());
+    }
+
+    #[test]
+    fn qname_value_namespaces() {
+        // the prefix in the schema's value resolves against the schema's declarations, and the
+        // prefix in the document against the document's, so only the URIs need agree
+        let fixture = Fixture::correct(
+            "namespace foo = \"urn:foo\"
+            start = element a { xsd:QName \"foo:bar\" }",
+        );
+        fixture.valid("<a xmlns:foo='urn:foo'>foo:bar</a>");
+        fixture.valid("<a xmlns:f='urn:foo'>f:bar</a>");
+        fixture.invalid("<a xmlns:foo='urn:other'>foo:bar</a>");
+        fixture.invalid("<a xmlns:foo='urn:foo'>foo:baz</a>");
+        fixture.invalid("<a>foo:bar</a>");
+        fixture.invalid("<a xmlns='urn:foo'>bar</a>");
+
+        // a fixed set of names is a choice of values, each compared in the same way
+        let fixture = Fixture::correct(
+            "namespace foo = \"urn:foo\"
+            start = element a { xsd:QName \"foo:bar\" | xsd:QName \"foo:baz\" }",
+        );
+        fixture.valid("<a xmlns:f='urn:foo'>f:baz</a>");
+        fixture.invalid("<a xmlns:f='urn:foo'>f:qux</a>");
+        fixture.invalid("<a xmlns:f='urn:other'>f:bar</a>");
+
+        // an unprefixed name is in the default namespace, on both sides
+        let fixture = Fixture::correct(
+            "default namespace = \"urn:d\"
+            start = element a { xsd:QName \"bar\" }",
+        );
+        fixture.valid("<a xmlns='urn:d'>bar</a>");
+        fixture.valid("<d:a xmlns:d='urn:d'>d:bar</d:a>");
+        fixture.invalid("<d:a xmlns:d='urn:d'>bar</d:a>");
+
+        let fixture = Fixture::correct("start = element a { xsd:QName \"bar\" }");
+        fixture.valid("<a>bar</a>");
+        fixture.invalid("<a xmlns:foo='urn:foo'>foo:bar</a>");
+
+        // the predeclared xml prefix
+        let fixture = Fixture::correct("start = element a { xsd:QName \"xml:lang\" }");
+        fixture.valid("<a>xml:lang</a>");
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn xml_error_diagnostic() {
+        let fixture = Fixture::correct("start = element a { element b { empty }* }");
+        let doc = "<a>\n  <b/>\n  <b =></b>\n</a>\n";
+        let mut v = Validator::new(fixture.schema.clone(), xmlparser::Tokenizer::from(doc));
+        let err = std::iter::from_fn(|| v.validate_next())
+            .find_map(|r| r.err())
+            .unwrap();
+        let crate::ValidatorError::Xml(ref xml_err) = err else {
+            panic!("{err:?}");
+        };
+        assert_eq!(xml_err.pos().row, 3);
+        let (map, d) = v.diagnostic("doc.xml".to_string(), doc.to_string(), &err);
+        let span = d[0].spans[0].span;
+        assert_eq!(span.low(), span.high());
+        // codemap counts lines and columns from 0, and xmlparser from 1
+        let loc = map.look_up_pos(span.low());
+        assert_eq!(
+            (loc.position.line + 1, loc.position.column + 1),
+            (3, xml_err.pos().col as usize)
+        );
+        assert!(xml_err.pos().col > 3);
+    }
+
+    #[test]
+    fn trailing_content() {
+        use crate::OwnedValidatorError;
+
+        let fixture = Fixture::correct("start = element a { empty }");
+        fixture.valid("<a/><!-- ok -->");
+        fixture.valid("<a/> \n<?pi?>\n");
+        assert_matches!(
+            &fixture.errors("<a/>junk")[..],
+            [OwnedValidatorError::TrailingContent { location }] => assert_eq!(location.span, 4..4)
+        );
+        assert_matches!(
+            &fixture.errors("<a></a>\n<!-- two roots -->\n<b/>")[..],
+            [OwnedValidatorError::TrailingContent { location }] => {
+                assert_eq!((location.line, location.column), (3, 1));
+            }
+        );
+        // the document element ending early is reported as such, and not as trailing content
+        assert_matches!(
+            &fixture.errors("<a><b/></a>junk")[..],
+            [
+                OwnedValidatorError::NotAllowed { .. },
+                OwnedValidatorError::TrailingContent { .. }
+            ]
+        );
+        // malformed XML before the document element has ended is not trailing content
+        assert_matches!(
+            &fixture.errors("<a><</a>")[..],
+            [OwnedValidatorError::Xml { .. }]
+        );
+    }
+
+    #[test]
+    fn misplaced_declaration() {
+        use crate::OwnedValidatorError;
+
+        let fixture = Fixture::correct("start = element a { element b { empty }* }");
+        fixture.valid("<?xml version='1.0'?>\n<a><b/></a>");
+        assert_matches!(
+            &fixture.errors("<a><?xml version='1.0'?><b/></a>")[..],
+            [OwnedValidatorError::MisplacedDeclaration { location }] => {
+                assert_eq!(location.span, 3..24)
+            }
+        );
+        assert_matches!(
+            &fixture.errors("<?xml version='1.0'?>\n<?xml version='1.0'?><a/>")[..],
+            [OwnedValidatorError::MisplacedDeclaration { location }] => {
+                assert_eq!((location.line, location.column), (2, 1))
+            }
+        );
+        assert_matches!(
+            &fixture.errors("<!-- first -->\n<?xml version='1.0'?><a/>")[..],
+            [OwnedValidatorError::MisplacedDeclaration { .. }]
+        );
+        // after the document element, it is not reported as trailing content
+        assert_matches!(
+            &fixture.errors("<a/>\n<?xml version='1.0'?>")[..],
+            [OwnedValidatorError::MisplacedDeclaration { .. }]
+        );
+        // a processing instruction whose target merely begins with xml is not a declaration
+        assert_matches!(
+            &fixture.errors("<a><?xml-stylesheet href='s.css'?></a>")[..],
+            []
+        );
+
+        // tokens from elsewhere are checked by the validator itself
+        let doc = "<?xml version='1.0'?><a/>";
+        let tokens: Vec<_> = xmlparser::Tokenizer::from(doc).collect();
+        let mut v = Validator::from_tokens(
+            fixture.schema.clone(),
+            tokens.iter().chain(&tokens).copied(),
+        );
+        let errors: Vec<_> = std::iter::from_fn(|| v.validate_next())
+            .filter_map(|r| r.err())
+            .map(|e| e.code())
+            .collect();
+        assert_eq!(errors.first(), Some(&"misplaced-declaration"));
+    }
 }
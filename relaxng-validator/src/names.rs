@@ -0,0 +1,273 @@
+//! Names interned against a schema, for programs that generate the documents they validate
+//!
+//! A generator looks up each name it will emit once, with `SchemaNames::get()`, so that a name
+//! the schema never mentions is caught when the generator is set up rather than when its output
+//! is validated.  It then describes its output as `Event`s holding the `InternedName` handles,
+//! and `SchemaNames::tokens()` turns those into the tokens that `Validator::from_tokens()`
+//! validates, with the namespace of every name already bound to a prefix declared on the root
+//! element.  Validating the text of a document remains the usual way in.
+//!
+//! ```
+//! # #[cfg(feature = "std")] {
+//! use relaxng_model::Syntax;
+//! use relaxng_validator::Validator;
+//! use relaxng_validator::cache::SchemaCache;
+//! use relaxng_validator::names::{Event, SchemaNames};
+//!
+//! let schema = SchemaCache::compile(
+//!     "default namespace = 'urn:x'
+//!      start = element list { element item { attribute n { xsd:int }, text }* }",
+//!     Syntax::Compact,
+//! )
+//! .unwrap();
+//! let names = SchemaNames::new(&schema.model());
+//! let list = names.get("urn:x", "list").unwrap();
+//! let item = names.get("urn:x", "item").unwrap();
+//! let n = names.get("", "n").unwrap();
+//! assert!(names.get("urn:x", "entry").is_none());
+//!
+//! let events = [
+//!     Event::Start(list),
+//!     Event::Start(item),
+//!     Event::Attribute(n, "1"),
+//!     Event::Text("first"),
+//!     Event::End,
+//!     Event::End,
+//! ];
+//! let mut v = Validator::from_tokens(schema.model(), names.tokens(events));
+//! while let Some(result) = v.validate_next() {
+//!     assert!(result.is_ok());
+//! }
+//! # }
+//! ```
+
+use alloc::collections::VecDeque;
+use alloc::rc::Rc;
+use core::cell::RefCell;
+use relaxng_model::model::{DefineRule, NameClass, Pattern};
+use relaxng_model::visit::{Visitor, Walker, walk_pattern};
+use std::collections::HashMap;
+use xmlparser::{ElementEnd, StrSpan, Token};
+
+const XML_NAMESPACE: &str = "http://www.w3.org/XML/1998/namespace";
+
+/// A handle for one of the names in a `SchemaNames`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct InternedName(u32);
+
+/// The element and attribute names that a schema's name classes mention, each with a prefix
+/// for its namespace
+#[derive(Debug, Default)]
+pub struct SchemaNames {
+    /// the prefix and local name of each name, indexed by `InternedName`
+    names: Vec<(String, String)>,
+    /// the handle of each name, by namespace URI and local name
+    index: HashMap<(String, String), InternedName>,
+    /// each namespace URI other than the empty one, with the prefix it is given
+    namespaces: Vec<(String, String)>,
+}
+
+impl SchemaNames {
+    /// The names mentioned by the given schema, as an element or attribute name on its own or
+    /// as an alternative in a choice of names.  Names which the schema only allows by way of
+    /// `anyName` or `nsName` are not included.
+    pub fn new(model: &Rc<RefCell<Option<DefineRule>>>) -> SchemaNames {
+        let mut names = SchemaNames::default();
+        if let Some(rule) = model.borrow().as_ref() {
+            walk_pattern(&mut names, rule.pattern());
+        }
+        names
+    }
+
+    /// The handle for the name with the given namespace URI (`""` for no namespace) and local
+    /// name, or `None` if the schema does not mention it
+    pub fn get(&self, namespace_uri: &str, local: &str) -> Option<InternedName> {
+        self.index
+            .get(&(namespace_uri.to_string(), local.to_string()))
+            .copied()
+    }
+
+    /// The prefix and local name that the given name is written with
+    pub fn name(&self, name: InternedName) -> (&str, &str) {
+        let (prefix, local) = &self.names[name.0 as usize];
+        (prefix, local)
+    }
+
+    /// The tokens of the document that the given events describe, for passing to
+    /// `Validator::from_tokens()`.  The root element declares a prefix for each namespace in
+    /// the schema, so no other namespace declarations are needed.
+    ///
+    /// Errors found in the tokens have spans which are offsets into the names and values of
+    /// the events rather than into any document.
+    ///
+    /// # Panics
+    ///
+    /// If an `Event::Attribute` does not follow an `Event::Start` or another attribute, or an
+    /// `Event::End` has no element to end.
+    pub fn tokens<'a>(
+        &'a self,
+        events: impl IntoIterator<Item = Event<'a>> + 'a,
+    ) -> impl Iterator<Item = Result<Token<'a>, xmlparser::Error>> + 'a {
+        Tokens {
+            names: self,
+            events: Box::new(events.into_iter()),
+            open: vec![],
+            in_start_tag: false,
+            started: false,
+            pending: VecDeque::new(),
+        }
+    }
+
+    fn intern(&mut self, name_class: &NameClass) {
+        match name_class {
+            NameClass::Named {
+                namespace_uri,
+                name,
+            } => {
+                let key = (namespace_uri.clone(), name.clone());
+                if self.index.contains_key(&key) {
+                    return;
+                }
+                let prefix = self.prefix(namespace_uri);
+                self.index
+                    .insert(key, InternedName(self.names.len() as u32));
+                self.names.push((prefix, name.clone()));
+            }
+            NameClass::Alt { a, b } => {
+                self.intern(a);
+                self.intern(b);
+            }
+            NameClass::NsName { .. } | NameClass::AnyName { .. } => {}
+        }
+    }
+
+    /// The prefix for the given namespace, chosen the first time it is asked for
+    fn prefix(&mut self, namespace_uri: &str) -> String {
+        match namespace_uri {
+            "" => String::new(),
+            XML_NAMESPACE => "xml".to_string(),
+            _ => match self.namespaces.iter().find(|(uri, _)| uri == namespace_uri) {
+                Some((_, prefix)) => prefix.clone(),
+                None => {
+                    let prefix = format!("ns{}", self.namespaces.len() + 1);
+                    self.namespaces
+                        .push((namespace_uri.to_string(), prefix.clone()));
+                    prefix
+                }
+            },
+        }
+    }
+}
+
+impl Visitor for SchemaNames {
+    fn visit_element(&mut self, walker: &mut Walker, name_class: &NameClass, content: &Pattern) {
+        self.intern(name_class);
+        walker.walk(self, content)
+    }
+
+    fn visit_attribute(&mut self, walker: &mut Walker, name_class: &NameClass, content: &Pattern) {
+        self.intern(name_class);
+        walker.walk(self, content)
+    }
+}
+
+/// A part of a generated document, in document order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event<'a> {
+    /// the start of an element, followed by any attributes it has
+    Start(InternedName),
+    /// an attribute, with its value written as it would be in a document, with `&`, `<` and
+    /// quotes escaped
+    Attribute(InternedName, &'a str),
+    /// text content, written as it would be in a document, with `&` and `<` escaped
+    Text(&'a str),
+    /// the end of the innermost element that is still open
+    End,
+}
+
+struct Tokens<'a> {
+    names: &'a SchemaNames,
+    events: Box<dyn Iterator<Item = Event<'a>> + 'a>,
+    /// the names of the open elements
+    open: Vec<InternedName>,
+    /// whether the start tag of the innermost open element has not been ended yet
+    in_start_tag: bool,
+    /// whether the root element has started
+    started: bool,
+    pending: VecDeque<Token<'a>>,
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = Result<Token<'a>, xmlparser::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending.is_empty() {
+            let event = self.events.next();
+            if self.in_start_tag && !matches!(event, Some(Event::Attribute(..))) {
+                self.in_start_tag = false;
+                if let Some(Event::End) = event {
+                    self.open.pop();
+                    self.pending.push_back(Token::ElementEnd {
+                        end: ElementEnd::Empty,
+                        span: StrSpan::from("/>"),
+                    });
+                    continue;
+                }
+                self.pending.push_back(Token::ElementEnd {
+                    end: ElementEnd::Open,
+                    span: StrSpan::from(">"),
+                });
+            }
+            let Some(event) = event else {
+                break;
+            };
+            match event {
+                Event::Start(name) => {
+                    let (prefix, local) = self.names.name(name);
+                    self.pending.push_back(Token::ElementStart {
+                        prefix: StrSpan::from(prefix),
+                        local: StrSpan::from(local),
+                        span: StrSpan::from(local),
+                    });
+                    if !core::mem::replace(&mut self.started, true) {
+                        for (uri, prefix) in &self.names.namespaces {
+                            self.pending.push_back(Token::Attribute {
+                                prefix: StrSpan::from("xmlns"),
+                                local: StrSpan::from(prefix.as_str()),
+                                value: StrSpan::from(uri.as_str()),
+                                span: StrSpan::from(uri.as_str()),
+                            });
+                        }
+                    }
+                    self.open.push(name);
+                    self.in_start_tag = true;
+                }
+                Event::Attribute(name, value) => {
+                    assert!(
+                        self.in_start_tag,
+                        "an attribute must follow the start of an element or another attribute"
+                    );
+                    let (prefix, local) = self.names.name(name);
+                    self.pending.push_back(Token::Attribute {
+                        prefix: StrSpan::from(prefix),
+                        local: StrSpan::from(local),
+                        value: StrSpan::from(value),
+                        span: StrSpan::from(value),
+                    });
+                }
+                Event::Text(text) => self.pending.push_back(Token::Text {
+                    text: StrSpan::from(text),
+                }),
+                Event::End => {
+                    let name = self.open.pop().expect("no element to end");
+                    let (prefix, local) = self.names.name(name);
+                    self.pending.push_back(Token::ElementEnd {
+                        end: ElementEnd::Close(StrSpan::from(prefix), StrSpan::from(local)),
+                        span: StrSpan::from(local),
+                    });
+                }
+            }
+        }
+        self.pending.pop_front().map(Ok)
+    }
+}
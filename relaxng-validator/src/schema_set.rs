@@ -0,0 +1,140 @@
+//! Validation of documents of several kinds, each against the schema registered for the
+//! namespace of its root element
+
+use crate::{ValidationOutcome, Validator};
+use alloc::rc::Rc;
+use core::cell::RefCell;
+use core::fmt;
+use relaxng_model::model::DefineRule;
+use std::collections::HashMap;
+use xmlparser::{Token, Tokenizer};
+
+/// Compiled schemas, each registered for the namespace URI of the root elements of the
+/// documents it validates
+///
+/// ```
+/// # #[cfg(feature = "std")] {
+/// use relaxng_model::Syntax;
+/// use relaxng_validator::cache::SchemaCache;
+/// use relaxng_validator::schema_set::SchemaSet;
+///
+/// let config = SchemaCache::compile(
+///     "default namespace = 'urn:config' start = element config { empty }",
+///     Syntax::Compact,
+/// )
+/// .unwrap();
+/// let mut set = SchemaSet::new();
+/// set.insert("urn:config", config.model());
+/// assert!(set.validate("<config xmlns='urn:config'/>").unwrap().ok);
+/// assert!(set.validate("<config/>").is_err());
+/// # }
+/// ```
+#[derive(Default)]
+pub struct SchemaSet {
+    schemas: HashMap<String, Rc<RefCell<Option<DefineRule>>>>,
+}
+
+impl SchemaSet {
+    pub fn new() -> SchemaSet {
+        SchemaSet::default()
+    }
+
+    /// Validate documents whose root element is in the given namespace (`""` for no namespace)
+    /// against the given schema, returning the schema previously registered for it, if any
+    pub fn insert(
+        &mut self,
+        namespace_uri: impl Into<String>,
+        model: Rc<RefCell<Option<DefineRule>>>,
+    ) -> Option<Rc<RefCell<Option<DefineRule>>>> {
+        self.schemas.insert(namespace_uri.into(), model)
+    }
+
+    /// The schema registered for the given namespace
+    pub fn get(&self, namespace_uri: &str) -> Option<Rc<RefCell<Option<DefineRule>>>> {
+        self.schemas.get(namespace_uri).cloned()
+    }
+
+    /// A validator for the given document, using the schema registered for the namespace of
+    /// its root element
+    pub fn validator<'a>(&self, document: &'a str) -> Result<Validator<'a>, NoMatchingSchema> {
+        Ok(Validator::new(
+            self.schema_for(document)?,
+            Tokenizer::from(document),
+        ))
+    }
+
+    /// Validate the whole of the given document, as with `crate::validate()`, using the schema
+    /// registered for the namespace of its root element
+    pub fn validate(&self, document: &str) -> Result<ValidationOutcome, NoMatchingSchema> {
+        Ok(crate::validate(self.schema_for(document)?, document))
+    }
+
+    fn schema_for(
+        &self,
+        document: &str,
+    ) -> Result<Rc<RefCell<Option<DefineRule>>>, NoMatchingSchema> {
+        let namespace_uri = root_namespace(document).ok_or(NoMatchingSchema {
+            namespace_uri: None,
+        })?;
+        self.get(&namespace_uri).ok_or(NoMatchingSchema {
+            namespace_uri: Some(namespace_uri),
+        })
+    }
+}
+
+/// The namespace URI of the document's root element, found by reading as far as the end of its
+/// start tag, or `None` if the document is not well-formed that far or uses an undeclared prefix
+fn root_namespace(document: &str) -> Option<String> {
+    let mut tokens = Tokenizer::from(document);
+    let prefix = loop {
+        if let Token::ElementStart { prefix, .. } = tokens.next()?.ok()? {
+            break prefix.as_str();
+        }
+    };
+    let mut namespace_uri = match prefix {
+        "" => Some(""),
+        "xml" => Some("http://www.w3.org/XML/1998/namespace"),
+        _ => None,
+    };
+    loop {
+        match tokens.next()?.ok()? {
+            Token::Attribute {
+                prefix: p,
+                local,
+                value,
+                ..
+            } => {
+                let declared = match p.as_str() {
+                    "" => local.as_str() == "xmlns" && prefix.is_empty(),
+                    "xmlns" => local.as_str() == prefix,
+                    _ => false,
+                };
+                if declared {
+                    namespace_uri = Some(value.as_str());
+                }
+            }
+            Token::ElementEnd { .. } => return namespace_uri.map(str::to_string),
+            _ => return None,
+        }
+    }
+}
+
+/// The reason a `SchemaSet` has no schema for a document
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NoMatchingSchema {
+    /// the namespace of the document's root element, or `None` if it could not be found because
+    /// the start tag of the root element is missing, not well-formed, or uses an undeclared
+    /// prefix
+    pub namespace_uri: Option<String>,
+}
+
+impl fmt::Display for NoMatchingSchema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.namespace_uri {
+            Some(uri) => write!(f, "No schema for documents in the namespace {uri:?}"),
+            None => write!(f, "Could not find the namespace of the root element"),
+        }
+    }
+}
+
+impl core::error::Error for NoMatchingSchema {}
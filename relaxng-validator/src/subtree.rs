@@ -0,0 +1,234 @@
+//! Selection of element subtrees from a larger document, so that each subtree can be validated
+//! against a schema independently of the surrounding document
+
+use std::fmt;
+use std::str::FromStr;
+use xmlparser::{ElementEnd, StrSpan, Token, Tokenizer};
+
+/// Identifies the elements whose subtrees are to be validated
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selector {
+    local: String,
+    /// `None` matches elements with the given local name in any namespace (or none)
+    namespace: Option<String>,
+}
+
+impl Selector {
+    /// Matches elements with the given local name, whatever their namespace
+    pub fn new(local: impl Into<String>) -> Selector {
+        Selector {
+            local: local.into(),
+            namespace: None,
+        }
+    }
+
+    /// Matches only elements in the given namespace; use `""` for elements in no namespace
+    pub fn with_namespace(mut self, namespace: impl Into<String>) -> Selector {
+        self.namespace = Some(namespace.into());
+        self
+    }
+
+    fn matches(&self, namespace_uri: &str, local: &str) -> bool {
+        self.local == local
+            && self
+                .namespace
+                .as_ref()
+                .is_none_or(|ns| ns.as_str() == namespace_uri)
+    }
+}
+
+/// Parses either a plain local name like `entry`, or a name in 'Clark notation' like
+/// `{http://www.w3.org/2005/Atom}entry`
+impl FromStr for Selector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (namespace, local) = if let Some(rest) = s.strip_prefix('{') {
+            let (ns, local) = rest
+                .split_once('}')
+                .ok_or_else(|| format!("missing '}}' in {s:?}"))?;
+            (Some(ns), local)
+        } else {
+            (None, s)
+        };
+        if local.is_empty() || local.contains(':') {
+            return Err(format!("expected an element local name, got {local:?}"));
+        }
+        let selector = Selector::new(local);
+        Ok(match namespace {
+            Some(ns) => selector.with_namespace(ns),
+            None => selector,
+        })
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.namespace {
+            Some(ns) => write!(f, "{{{}}}{}", ns, self.local),
+            None => write!(f, "{}", self.local),
+        }
+    }
+}
+
+/// An iterator over the subtrees of a document whose root elements match a `Selector`.
+///
+/// Each item is the token stream for one subtree, which can be passed (each token wrapped in
+/// `Ok`) to `Validator::from_tokens()`.  The stream is prefixed with any entity declarations from the
+/// document's DTD, and the namespace declarations in scope from ancestor elements are copied
+/// onto the subtree's root element.  When matches are nested, only the outermost is produced
+/// (the inner match is validated as part of it).
+pub struct Subtrees<'a> {
+    tokenizer: Tokenizer<'a>,
+    selector: Selector,
+    /// DTD tokens seen so far
+    dtd: Vec<Token<'a>>,
+    /// for each open element outside any match, the xmlns attributes it declared
+    scopes: Vec<Vec<Token<'a>>>,
+    /// the tokens of a start tag that we have not yet seen the end of
+    start_tag: Vec<Token<'a>>,
+    /// the tokens of the match in progress, and the element depth within it
+    current: Option<(Vec<Token<'a>>, usize)>,
+}
+
+impl<'a> Subtrees<'a> {
+    pub fn new(tokenizer: Tokenizer<'a>, selector: Selector) -> Subtrees<'a> {
+        Subtrees {
+            tokenizer,
+            selector,
+            dtd: vec![],
+            scopes: vec![],
+            start_tag: vec![],
+            current: None,
+        }
+    }
+
+    fn lookup_namespace_uri(&self, prefix: &str) -> Option<&'a str> {
+        self.start_tag
+            .iter()
+            .chain(self.scopes.iter().rev().flatten())
+            .find_map(|tok| match tok {
+                Token::Attribute {
+                    prefix: p,
+                    local,
+                    value,
+                    ..
+                } if (prefix.is_empty() && p.is_empty() && local.as_str() == "xmlns")
+                    || (p.as_str() == "xmlns" && local.as_str() == prefix) =>
+                {
+                    Some(value.as_str())
+                }
+                _ => None,
+            })
+    }
+
+    /// Called at the end of a start tag outside of any match, to decide if the element begins
+    /// a new match
+    fn start_tag_matches(&self) -> bool {
+        let Some(Token::ElementStart { prefix, local, .. }) = self.start_tag.first() else {
+            return false;
+        };
+        let namespace_uri = self.lookup_namespace_uri(prefix.as_str()).unwrap_or("");
+        self.selector.matches(namespace_uri, local.as_str())
+    }
+
+    /// Namespace declarations from ancestor elements which the start tag does not itself
+    /// override
+    fn inherited_namespaces(&self) -> Vec<Token<'a>> {
+        let mut seen: Vec<StrSpan<'a>> = self
+            .start_tag
+            .iter()
+            .filter_map(|tok| xmlns_prefix(tok))
+            .collect();
+        let mut result = vec![];
+        for tok in self.scopes.iter().rev().flatten() {
+            if let Some(prefix) = xmlns_prefix(tok)
+                && !seen.iter().any(|p| p.as_str() == prefix.as_str())
+            {
+                seen.push(prefix);
+                result.push(*tok);
+            }
+        }
+        result
+    }
+}
+
+/// If the token is a namespace declaration, the prefix it declares (empty for the default
+/// namespace)
+fn xmlns_prefix<'a>(tok: &Token<'a>) -> Option<StrSpan<'a>> {
+    match tok {
+        Token::Attribute { prefix, local, .. }
+            if prefix.is_empty() && local.as_str() == "xmlns" =>
+        {
+            Some(*prefix)
+        }
+        Token::Attribute { prefix, local, .. } if prefix.as_str() == "xmlns" => Some(*local),
+        _ => None,
+    }
+}
+
+impl<'a> Iterator for Subtrees<'a> {
+    type Item = Result<Vec<Token<'a>>, xmlparser::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let tok = match self.tokenizer.next()? {
+                Ok(tok) => tok,
+                Err(e) => return Some(Err(e)),
+            };
+            if let Some((tokens, depth)) = &mut self.current {
+                tokens.push(tok);
+                match tok {
+                    Token::ElementStart { .. } => *depth += 1,
+                    Token::ElementEnd {
+                        end: ElementEnd::Close(..) | ElementEnd::Empty,
+                        ..
+                    } => {
+                        *depth -= 1;
+                        if *depth == 0 {
+                            let (tokens, _) = self.current.take().unwrap();
+                            return Some(Ok(tokens));
+                        }
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+            match tok {
+                Token::DtdStart { .. } | Token::EntityDeclaration { .. } | Token::DtdEnd { .. } => {
+                    self.dtd.push(tok)
+                }
+                Token::ElementStart { .. } | Token::Attribute { .. } => self.start_tag.push(tok),
+                Token::ElementEnd { end, .. } => match end {
+                    ElementEnd::Open | ElementEnd::Empty => {
+                        if self.start_tag_matches() {
+                            let mut tokens = self.dtd.clone();
+                            tokens.push(self.start_tag[0]);
+                            tokens.extend(self.inherited_namespaces());
+                            tokens.extend(self.start_tag.drain(1..));
+                            tokens.push(tok);
+                            self.start_tag.clear();
+                            if end == ElementEnd::Empty {
+                                return Some(Ok(tokens));
+                            }
+                            self.current = Some((tokens, 1));
+                        } else {
+                            let declarations = self
+                                .start_tag
+                                .drain(..)
+                                .filter(|tok| xmlns_prefix(tok).is_some())
+                                .collect();
+                            if end == ElementEnd::Open {
+                                self.scopes.push(declarations);
+                            }
+                        }
+                    }
+                    ElementEnd::Close(..) => {
+                        self.scopes.pop();
+                    }
+                },
+                _ => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,309 @@
+//! Expansion of XInclude `include` elements, so that a document assembled from several files is
+//! validated as the single document it represents
+//!
+//! Only inclusion of a whole XML document is supported: `parse="text"`, `xpointer` and hrefs
+//! which are not relative file paths are reported as errors, and `fallback` is never used.
+
+use crate::{Tokens, ValidatorError};
+use alloc::rc::Rc;
+use core::cell::RefCell;
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+use typed_arena::Arena;
+use xmlparser::{ElementEnd, StrSpan, Token, Tokenizer};
+
+/// The namespace of the `include` element
+pub const XINCLUDE_NS: &str = "http://www.w3.org/2001/XInclude";
+
+/// The files that the documents being validated include, which are read as they are needed and
+/// kept for as long as the validators that use them.
+///
+/// ```no_run
+/// # use relaxng_validator::Validator;
+/// # use relaxng_validator::xinclude::XIncludeFiles;
+/// # fn f(model: std::rc::Rc<std::cell::RefCell<Option<relaxng_model::model::DefineRule>>>, doc: &str) {
+/// let files = XIncludeFiles::new("docs");
+/// let mut v = Validator::new(model, xmlparser::Tokenizer::from(doc)).with_xinclude(&files);
+/// while let Some(result) = v.validate_next() {
+///     // ...
+/// }
+/// # }
+/// ```
+pub struct XIncludeFiles {
+    /// the directory against which hrefs in the document being validated are resolved
+    base_dir: PathBuf,
+    /// the path and text of each file read
+    texts: Arena<(PathBuf, String)>,
+}
+
+impl XIncludeFiles {
+    /// Resolve hrefs in the document being validated against the given directory, which will
+    /// usually be the one containing the document
+    pub fn new(base_dir: impl Into<PathBuf>) -> XIncludeFiles {
+        XIncludeFiles {
+            base_dir: base_dir.into(),
+            texts: Arena::new(),
+        }
+    }
+}
+
+/// A document (either the one being validated, or one that it includes) whose tokens are being
+/// produced
+struct Source<'a> {
+    tokens: Tokens<'a>,
+    /// the path and text of an included document, or `None` for the document being validated
+    file: Option<(&'a Path, &'a str)>,
+    /// whether the root element of the document is still to come
+    before_root: bool,
+}
+
+/// The tokens of a document, with each `include` element replaced by the tokens of the document
+/// it refers to
+pub(crate) struct Expand<'a> {
+    files: &'a XIncludeFiles,
+    /// the document being validated, followed by the chain of included documents leading to the
+    /// current token
+    sources: Vec<Source<'a>>,
+    /// the namespace declarations of each open element, as (prefix, uri) pairs
+    namespaces: Vec<Vec<(&'a str, &'a str)>>,
+    /// tokens of a start tag which was read in full before being passed on
+    pending: VecDeque<Token<'a>>,
+    /// the path and text of each document included so far, shared with the validator
+    included: Rc<RefCell<Vec<(&'a Path, &'a str)>>>,
+}
+
+impl<'a> Expand<'a> {
+    pub(crate) fn new(
+        tokens: Tokens<'a>,
+        files: &'a XIncludeFiles,
+        included: Rc<RefCell<Vec<(&'a Path, &'a str)>>>,
+    ) -> Expand<'a> {
+        Expand {
+            files,
+            sources: vec![Source {
+                tokens,
+                file: None,
+                before_root: false,
+            }],
+            namespaces: vec![],
+            pending: VecDeque::new(),
+            included,
+        }
+    }
+
+    fn namespace_uri(&self, prefix: &str) -> Option<&'a str> {
+        self.namespaces
+            .iter()
+            .rev()
+            .flatten()
+            .find(|(p, _)| *p == prefix)
+            .map(|(_, uri)| *uri)
+    }
+
+    fn next_token(&mut self) -> Option<Result<Token<'a>, ValidatorError<'a>>> {
+        self.sources.last_mut().unwrap().tokens.next()
+    }
+
+    /// Read the rest of the start tag beginning with the given token, and either queue its
+    /// tokens to be passed on, or if it is an `include` element, the tokens of the included
+    /// document
+    fn start_tag(&mut self, start: Token<'a>) -> Result<(), ValidatorError<'a>> {
+        let mut tag = vec![start];
+        let end = loop {
+            let Some(tok) = self.next_token() else {
+                // the tokenizer reports the unfinished tag, so there is nothing more to do
+                self.pending.extend(tag);
+                return Ok(());
+            };
+            let tok = tok?;
+            tag.push(tok);
+            if let Token::ElementEnd { end, span } = tok {
+                break (end, span);
+            }
+        };
+        let mut declarations = vec![];
+        let mut href = None;
+        let mut parse = None;
+        let mut xpointer = false;
+        for tok in &tag {
+            if let Token::Attribute {
+                prefix,
+                local,
+                value,
+                ..
+            } = tok
+            {
+                match (prefix.as_str(), local.as_str()) {
+                    ("", "xmlns") => declarations.push(("", value.as_str())),
+                    ("xmlns", prefix) => declarations.push((prefix, value.as_str())),
+                    ("", "href") => href = Some(*value),
+                    ("", "parse") => parse = Some(*value),
+                    ("", "xpointer") => xpointer = true,
+                    _ => {}
+                }
+            }
+        }
+        let source = self.sources.last_mut().unwrap();
+        if core::mem::take(&mut source.before_root)
+            && !declarations.iter().any(|(prefix, _)| prefix.is_empty())
+            && self.namespace_uri("").is_some_and(|uri| !uri.is_empty())
+        {
+            // the root of an included document is in no namespace unless it says otherwise,
+            // whatever the default namespace where it was included
+            declarations.push(("", ""));
+            let attr = Token::Attribute {
+                prefix: StrSpan::from(""),
+                local: StrSpan::from("xmlns"),
+                value: StrSpan::from(""),
+                span: StrSpan::from("xmlns=\"\""),
+            };
+            tag.insert(tag.len() - 1, attr);
+        }
+        self.namespaces.push(declarations);
+        let Token::ElementStart {
+            prefix,
+            local,
+            span: start_span,
+        } = start
+        else {
+            unreachable!()
+        };
+        if local.as_str() != "include" || self.namespace_uri(prefix.as_str()) != Some(XINCLUDE_NS) {
+            if let (ElementEnd::Empty, _) = end {
+                self.namespaces.pop();
+            }
+            self.pending.extend(tag);
+            return Ok(());
+        }
+        self.namespaces.pop();
+        if let (ElementEnd::Open, _) = end {
+            // any fallback content is unused, since failing to include the document is an error
+            self.skip_content()?;
+        }
+        let span = start_span.start()..end.1.end();
+        let error = |message: String| ValidatorError::XInclude {
+            message,
+            span: span.clone(),
+        };
+        if let Some(parse) = parse
+            && parse.as_str() != "xml"
+        {
+            return Err(error(format!(
+                "parse={:?} is not supported, only \"xml\"",
+                parse.as_str()
+            )));
+        }
+        if xpointer {
+            return Err(error("xpointer is not supported".to_string()));
+        }
+        let href = match href {
+            Some(href) if !href.as_str().is_empty() => href.as_str(),
+            _ => return Err(error("href must give the file to include".to_string())),
+        };
+        if href.contains(['#', ':']) {
+            return Err(error(format!(
+                "href {href:?} is not supported, only a relative file path"
+            )));
+        }
+        let dir = match self.sources.last().unwrap().file {
+            Some((file, _)) => file.parent().unwrap_or(Path::new("")),
+            None => &self.files.base_dir,
+        };
+        let file = dir.join(href);
+        if self
+            .sources
+            .iter()
+            .any(|s| s.file.is_some_and(|(f, _)| same_file(f, &file)))
+        {
+            return Err(error(format!("{} includes itself", file.display())));
+        }
+        let text = fs::read_to_string(&file)
+            .map_err(|e| error(format!("could not read {}: {e}", file.display())))?;
+        let (file, text) = self.files.texts.alloc((file, text));
+        let (file, text): (&'a Path, &'a str) = (file, text);
+        self.included.borrow_mut().push((file, text));
+        self.sources.push(Source {
+            tokens: Box::new(Tokenizer::from(text).map(|r| r.map_err(ValidatorError::Xml))),
+            file: Some((file, text)),
+            before_root: true,
+        });
+        Ok(())
+    }
+
+    /// The error, as found in the document being read, whose text its spans refer to
+    fn in_source(&self, error: ValidatorError<'a>) -> ValidatorError<'a> {
+        match self.sources.last().unwrap().file {
+            Some((path, text)) => ValidatorError::Included {
+                path,
+                text,
+                error: Box::new(error),
+            },
+            None => error,
+        }
+    }
+
+    /// Consume the content and end tag of an element whose start tag has been read
+    fn skip_content(&mut self) -> Result<(), ValidatorError<'a>> {
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next_token() {
+                None => return Ok(()),
+                Some(Err(e)) => return Err(e),
+                Some(Ok(Token::ElementEnd { end, .. })) => match end {
+                    ElementEnd::Open => depth += 1,
+                    ElementEnd::Close(..) => depth -= 1,
+                    ElementEnd::Empty => {}
+                },
+                Some(Ok(_)) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+impl<'a> Iterator for Expand<'a> {
+    type Item = Result<Token<'a>, ValidatorError<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(tok) = self.pending.pop_front() {
+                return Some(Ok(tok));
+            }
+            let tok = match self.next_token() {
+                Some(Ok(tok)) => tok,
+                Some(Err(e)) => return Some(Err(self.in_source(e))),
+                None if self.sources.len() > 1 => {
+                    self.sources.pop();
+                    continue;
+                }
+                None => return None,
+            };
+            match tok {
+                Token::ElementStart { .. } => {
+                    if let Err(e) = self.start_tag(tok) {
+                        return Some(Err(self.in_source(e)));
+                    }
+                }
+                Token::ElementEnd {
+                    end: ElementEnd::Close(..),
+                    ..
+                } => {
+                    self.namespaces.pop();
+                    return Some(Ok(tok));
+                }
+                // the declaration of an included document, which would be out of place in the
+                // document including it
+                Token::Declaration { .. } if self.sources.len() > 1 => {}
+                _ => return Some(Ok(tok)),
+            }
+        }
+    }
+}
@@ -0,0 +1,89 @@
+//! Generators of schemas and documents in the XML syntax that grow with a parameter, shared by
+//! the stress tests and the benchmarks so that each measures the same thing
+
+use std::fmt::Write;
+
+/// A schema whose root element holds an interleave of `n` optional elements
+pub fn gen_interleave_schema(n: usize) -> String {
+    let mut rng = String::with_capacity(n * 100);
+    rng.push_str(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<grammar xmlns="http://relaxng.org/ns/structure/1.0"
+         datatypeLibrary="http://www.w3.org/2001/XMLSchema-datatypes">
+  <start>
+    <element name="root">
+      <interleave>
+"#,
+    );
+    for i in 0..n {
+        writeln!(
+            rng,
+            "        <optional><element name=\"e{i}\"><text/></element></optional>"
+        )
+        .unwrap();
+    }
+    rng.push_str(
+        r#"      </interleave>
+    </element>
+  </start>
+</grammar>
+"#,
+    );
+    rng
+}
+
+/// A document holding all the elements of `gen_interleave_schema(n)`, in the reverse of the
+/// order they are declared in
+pub fn gen_interleave_doc(n: usize) -> String {
+    let mut xml = String::with_capacity(n * 50);
+    xml.push_str("<?xml version=\"1.0\"?>\n<root>\n");
+    // Elements in reverse order to maximize interleave work
+    for i in (0..n).rev() {
+        writeln!(xml, "  <e{i}>value {i}</e{i}>").unwrap();
+    }
+    xml.push_str("</root>\n");
+    xml
+}
+
+/// A schema whose root element holds one or more of a choice between `n_branches` elements
+pub fn gen_choice_schema(n_branches: usize) -> String {
+    let mut rng = String::with_capacity(n_branches * 80);
+    rng.push_str(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<grammar xmlns="http://relaxng.org/ns/structure/1.0">
+  <start>
+    <element name="root">
+      <oneOrMore>
+        <choice>
+"#,
+    );
+    for i in 0..n_branches {
+        writeln!(
+            rng,
+            "          <element name=\"branch{i}\"><text/></element>"
+        )
+        .unwrap();
+    }
+    rng.push_str(
+        r#"        </choice>
+      </oneOrMore>
+    </element>
+  </start>
+</grammar>
+"#,
+    );
+    rng
+}
+
+/// A document whose root element holds `n_children` of the elements of
+/// `gen_choice_schema(n_branches)`, cycling through the branches
+pub fn gen_choice_doc(n_branches: usize, n_children: usize) -> String {
+    let mut xml = String::with_capacity(n_children * 40);
+    xml.push_str("<?xml version=\"1.0\"?>\n<root>\n");
+    for i in 0..n_children {
+        let branch = i % n_branches;
+        writeln!(xml, "  <branch{branch}>text</branch{branch}>").unwrap();
+    }
+    xml.push_str("</root>\n");
+    xml
+}
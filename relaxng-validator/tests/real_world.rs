@@ -65,9 +65,7 @@ fn compile_schema(schema: &str) -> Result<(), String> {
 macro_rules! skip_if_missing {
     () => {
         if !schemas_available() {
-            eprintln!(
-                "Skipping real-world test: run ./testdata/download.sh first"
-            );
+            eprintln!("Skipping real-world test: run ./testdata/download.sh first");
             return;
         }
     };
@@ -83,6 +81,22 @@ fn atom_compiles() {
     compile_schema("atom/atom.rng").expect("Atom schema should compile");
 }
 
+#[test]
+fn atom_namespaces() {
+    skip_if_missing!();
+    let mut compiler = Compiler::new(FsFiles, Syntax::Xml);
+    compiler
+        .compile(&schema_dir().join("atom/atom.rng"))
+        .expect("Atom schema should compile");
+    let namespaces = compiler.namespaces();
+    assert!(
+        namespaces
+            .iter()
+            .any(|(_, uri)| uri == "http://www.w3.org/2005/Atom"),
+        "{namespaces:?}"
+    );
+}
+
 #[test]
 fn atom_valid_minimal_feed() {
     skip_if_missing!();
@@ -313,9 +327,10 @@ fn docbook_compiles() {
 // ═══════════════════════════════════════════════════════════════════════════════
 // XHTML 1.1  (W3C modular .rng — tests include support)
 //
-// Known issue: xhtml-datatypes-1.rng sets datatypeLibrary on its own
-// <grammar>, but the compiler does not propagate it across include boundaries,
-// causing a "relaxng built-in datatype supports no parameters" error.
+// xhtml-datatypes-1.rng sets the XML Schema datatypeLibrary on its own
+// <grammar> and gives facets to type="token", which used to be taken as the
+// built-in token whatever the library, failing with "built-in datatypes take
+// no parameters".
 // ═══════════════════════════════════════════════════════════════════════════════
 
 #[test]
@@ -325,14 +340,5 @@ fn xhtml_compiles() {
         eprintln!("Skipping: XHTML schemas not downloaded");
         return;
     }
-    let result = compile_schema("xhtml11/xhtml11-1.rng");
-    match result {
-        Ok(()) => {} // If/when fixed, this is the expected path.
-        Err(e) => {
-            eprintln!(
-                "KNOWN BUG: XHTML 1.1 schema fails to compile \
-                 (datatypeLibrary not propagated across include): {e}"
-            );
-        }
-    }
+    compile_schema("xhtml11/xhtml11-1.rng").expect("XHTML 1.1 schema should compile");
 }
@@ -65,10 +65,10 @@ fn process_suite(
                 process_suite(emitter, file, stats, child)
             } else if child.tag_name() == ExpandedName::from("testCase") {
                 process_case(emitter, file, stats, child)
-            } else if child.tag_name() == ExpandedName::from("documentation") {
-                if let Some(text) = child.text() {
-                    eprintln!("== {text} ==");
-                }
+            } else if child.tag_name() == ExpandedName::from("documentation")
+                && let Some(text) = child.text()
+            {
+                eprintln!("== {text} ==");
             }
         }
     }
@@ -376,15 +376,15 @@ fn run_test(test_case: TestCase) -> TestResult {
 fn stringify(node: Node) -> String {
     let mut res = String::new();
     // extra work to give the first line consistent indentation with the rest of the lines
-    if let Some(prev) = node.prev_sibling() {
-        if let Some(text) = prev.text() {
-            let last_line = if let Some(pos) = text.rfind('\n') {
-                &text[pos + 1..]
-            } else {
-                text
-            };
-            res.push_str(last_line);
-        }
+    if let Some(prev) = node.prev_sibling()
+        && let Some(text) = prev.text()
+    {
+        let last_line = if let Some(pos) = text.rfind('\n') {
+            &text[pos + 1..]
+        } else {
+            text
+        };
+        res.push_str(last_line);
     }
     res.push_str(&node.document().input_text()[node.range()]);
     res
@@ -7,6 +7,9 @@
 // Run with: cargo test --test stress
 // Run the larger sizes: cargo test --test stress -- --ignored
 
+mod generators;
+
+use generators::{gen_choice_doc, gen_choice_schema, gen_interleave_doc, gen_interleave_schema};
 use relaxng_model::{Compiler, FsFiles, Syntax};
 use relaxng_validator::Validator;
 use std::fmt::Write;
@@ -44,43 +47,6 @@ fn validate_generated(schema_xml: &str, doc_xml: &str) -> Result<(), String> {
 //  stresses the interleave derivative computation which historically caused
 //  exponential blowup.
 
-fn gen_interleave_schema(n: usize) -> String {
-    let mut rng = String::with_capacity(n * 100);
-    rng.push_str(r#"<?xml version="1.0" encoding="UTF-8"?>
-<grammar xmlns="http://relaxng.org/ns/structure/1.0"
-         datatypeLibrary="http://www.w3.org/2001/XMLSchema-datatypes">
-  <start>
-    <element name="root">
-      <interleave>
-"#);
-    for i in 0..n {
-        write!(
-            rng,
-            "        <optional><element name=\"e{i}\"><text/></element></optional>\n"
-        )
-        .unwrap();
-    }
-    rng.push_str(
-        r#"      </interleave>
-    </element>
-  </start>
-</grammar>
-"#,
-    );
-    rng
-}
-
-fn gen_interleave_doc(n: usize) -> String {
-    let mut xml = String::with_capacity(n * 50);
-    xml.push_str("<?xml version=\"1.0\"?>\n<root>\n");
-    // Elements in reverse order to maximize interleave work
-    for i in (0..n).rev() {
-        write!(xml, "  <e{i}>value {i}</e{i}>\n").unwrap();
-    }
-    xml.push_str("</root>\n");
-    xml
-}
-
 fn validate_interleave(n: usize) {
     let schema_xml = gen_interleave_schema(n);
     let doc_xml = gen_interleave_doc(n);
@@ -128,7 +94,6 @@ fn interleave_stress_30() {
 }
 
 #[test]
-#[ignore] // May be slow depending on interleave optimization
 fn interleave_stress_50() {
     validate_interleave(50);
 }
@@ -143,23 +108,25 @@ fn interleave_stress_50() {
 
 fn gen_wide_attr_schema(n_required: usize, n_optional: usize) -> String {
     let mut rng = String::with_capacity((n_required + n_optional) * 80);
-    rng.push_str(r#"<?xml version="1.0" encoding="UTF-8"?>
+    rng.push_str(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
 <grammar xmlns="http://relaxng.org/ns/structure/1.0"
          datatypeLibrary="http://www.w3.org/2001/XMLSchema-datatypes">
   <start>
     <element name="root">
-"#);
+"#,
+    );
     for i in 0..n_required {
-        write!(
+        writeln!(
             rng,
-            "      <attribute name=\"req{i}\"><data type=\"string\"/></attribute>\n"
+            "      <attribute name=\"req{i}\"><data type=\"string\"/></attribute>"
         )
         .unwrap();
     }
     for i in 0..n_optional {
-        write!(
+        writeln!(
             rng,
-            "      <optional><attribute name=\"opt{i}\"><data type=\"string\"/></attribute></optional>\n"
+            "      <optional><attribute name=\"opt{i}\"><data type=\"string\"/></attribute></optional>"
         )
         .unwrap();
     }
@@ -232,12 +199,14 @@ fn wide_attrs_missing_required() {
 
 fn gen_deep_nesting_schema(depth: usize) -> String {
     let mut rng = String::with_capacity(depth * 120);
-    rng.push_str(r#"<?xml version="1.0" encoding="UTF-8"?>
+    rng.push_str(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
 <grammar xmlns="http://relaxng.org/ns/structure/1.0">
   <start>
-"#);
+"#,
+    );
     for i in 0..depth {
-        write!(rng, "    <element name=\"e{i}\">\n").unwrap();
+        writeln!(rng, "    <element name=\"e{i}\">").unwrap();
     }
     rng.push_str("    <text/>\n");
     for _ in 0..depth {
@@ -313,44 +282,6 @@ fn deep_nesting_wrong_leaf() {
 //  Schema with N alternative child element types in a choice, then a document
 //  that picks different branches. Tests choice derivative computation.
 
-fn gen_choice_schema(n_branches: usize) -> String {
-    let mut rng = String::with_capacity(n_branches * 80);
-    rng.push_str(r#"<?xml version="1.0" encoding="UTF-8"?>
-<grammar xmlns="http://relaxng.org/ns/structure/1.0">
-  <start>
-    <element name="root">
-      <oneOrMore>
-        <choice>
-"#);
-    for i in 0..n_branches {
-        write!(
-            rng,
-            "          <element name=\"branch{i}\"><text/></element>\n"
-        )
-        .unwrap();
-    }
-    rng.push_str(
-        r#"        </choice>
-      </oneOrMore>
-    </element>
-  </start>
-</grammar>
-"#,
-    );
-    rng
-}
-
-fn gen_choice_doc(n_branches: usize, n_children: usize) -> String {
-    let mut xml = String::with_capacity(n_children * 40);
-    xml.push_str("<?xml version=\"1.0\"?>\n<root>\n");
-    for i in 0..n_children {
-        let branch = i % n_branches;
-        write!(xml, "  <branch{branch}>text</branch{branch}>\n").unwrap();
-    }
-    xml.push_str("</root>\n");
-    xml
-}
-
 #[test]
 fn choice_50_branches_200_children() {
     let schema = gen_choice_schema(50);
@@ -383,5 +314,85 @@ fn choice_wrong_branch_name() {
     let pos = doc.rfind("</root>").unwrap();
     doc.insert_str(pos, "  <nonexistent>x</nonexistent>\n");
     let result = validate_generated(&schema, &doc);
-    assert!(result.is_err(), "element outside choice branches should fail");
+    assert!(
+        result.is_err(),
+        "element outside choice branches should fail"
+    );
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+//  Long text stress test
+// ══════════════════════════════════════════════════════════════════════════════
+//
+//  A single element holding a very long list, checking that the time taken
+//  grows linearly with the length of the text, including when the text is
+//  split into many tokens (here by comments) that are buffered before being
+//  checked.
+
+fn gen_long_list_schema() -> String {
+    r#"<?xml version="1.0" encoding="UTF-8"?>
+<grammar xmlns="http://relaxng.org/ns/structure/1.0"
+         datatypeLibrary="http://www.w3.org/2001/XMLSchema-datatypes">
+  <start>
+    <element name="root">
+      <list><zeroOrMore><data type="int"/></zeroOrMore></list>
+    </element>
+  </start>
+</grammar>
+"#
+    .to_string()
+}
+
+/// A document whose root holds `n` integers, with a comment after every `chunk` of them
+fn gen_long_list_doc(n: usize, chunk: usize) -> String {
+    let mut xml = String::with_capacity(n * 8);
+    xml.push_str("<?xml version=\"1.0\"?>\n<root>");
+    for i in 0..n {
+        write!(xml, "{i} ").unwrap();
+        if (i + 1) % chunk == 0 {
+            xml.push_str("<!---->");
+        }
+    }
+    xml.push_str("</root>\n");
+    xml
+}
+
+#[test]
+fn long_list_100k_items() {
+    let schema = gen_long_list_schema();
+    let doc = gen_long_list_doc(100_000, usize::MAX);
+    let start = Instant::now();
+    validate_generated(&schema, &doc).expect("valid long list");
+    eprintln!(
+        "  [stress] list of 100k items — {:.2}ms",
+        start.elapsed().as_secs_f64() * 1000.0
+    );
+}
+
+#[test]
+fn long_list_100k_items_in_chunks() {
+    let schema = gen_long_list_schema();
+    let doc = gen_long_list_doc(100_000, 10);
+    let start = Instant::now();
+    validate_generated(&schema, &doc).expect("valid long list");
+    eprintln!(
+        "  [stress] list of 100k items in 10k chunks — {:.2}ms",
+        start.elapsed().as_secs_f64() * 1000.0
+    );
+}
+
+#[test]
+fn long_list_invalid_last_item() {
+    let schema = gen_long_list_schema();
+    let doc = gen_long_list_doc(100_000, usize::MAX).replace("99999 ", "x ");
+    let start = Instant::now();
+    let result = validate_generated(&schema, &doc);
+    assert!(
+        result.is_err(),
+        "a list item which is not an int should fail"
+    );
+    eprintln!(
+        "  [stress] list of 100k items, the last invalid — {:.2}ms",
+        start.elapsed().as_secs_f64() * 1000.0
+    );
 }
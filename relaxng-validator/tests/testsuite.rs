@@ -0,0 +1,110 @@
+// Runs the validator over a copy of the RELAX NG test suite laid out as directories, as
+// distributed at https://relaxng.org/#conformance, rather than as the single spectest.xml file.
+//
+// Each test case is a directory holding either an incorrect schema `i.rng`, which must fail to
+// compile, or a correct schema `c.rng` together with instances `*.v.xml`, which must be valid,
+// and `*.i.xml`, which must be invalid.  Any other files in the directory are there for the
+// schema to include.  Directories may be nested to any depth.
+//
+// The suite is not checked in.  Point RNG_TESTSUITE at a copy of it and run:
+//
+//     RNG_TESTSUITE=path/to/testSuite cargo test --test testsuite -- --ignored --nocapture
+
+use relaxng_model::{Compiler, FsFiles, Syntax};
+use std::fs;
+use std::panic;
+use std::path::{Path, PathBuf};
+
+#[test]
+#[ignore]
+fn testsuite() {
+    let Ok(root) = std::env::var("RNG_TESTSUITE") else {
+        eprintln!("RNG_TESTSUITE is not set, so there is no test suite to run");
+        return;
+    };
+    let mut cases = vec![];
+    find_cases(Path::new(&root), &mut cases);
+    assert!(!cases.is_empty(), "no test cases found in {root}");
+
+    let mut failures = vec![];
+    for case in &cases {
+        let result = panic::catch_unwind(|| run_case(case))
+            .unwrap_or_else(|_| Err("the compiler or validator panicked".to_string()));
+        if let Err(reason) = result {
+            failures.push((case, reason));
+        }
+    }
+    for (case, reason) in &failures {
+        eprintln!("FAILED {}: {reason}", case.display());
+    }
+    eprintln!(
+        "{} passed, {} failed",
+        cases.len() - failures.len(),
+        failures.len()
+    );
+    assert!(failures.is_empty(), "{} test cases failed", failures.len());
+}
+
+/// Collect the test case directories below the given one, in order of their paths
+fn find_cases(dir: &Path, cases: &mut Vec<PathBuf>) {
+    if dir.join("c.rng").is_file() || dir.join("i.rng").is_file() {
+        cases.push(dir.to_path_buf());
+    }
+    let mut entries: Vec<_> = fs::read_dir(dir)
+        .unwrap_or_else(|e| panic!("reading {}: {e}", dir.display()))
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.is_dir())
+        .collect();
+    entries.sort();
+    for entry in entries {
+        find_cases(&entry, cases);
+    }
+}
+
+/// The instance documents of a test case with names ending in the given suffix
+fn instances(case: &Path, suffix: &str) -> Vec<PathBuf> {
+    let mut paths: Vec<_> = fs::read_dir(case)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.ends_with(suffix))
+        })
+        .collect();
+    paths.sort();
+    paths
+}
+
+fn run_case(case: &Path) -> Result<(), String> {
+    let mut compiler = Compiler::new(FsFiles, Syntax::Xml);
+    let incorrect = case.join("i.rng");
+    if incorrect.is_file() {
+        return match compiler.compile(&incorrect) {
+            Ok(_) => Err("incorrect schema was accepted".to_string()),
+            Err(_) => Ok(()),
+        };
+    }
+    let model = compiler
+        .compile(&case.join("c.rng"))
+        .map_err(|e| format!("correct schema was rejected: {e:?}"))?;
+    for (suffix, expect_valid) in [(".v.xml", true), (".i.xml", false)] {
+        for instance in instances(case, suffix) {
+            let doc = fs::read_to_string(&instance)
+                .map_err(|e| format!("reading {}: {e}", instance.display()))?;
+            let outcome = relaxng_validator::validate(model.clone(), &doc);
+            if outcome.ok != expect_valid {
+                let name = instance.file_name().unwrap().to_string_lossy();
+                return Err(if expect_valid {
+                    format!(
+                        "valid instance {name} was rejected: {:?}",
+                        outcome.errors[0]
+                    )
+                } else {
+                    format!("invalid instance {name} was accepted")
+                });
+            }
+        }
+    }
+    Ok(())
+}